@@ -10,11 +10,12 @@
 //! 5. 主编排 Agent - 协调整个流程
 
 use rig::prelude::*;
-use rig::agent::{Agent, AgentBuilder};
-use rig::completion::{Chat, CompletionModel, Prompt, PromptError, ToolDefinition};
-use rig::tool::Tool;
+use rig::agent::AgentBuilder;
+use rig::completion::Prompt;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use rig::tools::{
+    ExperimentalDataReader, HistoricalDataQuery, MLPerformancePredictor, TopPhiSimulator,
+};
 use std::collections::HashMap;
 
 // ============= 数据结构定义 =============
@@ -76,353 +77,6 @@ struct OptimizationSuggestion {
     priority: i32,
 }
 
-// ============= 模拟工具定义 =============
-
-/// TopPhi 涂层沉积形貌模拟工具（模拟）
-#[derive(Deserialize, Serialize)]
-struct TopPhiSimulator;
-
-#[derive(Deserialize)]
-struct TopPhiArgs {
-    composition: String,
-    process_params: String,
-    structure: String,
-}
-
-impl Tool for TopPhiSimulator {
-    const NAME: &'static str = "topPhi_simulator";
-    type Error = anyhow::Error;
-    type Args = TopPhiArgs;
-    type Output = String;
-
-    async fn definition(&self, _prompt: String) -> ToolDefinition {
-        serde_json::from_value(json!({
-            "name": "topPhi_simulator",
-            "description": "TopPhi 模拟工具 - 预测涂层沉积形貌和微观结构",
-            "parameters": {
-                "type": "object",
-                "properties": {
-                    "composition": {
-                        "type": "string",
-                        "description": "涂层成分信息（JSON格式）"
-                    },
-                    "process_params": {
-                        "type": "string",
-                        "description": "工艺参数（JSON格式）"
-                    },
-                    "structure": {
-                        "type": "string",
-                        "description": "预计沉积结构（JSON格式）"
-                    }
-                },
-                "required": ["composition", "process_params", "structure"]
-            }
-        }))
-        .expect("Tool Definition")
-    }
-
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        println!("\n[TopPhi模拟器] 开始模拟涂层沉积...");
-        println!("  - 成分: {}", args.composition);
-        println!("  - 工艺参数: {}", args.process_params);
-        println!("  - 结构: {}", args.structure);
-        
-        // 模拟输出
-        let result = format!(
-            "TopPhi模拟结果:\n\
-            形貌特征: 柱状晶结构，晶粒尺寸约 50-80 nm\n\
-            表面粗糙度: Ra = 0.15 μm\n\
-            致密度: 98.5%\n\
-            应力状态: 压应力 -2.3 GPa\n\
-            界面结合: 良好，无明显缺陷\n\
-            预测生长速率: 2.5 μm/h"
-        );
-        
-        println!("  ✓ 模拟完成\n");
-        Ok(result)
-    }
-}
-
-/// ML 性能预测模型工具（模拟）
-#[derive(Deserialize, Serialize)]
-struct MLPerformancePredictor;
-
-#[derive(Deserialize)]
-struct MLPredictorArgs {
-    composition: String,
-    process_params: String,
-    structure: String,
-    simulation_result: String,
-}
-
-impl Tool for MLPerformancePredictor {
-    const NAME: &'static str = "ml_performance_predictor";
-    type Error = anyhow::Error;
-    type Args = MLPredictorArgs;
-    type Output = String;
-
-    async fn definition(&self, _prompt: String) -> ToolDefinition {
-        serde_json::from_value(json!({
-            "name": "ml_performance_predictor",
-            "description": "机器学习模型 - 预测涂层性能（硬度、附着力、耐磨性等）",
-            "parameters": {
-                "type": "object",
-                "properties": {
-                    "composition": {
-                        "type": "string",
-                        "description": "涂层成分"
-                    },
-                    "process_params": {
-                        "type": "string",
-                        "description": "工艺参数"
-                    },
-                    "structure": {
-                        "type": "string",
-                        "description": "涂层结构"
-                    },
-                    "simulation_result": {
-                        "type": "string",
-                        "description": "TopPhi模拟结果"
-                    }
-                },
-                "required": ["composition", "process_params", "structure", "simulation_result"]
-            }
-        }))
-        .expect("Tool Definition")
-    }
-
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        println!("\n[ML性能预测器] 使用机器学习模型预测性能...");
-        
-        // 模拟 ML 预测
-        let result = json!({
-            "predicted_hardness": 3250.0,
-            "hardness_confidence": 0.92,
-            "predicted_adhesion": 68.5,
-            "adhesion_confidence": 0.88,
-            "predicted_wear_rate": 1.2e-6,
-            "wear_confidence": 0.85,
-            "friction_coefficient": 0.35,
-            "model_version": "v2.3.1",
-            "feature_importance": {
-                "composition": 0.45,
-                "process_temp": 0.25,
-                "bias_voltage": 0.18,
-                "structure": 0.12
-            }
-        });
-        
-        println!("  ✓ 预测完成");
-        println!("    - 硬度: 3250 HV (置信度: 92%)");
-        println!("    - 附着力: 68.5 N (置信度: 88%)");
-        println!("    - 磨损率: 1.2×10⁻⁶ mm³/Nm (置信度: 85%)\n");
-        
-        Ok(serde_json::to_string_pretty(&result)?)
-    }
-}
-
-/// 历史数据查询工具（模拟）
-#[derive(Deserialize, Serialize)]
-struct HistoricalDataQuery;
-
-#[derive(Deserialize)]
-struct HistoricalQueryArgs {
-    composition_range: String,
-    process_range: String,
-}
-
-impl Tool for HistoricalDataQuery {
-    const NAME: &'static str = "historical_data_query";
-    type Error = anyhow::Error;
-    type Args = HistoricalQueryArgs;
-    type Output = String;
-
-    async fn definition(&self, _prompt: String) -> ToolDefinition {
-        serde_json::from_value(json!({
-            "name": "historical_data_query",
-            "description": "查询历史实验数据库，找到相似配方的历史记录",
-            "parameters": {
-                "type": "object",
-                "properties": {
-                    "composition_range": {
-                        "type": "string",
-                        "description": "成分范围"
-                    },
-                    "process_range": {
-                        "type": "string",
-                        "description": "工艺参数范围"
-                    }
-                },
-                "required": ["composition_range", "process_range"]
-            }
-        }))
-        .expect("Tool Definition")
-    }
-
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        println!("\n[历史数据库] 查询相似配方...");
-        println!("  - 成分范围: {}", args.composition_range);
-        println!("  - 工艺范围: {}", args.process_range);
-        
-        // 模拟查询结果
-        let result = json!({
-            "matched_records": 5,
-            "similar_samples": [
-                {
-                    "sample_id": "TiAlN-2023-045",
-                    "composition": {"Al": 0.52, "Ti": 0.38, "N": 0.10},
-                    "hardness": 3180.0,
-                    "adhesion": 65.2,
-                    "similarity": 0.94
-                },
-                {
-                    "sample_id": "TiAlN-2023-072",
-                    "composition": {"Al": 0.50, "Ti": 0.40, "N": 0.10},
-                    "hardness": 3320.0,
-                    "adhesion": 70.1,
-                    "similarity": 0.91
-                },
-                {
-                    "sample_id": "TiAlN-2022-156",
-                    "composition": {"Al": 0.48, "Ti": 0.42, "N": 0.10},
-                    "hardness": 3050.0,
-                    "adhesion": 62.8,
-                    "similarity": 0.87
-                }
-            ],
-            "performance_range": {
-                "hardness": {"min": 3050.0, "max": 3320.0, "avg": 3183.3},
-                "adhesion": {"min": 62.8, "max": 70.1, "avg": 66.0}
-            }
-        });
-        
-        println!("  ✓ 找到 5 条相似记录");
-        println!("    - 硬度范围: 3050-3320 HV");
-        println!("    - 附着力范围: 62.8-70.1 N\n");
-        
-        Ok(serde_json::to_string_pretty(&result)?)
-    }
-}
-
-/// 实验数据读取工具（模拟）
-#[derive(Deserialize, Serialize)]
-struct ExperimentalDataReader;
-
-#[derive(Deserialize)]
-struct ExperimentDataArgs {
-    sample_id: String,
-}
-
-impl Tool for ExperimentalDataReader {
-    const NAME: &'static str = "experimental_data_reader";
-    type Error = anyhow::Error;
-    type Args = ExperimentDataArgs;
-    type Output = String;
-
-    async fn definition(&self, _prompt: String) -> ToolDefinition {
-        serde_json::from_value(json!({
-            "name": "experimental_data_reader",
-            "description": "读取实验验证后的实际性能数据（硬度、SEM图像分析等）",
-            "parameters": {
-                "type": "object",
-                "properties": {
-                    "sample_id": {
-                        "type": "string",
-                        "description": "样品编号"
-                    }
-                },
-                "required": ["sample_id"]
-            }
-        }))
-        .expect("Tool Definition")
-    }
-
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        println!("\n[实验数据读取] 读取样品 {} 的实验数据...", args.sample_id);
-        
-        // 模拟实验数据
-        let result = json!({
-            "sample_id": args.sample_id,
-            "measured_hardness": 3285.0,
-            "hardness_std_dev": 45.0,
-            "measured_adhesion": 67.2,
-            "adhesion_std_dev": 2.3,
-            "sem_analysis": {
-                "grain_size": "55-75 nm",
-                "phase_composition": "主相: 面心立方 TiAlN, 次相: 六方 AlN (约5%)",
-                "surface_quality": "均匀致密，无裂纹",
-                "interface_bonding": "良好"
-            },
-            "xrd_peaks": ["TiAlN(111)", "TiAlN(200)", "TiAlN(220)", "AlN(002)"],
-            "test_date": "2025-10-31",
-            "operator": "实验室-02"
-        });
-        
-        println!("  ✓ 数据读取完成");
-        println!("    - 实测硬度: 3285 ± 45 HV");
-        println!("    - 实测附着力: 67.2 ± 2.3 N");
-        println!("    - SEM分析: 晶粒尺寸 55-75 nm\n");
-        
-        Ok(serde_json::to_string_pretty(&result)?)
-    }
-}
-
-// ============= Agent 工具包装器 =============
-
-/// 包装 Agent 为工具的通用结构
-struct AgentTool<M: CompletionModel> {
-    agent: Agent<M>,
-    tool_name: &'static str,
-    description: String,
-}
-
-impl<M: CompletionModel> AgentTool<M> {
-    fn new(agent: Agent<M>, tool_name: &'static str, description: String) -> Self {
-        Self {
-            agent,
-            tool_name,
-            description,
-        }
-    }
-}
-
-#[derive(Deserialize)]
-struct AgentToolArgs {
-    input: String,
-}
-
-impl<M: CompletionModel> Tool for AgentTool<M> {
-    const NAME: &'static str = "agent_tool";
-    type Error = PromptError;
-    type Args = AgentToolArgs;
-    type Output = String;
-
-    async fn definition(&self, _prompt: String) -> ToolDefinition {
-        serde_json::from_value(json!({
-            "name": self.tool_name,
-            "description": self.description,
-            "parameters": {
-                "type": "object",
-                "properties": {
-                    "input": {
-                        "type": "string",
-                        "description": "输入信息"
-                    }
-                },
-                "required": ["input"]
-            }
-        }))
-        .expect("Tool Definition")
-    }
-
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        println!("\n[{}] 处理中...", self.tool_name);
-        let result = self.agent.chat(&args.input, vec![]).await?;
-        println!("  ✓ 完成\n");
-        Ok(result)
-    }
-}
-
 // ============= 创建专业 Agent =============
 
 async fn create_coating_optimization_system(
@@ -480,9 +134,9 @@ async fn create_coating_optimization_system(
             
             风格：科学、客观、数据驱动
         ")
-        .tool(TopPhiSimulator)
-        .tool(MLPerformancePredictor)
-        .tool(HistoricalDataQuery)
+        .tool(TopPhiSimulator::new())
+        .tool(MLPerformancePredictor::new())
+        .tool(HistoricalDataQuery::new())
         .temperature(0.3)
         .build();
 
@@ -585,7 +239,7 @@ async fn create_coating_optimization_system(
             
             风格：系统化、决策清晰、目标导向
         ")
-        .tool(ExperimentalDataReader)
+        .tool(ExperimentalDataReader::new())
         .temperature(0.3)
         .build();
 
@@ -638,12 +292,38 @@ async fn create_coating_optimization_system(
             
             风格：专业、系统化、高效
         ")
-        .tool(requirement_agent)
-        .tool(prediction_agent)
-        .tool(composition_optimizer)
-        .tool(structure_optimizer)
-        .tool(process_optimizer)
-        .tool(iteration_agent)
+        .tool(requirement_agent.into_tool(
+            "requirement_extractor",
+            "需求提取专家：收集并验证涂层成分、工艺参数、结构信息和性能需求",
+        ))
+        .tool(
+            prediction_agent
+                .into_tool(
+                    "performance_predictor",
+                    "性能预测专家：结合 TopPhi 模拟、ML 模型和历史数据预测涂层性能",
+                )
+                .multi_turn(5),
+        )
+        .tool(composition_optimizer.into_tool(
+            "composition_optimizer",
+            "成分优化专家（P1）：调整元素配比以提升性能",
+        ))
+        .tool(structure_optimizer.into_tool(
+            "structure_optimizer",
+            "结构优化专家（P2）：优化层状结构、厚度和界面设计",
+        ))
+        .tool(process_optimizer.into_tool(
+            "process_optimizer",
+            "工艺优化专家（P3）：调整气压、流量、偏压和温度等工艺参数",
+        ))
+        .tool(
+            iteration_agent
+                .into_tool(
+                    "iteration_manager",
+                    "迭代优化管理专家：对比预测与实测数据，跟踪实验并生成下一轮方案",
+                )
+                .multi_turn(5),
+        )
         .temperature(0.5)
         .build();
 
@@ -6,9 +6,12 @@
 //! 3. 路由模式
 //! 4. 复杂工作流
 
+use std::sync::Arc;
+
 use rig::prelude::*;
 use rig::pipeline::{self, Op, TryOp, passthrough};
 use rig::pipeline::agent_ops::extract;
+use rig::extractor::ExtractionError;
 use rig::{parallel};
 use rig::providers::openai::Client;
 use schemars::JsonSchema;
@@ -46,12 +49,19 @@ struct SentimentScore {
     sentiment: String,
 }
 
-#[derive(Deserialize, JsonSchema, Serialize, Debug)]
+#[derive(Deserialize, JsonSchema, Serialize, Debug, Clone)]
 struct SEOScore {
     score: f32,
     recommendations: Vec<String>,
 }
 
+// 循环改进过程中的文章草稿及其质量评估
+#[derive(Debug)]
+struct Draft {
+    content: String,
+    quality: QualityScore,
+}
+
 // ============= Pipeline 示例 =============
 
 async fn example_pipeline_chain(client: &Client) -> Result<(), anyhow::Error> {
@@ -139,20 +149,25 @@ async fn example_parallel_execution(client: &Client) -> Result<(), anyhow::Error
         )
         .build();
 
+    // SEO 评估失败时使用默认分数，而不是让整个并行阶段失败
+    let default_seo = SEOScore {
+        score: 0.0,
+        recommendations: vec!["SEO 评估失败，请稍后重试".to_string()],
+    };
+
     // 创建并行 Pipeline
     let parallel_pipeline = pipeline::new()
         .chain(parallel!(
             passthrough(),
             extract(quality_agent),
             extract(sentiment_agent),
-            extract(seo_agent)
+            extract(seo_agent).unwrap_or(default_seo)
         ))
         .map(|(text, quality, sentiment, seo)| {
-            let quality = quality.unwrap();
-            let sentiment = sentiment.unwrap();
-            let seo = seo.unwrap();
+            let quality = quality?;
+            let sentiment = sentiment?;
 
-            format!(
+            Ok::<_, ExtractionError>(format!(
                 "原文: {}\n\n\
                 === 评估结果 ===\n\
                 质量分数: {:.2}/1.0\n\
@@ -168,14 +183,14 @@ async fn example_parallel_execution(client: &Client) -> Result<(), anyhow::Error
                 sentiment.sentiment,
                 seo.score,
                 seo.recommendations
-            )
+            ))
         });
 
     let test_text = "Rust 是一种系统编程语言，它专注于安全性、速度和并发性。\
                      Rust 的所有权系统使其能够保证内存安全，而无需垃圾回收。\
                      这使得 Rust 成为构建高性能应用程序的理想选择。";
 
-    let result = parallel_pipeline.call(test_text).await;
+    let result = parallel_pipeline.try_call(test_text).await?;
 
     println!("{}\n", result);
 
@@ -285,14 +300,42 @@ async fn example_complex_workflow(client: &Client) -> Result<(), anyhow::Error>
     }
     println!();
 
-    // 步骤 2: 为第一个创意撰写内容
+    // 并发评估每个创意的潜力（最多同时 3 个），选出得分最高的创意
+    let idea_scorer = client
+        .extractor::<QualityScore>("gpt-4o")
+        .preamble("评估内容创意的潜力（0-1），并给出简短理由。")
+        .build();
+
+    let idea_scoring = pipeline::new()
+        .map(|ideas: Vec<ContentIdea>| {
+            ideas
+                .into_iter()
+                .map(|idea| format!("标题: {}\n描述: {}", idea.title, idea.description))
+                .collect::<Vec<_>>()
+        })
+        .try_map_each(extract(idea_scorer), 3);
+
+    println!("评估创意潜力...");
+    let idea_scores = idea_scoring.call(ideas.ideas.clone()).await?;
+    for (idea, score) in ideas.ideas.iter().zip(&idea_scores) {
+        println!("  {:.2} - {}", score.score, idea.title);
+    }
+    let best = idea_scores
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.score.total_cmp(&b.score))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    println!();
+
+    // 步骤 2: 为得分最高的创意撰写内容
     let writer_agent = client
         .extractor::<ContentPiece>("gpt-4o")
         .preamble("根据创意撰写详细的文章内容（200-300字）。")
         .temperature(0.7)
         .build();
 
-    let first_idea = &ideas.ideas[0];
+    let first_idea = &ideas.ideas[best];
     println!("步骤 2: 撰写文章 '{}'...", first_idea.title);
     
     let content = writer_agent
@@ -327,41 +370,69 @@ async fn example_complex_workflow(client: &Client) -> Result<(), anyhow::Error>
     println!("  质量分数: {:.2}", quality_result.score);
     println!("  SEO 分数: {:.2}\n", seo_result.score);
 
-    // 步骤 4: 决策 - 是否需要改进
+    // 步骤 4: 循环改进 - 质量不达标时改进内容并重新评估，最多 3 轮
     let min_quality = 0.7;
     let min_seo = 0.6;
 
-    if quality_result.score >= min_quality && seo_result.score >= min_seo {
-        println!("✓ 内容通过质量检查，可以发布！");
-    } else {
-        println!("✗ 内容需要改进：");
-        
-        if quality_result.score < min_quality {
-            println!("  - 质量: {}", quality_result.feedback);
-        }
-        
-        if seo_result.score < min_seo {
-            println!("  - SEO: {:?}", seo_result.recommendations);
+    if seo_result.score < min_seo {
+        println!("SEO 建议: {:?}\n", seo_result.recommendations);
+    }
+
+    let editor_agent = client
+        .agent("gpt-4o")
+        .preamble("根据反馈改进文章内容。")
+        .build();
+    let quality_agent = Arc::new(quality_agent);
+    let recommendations = seo_result.recommendations.clone();
+
+    let refine = pipeline::then(move |draft: Draft| {
+        let editor_agent = editor_agent.clone();
+        let quality_agent = quality_agent.clone();
+        let recommendations = recommendations.clone();
+        async move {
+            println!("步骤 4: 改进内容（当前质量 {:.2}）...", draft.quality.score);
+            let improvement_prompt = format!(
+                "请改进以下文章：\n\n{}\n\n质量反馈: {}\n\nSEO 建议: {:?}",
+                draft.content, draft.quality.feedback, recommendations
+            );
+
+            let improved = match editor_agent.prompt(&improvement_prompt).await {
+                Ok(improved) => improved,
+                Err(e) => {
+                    println!("  改进失败: {}", e);
+                    return draft;
+                }
+            };
+
+            match quality_agent.extract(&improved).await {
+                Ok(quality) => {
+                    println!("  新的质量分数: {:.2}", quality.score);
+                    Draft { content: improved, quality }
+                }
+                Err(e) => {
+                    println!("  重新评估失败: {}", e);
+                    draft
+                }
+            }
         }
+    });
+
+    let refinement = pipeline::new().loop_while(
+        move |draft: &Draft| draft.quality.score < min_quality,
+        refine,
+        3,
+    );
 
-        // 步骤 5: 改进内容
-        println!("\n步骤 4: 改进内容...");
-        let editor_agent = client
-            .agent("gpt-4o")
-            .preamble("根据反馈改进文章内容。")
-            .build();
-
-        let improvement_prompt = format!(
-            "请改进以下文章：\n\n{}\n\n质量反馈: {}\n\nSEO 建议: {:?}",
-            content.content,
-            quality_result.feedback,
-            seo_result.recommendations
-        );
-
-        let improved = editor_agent.prompt(&improvement_prompt).await?;
-        println!("内容已改进！\n");
-        println!("改进后的内容:\n{}", improved);
+    let refined = refinement
+        .call(Draft { content: content.content, quality: quality_result })
+        .await?;
+
+    if refined.value.quality.score >= min_quality {
+        println!("\n✓ 内容通过质量检查（改进 {} 轮），可以发布！", refined.iterations);
+    } else {
+        println!("\n✗ 改进 {} 轮后质量仍未达标: {}", refined.iterations, refined.value.quality.feedback);
     }
+    println!("\n最终内容:\n{}", refined.value.content);
 
     Ok(())
 }
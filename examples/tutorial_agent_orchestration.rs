@@ -335,6 +335,8 @@ async fn example_specialized_team(client: &Client) -> Result<(), anyhow::Error>
         .tool(product_expert)
         .tool(order_expert)
         .tool(refund_expert)
+        // 同一轮中委派给多个专家时并发执行，结果仍按调用顺序返回
+        .parallel_tool_calls(3)
         .build();
 
     // 测试多个客户咨询
@@ -0,0 +1,58 @@
+//! 通过 axum 以 SSE（server-sent events）形式转发智能体的流式输出
+//!
+//! 运行示例：
+//! ```bash
+//! DASHSCOPE_API_KEY=your_api_key cargo run --example agent_axum_sse --features axum
+//! curl -N "http://127.0.0.1:3000/chat?prompt=讲一个关于人工智能的故事"
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::{Query, State},
+    response::IntoResponse,
+    routing::get,
+};
+use rig::agent::{Agent, axum::sse_response};
+use rig::prelude::*;
+use rig::providers::qwen;
+use rig::streaming::StreamingPrompt;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct ChatQuery {
+    prompt: String,
+}
+
+async fn chat(
+    State(agent): State<Arc<Agent<qwen::CompletionModel>>>,
+    Query(query): Query<ChatQuery>,
+) -> impl IntoResponse {
+    // 每个事件形如 `event: text\ndata: {"type":"text","text":"..."}`，以 `final` 或 `error` 事件结束
+    sse_response(agent.stream_prompt(query.prompt).multi_turn(5).await)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let client: qwen::Client = qwen::Client::from_env();
+    let agent = client
+        .agent(qwen::QWEN_PLUS)
+        .preamble("你是一个擅长讲故事的AI助手。")
+        .build();
+
+    let app = Router::new()
+        .route("/chat", get(chat))
+        .with_state(Arc::new(agent));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("SSE 服务已启动：http://127.0.0.1:3000/chat?prompt=...");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
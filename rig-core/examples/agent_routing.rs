@@ -1,4 +1,6 @@
-use rig::pipeline::{self, Op, TryOp};
+use std::collections::HashMap;
+
+use rig::pipeline::{self, Op, boxed};
 use rig::prelude::*;
 use rig::providers::openai;
 use rig::providers::openai::client::Client;
@@ -19,22 +21,45 @@ async fn main() -> Result<(), anyhow::Error> {
         .build();
 
     let default_agent = openai_client.agent(openai::GPT_4).build();
+
+    // Each category gets its own prompt, sent to the agent with no pre-amble
+    let route = |prompt: &'static str| {
+        boxed(
+            pipeline::map(move |_: String| prompt.to_string())
+                .prompt(default_agent.clone())
+                .map(|response| response.map_err(|e| e.to_string())),
+        )
+    };
+    let routes = HashMap::from([
+        (
+            "cow".to_string(),
+            route("Tell me a fact about the United States of America."),
+        ),
+        (
+            "sheep".to_string(),
+            route("Calculate 5+5 for me. Return only the number."),
+        ),
+        ("dog".to_string(), route("Write me a poem about cashews")),
+    ]);
+
     let chain = pipeline::new()
         // Use our classifier agent to classify the agent under a number of fixed topics
         .prompt(animal_agent)
-        // Change the prompt depending on the output from the prompt
-        .map_ok(|x: String| match x.trim() {
-            "cow" => Ok("Tell me a fact about the United States of America.".to_string()),
-            "sheep" => Ok("Calculate 5+5 for me. Return only the number.".to_string()),
-            "dog" => Ok("Write me a poem about cashews".to_string()),
-            message => Err(format!("Could not process - received category: {message}")),
-        })
-        .map(|x| x.unwrap().unwrap())
-        // Send the prompt back into another agent with no pre-amble
-        .prompt(default_agent);
+        .map(|category| category.map(|x| x.trim().to_string()).unwrap_or_default())
+        // Route the statement depending on the output from the classifier
+        .branch(
+            |category: &String| category.clone(),
+            routes,
+            boxed(pipeline::map(|category: String| {
+                Err::<String, _>(format!("Could not process - received category: {category}"))
+            })),
+        );
 
     // Prompt the agent and print the response
-    let response = chain.try_call("Sheep can self-medicate").await?;
+    let response = chain
+        .call("Sheep can self-medicate")
+        .await
+        .map_err(anyhow::Error::msg)?;
 
     println!("Pipeline result: {response:?}");
 
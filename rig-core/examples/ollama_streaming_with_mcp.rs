@@ -1,8 +1,7 @@
 use anyhow::Result;
 use rig::prelude::*;
 use rig::{providers, streaming::StreamingPrompt};
-use futures::StreamExt;
-use rig::agent::stream_to_stdout;
+use rig::agent::stream_to_stdout_verbose;
 use rmcp::{
     model::{ClientCapabilities, ClientInfo, Implementation, Tool as McpTool},
     transport::{StreamableHttpClientTransport},
@@ -62,59 +61,24 @@ async fn main() -> Result<(), anyhow::Error> {
     // tracing::info!("Available MCP tools: {:?}", tools.iter().map(|t| &t.name).collect::<Vec<_>>());
 
     // 3. 创建 Ollama 客户端和 Agent
-    let mut agent_builder = providers::ollama::Client::new()
+    let ollama_client: providers::ollama::Client =
+        providers::ollama::Client::new(rig::client::Nothing)?;
+    let agent = ollama_client
         .agent("qwen3:4b")
         .preamble(
             "你是一个材料方向的助理，擅长数学计算和使用工具进行计算。
             ",
         )
-        .max_tokens(1024);
+        .max_tokens(1024)
         .tool(rig::tools::ThinkTool)
-        .rmcp_tools(tools, mcp_client.peer().to_owned());
-
-    let agent = agent_builder.build();
+        .rmcp_tools(tools, mcp_client.peer().to_owned())
+        .build();
 
 
     let mut stream = agent.stream_prompt("列出我的任务").await;
 
-    // // 直接处理流式输出
-    // let mut response_text = String::new();
-    // println!("AI 回答（流式）：");
-
-    // while let Some(chunk_result) = stream.next().await {
-    //     match chunk_result {
-    //         Ok(chunk) => {
-    //             match chunk {
-    //                 rig::agent::MultiTurnStreamItem::StreamItem(rig::streaming::StreamedAssistantContent::Text(text)) => {
-    //                     print!("{}", text.text);
-    //                     response_text.push_str(&text.text);
-    //                 }
-    //                 rig::agent::MultiTurnStreamItem::StreamItem(rig::streaming::StreamedAssistantContent::ToolCall(tool_call)) => {
-    //                     println!("\n[工具调用] {}: {}({})",
-    //                         tool_call.id,
-    //                         tool_call.function.name,
-    //                         tool_call.function.arguments);
-    //                 }
-    //                 rig::agent::MultiTurnStreamItem::StreamItem(rig::streaming::StreamedAssistantContent::Reasoning(reasoning)) => {
-    //                     println!("{}", reasoning.reasoning.join(" "));
-    //                 }
-    //                 rig::agent::MultiTurnStreamItem::FinalResponse(final_response) => {
-    //                     println!("\n[最终响应]: {}", final_response.response());
-    //                     response_text.push_str(&final_response.response());
-    //                 }
-    //                 _ => {
-    //                     // 处理其他可能的变体
-    //                     println!("\n[其他流式内容]");
-    //                 }
-    //             }
-    //         }
-    //         Err(e) => {
-    //             eprintln!("流式输出错误: {}", e);
-    //             break;
-    //         }
-    //     }
-    // }
-    let res = stream_to_stdout(&mut stream).await?;
+    // 内联打印工具调用、工具结果与推理过程，便于调试
+    let res = stream_to_stdout_verbose(&mut stream).await?;
     println!("Token usage response: {usage:?}", usage = res.usage());
     println!("Final text response: {message:?}", message = res.response());
     Ok(())
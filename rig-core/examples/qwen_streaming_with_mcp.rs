@@ -9,9 +9,9 @@
 //! ```
 
 use anyhow::Result;
-use rig::agent::stream_to_stdout;
+use rig::agent::stream_to_stdout_verbose;
 use rig::prelude::*;
-use rig::{completion::ToolDefinition, providers, streaming::StreamingPrompt, tool::Tool};
+use rig::{providers, streaming::StreamingPrompt};
 
 use rmcp::{
     model::{ClientCapabilities, ClientInfo, Implementation, Tool as McpTool},
@@ -19,7 +19,6 @@ use rmcp::{
     transport::streamable_http_client::StreamableHttpClientTransportConfig,
     ServiceExt,
 };
-use futures::StreamExt;
 #[tokio::main]
 async fn main() -> Result<()> {
     // 初始化日志
@@ -30,7 +29,7 @@ async fn main() -> Result<()> {
     println!("=== 通义千问 + MCP 服务器流式输出示例 ===\n");
 
     // 1. 创建通义千问客户端
-    // let client: qwen::Client = qwen::Client::from_env();
+    let client: providers::qwen::Client = providers::qwen::Client::from_env();
 
     // 2. 配置 MCP 服务器连接
     let mcp_server_url = "http://127.0.0.1:3001/mcp".to_string();
@@ -74,9 +73,7 @@ async fn main() -> Result<()> {
     // tracing::info!("Available MCP tools: {:?}", tools.iter().map(|t| &t.name).collect::<Vec<_>>());
     
     // 5. 创建 Qwen Agent 并添加 MCP 工具支持
-    // let mut agent_builder: rig::agent::AgentBuilderSimple<providers::qwen::CompletionModel> = providers::qwen::Client::from_env()
-    let api_key = "sk-348d7ca647714c52aca12ea106cfa895";
-    let mut agent_builder = providers::qwen::Client::new_with_api_key(&api_key)
+    let agent = client
         .agent("qwen-plus")
         .preamble(
             "你是一个智能助手，可以连接到 MCP 服务器来使用各种工具。
@@ -86,53 +83,14 @@ async fn main() -> Result<()> {
         )
         .temperature(0.7)
         .tool(rig::tools::ThinkTool)
-        .tool(rig::tools::ListTasks);
-        // .rmcp_tools(tools, mcp_client.peer().to_owned());
-
-    let agent = agent_builder.build();
+        .rmcp_tools(tools, mcp_client.peer().to_owned())
+        .build();
 
     let mut stream = agent.stream_prompt("列出我的任务").await;
-    let res = stream_to_stdout(&mut stream).await?;
+    // 内联打印工具调用、工具结果与推理过程，便于调试
+    let res = stream_to_stdout_verbose(&mut stream).await?;
 
     println!("Token usage response: {usage:?}", usage = res.usage());
     println!("Final text response: {message:?}", message = res.response());
-    
-    // // 直接处理流式输出
-    // let mut response_text = String::new();
-    // println!("AI 回答（流式）：");
-
-    // while let Some(chunk_result) = stream.next().await {
-    //     match chunk_result {
-    //         Ok(chunk) => {
-    //             match chunk {
-    //                 rig::agent::MultiTurnStreamItem::StreamItem(rig::streaming::StreamedAssistantContent::Text(text)) => {
-    //                     print!("{}", text.text);
-    //                     response_text.push_str(&text.text);
-    //                 }
-    //                 rig::agent::MultiTurnStreamItem::StreamItem(rig::streaming::StreamedAssistantContent::ToolCall(tool_call)) => {
-    //                     println!("\n[工具调用] {}: {}({})",
-    //                         tool_call.id,
-    //                         tool_call.function.name,
-    //                         tool_call.function.arguments);
-    //                 }
-    //                 rig::agent::MultiTurnStreamItem::StreamItem(rig::streaming::StreamedAssistantContent::Reasoning(reasoning)) => {
-    //                     println!("\n[推理] {}", reasoning.reasoning.join(" "));
-    //                 }
-    //                 rig::agent::MultiTurnStreamItem::FinalResponse(final_response) => {
-    //                     println!("\n[最终响应]: {}", final_response.response());
-    //                     response_text.push_str(&final_response.response());
-    //                 }
-    //                 _ => {
-    //                     // 处理其他可能的变体
-    //                     println!("\n[其他流式内容]");
-    //                 }
-    //             }
-    //         }
-    //         Err(e) => {
-    //             eprintln!("流式输出错误: {}", e);
-    //             break;
-    //         }
-    //     }
-    // }
     Ok(())
 }
\ No newline at end of file
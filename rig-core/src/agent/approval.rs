@@ -0,0 +1,259 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+
+/// The answer to a tool call awaiting approval.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    /// Execute the tool call
+    Approved,
+    /// Do not execute the tool call: the reason is sent to the model as the tool result instead
+    Rejected(String),
+}
+
+/// Answers the tool calls awaiting approval in a streaming response.
+///
+/// Tools marked with [requires_approval](crate::agent::AgentBuilder::requires_approval) are not
+/// executed right away: the stream yields a
+/// [PendingApproval](crate::agent::MultiTurnStreamItem::PendingApproval) item for the call, then
+/// pauses until the call is [approved](Self::approve) or [rejected](Self::reject) through the
+/// handle of the request. The handle can be cloned and moved to another task, eg. one waiting for
+/// a human decision.
+///
+/// # Example
+/// ```rust,ignore
+/// let request = agent.stream_prompt("Submit a Scheil task for AL-MG-SI").multi_turn(5);
+/// let approvals = request.approvals();
+/// let mut stream = request.await;
+///
+/// while let Some(item) = stream.next().await {
+///     if let MultiTurnStreamItem::PendingApproval { call } = item? {
+///         if ask_user(&call) {
+///             approvals.approve(&call.id);
+///         } else {
+///             approvals.reject(&call.id, "The user rejected the submission");
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ApprovalHandle {
+    pending: Arc<Mutex<HashMap<String, oneshot::Sender<ApprovalDecision>>>>,
+}
+
+impl ApprovalHandle {
+    /// Approve the pending tool call with id `tool_call_id`. Returns false if no such call is
+    /// awaiting approval.
+    pub fn approve(&self, tool_call_id: &str) -> bool {
+        self.decide(tool_call_id, ApprovalDecision::Approved)
+    }
+
+    /// Reject the pending tool call with id `tool_call_id`, sending `reason` to the model as the
+    /// tool result. Returns false if no such call is awaiting approval.
+    pub fn reject(&self, tool_call_id: &str, reason: impl Into<String>) -> bool {
+        self.decide(tool_call_id, ApprovalDecision::Rejected(reason.into()))
+    }
+
+    /// The ids of the tool calls awaiting approval.
+    pub fn pending(&self) -> Vec<String> {
+        self.pending.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn decide(&self, tool_call_id: &str, decision: ApprovalDecision) -> bool {
+        let sender = self.pending.lock().unwrap().remove(tool_call_id);
+        sender.is_some_and(|sender| sender.send(decision).is_ok())
+    }
+
+    /// Mark the tool call with id `tool_call_id` as awaiting approval.
+    pub(crate) fn register(&self, tool_call_id: &str) -> oneshot::Receiver<ApprovalDecision> {
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(tool_call_id.to_string(), tx);
+        rx
+    }
+}
+
+/// The tool result of a call requiring approval made outside of a streaming request, where it
+/// cannot be approved.
+pub(crate) fn approval_unavailable(tool_name: &str) -> String {
+    format!(
+        "The tool `{tool_name}` requires approval, which is only available for streaming requests. \
+        The call was not executed."
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{Agent, AgentBuilder, MultiTurnStreamItem, collect_stream_to_messages},
+        completion::{Prompt, ToolDefinition},
+        message::{Message, ToolResultContent, UserContent},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[derive(Deserialize)]
+    struct SubmitArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Submit error")]
+    struct SubmitError;
+
+    struct Submit {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Tool for Submit {
+        const NAME: &'static str = "calphamesh_submit_scheil_task";
+        type Error = SubmitError;
+        type Args = SubmitArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Submit a Scheil task".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok("task 42 submitted".to_string())
+        }
+    }
+
+    fn approval_agent() -> (Agent<MockCompletionModel>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "calphamesh_submit_scheil_task", json!({}))
+            .with_text("done");
+        let agent = AgentBuilder::new(model)
+            .tool(Submit {
+                calls: calls.clone(),
+            })
+            .requires_approval("calphamesh_submit_scheil_task")
+            .build();
+
+        (agent, calls)
+    }
+
+    fn tool_results(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                Message::User { content } => match content.first() {
+                    UserContent::ToolResult(result) => match result.content.first() {
+                        ToolResultContent::Text(text) => Some(text.text),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Stream a prompt, answering each approval request with `decide`.
+    async fn run_with_decision(
+        agent: &Agent<MockCompletionModel>,
+        decide: impl Fn(&ApprovalHandle, &str),
+    ) -> (Vec<Message>, Vec<String>) {
+        let request = agent.stream_prompt("submit the task").multi_turn(3);
+        let approvals = request.approvals();
+        let mut stream = request.await;
+
+        let mut pending = Vec::new();
+        let (messages, _) = collect_stream_to_messages(&mut stream, |item| {
+            if let MultiTurnStreamItem::PendingApproval { call } = item {
+                assert_eq!(approvals.pending(), vec![call.id.clone()]);
+                pending.push(call.id.clone());
+                decide(&approvals, &call.id);
+            }
+        })
+        .await
+        .unwrap();
+
+        (messages, pending)
+    }
+
+    #[tokio::test]
+    async fn test_approved_tool_call_is_executed() {
+        let (agent, calls) = approval_agent();
+
+        let (messages, pending) = run_with_decision(&agent, |approvals, id| {
+            assert!(approvals.approve(id));
+        })
+        .await;
+
+        assert_eq!(pending, vec!["call_1"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(tool_results(&messages), vec!["\"task 42 submitted\""]);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_tool_call_feeds_reason_back() {
+        let (agent, calls) = approval_agent();
+        let model = agent.model.clone();
+
+        let (messages, pending) = run_with_decision(&agent, |approvals, id| {
+            assert!(approvals.reject(id, "Rejected: too expensive"));
+        })
+        .await;
+
+        assert_eq!(pending, vec!["call_1"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(tool_results(&messages), vec!["Rejected: too expensive"]);
+
+        let history: Vec<_> = model.requests()[1].chat_history.iter().cloned().collect();
+        assert_eq!(tool_results(&history), vec!["Rejected: too expensive"]);
+    }
+
+    #[tokio::test]
+    async fn test_approval_from_another_task() {
+        let (agent, calls) = approval_agent();
+
+        let request = agent.stream_prompt("submit the task").multi_turn(3);
+        let approvals = request.approvals();
+        let mut stream = request.await;
+
+        while let Some(item) = stream.next().await {
+            if let MultiTurnStreamItem::PendingApproval { call } = item.unwrap() {
+                let approvals = approvals.clone();
+                tokio::spawn(async move { approvals.approve(&call.id) });
+            }
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(!approvals.approve("call_1"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_requiring_approval_is_not_executed_by_prompt() {
+        let (agent, calls) = approval_agent();
+        let model = agent.model.clone();
+
+        agent.prompt("submit the task").multi_turn(3).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        let history: Vec<_> = model.requests()[1].chat_history.iter().cloned().collect();
+        assert_eq!(
+            tool_results(&history),
+            vec![approval_unavailable("calphamesh_submit_scheil_task")]
+        );
+    }
+}
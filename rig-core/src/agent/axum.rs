@@ -0,0 +1,189 @@
+//! Serving agent streams as server-sent events with [axum].
+//!
+//! # Example
+//! ```rust,ignore
+//! use axum::{Router, extract::State, response::IntoResponse, routing::get};
+//! use rig::agent::axum::sse_response;
+//!
+//! async fn chat(State(agent): State<Arc<Agent<CompletionModel>>>) -> impl IntoResponse {
+//!     sse_response(agent.stream_prompt("Design a coating").multi_turn(5).await)
+//! }
+//!
+//! let app = Router::new().route("/chat", get(chat)).with_state(agent);
+//! ```
+use std::convert::Infallible;
+
+use ::axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{Stream, StreamExt, stream::BoxStream};
+
+use crate::{
+    completion::GetTokenUsage,
+    streaming::{StreamedAssistantContent, StreamingCompletionResponse},
+};
+
+use super::{StreamEvent, prompt_request::streaming::StreamingResult};
+
+/// A stream that can be sent to clients as [StreamEvent]s.
+///
+/// Implemented for multi-turn agent streams and for the [StreamingCompletionResponse] of a
+/// single completion.
+pub trait IntoStreamEvents {
+    fn into_stream_events(self) -> BoxStream<'static, StreamEvent>;
+}
+
+impl<R: 'static> IntoStreamEvents for StreamingResult<R> {
+    fn into_stream_events(self) -> BoxStream<'static, StreamEvent> {
+        self.filter_map(|item| futures::future::ready(StreamEvent::from_item(item)))
+            .boxed()
+    }
+}
+
+impl<R> IntoStreamEvents for StreamingCompletionResponse<R>
+where
+    R: Clone + Unpin + GetTokenUsage + Send + 'static,
+{
+    fn into_stream_events(self) -> BoxStream<'static, StreamEvent> {
+        self.scan(String::new(), |response, item| {
+            let event = match item {
+                Ok(StreamedAssistantContent::Text(text)) => {
+                    response.push_str(&text.text);
+                    Some(StreamEvent::Text { text: text.text })
+                }
+                Ok(StreamedAssistantContent::ToolCall(tool_call)) => Some(StreamEvent::ToolCall {
+                    id: tool_call.id,
+                    call_id: tool_call.call_id,
+                    name: tool_call.function.name,
+                    arguments: tool_call.function.arguments,
+                }),
+                Ok(StreamedAssistantContent::ToolResult {
+                    id,
+                    call_id,
+                    name,
+                    result,
+                }) => Some(StreamEvent::ToolResult {
+                    id,
+                    call_id,
+                    name,
+                    content: result,
+                }),
+                Ok(StreamedAssistantContent::Reasoning(reasoning)) => {
+                    Some(StreamEvent::Reasoning {
+                        reasoning: reasoning.reasoning.join("\n"),
+                    })
+                }
+                Ok(StreamedAssistantContent::Final(res)) => Some(StreamEvent::Final {
+                    response: std::mem::take(response),
+                    usage: res.token_usage().unwrap_or_default(),
+                }),
+                Ok(_) => None,
+                Err(err) => Some(StreamEvent::Error {
+                    message: err.to_string(),
+                }),
+            };
+            futures::future::ready(Some(event))
+        })
+        .filter_map(futures::future::ready)
+        .boxed()
+    }
+}
+
+/// Turn a stream into an `axum` server-sent events response.
+///
+/// Each [StreamEvent] is sent as a JSON `data` line, with the event's `type` as the SSE event
+/// name. The response ends after a `final`, `error` or `budget_exceeded` event, and sends
+/// keep-alive comments while the model is busy, e.g. running tools.
+pub fn sse_response(
+    stream: impl IntoStreamEvents,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>> + Send + 'static> {
+    let mut events = stream.into_stream_events();
+    let events = async_stream::stream! {
+        while let Some(event) = events.next().await {
+            let is_terminal = event.is_terminal();
+            yield Ok(sse_event(&event));
+            if is_terminal {
+                break;
+            }
+        }
+    };
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn sse_event(event: &StreamEvent) -> Event {
+    let sse_event = Event::default().event(event.event_type());
+    match serde_json::to_string(event) {
+        Ok(data) => sse_event.data(data),
+        Err(err) => Event::default()
+            .event("error")
+            .data(serde_json::json!({"type": "error", "message": err.to_string()}).to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::axum::{body::to_bytes, response::IntoResponse};
+
+    use super::*;
+    use crate::{
+        agent::AgentBuilder,
+        completion::{CompletionModel, Usage},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+    };
+
+    async fn body(response: impl IntoResponse) -> String {
+        let bytes = to_bytes(response.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sse_response_frames_events() {
+        let model = MockCompletionModel::new()
+            .with_text("Hello")
+            .with_usage(Usage {
+                input_tokens: 3,
+                output_tokens: 2,
+                total_tokens: 5,
+            });
+        let agent = AgentBuilder::new(model).build();
+
+        let response = sse_response(agent.stream_prompt("hi").await);
+
+        assert_eq!(
+            body(response).await,
+            "event: text\ndata: {\"type\":\"text\",\"text\":\"Hello\"}\n\n\
+             event: final\ndata: {\"type\":\"final\",\"response\":\"Hello\",\
+             \"usage\":{\"input_tokens\":3,\"output_tokens\":2,\"total_tokens\":5}}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sse_response_ends_on_error() {
+        let model = MockCompletionModel::new().with_stream_error("overloaded");
+        let agent = AgentBuilder::new(model).build();
+
+        let body = body(sse_response(agent.stream_prompt("hi").await)).await;
+
+        assert!(body.starts_with("event: error\ndata: {\"type\":\"error\",\"message\":"));
+        assert!(body.contains("overloaded"));
+        assert_eq!(body.matches("\n\n").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sse_response_from_completion_stream() {
+        let model = MockCompletionModel::new().with_text("Hello");
+        let request = model.completion_request("hi").build();
+        let stream = model.stream(request).await.unwrap();
+
+        let body = body(sse_response(stream)).await;
+
+        assert_eq!(
+            body,
+            "event: text\ndata: {\"type\":\"text\",\"text\":\"Hello\"}\n\n\
+             event: final\ndata: {\"type\":\"final\",\"response\":\"Hello\",\
+             \"usage\":{\"input_tokens\":0,\"output_tokens\":0,\"total_tokens\":0}}\n\n"
+        );
+    }
+}
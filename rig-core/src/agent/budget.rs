@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+
+use crate::completion::Usage;
+
+/// Prices of a model's tokens, used to estimate the cost of prompt requests.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TokenPrice {
+    /// Price of one million input tokens
+    pub input_per_million: f64,
+    /// Price of one million output tokens
+    pub output_per_million: f64,
+}
+
+impl TokenPrice {
+    pub fn new(input_per_million: f64, output_per_million: f64) -> Self {
+        Self {
+            input_per_million,
+            output_per_million,
+        }
+    }
+
+    /// The estimated cost of `usage`.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input_per_million
+            + usage.output_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// A cap on the usage of a prompt request, see
+/// [PromptRequest::max_total_tokens](crate::agent::PromptRequest::max_total_tokens) and
+/// [PromptRequest::max_estimated_cost](crate::agent::PromptRequest::max_estimated_cost).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLimit {
+    /// Maximum number of tokens used by the agent and its sub-agents
+    TotalTokens(u64),
+    /// Maximum estimated cost of the tokens used by the agent and its sub-agents
+    EstimatedCost(f64),
+}
+
+impl std::fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetLimit::TotalTokens(tokens) => write!(f, "{tokens} total tokens"),
+            BudgetLimit::EstimatedCost(cost) => write!(f, "an estimated cost of {cost}"),
+        }
+    }
+}
+
+/// The usage caps of a prompt request.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Budget {
+    max_total_tokens: Option<u64>,
+    max_estimated_cost: Option<f64>,
+    token_price: Option<TokenPrice>,
+}
+
+impl Budget {
+    pub(crate) fn set_max_total_tokens(&mut self, tokens: u64) {
+        self.max_total_tokens = Some(tokens);
+    }
+
+    pub(crate) fn set_max_estimated_cost(&mut self, cost: f64) {
+        self.max_estimated_cost = Some(cost);
+    }
+
+    pub(crate) fn set_token_price(&mut self, price: TokenPrice) {
+        self.token_price = Some(price);
+    }
+
+    /// The first cap exceeded by `usage`, if any. The cost cap is only checked when the token
+    /// price is known.
+    pub(crate) fn exceeded(&self, usage: &Usage) -> Option<BudgetLimit> {
+        let total_tokens = usage
+            .total_tokens
+            .max(usage.input_tokens + usage.output_tokens);
+        if let Some(max) = self.max_total_tokens
+            && total_tokens > max
+        {
+            return Some(BudgetLimit::TotalTokens(max));
+        }
+
+        match (self.max_estimated_cost, self.token_price) {
+            (Some(max), Some(price)) if price.cost(usage) > max => {
+                Some(BudgetLimit::EstimatedCost(max))
+            }
+            (Some(_), None) => {
+                tracing::warn!(
+                    "The request has a maximum estimated cost but no token price, the cost is not checked"
+                );
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{Agent, AgentBuilder, StreamEvent, stream_collect, stream_to_channel},
+        completion::{Prompt, PromptError, ToolDefinition},
+        message::{Message, UserContent},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct SearchArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Search error")]
+    struct SearchError;
+
+    struct Search;
+
+    impl Tool for Search {
+        const NAME: &'static str = "search";
+        type Error = SearchError;
+        type Args = SearchArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Search".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok("no results".to_string())
+        }
+    }
+
+    /// An agent searching on every turn, each completion using (60, 40) tokens.
+    fn searching_agent() -> (Agent<MockCompletionModel>, MockCompletionModel) {
+        let mut model = MockCompletionModel::new().with_usage(usage(60, 40));
+        for turn in 0..5 {
+            model = model.with_tool_call(&format!("call_{turn}"), "search", json!({}));
+        }
+        let agent = AgentBuilder::new(model.clone()).tool(Search).build();
+        (agent, model)
+    }
+
+    #[test]
+    fn test_token_price_cost() {
+        let price = TokenPrice::new(2.0, 6.0);
+
+        assert_eq!(price.cost(&usage(500_000, 1_000_000)), 7.0);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_stops_after_turn_exceeding_token_cap() {
+        let (agent, model) = searching_agent();
+
+        let error = agent
+            .prompt("Find coatings")
+            .multi_turn(10)
+            .max_total_tokens(250)
+            .await
+            .unwrap_err();
+
+        let PromptError::BudgetExceeded {
+            limit,
+            chat_history,
+            usage: run_usage,
+        } = error
+        else {
+            panic!("expected a budget error, got {error:?}");
+        };
+        assert_eq!(limit, BudgetLimit::TotalTokens(250));
+        assert_eq!(run_usage, usage(180, 120));
+        assert_eq!(model.requests().len(), 3);
+        let Some(Message::User { content }) = chat_history.last() else {
+            panic!("expected the transcript to end with a tool result");
+        };
+        assert!(matches!(content.first(), UserContent::ToolResult(_)));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_stops_after_turn_exceeding_cost_cap() {
+        let (agent, model) = searching_agent();
+
+        // Every turn costs 0.00036.
+        let error = agent
+            .prompt("Find coatings")
+            .multi_turn(10)
+            .token_price(TokenPrice::new(2.0, 6.0))
+            .max_estimated_cost(0.0005)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            PromptError::BudgetExceeded {
+                limit: BudgetLimit::EstimatedCost(_),
+                ..
+            }
+        ));
+        assert_eq!(model.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cost_cap_is_ignored_without_token_price() {
+        let model = MockCompletionModel::new()
+            .with_usage(usage(60, 40))
+            .with_text("done");
+        let agent = AgentBuilder::new(model).build();
+
+        let response = agent.prompt("Hello").max_estimated_cost(0.0).await.unwrap();
+
+        assert_eq!(response, "done");
+    }
+
+    #[tokio::test]
+    async fn test_streaming_stops_after_turn_exceeding_token_cap() {
+        let (agent, model) = searching_agent();
+
+        let error = stream_collect(
+            agent
+                .stream_prompt("Find coatings")
+                .multi_turn(10)
+                .max_total_tokens(250),
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+
+        assert!(error.to_string().contains("250 total tokens"));
+        assert_eq!(model.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_ends_with_budget_event() {
+        let (agent, _) = searching_agent();
+        let stream = agent
+            .stream_prompt("Find coatings")
+            .multi_turn(10)
+            .max_total_tokens(150)
+            .await;
+
+        let mut receiver = stream_to_channel(stream);
+        let mut last = None;
+        while let Some(event) = receiver.recv().await {
+            last = Some(event);
+        }
+
+        assert_eq!(
+            last,
+            Some(StreamEvent::BudgetExceeded {
+                limit: BudgetLimit::TotalTokens(150),
+                usage: usage(120, 80),
+            })
+        );
+    }
+}
@@ -1,22 +1,37 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
 use tokio::sync::RwLock;
 
 use crate::{
-    completion::{CompletionModel, Document},
+    completion::{CompletionModel, CompletionModelDyn, Document, Message},
     message::ToolChoice,
     tool::{
-        Tool, ToolSet,
+        Tool, ToolDyn, ToolSet,
         server::{ToolServer, ToolServerHandle},
     },
     vector_store::VectorStoreIndexDyn,
+    wasm_compat::WasmCompatSend,
 };
 
 #[cfg(feature = "rmcp")]
 #[cfg_attr(docsrs, doc(cfg(feature = "rmcp")))]
 use crate::tool::rmcp::McpTool as RmcpTool;
 
-use super::Agent;
+use super::{
+    Agent,
+    deferred_tools::{DeferredTools, ToolProviderError},
+    fallback::CompletionRetryPolicy,
+    history::{HistoryPolicy, TokenEstimator, estimate_tokens},
+    middleware::AgentMiddleware,
+    observer::AgentObserver,
+    tool_hook::{HookDecision, ToolCallInfo, ToolHooks, ToolResultInfo},
+    tool_output::ToolOutputPolicy,
+    tool_policy::{ToolRetries, ToolRetryPolicy, ToolTimeouts},
+};
 
 /// A builder for creating an agent
 ///
@@ -65,6 +80,36 @@ where
     tool_server_handle: Option<ToolServerHandle>,
     /// Whether or not the underlying LLM should be forced to use a tool before providing a response.
     tool_choice: Option<ToolChoice>,
+    /// Policy used to trim the chat history before each completion request
+    history_policy: Option<HistoryPolicy>,
+    /// Estimates message sizes for the history policy
+    token_estimator: Option<TokenEstimator>,
+    /// Observers notified of the lifecycle events of multi-turn runs
+    observers: Vec<Arc<dyn AgentObserver>>,
+    /// Maximum number of tool calls of a single turn that are executed concurrently
+    tool_concurrency: usize,
+    /// Timeouts of the agent's tool calls
+    tool_timeouts: ToolTimeouts,
+    /// Retry policies of the agent's tool calls
+    tool_retries: ToolRetries,
+    /// Hooks vetting, rewriting and logging the agent's tool calls
+    tool_hooks: ToolHooks,
+    /// Names of the tools whose calls must be approved before being executed
+    approval_required: HashSet<String>,
+    /// Default maximum number of turns of the agent's prompt requests
+    max_turns: usize,
+    /// Models the requests of the agent fall back to, in order, when its model fails
+    fallback_models: Vec<Arc<dyn CompletionModelDyn>>,
+    /// Policy retrying failed completion requests on the same model
+    completion_retry: Option<CompletionRetryPolicy>,
+    /// Variables substituted into the preamble when the agent is built
+    preamble_vars: HashMap<String, String>,
+    /// Tools added to the tool server on the first prompt of the agent
+    deferred_tools: Vec<DeferredTools>,
+    /// Policy shrinking long tool outputs before they are sent to the model
+    tool_output_policy: Option<ToolOutputPolicy>,
+    /// Layers wrapping each completion request and tool call, in registration order
+    middleware: Vec<Arc<dyn AgentMiddleware>>,
 }
 
 impl<M> AgentBuilder<M>
@@ -84,6 +129,21 @@ where
             dynamic_context: vec![],
             tool_server_handle: None,
             tool_choice: None,
+            history_policy: None,
+            token_estimator: None,
+            observers: vec![],
+            tool_concurrency: 1,
+            tool_timeouts: ToolTimeouts::default(),
+            tool_retries: ToolRetries::default(),
+            tool_hooks: ToolHooks::default(),
+            approval_required: HashSet::new(),
+            max_turns: 0,
+            fallback_models: vec![],
+            completion_retry: None,
+            preamble_vars: HashMap::new(),
+            deferred_tools: vec![],
+            tool_output_policy: None,
+            middleware: vec![],
         }
     }
 
@@ -121,6 +181,19 @@ where
         self
     }
 
+    /// Set a preamble template. Each `{name}` placeholder is replaced by the value of the
+    /// variable `name` (see [Self::preamble_var]) when the agent is built. Placeholders without a
+    /// matching variable are left as is.
+    pub fn preamble_template(self, template: &str) -> Self {
+        self.preamble(template)
+    }
+
+    /// Set the value substituted for the `{name}` placeholders of the preamble
+    pub fn preamble_var(mut self, name: &str, value: impl ToString) -> Self {
+        self.preamble_vars.insert(name.into(), value.to_string());
+        self
+    }
+
     /// Add a static context document to the agent
     pub fn context(mut self, doc: &str) -> Self {
         self.static_context.push(Document {
@@ -146,14 +219,82 @@ where
             static_tools,
             additional_params: self.additional_params,
             max_tokens: self.max_tokens,
-            dynamic_context: vec![],
+            dynamic_context: self.dynamic_context,
             dynamic_tools: vec![],
             temperature: self.temperature,
             tools,
             tool_choice: self.tool_choice,
+            history_policy: self.history_policy,
+            token_estimator: self.token_estimator,
+            observers: self.observers,
+            tool_concurrency: self.tool_concurrency,
+            tool_timeouts: self.tool_timeouts,
+            tool_retries: self.tool_retries,
+            tool_hooks: self.tool_hooks,
+            approval_required: self.approval_required,
+            max_turns: self.max_turns,
+            fallback_models: self.fallback_models,
+            completion_retry: self.completion_retry,
+            preamble_vars: self.preamble_vars,
+            deferred_tools: self.deferred_tools,
+            tool_output_policy: self.tool_output_policy,
+            middleware: self.middleware,
+        }
+    }
+
+    /// Add several static tools to the agent, eg. a list built from configuration
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let mut tools: Vec<Box<dyn ToolDyn>> = vec![Box::new(SubmitPointTask), Box::new(ListTasks)];
+    /// if config.allow_scheil {
+    ///     tools.push(Box::new(SubmitScheilTask));
+    /// }
+    /// let agent = AgentBuilder::new(model).tools(tools).build();
+    /// ```
+    pub fn tools(self, tools: impl IntoIterator<Item = Box<dyn ToolDyn>>) -> AgentBuilderSimple<M> {
+        let tools: Vec<_> = tools.into_iter().collect();
+        let static_tools = tools.iter().map(|tool| tool.name()).collect();
+        let mut toolset = ToolSet::default();
+        toolset.extend(tools);
+
+        AgentBuilderSimple {
+            name: self.name,
+            description: self.description,
+            model: self.model,
+            preamble: self.preamble,
+            static_context: self.static_context,
+            static_tools,
+            additional_params: self.additional_params,
+            max_tokens: self.max_tokens,
+            dynamic_context: self.dynamic_context,
+            dynamic_tools: vec![],
+            temperature: self.temperature,
+            tools: toolset,
+            tool_choice: self.tool_choice,
+            history_policy: self.history_policy,
+            token_estimator: self.token_estimator,
+            observers: self.observers,
+            tool_concurrency: self.tool_concurrency,
+            tool_timeouts: self.tool_timeouts,
+            tool_retries: self.tool_retries,
+            tool_hooks: self.tool_hooks,
+            approval_required: self.approval_required,
+            max_turns: self.max_turns,
+            fallback_models: self.fallback_models,
+            completion_retry: self.completion_retry,
+            preamble_vars: self.preamble_vars,
+            deferred_tools: self.deferred_tools,
+            tool_output_policy: self.tool_output_policy,
+            middleware: self.middleware,
         }
     }
 
+    /// Add a static tool to the agent if `tool` is `Some`
+    pub fn maybe_tool(self, tool: Option<impl Tool + 'static>) -> AgentBuilderSimple<M> {
+        self.tools(tool.map(|tool| Box::new(tool) as Box<dyn ToolDyn>))
+    }
+
     pub fn tool_server_handle(mut self, handle: ToolServerHandle) -> Self {
         self.tool_server_handle = Some(handle);
         self
@@ -180,11 +321,26 @@ where
             static_tools,
             additional_params: self.additional_params,
             max_tokens: self.max_tokens,
-            dynamic_context: vec![],
+            dynamic_context: self.dynamic_context,
             dynamic_tools: vec![],
             temperature: self.temperature,
             tools,
             tool_choice: self.tool_choice,
+            history_policy: self.history_policy,
+            token_estimator: self.token_estimator,
+            observers: self.observers,
+            tool_concurrency: self.tool_concurrency,
+            tool_timeouts: self.tool_timeouts,
+            tool_retries: self.tool_retries,
+            tool_hooks: self.tool_hooks,
+            approval_required: self.approval_required,
+            max_turns: self.max_turns,
+            fallback_models: self.fallback_models,
+            completion_retry: self.completion_retry,
+            preamble_vars: self.preamble_vars,
+            deferred_tools: self.deferred_tools,
+            tool_output_policy: self.tool_output_policy,
+            middleware: self.middleware,
         }
     }
 
@@ -218,11 +374,26 @@ where
             static_tools,
             additional_params: self.additional_params,
             max_tokens: self.max_tokens,
-            dynamic_context: vec![],
+            dynamic_context: self.dynamic_context,
             dynamic_tools: vec![],
             temperature: self.temperature,
             tools,
             tool_choice: self.tool_choice,
+            history_policy: self.history_policy,
+            token_estimator: self.token_estimator,
+            observers: self.observers,
+            tool_concurrency: self.tool_concurrency,
+            tool_timeouts: self.tool_timeouts,
+            tool_retries: self.tool_retries,
+            tool_hooks: self.tool_hooks,
+            approval_required: self.approval_required,
+            max_turns: self.max_turns,
+            fallback_models: self.fallback_models,
+            completion_retry: self.completion_retry,
+            preamble_vars: self.preamble_vars,
+            deferred_tools: self.deferred_tools,
+            tool_output_policy: self.tool_output_policy,
+            middleware: self.middleware,
         }
     }
 
@@ -238,6 +409,37 @@ where
         self
     }
 
+    /// Add tools produced by `provider` the first time the agent is prompted, eg. tools
+    /// discovered on MCP servers that are only connected to at runtime. The provider is called
+    /// once, or again on the next prompt if it failed, in which case the prompt fails too.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let agent = AgentBuilder::new(model)
+    ///     .deferred_tools(move || {
+    ///         let servers = servers.clone();
+    ///         async move {
+    ///             let mut tools: Vec<Box<dyn ToolDyn>> = vec![];
+    ///             for server in servers.connect_all().await? {
+    ///                 for tool in server.list_all_tools().await? {
+    ///                     tools.push(Box::new(McpTool::from_mcp_server(tool, server.peer().clone())));
+    ///                 }
+    ///             }
+    ///             Ok::<_, anyhow::Error>(tools)
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn deferred_tools<F, Fut, E>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<Box<dyn ToolDyn>>, E>> + WasmCompatSend + 'static,
+        E: Into<ToolProviderError>,
+    {
+        self.deferred_tools.push(DeferredTools::new(provider));
+        self
+    }
+
     pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
         self.tool_choice = Some(tool_choice);
         self
@@ -263,11 +465,26 @@ where
             static_tools: vec![],
             additional_params: self.additional_params,
             max_tokens: self.max_tokens,
-            dynamic_context: vec![],
+            dynamic_context: self.dynamic_context,
             dynamic_tools,
             temperature: self.temperature,
             tools: toolset,
             tool_choice: self.tool_choice,
+            history_policy: self.history_policy,
+            token_estimator: self.token_estimator,
+            observers: self.observers,
+            tool_concurrency: self.tool_concurrency,
+            tool_timeouts: self.tool_timeouts,
+            tool_retries: self.tool_retries,
+            tool_hooks: self.tool_hooks,
+            approval_required: self.approval_required,
+            max_turns: self.max_turns,
+            fallback_models: self.fallback_models,
+            completion_retry: self.completion_retry,
+            preamble_vars: self.preamble_vars,
+            deferred_tools: self.deferred_tools,
+            tool_output_policy: self.tool_output_policy,
+            middleware: self.middleware,
         }
     }
 
@@ -283,12 +500,170 @@ where
         self
     }
 
-    /// Set additional parameters to be passed to the model
+    /// Set additional parameters to be passed to the model with every completion request, eg.
+    /// `json!({"enable_search": true, "top_p": 0.8})` for Qwen. Parameters set on a prompt request
+    /// (see [PromptRequest::additional_params](crate::agent::PromptRequest::additional_params))
+    /// take precedence over them.
     pub fn additional_params(mut self, params: serde_json::Value) -> Self {
         self.additional_params = Some(params);
         self
     }
 
+    /// Trim the chat history according to `policy` before each completion request
+    pub fn history_policy(mut self, policy: HistoryPolicy) -> Self {
+        self.history_policy = Some(policy);
+        self
+    }
+
+    /// Set the function used to estimate message sizes for the history policy.
+    /// Defaults to [estimate_tokens].
+    pub fn token_estimator(
+        mut self,
+        estimator: impl Fn(&Message) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.token_estimator = Some(Arc::new(estimator));
+        self
+    }
+
+    /// Add an observer notified of the lifecycle events of the agent's multi-turn runs
+    pub fn observer(mut self, observer: impl AgentObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Execute up to `max_concurrency` of the tool calls the model makes in a single turn
+    /// concurrently. Tool results are still sent back to the model in the order of the calls.
+    /// Defaults to 1, ie. tool calls are executed one after the other.
+    ///
+    /// This lets an orchestrator run independent sub-agents registered as tools (see
+    /// [Agent::into_tool]) at the same time when the model calls several of them in one turn.
+    pub fn parallel_tool_calls(mut self, max_concurrency: usize) -> Self {
+        self.tool_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Set the default maximum number of turns (ie. the maximum number of times the model can
+    /// call tools before writing a text response) of `prompt`, `chat` and `stream_prompt`
+    /// requests. A request's own [multi_turn](crate::agent::PromptRequest::multi_turn) overrides
+    /// it. Defaults to 0, ie. no multi-turn.
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    /// Add a model to the agent's fallback chain. When a completion request fails with a
+    /// [retryable](crate::completion::CompletionError::is_retryable) error, the same request is
+    /// sent to the next model of the chain. Streaming requests only fall back when the failure
+    /// occurs before the first token. The turns answered by a fallback model are recorded in the
+    /// [UsageBreakdown](crate::agent::UsageBreakdown) of the response.
+    ///
+    /// The fallback model may be of another type than the agent's model, eg. from another
+    /// provider. Its raw responses are not passed to the request's hooks, and its answers are
+    /// not continued when [auto_continue](crate::agent::PromptRequest::auto_continue) is set.
+    pub fn fallback_model<F>(mut self, model: F) -> Self
+    where
+        F: CompletionModel + 'static,
+    {
+        self.fallback_models.push(Arc::new(model));
+        self
+    }
+
+    /// Retry completion requests failing with a transient error, eg. a network error or a
+    /// `503`, up to `max_attempts` attempts in total, waiting `backoff` before the first retry
+    /// and doubling the delay on each further retry. The identical request is sent again, before
+    /// falling back to the next model of the chain. Errors about the size of the request or
+    /// authentication are never retried, see [CompletionRetryPolicy]. Streaming requests are only
+    /// retried when the failure occurs before the first token.
+    pub fn completion_retry(mut self, max_attempts: usize, backoff: Duration) -> Self {
+        self.completion_retry = Some(CompletionRetryPolicy::new(max_attempts, backoff));
+        self
+    }
+
+    /// Cancel tool calls still running after `timeout`. The model then receives a timeout error
+    /// as the tool result instead of the run hanging. See [ToolTimeouts].
+    pub fn tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_timeouts = self.tool_timeouts.default_timeout(timeout);
+        self
+    }
+
+    /// Set the timeout of the tool named `tool_name`, overriding [Self::tool_timeout].
+    pub fn tool_timeout_for(mut self, tool_name: impl Into<String>, timeout: Duration) -> Self {
+        self.tool_timeouts = self.tool_timeouts.tool(tool_name, timeout);
+        self
+    }
+
+    /// Set the timeouts of the agent's tool calls
+    pub fn tool_timeouts(mut self, tool_timeouts: ToolTimeouts) -> Self {
+        self.tool_timeouts = tool_timeouts;
+        self
+    }
+
+    /// Retry failed tool calls according to `policy`. See [ToolRetryPolicy].
+    pub fn tool_retry_policy(mut self, policy: ToolRetryPolicy) -> Self {
+        self.tool_retries = self.tool_retries.default_policy(policy);
+        self
+    }
+
+    /// Add a hook run before each tool call, which can allow, deny or rewrite the call. A denied
+    /// call is not executed, and the denial message is sent to the model as the tool result.
+    pub fn on_tool_call(
+        mut self,
+        hook: impl Fn(&ToolCallInfo) -> HookDecision + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_hooks = self.tool_hooks.on_call(hook);
+        self
+    }
+
+    /// Add a hook run after each tool call (including denied ones), eg. for auditing
+    pub fn on_tool_result(
+        mut self,
+        hook: impl Fn(&ToolResultInfo) + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_hooks = self.tool_hooks.on_result(hook);
+        self
+    }
+
+    /// Require calls to the tool named `tool_name` to be approved before they are executed.
+    /// Streaming requests pause on such calls until they are answered through their
+    /// [ApprovalHandle](crate::agent::ApprovalHandle). Other requests cannot approve calls, so they
+    /// reject them.
+    pub fn requires_approval(mut self, tool_name: impl Into<String>) -> Self {
+        self.approval_required.insert(tool_name.into());
+        self
+    }
+
+    /// Set the tool hooks of the agent, replacing the hooks added so far
+    pub fn tool_hooks(mut self, tool_hooks: ToolHooks) -> Self {
+        self.tool_hooks = tool_hooks;
+        self
+    }
+
+    /// Set the retry policy of the tool named `tool_name`, overriding [Self::tool_retry_policy].
+    pub fn tool_retry_policy_for(
+        mut self,
+        tool_name: impl Into<String>,
+        policy: ToolRetryPolicy,
+    ) -> Self {
+        self.tool_retries = self.tool_retries.tool(tool_name, policy);
+        self
+    }
+
+    /// Shrink tool outputs longer than the policy allows before they are sent to the model. A
+    /// [ToolOutputStrategy::StoreAndReference](super::ToolOutputStrategy::StoreAndReference)
+    /// policy also gives the agent the `fetch_tool_output` tool to read the stored outputs.
+    pub fn tool_output_policy(mut self, policy: ToolOutputPolicy) -> Self {
+        self.deferred_tools.extend(policy.deferred_tools());
+        self.tool_output_policy = Some(policy);
+        self
+    }
+
+    /// Wrap each completion request and tool call of the agent with `layer`, eg. for logging or
+    /// metrics. Layers run in registration order, see [AgentMiddleware].
+    pub fn layer(mut self, layer: impl AgentMiddleware + 'static) -> Self {
+        self.middleware.push(Arc::new(layer));
+        self
+    }
+
     /// Build the agent
     pub fn build(self) -> Agent<M> {
         let tool_server_handle = if let Some(handle) = self.tool_server_handle {
@@ -301,7 +676,7 @@ where
             name: self.name,
             description: self.description,
             model: Arc::new(self.model),
-            preamble: self.preamble,
+            preamble: render_preamble(self.preamble, &self.preamble_vars),
             static_context: self.static_context,
             temperature: self.temperature,
             max_tokens: self.max_tokens,
@@ -309,6 +684,22 @@ where
             tool_choice: self.tool_choice,
             dynamic_context: Arc::new(RwLock::new(self.dynamic_context)),
             tool_server_handle,
+            history_policy: self.history_policy,
+            token_estimator: self
+                .token_estimator
+                .unwrap_or_else(|| Arc::new(estimate_tokens)),
+            observers: self.observers,
+            tool_concurrency: self.tool_concurrency,
+            tool_timeouts: self.tool_timeouts,
+            tool_retries: self.tool_retries,
+            tool_hooks: self.tool_hooks,
+            approval_required: self.approval_required,
+            max_turns: self.max_turns,
+            fallback_models: self.fallback_models,
+            completion_retry: self.completion_retry,
+            deferred_tools: self.deferred_tools,
+            tool_output_policy: self.tool_output_policy,
+            middleware: self.middleware,
         }
     }
 }
@@ -364,6 +755,36 @@ where
     tools: ToolSet,
     /// Whether or not the underlying LLM should be forced to use a tool before providing a response.
     tool_choice: Option<ToolChoice>,
+    /// Policy used to trim the chat history before each completion request
+    history_policy: Option<HistoryPolicy>,
+    /// Estimates message sizes for the history policy
+    token_estimator: Option<TokenEstimator>,
+    /// Observers notified of the lifecycle events of multi-turn runs
+    observers: Vec<Arc<dyn AgentObserver>>,
+    /// Maximum number of tool calls of a single turn that are executed concurrently
+    tool_concurrency: usize,
+    /// Timeouts of the agent's tool calls
+    tool_timeouts: ToolTimeouts,
+    /// Retry policies of the agent's tool calls
+    tool_retries: ToolRetries,
+    /// Hooks vetting, rewriting and logging the agent's tool calls
+    tool_hooks: ToolHooks,
+    /// Names of the tools whose calls must be approved before being executed
+    approval_required: HashSet<String>,
+    /// Default maximum number of turns of the agent's prompt requests
+    max_turns: usize,
+    /// Models the requests of the agent fall back to, in order, when its model fails
+    fallback_models: Vec<Arc<dyn CompletionModelDyn>>,
+    /// Policy retrying failed completion requests on the same model
+    completion_retry: Option<CompletionRetryPolicy>,
+    /// Variables substituted into the preamble when the agent is built
+    preamble_vars: HashMap<String, String>,
+    /// Tools added to the tool server on the first prompt of the agent
+    deferred_tools: Vec<DeferredTools>,
+    /// Policy shrinking long tool outputs before they are sent to the model
+    tool_output_policy: Option<ToolOutputPolicy>,
+    /// Layers wrapping each completion request and tool call, in registration order
+    middleware: Vec<Arc<dyn AgentMiddleware>>,
 }
 
 impl<M> AgentBuilderSimple<M>
@@ -385,6 +806,21 @@ where
             dynamic_tools: vec![],
             tools: ToolSet::default(),
             tool_choice: None,
+            history_policy: None,
+            token_estimator: None,
+            observers: vec![],
+            tool_concurrency: 1,
+            tool_timeouts: ToolTimeouts::default(),
+            tool_retries: ToolRetries::default(),
+            tool_hooks: ToolHooks::default(),
+            approval_required: HashSet::new(),
+            max_turns: 0,
+            fallback_models: vec![],
+            completion_retry: None,
+            preamble_vars: HashMap::new(),
+            deferred_tools: vec![],
+            tool_output_policy: None,
+            middleware: vec![],
         }
     }
 
@@ -422,6 +858,19 @@ where
         self
     }
 
+    /// Set a preamble template. Each `{name}` placeholder is replaced by the value of the
+    /// variable `name` (see [Self::preamble_var]) when the agent is built. Placeholders without a
+    /// matching variable are left as is.
+    pub fn preamble_template(self, template: &str) -> Self {
+        self.preamble(template)
+    }
+
+    /// Set the value substituted for the `{name}` placeholders of the preamble
+    pub fn preamble_var(mut self, name: &str, value: impl ToString) -> Self {
+        self.preamble_vars.insert(name.into(), value.to_string());
+        self
+    }
+
     /// Add a static context document to the agent
     pub fn context(mut self, doc: &str) -> Self {
         self.static_context.push(Document {
@@ -440,6 +889,23 @@ where
         self
     }
 
+    /// Add several static tools to the agent, eg. a list built from configuration
+    pub fn tools(mut self, tools: impl IntoIterator<Item = Box<dyn ToolDyn>>) -> Self {
+        let tools: Vec<_> = tools.into_iter().collect();
+        self.static_tools
+            .extend(tools.iter().map(|tool| tool.name()));
+        self.tools.extend(tools);
+        self
+    }
+
+    /// Add a static tool to the agent if `tool` is `Some`
+    pub fn maybe_tool(self, tool: Option<impl Tool + 'static>) -> Self {
+        match tool {
+            Some(tool) => self.tool(tool),
+            None => self,
+        }
+    }
+
     /// Add an array of MCP tools (from `rmcp`) to the agent
     #[cfg(feature = "rmcp")]
     #[cfg_attr(docsrs, doc(cfg(feature = "rmcp")))]
@@ -470,6 +936,37 @@ where
         self
     }
 
+    /// Add tools produced by `provider` the first time the agent is prompted, eg. tools
+    /// discovered on MCP servers that are only connected to at runtime. The provider is called
+    /// once, or again on the next prompt if it failed, in which case the prompt fails too.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let agent = AgentBuilder::new(model)
+    ///     .deferred_tools(move || {
+    ///         let servers = servers.clone();
+    ///         async move {
+    ///             let mut tools: Vec<Box<dyn ToolDyn>> = vec![];
+    ///             for server in servers.connect_all().await? {
+    ///                 for tool in server.list_all_tools().await? {
+    ///                     tools.push(Box::new(McpTool::from_mcp_server(tool, server.peer().clone())));
+    ///                 }
+    ///             }
+    ///             Ok::<_, anyhow::Error>(tools)
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn deferred_tools<F, Fut, E>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<Box<dyn ToolDyn>>, E>> + WasmCompatSend + 'static,
+        E: Into<ToolProviderError>,
+    {
+        self.deferred_tools.push(DeferredTools::new(provider));
+        self
+    }
+
     pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
         self.tool_choice = Some(tool_choice);
         self
@@ -500,12 +997,170 @@ where
         self
     }
 
-    /// Set additional parameters to be passed to the model
+    /// Set additional parameters to be passed to the model with every completion request, eg.
+    /// `json!({"enable_search": true, "top_p": 0.8})` for Qwen. Parameters set on a prompt request
+    /// (see [PromptRequest::additional_params](crate::agent::PromptRequest::additional_params))
+    /// take precedence over them.
     pub fn additional_params(mut self, params: serde_json::Value) -> Self {
         self.additional_params = Some(params);
         self
     }
 
+    /// Trim the chat history according to `policy` before each completion request
+    pub fn history_policy(mut self, policy: HistoryPolicy) -> Self {
+        self.history_policy = Some(policy);
+        self
+    }
+
+    /// Set the function used to estimate message sizes for the history policy.
+    /// Defaults to [estimate_tokens].
+    pub fn token_estimator(
+        mut self,
+        estimator: impl Fn(&Message) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.token_estimator = Some(Arc::new(estimator));
+        self
+    }
+
+    /// Add an observer notified of the lifecycle events of the agent's multi-turn runs
+    pub fn observer(mut self, observer: impl AgentObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Execute up to `max_concurrency` of the tool calls the model makes in a single turn
+    /// concurrently. Tool results are still sent back to the model in the order of the calls.
+    /// Defaults to 1, ie. tool calls are executed one after the other.
+    ///
+    /// This lets an orchestrator run independent sub-agents registered as tools (see
+    /// [Agent::into_tool]) at the same time when the model calls several of them in one turn.
+    pub fn parallel_tool_calls(mut self, max_concurrency: usize) -> Self {
+        self.tool_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Set the default maximum number of turns (ie. the maximum number of times the model can
+    /// call tools before writing a text response) of `prompt`, `chat` and `stream_prompt`
+    /// requests. A request's own [multi_turn](crate::agent::PromptRequest::multi_turn) overrides
+    /// it. Defaults to 0, ie. no multi-turn.
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    /// Add a model to the agent's fallback chain. When a completion request fails with a
+    /// [retryable](crate::completion::CompletionError::is_retryable) error, the same request is
+    /// sent to the next model of the chain. Streaming requests only fall back when the failure
+    /// occurs before the first token. The turns answered by a fallback model are recorded in the
+    /// [UsageBreakdown](crate::agent::UsageBreakdown) of the response.
+    ///
+    /// The fallback model may be of another type than the agent's model, eg. from another
+    /// provider. Its raw responses are not passed to the request's hooks, and its answers are
+    /// not continued when [auto_continue](crate::agent::PromptRequest::auto_continue) is set.
+    pub fn fallback_model<F>(mut self, model: F) -> Self
+    where
+        F: CompletionModel + 'static,
+    {
+        self.fallback_models.push(Arc::new(model));
+        self
+    }
+
+    /// Retry completion requests failing with a transient error, eg. a network error or a
+    /// `503`, up to `max_attempts` attempts in total, waiting `backoff` before the first retry
+    /// and doubling the delay on each further retry. The identical request is sent again, before
+    /// falling back to the next model of the chain. Errors about the size of the request or
+    /// authentication are never retried, see [CompletionRetryPolicy]. Streaming requests are only
+    /// retried when the failure occurs before the first token.
+    pub fn completion_retry(mut self, max_attempts: usize, backoff: Duration) -> Self {
+        self.completion_retry = Some(CompletionRetryPolicy::new(max_attempts, backoff));
+        self
+    }
+
+    /// Cancel tool calls still running after `timeout`. The model then receives a timeout error
+    /// as the tool result instead of the run hanging. See [ToolTimeouts].
+    pub fn tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_timeouts = self.tool_timeouts.default_timeout(timeout);
+        self
+    }
+
+    /// Set the timeout of the tool named `tool_name`, overriding [Self::tool_timeout].
+    pub fn tool_timeout_for(mut self, tool_name: impl Into<String>, timeout: Duration) -> Self {
+        self.tool_timeouts = self.tool_timeouts.tool(tool_name, timeout);
+        self
+    }
+
+    /// Set the timeouts of the agent's tool calls
+    pub fn tool_timeouts(mut self, tool_timeouts: ToolTimeouts) -> Self {
+        self.tool_timeouts = tool_timeouts;
+        self
+    }
+
+    /// Retry failed tool calls according to `policy`. See [ToolRetryPolicy].
+    pub fn tool_retry_policy(mut self, policy: ToolRetryPolicy) -> Self {
+        self.tool_retries = self.tool_retries.default_policy(policy);
+        self
+    }
+
+    /// Add a hook run before each tool call, which can allow, deny or rewrite the call. A denied
+    /// call is not executed, and the denial message is sent to the model as the tool result.
+    pub fn on_tool_call(
+        mut self,
+        hook: impl Fn(&ToolCallInfo) -> HookDecision + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_hooks = self.tool_hooks.on_call(hook);
+        self
+    }
+
+    /// Add a hook run after each tool call (including denied ones), eg. for auditing
+    pub fn on_tool_result(
+        mut self,
+        hook: impl Fn(&ToolResultInfo) + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_hooks = self.tool_hooks.on_result(hook);
+        self
+    }
+
+    /// Require calls to the tool named `tool_name` to be approved before they are executed.
+    /// Streaming requests pause on such calls until they are answered through their
+    /// [ApprovalHandle](crate::agent::ApprovalHandle). Other requests cannot approve calls, so they
+    /// reject them.
+    pub fn requires_approval(mut self, tool_name: impl Into<String>) -> Self {
+        self.approval_required.insert(tool_name.into());
+        self
+    }
+
+    /// Set the tool hooks of the agent, replacing the hooks added so far
+    pub fn tool_hooks(mut self, tool_hooks: ToolHooks) -> Self {
+        self.tool_hooks = tool_hooks;
+        self
+    }
+
+    /// Set the retry policy of the tool named `tool_name`, overriding [Self::tool_retry_policy].
+    pub fn tool_retry_policy_for(
+        mut self,
+        tool_name: impl Into<String>,
+        policy: ToolRetryPolicy,
+    ) -> Self {
+        self.tool_retries = self.tool_retries.tool(tool_name, policy);
+        self
+    }
+
+    /// Shrink tool outputs longer than the policy allows before they are sent to the model. A
+    /// [ToolOutputStrategy::StoreAndReference](super::ToolOutputStrategy::StoreAndReference)
+    /// policy also gives the agent the `fetch_tool_output` tool to read the stored outputs.
+    pub fn tool_output_policy(mut self, policy: ToolOutputPolicy) -> Self {
+        self.deferred_tools.extend(policy.deferred_tools());
+        self.tool_output_policy = Some(policy);
+        self
+    }
+
+    /// Wrap each completion request and tool call of the agent with `layer`, eg. for logging or
+    /// metrics. Layers run in registration order, see [AgentMiddleware].
+    pub fn layer(mut self, layer: impl AgentMiddleware + 'static) -> Self {
+        self.middleware.push(Arc::new(layer));
+        self
+    }
+
     /// Build the agent
     pub fn build(self) -> Agent<M> {
         let tool_server_handle = ToolServer::new()
@@ -518,7 +1173,7 @@ where
             name: self.name,
             description: self.description,
             model: Arc::new(self.model),
-            preamble: self.preamble,
+            preamble: render_preamble(self.preamble, &self.preamble_vars),
             static_context: self.static_context,
             temperature: self.temperature,
             max_tokens: self.max_tokens,
@@ -526,6 +1181,209 @@ where
             tool_choice: self.tool_choice,
             dynamic_context: Arc::new(RwLock::new(self.dynamic_context)),
             tool_server_handle,
+            history_policy: self.history_policy,
+            token_estimator: self
+                .token_estimator
+                .unwrap_or_else(|| Arc::new(estimate_tokens)),
+            observers: self.observers,
+            tool_concurrency: self.tool_concurrency,
+            tool_timeouts: self.tool_timeouts,
+            tool_retries: self.tool_retries,
+            tool_hooks: self.tool_hooks,
+            approval_required: self.approval_required,
+            max_turns: self.max_turns,
+            fallback_models: self.fallback_models,
+            completion_retry: self.completion_retry,
+            deferred_tools: self.deferred_tools,
+            tool_output_policy: self.tool_output_policy,
+            middleware: self.middleware,
+        }
+    }
+}
+
+/// Substitute the `{name}` placeholders of `preamble` with the matching variables.
+fn render_preamble(preamble: Option<String>, vars: &HashMap<String, String>) -> Option<String> {
+    let preamble = preamble?;
+    if vars.is_empty() {
+        return Some(preamble);
+    }
+
+    let mut rendered = String::with_capacity(preamble.len());
+    let mut rest = preamble.as_str();
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        match placeholder
+            .find('}')
+            .and_then(|end| Some((end, vars.get(&placeholder[1..end])?)))
+        {
+            Some((end, value)) => {
+                rendered.push_str(value);
+                rest = &placeholder[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &placeholder[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+
+    Some(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockCompletionModel;
+
+    const COATING_TEMPLATE: &str = "You optimize coatings for the {category} category. \
+        Target a hardness of at least {target_hardness} GPa. Answer in JSON: {\"score\": ...}.";
+
+    #[tokio::test]
+    async fn test_preamble_template_substitutes_variables() {
+        let agents = ["P1", "P2"].map(|category| {
+            AgentBuilder::new(MockCompletionModel::new())
+                .preamble_template(COATING_TEMPLATE)
+                .preamble_var("category", category)
+                .preamble_var("target_hardness", 30)
+                .build()
+        });
+
+        assert_eq!(
+            agents[0].preamble.as_deref(),
+            Some(
+                "You optimize coatings for the P1 category. \
+                Target a hardness of at least 30 GPa. Answer in JSON: {\"score\": ...}."
+            )
+        );
+        assert_eq!(
+            agents[1].preamble.as_deref(),
+            Some(
+                "You optimize coatings for the P2 category. \
+                Target a hardness of at least 30 GPa. Answer in JSON: {\"score\": ...}."
+            )
+        );
+    }
+
+    #[test]
+    fn test_preamble_variables_are_not_substituted_recursively() {
+        let vars = HashMap::from([
+            ("a".to_string(), "{b}".to_string()),
+            ("b".to_string(), "B".to_string()),
+        ]);
+
+        assert_eq!(
+            render_preamble(Some("{a} {b} {c} {".to_string()), &vars).as_deref(),
+            Some("{b} B {c} {")
+        );
+    }
+
+    async fn requested_tool_names(agent: Agent<MockCompletionModel>) -> Vec<String> {
+        use crate::completion::Prompt;
+
+        let model = agent.model.clone();
+        agent.prompt("hi").await.unwrap();
+
+        let mut names: Vec<_> = model.requests()[0]
+            .tools
+            .iter()
+            .map(|tool| tool.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_tools_from_boxed_list() {
+        use crate::tools::{
+            GetTaskStatus, ListTasks, SubmitLineTask, SubmitPointTask, SubmitScheilTask, ThinkTool,
+        };
+
+        let tools: Vec<Box<dyn ToolDyn>> = vec![
+            Box::new(SubmitPointTask),
+            Box::new(SubmitLineTask),
+            Box::new(SubmitScheilTask),
+            Box::new(GetTaskStatus),
+            Box::new(ListTasks),
+        ];
+        let agent = AgentBuilder::new(MockCompletionModel::new())
+            .tools(tools)
+            .tools(vec![Box::new(ThinkTool) as Box<dyn ToolDyn>])
+            .build();
+
+        assert_eq!(
+            requested_tool_names(agent).await,
+            [
+                "calphamesh_get_task_status",
+                "calphamesh_list_tasks",
+                "calphamesh_submit_line_task",
+                "calphamesh_submit_point_task",
+                "calphamesh_submit_scheil_task",
+                "think",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maybe_tool() {
+        use crate::tools::{ListTasks, ThinkTool};
+
+        let agent = AgentBuilder::new(MockCompletionModel::new())
+            .maybe_tool(None::<ListTasks>)
+            .maybe_tool(Some(ThinkTool))
+            .maybe_tool(None::<ListTasks>)
+            .build();
+
+        assert_eq!(requested_tool_names(agent).await, ["think"]);
+    }
+
+    /// A model thinking forever, and the turn limit of the run ending with a `MaxDepthError`
+    fn always_thinking() -> MockCompletionModel {
+        (0..10).fold(MockCompletionModel::new(), |model, i| {
+            model.with_tool_call(
+                &format!("call_{i}"),
+                "think",
+                serde_json::json!({"thought": "hmm"}),
+            )
+        })
+    }
+
+    fn reached_max_depth(error: &crate::completion::PromptError) -> usize {
+        match error {
+            crate::completion::PromptError::MaxDepthError { max_depth, .. } => *max_depth,
+            _ => panic!("expected MaxDepthError, got {error:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_turns_is_the_default_turn_limit() {
+        use crate::{
+            agent::{StreamingError, stream_collect},
+            completion::Prompt,
+            streaming::StreamingPrompt,
+            tools::ThinkTool,
+        };
+
+        let agent = AgentBuilder::new(always_thinking())
+            .tool(ThinkTool)
+            .max_turns(2)
+            .build();
+
+        let error = agent.prompt("think").await.unwrap_err();
+        assert_eq!(reached_max_depth(&error), 2);
+
+        let error = agent.prompt("think").multi_turn(1).await.unwrap_err();
+        assert_eq!(reached_max_depth(&error), 1);
+
+        // A fresh model, as the runs above used up most of the scripted tool calls
+        let agent = AgentBuilder::new(always_thinking())
+            .tool(ThinkTool)
+            .max_turns(2)
+            .build();
+        match stream_collect(agent.stream_prompt("think"), |_| {}).await {
+            Err(StreamingError::Prompt(error)) => assert_eq!(reached_max_depth(&error), 2),
+            other => panic!("expected the stream to end with a MaxDepthError, got {other:?}"),
         }
     }
 }
@@ -1,18 +1,36 @@
-use super::prompt_request::{self, PromptRequest};
+use super::{
+    deferred_tools::DeferredTools,
+    fallback::CompletionRetryPolicy,
+    history::{HistoryPolicy, TokenEstimator},
+    middleware::AgentMiddleware,
+    observer::{AgentEvent, AgentObserver},
+    prompt_request::{self, PromptRequest},
+    tool_hook::{HookDecision, ToolCallInfo, ToolHooks, ToolResultInfo},
+    tool_output::ToolOutputPolicy,
+    tool_policy::{ToolRetries, ToolRetry, ToolTimeouts, tool_timeout_error},
+};
 use crate::{
+    OneOrMany,
     agent::prompt_request::streaming::StreamingPromptRequest,
     completion::{
-        Chat, Completion, CompletionError, CompletionModel, CompletionRequestBuilder, Document,
-        GetTokenUsage, Message, Prompt, PromptError,
+        Chat, Completion, CompletionError, CompletionModel, CompletionModelDyn,
+        CompletionRequestBuilder, Document, GetTokenUsage, Message, Prompt, PromptError,
     },
-    message::ToolChoice,
+    message::{ToolCall, ToolChoice, UserContent},
     streaming::{StreamingChat, StreamingCompletion, StreamingPrompt},
-    tool::server::ToolServerHandle,
+    tool::{
+        ToolSetError,
+        server::{ToolServerError, ToolServerHandle},
+    },
     vector_store::{VectorStoreError, request::VectorSearchRequest},
     wasm_compat::WasmCompatSend,
 };
 use futures::{StreamExt, TryStreamExt, stream};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
 use tokio::sync::RwLock;
 
 const UNKNOWN_AGENT_NAME: &str = "Unnamed Agent";
@@ -73,6 +91,34 @@ where
     pub dynamic_context: DynamicContextStore,
     /// Whether or not the underlying LLM should be forced to use a tool before providing a response.
     pub tool_choice: Option<ToolChoice>,
+    /// Policy used to trim the chat history before each completion request
+    pub history_policy: Option<HistoryPolicy>,
+    /// Estimates message sizes for the history policy
+    pub token_estimator: TokenEstimator,
+    /// Observers notified of the lifecycle events of multi-turn runs
+    pub observers: Vec<Arc<dyn AgentObserver>>,
+    /// Maximum number of tool calls of a single turn that are executed concurrently
+    pub tool_concurrency: usize,
+    /// Timeouts of the agent's tool calls
+    pub tool_timeouts: ToolTimeouts,
+    /// Retry policies of the agent's tool calls
+    pub tool_retries: ToolRetries,
+    /// Hooks vetting, rewriting and logging the agent's tool calls
+    pub tool_hooks: ToolHooks,
+    /// Names of the tools whose calls must be approved before being executed
+    pub approval_required: HashSet<String>,
+    /// Default maximum number of turns of the agent's prompt requests
+    pub max_turns: usize,
+    /// Models the requests of the agent fall back to, in order, when its model fails
+    pub fallback_models: Vec<Arc<dyn CompletionModelDyn>>,
+    /// Policy retrying failed completion requests on the same model
+    pub completion_retry: Option<CompletionRetryPolicy>,
+    /// Tools added to the tool server on the first prompt of the agent
+    pub(crate) deferred_tools: Vec<DeferredTools>,
+    /// Policy shrinking long tool outputs before they are sent to the model
+    pub tool_output_policy: Option<ToolOutputPolicy>,
+    /// Layers wrapping each completion request and tool call, in registration order
+    pub middleware: Vec<Arc<dyn AgentMiddleware>>,
 }
 
 impl<M> Agent<M>
@@ -83,6 +129,153 @@ where
     pub(crate) fn name(&self) -> &str {
         self.name.as_deref().unwrap_or(UNKNOWN_AGENT_NAME)
     }
+
+    /// Resume a run stopped by [PromptRequest::return_tool_calls] once the caller executed its
+    /// tool calls. `history` is the conversation ending with the assistant message holding the
+    /// calls (the history of the stopped request, or its response's messages), and `results`
+    /// pairs every call with its output. The results are sent to the model as if the agent had
+    /// executed the tools itself.
+    ///
+    /// # Panics
+    /// - If `results` is empty.
+    pub fn continue_with_tool_results<'a>(
+        &'a self,
+        history: &'a mut Vec<Message>,
+        results: impl IntoIterator<Item = (ToolCall, String)>,
+    ) -> PromptRequest<'a, prompt_request::Standard, M, ()> {
+        let content = results
+            .into_iter()
+            .map(|(tool_call, output)| match tool_call.call_id {
+                Some(call_id) => UserContent::tool_result_with_call_id(
+                    tool_call.id,
+                    call_id,
+                    OneOrMany::one(output.into()),
+                ),
+                None => UserContent::tool_result(tool_call.id, OneOrMany::one(output.into())),
+            })
+            .collect::<Vec<_>>();
+        let tool_results = Message::User {
+            content: OneOrMany::many(content).expect("there should be at least one tool result"),
+        };
+
+        PromptRequest::new(self, tool_results).with_history(history)
+    }
+
+    /// Execute a tool call on the agent's tool server, unless a tool hook denies it. The call
+    /// runs within its timeout and is retried according to its retry policy, `on_retry` being
+    /// called before each retry. Denials, errors and timeouts are returned as the tool output, so
+    /// that the model can react to them. The output is shrunk according to the tool output policy.
+    /// The call is wrapped by the tool call hooks of the agent's middleware.
+    pub(crate) async fn call_tool(
+        &self,
+        tool_call: &ToolCall,
+        on_retry: impl FnMut(&ToolRetry),
+    ) -> String {
+        self.before_tool_call(tool_call).await;
+        let started = Instant::now();
+
+        let info = ToolCallInfo::from(tool_call);
+        let (args, result, denied) = match self.tool_hooks.decide(&info) {
+            HookDecision::Deny(reason) => {
+                tracing::info!("Tool call {} was denied: {reason}", info.name);
+                (info.args, reason, true)
+            }
+            decision => {
+                let args = match decision {
+                    HookDecision::Mutate(args) => args,
+                    _ => info.args,
+                };
+                let result = self
+                    .execute_tool(tool_call, &args.to_string(), on_retry)
+                    .await;
+                (args, result, false)
+            }
+        };
+
+        self.tool_hooks.result(&ToolResultInfo {
+            id: info.id,
+            call_id: info.call_id,
+            name: info.name,
+            args,
+            result: result.clone(),
+            denied,
+        });
+
+        let result = match &self.tool_output_policy {
+            Some(policy) => policy.apply(&tool_call.function.name, result).await,
+            None => result,
+        };
+
+        self.after_tool_call(tool_call, &result, started.elapsed())
+            .await;
+        result
+    }
+
+    async fn execute_tool(
+        &self,
+        tool_call: &ToolCall,
+        args: &str,
+        mut on_retry: impl FnMut(&ToolRetry),
+    ) -> String {
+        let name = &tool_call.function.name;
+        let retry_policy = self.tool_retries.policy_for(name);
+
+        let mut attempt = 1;
+        loop {
+            let call = self.tool_server_handle.call_tool(name, args);
+            let error = match self.tool_timeouts.run(name, call).await {
+                Ok(Ok(output)) => return output,
+                Ok(Err(e)) => e,
+                Err(timeout) => {
+                    tracing::warn!("Tool {name} timed out after {timeout:?}");
+                    return tool_timeout_error(name, timeout);
+                }
+            };
+
+            let should_retry = match (&error, retry_policy) {
+                (
+                    ToolServerError::ToolsetError(ToolSetError::ToolCallError(tool_error)),
+                    Some(policy),
+                ) => policy.should_retry(attempt, tool_error),
+                _ => false,
+            };
+            if !should_retry {
+                tracing::warn!("Error while executing tool: {error}");
+                return if attempt > 1 {
+                    format!("{error} (after {attempt} attempts)")
+                } else {
+                    error.to_string()
+                };
+            }
+
+            let retry = ToolRetry {
+                id: tool_call.id.clone(),
+                call_id: tool_call.call_id.clone(),
+                tool_name: name.clone(),
+                attempt,
+                error: error.to_string(),
+            };
+            tracing::info!("Retrying tool {name} after failed attempt {attempt}: {error}");
+            on_retry(&retry);
+
+            if let Some(policy) = retry_policy {
+                futures_timer::Delay::new(policy.delay(attempt)).await;
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Whether calls to the tool named `tool_name` must be approved before being executed.
+    pub(crate) fn requires_approval(&self, tool_name: &str) -> bool {
+        self.approval_required.contains(tool_name)
+    }
+
+    /// Pass `event` to every observer of the agent.
+    pub(crate) fn notify(&self, event: AgentEvent) {
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
 }
 
 impl<M> Completion<M> for Agent<M>
@@ -96,6 +289,10 @@ where
     ) -> Result<CompletionRequestBuilder<M>, CompletionError> {
         let prompt = prompt.into();
 
+        for tools in &self.deferred_tools {
+            tools.resolve(&self.tool_server_handle).await?;
+        }
+
         // Find the latest message in the chat history that contains RAG text
         let rag_text = prompt.rag_text();
         let rag_text = rag_text.or_else(|| {
@@ -105,6 +302,20 @@ where
                 .find_map(|message| message.rag_text())
         });
 
+        let chat_history = match &self.history_policy {
+            Some(policy) => {
+                policy
+                    .apply(
+                        self.preamble.as_deref(),
+                        &prompt,
+                        chat_history,
+                        &self.token_estimator,
+                    )
+                    .await?
+            }
+            None => chat_history,
+        };
+
         let completion_request = self
             .model
             .completion_request(prompt)
@@ -0,0 +1,162 @@
+use std::{future::Future, sync::Arc};
+
+use tokio::sync::OnceCell;
+
+use crate::{
+    completion::CompletionError,
+    tool::{ToolDyn, server::ToolServerHandle},
+    wasm_compat::{WasmBoxedFuture, WasmCompatSend},
+};
+
+/// The error of a deferred tool provider, see [AgentBuilder::deferred_tools](super::AgentBuilder::deferred_tools).
+pub type ToolProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+type ToolProvider = Arc<
+    dyn Fn() -> WasmBoxedFuture<'static, Result<Vec<Box<dyn ToolDyn>>, ToolProviderError>>
+        + Send
+        + Sync,
+>;
+
+/// Tools produced by a provider the first time the agent is prompted.
+///
+/// Clones of an agent share the resolution, as they share the tool server.
+#[derive(Clone)]
+pub(crate) struct DeferredTools {
+    provider: ToolProvider,
+    resolved: Arc<OnceCell<()>>,
+}
+
+impl DeferredTools {
+    pub(crate) fn new<F, Fut, E>(provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<Box<dyn ToolDyn>>, E>> + WasmCompatSend + 'static,
+        E: Into<ToolProviderError>,
+    {
+        let provider: ToolProvider = Arc::new(move || {
+            let tools = provider();
+            Box::pin(async move { tools.await.map_err(Into::into) })
+        });
+
+        Self {
+            provider,
+            resolved: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Add the provided tools to the tool server of `handle`. The provider is only called on the
+    /// first call, or again after it failed.
+    pub(crate) async fn resolve(&self, handle: &ToolServerHandle) -> Result<(), CompletionError> {
+        self.resolved
+            .get_or_try_init(|| async {
+                let tools = (self.provider)()
+                    .await
+                    .map_err(CompletionError::RequestError)?;
+
+                for tool in tools {
+                    let name = tool.name();
+                    handle.add_boxed_tool(tool).await.map_err(|e| {
+                        CompletionError::RequestError(
+                            format!("Failed to add deferred tool {name}: {e}").into(),
+                        )
+                    })?;
+                }
+
+                Ok::<_, CompletionError>(())
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::{
+        agent::AgentBuilder,
+        completion::{Prompt, ToolDefinition},
+        message::{Message, ToolResultContent, UserContent},
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[derive(Deserialize)]
+    struct CountTasksArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Count error")]
+    struct CountError;
+
+    /// Stands in for a tool discovered on an MCP server
+    struct CountTasks;
+
+    impl Tool for CountTasks {
+        const NAME: &'static str = "count_tasks";
+        type Error = CountError;
+        type Args = CountTasksArgs;
+        type Output = u32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Count the running tasks".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(2)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deferred_tools_resolve_once_on_first_prompt() {
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "count_tasks", json!({}))
+            .with_text("2 tasks are running")
+            .with_text("Still running");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let agent = AgentBuilder::new(model.clone())
+            .deferred_tools({
+                let calls = calls.clone();
+                move || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async { Ok::<_, CountError>(vec![Box::new(CountTasks) as Box<dyn ToolDyn>]) }
+                }
+            })
+            .build();
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let mut history = vec![];
+        let response = agent
+            .prompt("How many tasks are running?")
+            .with_history(&mut history)
+            .multi_turn(2)
+            .await
+            .unwrap();
+        assert_eq!(response, "2 tasks are running");
+        agent.clone().prompt("And now?").await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let requests = model.requests();
+        assert_eq!(requests.len(), 3);
+        for request in &requests {
+            let tools: Vec<_> = request.tools.iter().map(|tool| &tool.name).collect();
+            assert_eq!(tools, ["count_tasks"]);
+        }
+        let tool_result = history.iter().find_map(|message| match message {
+            Message::User { content } => match content.first() {
+                UserContent::ToolResult(result) => Some(result.content.first()),
+                _ => None,
+            },
+            _ => None,
+        });
+        assert_eq!(tool_result, Some(ToolResultContent::text("2")));
+    }
+}
@@ -0,0 +1,560 @@
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+
+use crate::{
+    client::FinalCompletionResponse,
+    completion::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, GetTokenUsage,
+        Usage, fallback::first_item_checked,
+    },
+    streaming::{RawStreamingChoice, StreamingCompletionResponse},
+    wasm_compat::WasmCompatSend,
+};
+
+use super::Agent;
+
+/// Error messages of requests exceeding the context length of the model, matched in lowercase.
+const CONTEXT_LENGTH_MARKERS: [&str; 5] = [
+    "context length",
+    "context_length",
+    "maximum context",
+    "input length",
+    "too many tokens",
+];
+
+/// Error messages of requests rejected because of their credentials, matched in lowercase.
+const AUTHENTICATION_MARKERS: [&str; 3] = ["unauthorized", "api key", "apikey"];
+
+/// Whether the request which failed with `error` would fail again if sent as is: it was rejected
+/// because of its credentials (`401`, `403`) or its size (`413`, or `400` for exceeding the
+/// context length). The error message is only inspected when the provider's answer carried no
+/// status.
+fn is_permanent_error(error: &CompletionError) -> bool {
+    let message = error.to_string().to_lowercase();
+    let mentions = |markers: &[&str]| markers.iter().any(|marker| message.contains(marker));
+
+    match error.status() {
+        Some(
+            http::StatusCode::UNAUTHORIZED
+            | http::StatusCode::FORBIDDEN
+            | http::StatusCode::PAYLOAD_TOO_LARGE,
+        ) => true,
+        Some(http::StatusCode::BAD_REQUEST) => mentions(&CONTEXT_LENGTH_MARKERS),
+        Some(_) => false,
+        None => mentions(&CONTEXT_LENGTH_MARKERS) || mentions(&AUTHENTICATION_MARKERS),
+    }
+}
+
+/// When to send a failed completion request again to the same model, see
+/// [AgentBuilder::completion_retry](super::AgentBuilder::completion_retry).
+///
+/// Only [retryable](CompletionError::is_retryable) errors are retried, except the ones about the
+/// size of the request or authentication. A request is attempted again after a delay doubling on
+/// each attempt starting from `backoff`, as long as fewer than `max_attempts` attempts were made.
+#[derive(Debug, Clone, Copy)]
+pub struct CompletionRetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: usize,
+    /// Delay before the first retry
+    pub backoff: Duration,
+}
+
+impl CompletionRetryPolicy {
+    pub fn new(max_attempts: usize, backoff: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff,
+        }
+    }
+
+    /// Whether the attempt number `attempt` (counting from 1), which failed with `error`,
+    /// should be retried.
+    pub fn should_retry(&self, attempt: usize, error: &CompletionError) -> bool {
+        attempt < self.max_attempts && error.is_retryable() && !is_permanent_error(error)
+    }
+
+    /// The delay before retrying the attempt number `attempt` (counting from 1).
+    pub fn delay(&self, attempt: usize) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1) as u32))
+    }
+}
+
+/// The raw final response of a streamed turn. Fallback models can be of another type than the
+/// agent's model, so only the token usage of their final response is kept.
+#[derive(Clone)]
+pub(crate) enum ChainStreamingResponse<R> {
+    /// Final response of the agent's model
+    Model(R),
+    /// Final response of one of the agent's fallback models
+    Fallback(FinalCompletionResponse),
+}
+
+impl<R> ChainStreamingResponse<R> {
+    /// The final response, if it comes from the agent's model.
+    pub(crate) fn into_model(self) -> Option<R> {
+        match self {
+            Self::Model(response) => Some(response),
+            Self::Fallback(_) => None,
+        }
+    }
+}
+
+impl<R: GetTokenUsage> GetTokenUsage for ChainStreamingResponse<R> {
+    fn token_usage(&self) -> Option<Usage> {
+        match self {
+            Self::Model(response) => response.token_usage(),
+            Self::Fallback(response) => response.token_usage(),
+        }
+    }
+}
+
+/// Convert the final response of a streaming response with `convert`, leaving the other chunks
+/// untouched.
+fn map_final_response<R, T>(
+    response: StreamingCompletionResponse<R>,
+    convert: fn(R) -> T,
+) -> StreamingCompletionResponse<T>
+where
+    R: Clone + Unpin + GetTokenUsage + WasmCompatSend + 'static,
+    T: Clone + Unpin + GetTokenUsage + WasmCompatSend + 'static,
+{
+    let inner = response.inner.map(move |chunk| {
+        chunk.map(|chunk| match chunk {
+            RawStreamingChoice::Message(text) => RawStreamingChoice::Message(text),
+            RawStreamingChoice::ToolCall {
+                id,
+                call_id,
+                name,
+                arguments,
+            } => RawStreamingChoice::ToolCall {
+                id,
+                call_id,
+                name,
+                arguments,
+            },
+            RawStreamingChoice::ToolCallDelta { id, delta } => {
+                RawStreamingChoice::ToolCallDelta { id, delta }
+            }
+            RawStreamingChoice::Reasoning {
+                id,
+                reasoning,
+                signature,
+            } => RawStreamingChoice::Reasoning {
+                id,
+                reasoning,
+                signature,
+            },
+            RawStreamingChoice::FinalResponse(response) => {
+                RawStreamingChoice::FinalResponse(convert(response))
+            }
+        })
+    });
+
+    StreamingCompletionResponse::stream(Box::pin(inner))
+}
+
+impl<M> Agent<M>
+where
+    M: CompletionModel,
+{
+    /// Send `request` to the model at position `index` of the agent's fallback chain (0 for the
+    /// agent's own model). The raw response is only kept when the agent's model answered.
+    async fn chain_completion(
+        &self,
+        index: usize,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Option<M::Response>>, CompletionError> {
+        if index == 0 {
+            let response = self.model.completion(request).await?;
+            return Ok(CompletionResponse {
+                choice: response.choice,
+                usage: response.usage,
+                raw_response: Some(response.raw_response),
+            });
+        }
+
+        let response = self.fallback_models[index - 1].completion(request).await?;
+        Ok(CompletionResponse {
+            choice: response.choice,
+            usage: response.usage,
+            raw_response: None,
+        })
+    }
+
+    /// Stream `request` from the model at position `index` of the agent's fallback chain (0 for
+    /// the agent's own model).
+    async fn chain_stream(
+        &self,
+        index: usize,
+        request: CompletionRequest,
+    ) -> Result<
+        StreamingCompletionResponse<ChainStreamingResponse<M::StreamingResponse>>,
+        CompletionError,
+    >
+    where
+        M: 'static,
+    {
+        if index == 0 {
+            let response = self.model.stream(request).await?;
+            return Ok(map_final_response(response, ChainStreamingResponse::Model));
+        }
+
+        let response = self.fallback_models[index - 1].stream(request).await?;
+        Ok(map_final_response(
+            response,
+            ChainStreamingResponse::Fallback,
+        ))
+    }
+
+    /// Wait before retrying the attempt number `attempt` of a completion request, which failed
+    /// with `error`. Returns false without waiting if the agent's retry policy gives up.
+    async fn wait_for_retry(
+        &self,
+        model_index: usize,
+        attempt: usize,
+        error: &CompletionError,
+    ) -> bool {
+        let Some(policy) = self
+            .completion_retry
+            .filter(|policy| policy.should_retry(attempt, error))
+        else {
+            return false;
+        };
+
+        let delay = policy.delay(attempt);
+        tracing::warn!(
+            attempt,
+            max_attempts = policy.max_attempts,
+            model = model_index,
+            "Completion request failed, retrying in {delay:?}: {error}"
+        );
+        futures_timer::Delay::new(delay).await;
+        true
+    }
+
+    /// Send `request` to the agent's model, retrying it according to the agent's
+    /// [CompletionRetryPolicy] and then falling back to the next model of the chain on retryable
+    /// errors. Returns the response with the position in the fallback chain of the model that
+    /// answered (0 for the agent's own model).
+    pub(crate) async fn send_completion(
+        &self,
+        mut request: CompletionRequest,
+    ) -> Result<(CompletionResponse<Option<M::Response>>, usize), CompletionError> {
+        let last = self.fallback_models.len();
+        self.before_completion(&mut request).await;
+        let started = Instant::now();
+
+        for index in 0..=last {
+            let mut attempt = 1;
+            loop {
+                let error = match self.chain_completion(index, request.clone()).await {
+                    Ok(response) => {
+                        self.after_completion(&response.choice, response.usage, started.elapsed())
+                            .await;
+                        return Ok((response, index));
+                    }
+                    Err(error) => error,
+                };
+
+                if self.wait_for_retry(index, attempt, &error).await {
+                    attempt += 1;
+                } else if index < last && error.is_retryable() {
+                    tracing::warn!(
+                        "Completion request failed on model {index} of the chain, falling back: {error}"
+                    );
+                    break;
+                } else {
+                    return Err(error);
+                }
+            }
+        }
+
+        unreachable!("the model chain always contains the agent's model")
+    }
+
+    /// Stream `request` from the agent's model, retrying and then falling back to the next model
+    /// of the chain like [Self::send_completion], on errors that occur before the first token.
+    /// Returns the response with the position in the fallback chain of the model that answered
+    /// (0 for the agent's own model).
+    ///
+    /// The `after_completion` hooks of the agent's middleware are left to the caller, as they
+    /// run once the stream ended.
+    pub(crate) async fn stream_completion_with_fallback(
+        &self,
+        mut request: CompletionRequest,
+    ) -> Result<
+        (
+            StreamingCompletionResponse<ChainStreamingResponse<M::StreamingResponse>>,
+            usize,
+        ),
+        CompletionError,
+    >
+    where
+        M: 'static,
+    {
+        let last = self.fallback_models.len();
+        self.before_completion(&mut request).await;
+
+        for index in 0..=last {
+            let mut attempt = 1;
+            loop {
+                let response = match self.chain_stream(index, request.clone()).await {
+                    Ok(response) => first_item_checked(response).await,
+                    Err(error) => Err(error),
+                };
+                let error = match response {
+                    Ok(response) => return Ok((response, index)),
+                    Err(error) => error,
+                };
+
+                if self.wait_for_retry(index, attempt, &error).await {
+                    attempt += 1;
+                } else if index < last && error.is_retryable() {
+                    tracing::warn!(
+                        "Streaming request failed on model {index} of the chain before the first token, falling back: {error}"
+                    );
+                    break;
+                } else {
+                    return Err(error);
+                }
+            }
+        }
+
+        unreachable!("the model chain always contains the agent's model")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{AgentBuilder, stream_collect},
+        completion::{Prompt, ToolDefinition},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[tokio::test]
+    async fn test_prompt_falls_back_on_retryable_error() {
+        let primary = MockCompletionModel::new().with_error("503 Service Unavailable");
+        let fallback = MockCompletionModel::new().with_text("recovered");
+        let agent = AgentBuilder::new(primary.clone())
+            .fallback_model(fallback.clone())
+            .build();
+
+        let response = agent.prompt("Hello").extended_details().await.unwrap();
+
+        assert_eq!(response.output, "recovered");
+        assert_eq!(response.usage_breakdown.fallbacks.get(&1), Some(&1));
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(
+            fallback.requests()[0].chat_history,
+            primary.requests()[0].chat_history
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prompt_does_not_fall_back_on_client_error() {
+        let primary = MockCompletionModel::new().with_error("400 Bad Request: InvalidParameter");
+        let fallback = MockCompletionModel::new();
+        let agent = AgentBuilder::new(primary)
+            .fallback_model(fallback.clone())
+            .build();
+
+        assert!(agent.prompt("Hello").await.is_err());
+        assert!(fallback.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prompt_tries_every_fallback_model() {
+        let primary = MockCompletionModel::new().with_error("503 Service Unavailable");
+        let second = MockCompletionModel::new().with_error("connection reset");
+        let third = MockCompletionModel::new().with_text("recovered");
+        let agent = AgentBuilder::new(primary)
+            .fallback_model(second)
+            .fallback_model(third)
+            .build();
+
+        let response = agent.prompt("Hello").extended_details().await.unwrap();
+
+        assert_eq!(response.output, "recovered");
+        assert_eq!(response.usage_breakdown.fallbacks.get(&1), Some(&2));
+    }
+
+    #[derive(Deserialize)]
+    struct AddArgs {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Math error")]
+    struct MathError;
+
+    struct Adder;
+
+    impl Tool for Adder {
+        const NAME: &'static str = "add";
+        type Error = MathError;
+        type Args = AddArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: "add".to_string(),
+                description: "Add x and y together".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x + args.y)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_retries_failed_turn_in_multi_turn_run() {
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "add", json!({"x": 2, "y": 3}))
+            .with_error("503 Service Unavailable")
+            .with_text("5");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .completion_retry(3, Duration::ZERO)
+            .build();
+
+        let response = agent.prompt("Add 2 and 3").multi_turn(2).await.unwrap();
+
+        assert_eq!(response, "5");
+        let requests = model.requests();
+        assert_eq!(requests.len(), 3);
+        // The failed turn is retried with the identical request
+        assert_eq!(requests[2].chat_history, requests[1].chat_history);
+        assert_eq!(requests[2].tools, requests[1].tools);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_retries_failed_turn_in_multi_turn_run() {
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "add", json!({"x": 2, "y": 3}))
+            .with_stream_error("connection reset")
+            .with_text("5");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .completion_retry(2, Duration::ZERO)
+            .build();
+
+        let (_, final_response) =
+            stream_collect(agent.stream_prompt("Add 2 and 3").multi_turn(2), |_| {})
+                .await
+                .unwrap();
+
+        assert_eq!(final_response.response(), "5");
+        let requests = model.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].chat_history, requests[1].chat_history);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_gives_up_after_max_attempts() {
+        let model = MockCompletionModel::new()
+            .with_error("503 Service Unavailable")
+            .with_error("503 Service Unavailable")
+            .with_text("too late");
+        let agent = AgentBuilder::new(model.clone())
+            .completion_retry(2, Duration::ZERO)
+            .build();
+
+        assert!(agent.prompt("Hello").await.is_err());
+        assert_eq!(model.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_does_not_retry_permanent_errors() {
+        for message in [
+            "401 Unauthorized: InvalidApiKey",
+            "400 Bad Request: Range of input length should be [1, 30720]",
+            "context length exceeded",
+        ] {
+            let model = MockCompletionModel::new()
+                .with_error(message)
+                .with_text("ok");
+            let agent = AgentBuilder::new(model.clone())
+                .completion_retry(3, Duration::ZERO)
+                .build();
+
+            assert!(agent.prompt("Hello").await.is_err(), "{message}");
+            assert_eq!(model.requests().len(), 1, "{message}");
+        }
+    }
+
+    #[test]
+    fn test_permanent_errors_are_classified_by_status() {
+        let status_error = |status: u16| {
+            CompletionError::HttpError(crate::http_client::Error::InvalidStatusCode(
+                http::StatusCode::from_u16(status).unwrap(),
+            ))
+        };
+        let provider_error = |message: &str| CompletionError::ProviderError(message.to_string());
+
+        assert!(is_permanent_error(&status_error(401)));
+        assert!(is_permanent_error(&status_error(403)));
+        assert!(is_permanent_error(&status_error(413)));
+        assert!(is_permanent_error(&provider_error(
+            "401 Unauthorized: InvalidApiKey"
+        )));
+        assert!(is_permanent_error(&provider_error(
+            "403 Forbidden: AccessDenied"
+        )));
+        assert!(is_permanent_error(&provider_error("413 Payload Too Large")));
+        assert!(is_permanent_error(&provider_error(
+            "400 Bad Request: context_length_exceeded"
+        )));
+        assert!(!is_permanent_error(&provider_error(
+            "400 Bad Request: InvalidParameter"
+        )));
+        // The message is not inspected when the provider answered with a status
+        assert!(!is_permanent_error(&provider_error(
+            "503 Service Unavailable: no api key available in the pool"
+        )));
+        assert!(!is_permanent_error(&status_error(429)));
+        // Errors without a status fall back to the message
+        assert!(is_permanent_error(&provider_error(
+            "context length exceeded"
+        )));
+        assert!(is_permanent_error(&provider_error("Invalid API key")));
+        assert!(!is_permanent_error(&provider_error("connection reset")));
+    }
+
+    #[test]
+    fn test_completion_retry_delay_doubles() {
+        let policy = CompletionRetryPolicy::new(4, Duration::from_millis(100));
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_falls_back_before_first_token() {
+        let primary = MockCompletionModel::new().with_stream_error("503 Service Unavailable");
+        let fallback = MockCompletionModel::new().with_text("recovered");
+        let agent = AgentBuilder::new(primary.clone())
+            .fallback_model(fallback.clone())
+            .build();
+
+        let (_, final_response) = stream_collect(agent.stream_prompt("Hello"), |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(final_response.response(), "recovered");
+        assert_eq!(final_response.usage_breakdown().fallbacks.get(&1), Some(&1));
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(fallback.requests().len(), 1);
+    }
+}
@@ -0,0 +1,934 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    completion::{CompletionError, CompletionModel, CompletionModelDyn, Message},
+    message::{AssistantContent, ToolResultContent, UserContent},
+};
+
+/// Prefix marking a message that holds a summary of earlier conversation, written by
+/// [HistoryPolicy::Summarize].
+pub const SUMMARY_TAG: &str = "[Summary of earlier conversation]";
+
+const SUMMARIZER_PREAMBLE: &str = "You compress conversations between a user, an AI assistant \
+and its tools. Summarize the conversation you are given in a few short paragraphs, keeping every \
+fact, number, decision and open question that later turns may rely on. Reply with the summary only.";
+
+/// Estimates the number of tokens a message will take up in a completion request.
+pub type TokenEstimator = Arc<dyn Fn(&Message) -> usize + Send + Sync>;
+
+/// The default [TokenEstimator]: roughly one token per four characters of message content.
+pub fn estimate_tokens(message: &Message) -> usize {
+    let chars: usize = match message {
+        Message::User { content } => content.iter().map(user_content_chars).sum(),
+        Message::Assistant { content, .. } => content.iter().map(assistant_content_chars).sum(),
+    };
+
+    chars.div_ceil(4)
+}
+
+fn user_content_chars(content: &UserContent) -> usize {
+    match content {
+        UserContent::Text(text) => text.text.chars().count(),
+        UserContent::ToolResult(result) => result
+            .content
+            .iter()
+            .map(|content| match content {
+                ToolResultContent::Text(text) => text.text.chars().count(),
+                ToolResultContent::Image(image) => json_chars(image),
+            })
+            .sum(),
+        other => json_chars(other),
+    }
+}
+
+fn json_chars(value: &impl serde::Serialize) -> usize {
+    serde_json::to_string(value).map_or(0, |json| json.chars().count())
+}
+
+fn assistant_content_chars(content: &AssistantContent) -> usize {
+    match content {
+        AssistantContent::Text(text) => text.text.chars().count(),
+        AssistantContent::ToolCall(call) => {
+            call.function.name.chars().count() + call.function.arguments.to_string().chars().count()
+        }
+        AssistantContent::Reasoning(reasoning) => {
+            reasoning.reasoning.iter().map(|r| r.chars().count()).sum()
+        }
+    }
+}
+
+/// How an agent trims its chat history before each completion request.
+///
+/// # Example
+/// ```rust,ignore
+/// let agent = AgentBuilder::new(model)
+///     .history_policy(HistoryPolicy::TokenBudget {
+///         max_tokens: 24_000,
+///         keep_system: true,
+///         keep_last_n: 6,
+///     })
+///     .build();
+/// ```
+#[derive(Clone)]
+pub enum HistoryPolicy {
+    /// Drop the oldest messages until the request fits in `max_tokens` (as counted by the agent's
+    /// [TokenEstimator], including the preamble and the prompt).
+    ///
+    /// The preamble and prompt are never dropped. If `keep_system` is set, neither is the first
+    /// message of the history, which usually holds the original task or shared context. The last
+    /// `keep_last_n` messages are always kept, even if that exceeds the budget. An assistant
+    /// message with tool calls and the tool results answering it are kept or dropped together.
+    TokenBudget {
+        max_tokens: usize,
+        keep_system: bool,
+        keep_last_n: usize,
+    },
+    /// Once the request exceeds `threshold_tokens`, ask `summarizer` (usually a cheaper model) to
+    /// summarize the oldest `summarize_oldest` messages, and replace them with a single user
+    /// message starting with [SUMMARY_TAG].
+    ///
+    /// A summary left by a previous compaction is folded into the new one rather than nested in
+    /// it. Tool call / tool result pairs are never split, and the most recent message group is
+    /// never summarized. See [HistoryPolicy::summarize].
+    Summarize {
+        threshold_tokens: usize,
+        summarize_oldest: usize,
+        summarizer: Arc<dyn CompletionModelDyn>,
+    },
+}
+
+impl HistoryPolicy {
+    /// Create a [HistoryPolicy::Summarize] policy using `model` as the summarizer.
+    pub fn summarize<M>(threshold_tokens: usize, summarize_oldest: usize, model: M) -> Self
+    where
+        M: CompletionModel + 'static,
+    {
+        HistoryPolicy::Summarize {
+            threshold_tokens,
+            summarize_oldest,
+            summarizer: Arc::new(model),
+        }
+    }
+}
+
+impl std::fmt::Debug for HistoryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryPolicy::TokenBudget {
+                max_tokens,
+                keep_system,
+                keep_last_n,
+            } => f
+                .debug_struct("TokenBudget")
+                .field("max_tokens", max_tokens)
+                .field("keep_system", keep_system)
+                .field("keep_last_n", keep_last_n)
+                .finish(),
+            HistoryPolicy::Summarize {
+                threshold_tokens,
+                summarize_oldest,
+                ..
+            } => f
+                .debug_struct("Summarize")
+                .field("threshold_tokens", threshold_tokens)
+                .field("summarize_oldest", summarize_oldest)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl HistoryPolicy {
+    pub(crate) async fn apply(
+        &self,
+        preamble: Option<&str>,
+        prompt: &Message,
+        history: Vec<Message>,
+        estimator: &TokenEstimator,
+    ) -> Result<Vec<Message>, CompletionError> {
+        let reserved =
+            preamble.map_or(0, |preamble| estimator(&Message::user(preamble))) + estimator(prompt);
+
+        match self {
+            HistoryPolicy::TokenBudget {
+                max_tokens,
+                keep_system,
+                keep_last_n,
+            } => Ok(fit_token_budget(
+                history,
+                max_tokens.saturating_sub(reserved),
+                *keep_system,
+                *keep_last_n,
+                estimator,
+            )),
+            HistoryPolicy::Summarize {
+                threshold_tokens,
+                summarize_oldest,
+                summarizer,
+            } => {
+                let used: usize = history.iter().map(|message| estimator(message)).sum();
+                if reserved + used <= *threshold_tokens {
+                    return Ok(history);
+                }
+
+                summarize_oldest_messages(history, *summarize_oldest, summarizer.as_ref()).await
+            }
+        }
+    }
+}
+
+/// Split the history into groups that must be kept or dropped as a whole: an assistant message
+/// with tool calls together with the tool result messages that follow it, or a single message.
+fn group_messages(history: &[Message]) -> Vec<Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+
+    while start < history.len() {
+        let mut end = start + 1;
+        if has_tool_calls(&history[start]) {
+            while end < history.len() && has_tool_results(&history[end]) {
+                end += 1;
+            }
+        }
+        groups.push(start..end);
+        start = end;
+    }
+
+    groups
+}
+
+fn has_tool_calls(message: &Message) -> bool {
+    matches!(message, Message::Assistant { content, .. }
+        if content.iter().any(|c| matches!(c, AssistantContent::ToolCall(_))))
+}
+
+fn has_tool_results(message: &Message) -> bool {
+    matches!(message, Message::User { content }
+        if content.iter().any(|c| matches!(c, UserContent::ToolResult(_))))
+}
+
+fn fit_token_budget(
+    history: Vec<Message>,
+    budget: usize,
+    keep_system: bool,
+    keep_last_n: usize,
+    estimator: &TokenEstimator,
+) -> Vec<Message> {
+    let costs: Vec<usize> = history.iter().map(|message| estimator(message)).collect();
+    if costs.iter().sum::<usize>() <= budget {
+        return history;
+    }
+
+    let groups = group_messages(&history);
+    let cost = |group: &Range<usize>| costs[group.clone()].iter().sum::<usize>();
+    let start_of = |index: usize| groups.get(index).map_or(history.len(), |g| g.start);
+
+    // Groups that are always kept: the leading group and enough trailing groups to cover
+    // `keep_last_n` messages
+    let pinned = usize::from(keep_system && !groups.is_empty());
+    let mut tail = groups.len();
+    while tail > pinned && history.len() - start_of(tail) < keep_last_n {
+        tail -= 1;
+    }
+
+    let mut used: usize = groups[..pinned]
+        .iter()
+        .chain(&groups[tail..])
+        .map(cost)
+        .sum();
+
+    // Keep as many of the most recent middle groups as fit, so the kept history stays contiguous
+    let mut keep_from = tail;
+    while keep_from > pinned {
+        let group_cost = cost(&groups[keep_from - 1]);
+        if used + group_cost > budget {
+            break;
+        }
+        used += group_cost;
+        keep_from -= 1;
+    }
+
+    let (pinned_end, middle_start) = (start_of(pinned), start_of(keep_from));
+    tracing::debug!(
+        dropped = middle_start - pinned_end,
+        "Trimmed chat history to fit token budget"
+    );
+
+    history
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| *index < pinned_end || *index >= middle_start)
+        .map(|(_, message)| message)
+        .collect()
+}
+
+async fn summarize_oldest_messages(
+    mut history: Vec<Message>,
+    count: usize,
+    summarizer: &dyn CompletionModelDyn,
+) -> Result<Vec<Message>, CompletionError> {
+    if count == 0 {
+        return Ok(history);
+    }
+
+    // Extend the range to the end of a message group and never summarize the last group, so
+    // that tool call / tool result pairs stay together and there is always recent context
+    let groups = group_messages(&history);
+    let end = groups
+        .iter()
+        .map(|group| group.end)
+        .take(groups.len().saturating_sub(1))
+        .find(|end| *end >= count)
+        .or_else(|| groups.iter().rev().nth(1).map(|group| group.end))
+        .unwrap_or(0);
+
+    // Nothing to gain from re-summarizing a lone summary
+    if end == 0 || (end == 1 && summary_text(&history[0]).is_some()) {
+        return Ok(history);
+    }
+
+    let summarized: Vec<Message> = history.drain(..end).collect();
+
+    let response = summarizer
+        .completion_request(Message::user(render_transcript(&summarized)))
+        .preamble(SUMMARIZER_PREAMBLE.to_string())
+        .send()
+        .await?;
+
+    let summary = response
+        .choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    tracing::debug!(
+        summarized = summarized.len(),
+        "Replaced oldest chat history messages with a summary"
+    );
+
+    history.insert(
+        0,
+        Message::user(format!("{SUMMARY_TAG}\n{}", summary.trim())),
+    );
+    Ok(history)
+}
+
+/// The summary text of a message written by [HistoryPolicy::Summarize], without the tag.
+fn summary_text(message: &Message) -> Option<&str> {
+    let Message::User { content } = message else {
+        return None;
+    };
+
+    content.iter().find_map(|content| match content {
+        UserContent::Text(text) => text
+            .text
+            .strip_prefix(SUMMARY_TAG)
+            .map(|summary| summary.trim_start()),
+        _ => None,
+    })
+}
+
+/// Render messages as a plain text transcript for the summarizer.
+fn render_transcript(messages: &[Message]) -> String {
+    let mut lines = Vec::new();
+
+    for message in messages {
+        if let Some(summary) = summary_text(message) {
+            lines.push(format!("Summary of the conversation so far: {summary}"));
+            continue;
+        }
+
+        match message {
+            Message::User { content } => {
+                for content in content.iter() {
+                    match content {
+                        UserContent::Text(text) => lines.push(format!("User: {}", text.text)),
+                        UserContent::ToolResult(result) => {
+                            let text = result
+                                .content
+                                .iter()
+                                .filter_map(|content| match content {
+                                    ToolResultContent::Text(text) => Some(text.text.as_str()),
+                                    ToolResultContent::Image(_) => None,
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            lines.push(format!("Tool result ({}): {text}", result.id));
+                        }
+                        _ => lines.push("User: [attachment]".to_string()),
+                    }
+                }
+            }
+            Message::Assistant { content, .. } => {
+                for content in content.iter() {
+                    match content {
+                        AssistantContent::Text(text) => {
+                            lines.push(format!("Assistant: {}", text.text))
+                        }
+                        AssistantContent::ToolCall(call) => lines.push(format!(
+                            "Assistant called tool {} ({}): {}",
+                            call.function.name, call.id, call.function.arguments
+                        )),
+                        AssistantContent::Reasoning(_) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Version of the file format written by [save_json] and [HistoryFile::save].
+pub const HISTORY_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HistoryError {
+    #[error("Failed to access history file `{}`: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("History file `{}` is corrupt: {reason}", path.display())]
+    Corrupt { path: PathBuf, reason: String },
+    #[error(
+        "History file `{}` has schema version {found}, but this version of rig reads version {expected}",
+        path.display()
+    )]
+    UnsupportedVersion {
+        path: PathBuf,
+        found: u64,
+        expected: u32,
+    },
+}
+
+/// A chat history saved to disk, together with metadata describing where it came from.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::agent::history::HistoryFile;
+///
+/// HistoryFile::new(history)
+///     .agent("coating-optimizer")
+///     .model("qwen-plus")
+///     .save("session.json")?;
+///
+/// let history = HistoryFile::load("session.json")?.messages;
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryFile {
+    pub schema_version: u32,
+    pub created_at: DateTime<Utc>,
+    /// Name of the agent the history belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// Model the agent was using.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub messages: Vec<Message>,
+}
+
+impl HistoryFile {
+    pub fn new(messages: Vec<Message>) -> Self {
+        Self {
+            schema_version: HISTORY_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            agent: None,
+            model: None,
+            messages,
+        }
+    }
+
+    pub fn agent(mut self, name: impl Into<String>) -> Self {
+        self.agent = Some(name.into());
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Write the history to `path` as pretty-printed JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), HistoryError> {
+        write_versioned(path.as_ref(), self)
+    }
+
+    /// Read a history written with [HistoryFile::save] or [save_json].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        read_versioned(path.as_ref())
+    }
+}
+
+/// Save `messages` to `path` so a later process can resume the conversation with [load_json].
+pub fn save_json(path: impl AsRef<Path>, messages: &[Message]) -> Result<(), HistoryError> {
+    HistoryFile::new(messages.to_vec()).save(path)
+}
+
+/// Load the messages of a history written with [save_json] or [HistoryFile::save].
+pub fn load_json(path: impl AsRef<Path>) -> Result<Vec<Message>, HistoryError> {
+    Ok(HistoryFile::load(path)?.messages)
+}
+
+pub(crate) fn write_versioned<T>(path: &Path, value: &T) -> Result<(), HistoryError>
+where
+    T: Serialize,
+{
+    let json = serde_json::to_vec_pretty(value).map_err(|e| HistoryError::Corrupt {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    })?;
+
+    std::fs::write(path, json).map_err(|source| HistoryError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Read a JSON file carrying a `schema_version` field, checking the version before
+/// deserializing the rest so that files from other versions get a clear error.
+pub(crate) fn read_versioned<T>(path: &Path) -> Result<T, HistoryError>
+where
+    T: DeserializeOwned,
+{
+    let corrupt = |reason: String| HistoryError::Corrupt {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let bytes = std::fs::read(path).map_err(|source| HistoryError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let value: serde_json::Value =
+        serde_json::from_slice(&bytes).map_err(|e| corrupt(format!("invalid JSON: {e}")))?;
+
+    let version = value
+        .get("schema_version")
+        .ok_or_else(|| corrupt("missing `schema_version` field".to_string()))?
+        .as_u64()
+        .ok_or_else(|| corrupt("`schema_version` is not a number".to_string()))?;
+
+    if version != u64::from(HISTORY_SCHEMA_VERSION) {
+        return Err(HistoryError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            found: version,
+            expected: HISTORY_SCHEMA_VERSION,
+        });
+    }
+
+    serde_json::from_value(value).map_err(|e| corrupt(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        OneOrMany,
+        agent::AgentBuilder,
+        completion::Prompt,
+        message::{ToolCall, ToolFunction, ToolResult},
+        test_utils::MockCompletionModel,
+    };
+
+    fn tool_calls(ids: &[&str]) -> Message {
+        Message::Assistant {
+            id: None,
+            content: OneOrMany::many(ids.iter().map(|id| {
+                AssistantContent::ToolCall(ToolCall {
+                    id: id.to_string(),
+                    call_id: None,
+                    function: ToolFunction {
+                        name: "lookup".to_string(),
+                        arguments: serde_json::json!({}),
+                    },
+                })
+            }))
+            .unwrap(),
+        }
+    }
+
+    fn tool_result(id: &str) -> Message {
+        Message::User {
+            content: OneOrMany::one(UserContent::ToolResult(ToolResult {
+                id: id.to_string(),
+                call_id: None,
+                content: OneOrMany::one(ToolResultContent::text("result")),
+            })),
+        }
+    }
+
+    fn history() -> Vec<Message> {
+        vec![
+            Message::user("task"),
+            tool_calls(&["a"]),
+            tool_result("a"),
+            Message::assistant("first answer"),
+            Message::user("follow up"),
+            tool_calls(&["b", "c"]),
+            tool_result("b"),
+            tool_result("c"),
+            Message::assistant("second answer"),
+            Message::user("another question"),
+        ]
+    }
+
+    fn fixed_estimator() -> TokenEstimator {
+        Arc::new(|_| 10)
+    }
+
+    fn token_budget(max_tokens: usize, keep_system: bool, keep_last_n: usize) -> HistoryPolicy {
+        HistoryPolicy::TokenBudget {
+            max_tokens,
+            keep_system,
+            keep_last_n,
+        }
+    }
+
+    /// Every tool result must directly follow the tool call message (or sibling results) it answers.
+    fn assert_pairs_intact(messages: &[Message]) {
+        for (index, message) in messages.iter().enumerate() {
+            if has_tool_results(message) {
+                assert!(index > 0, "history starts with an orphaned tool result");
+                let previous = &messages[index - 1];
+                assert!(has_tool_calls(previous) || has_tool_results(previous));
+            }
+            if has_tool_calls(message) {
+                let Message::Assistant { content, .. } = message else {
+                    unreachable!()
+                };
+                let results = messages[index + 1..]
+                    .iter()
+                    .take_while(|m| has_tool_results(m))
+                    .count();
+                assert_eq!(results, content.len(), "tool call split from its results");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_within_budget_is_untouched() {
+        let trimmed = token_budget(1_000, true, 2)
+            .apply(
+                None,
+                &Message::user("prompt"),
+                history(),
+                &fixed_estimator(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(trimmed, history());
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_never_splits_tool_pairs() {
+        let estimator = fixed_estimator();
+        for max_tokens in (0..=120).step_by(5) {
+            for keep_last_n in 0..4 {
+                for keep_system in [true, false] {
+                    let trimmed = token_budget(max_tokens, keep_system, keep_last_n)
+                        .apply(
+                            Some("preamble"),
+                            &Message::user("prompt"),
+                            history(),
+                            &estimator,
+                        )
+                        .await
+                        .unwrap();
+
+                    assert_pairs_intact(&trimmed);
+                    assert!(trimmed.len() >= keep_last_n.min(history().len()));
+                    if keep_last_n > 0 {
+                        assert_eq!(trimmed.last(), history().last());
+                    }
+                    if keep_system {
+                        assert_eq!(trimmed.first(), history().first());
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_is_respected() {
+        let estimator: TokenEstimator = Arc::new(estimate_tokens);
+        let prompt = Message::user("prompt");
+        let preamble = "You are a helpful assistant.";
+        let reserved = estimator(&Message::user(preamble)) + estimator(&prompt);
+        let full: usize = history().iter().map(|m| estimator(m)).sum();
+
+        for max_tokens in reserved + 4..reserved + full {
+            let trimmed = token_budget(max_tokens, false, 1)
+                .apply(Some(preamble), &prompt, history(), &estimator)
+                .await
+                .unwrap();
+
+            let used: usize = trimmed.iter().map(|m| estimator(m)).sum();
+            assert!(reserved + used <= max_tokens, "{max_tokens}: {used}");
+            assert_pairs_intact(&trimmed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_drops_oldest_middle_messages() {
+        // Room for the prompt plus 6 history messages: the tool call group is kept whole, which
+        // leaves no room for "follow up" before it
+        let trimmed = token_budget(70, true, 1)
+            .apply(
+                None,
+                &Message::user("prompt"),
+                history(),
+                &fixed_estimator(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            trimmed,
+            vec![
+                Message::user("task"),
+                tool_calls(&["b", "c"]),
+                tool_result("b"),
+                tool_result("c"),
+                Message::assistant("second answer"),
+                Message::user("another question"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_agent_applies_history_policy() {
+        let model = MockCompletionModel::new().with_text("done");
+        let agent = AgentBuilder::new(model.clone())
+            .history_policy(token_budget(40, true, 1))
+            .token_estimator(|_| 10)
+            .build();
+
+        let mut chat_history = history();
+        agent
+            .prompt("prompt")
+            .with_history(&mut chat_history)
+            .await
+            .unwrap();
+
+        let sent: Vec<Message> = model.requests()[0]
+            .chat_history
+            .clone()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                Message::user("task"),
+                Message::assistant("second answer"),
+                Message::user("another question"),
+                Message::user("prompt"),
+            ]
+        );
+    }
+
+    fn sent_text(model: &MockCompletionModel, index: usize) -> String {
+        let requests = model.requests();
+        match requests[index].chat_history.iter().last().unwrap() {
+            Message::User { content } => match content.first() {
+                UserContent::Text(text) => text.text,
+                other => panic!("unexpected content {other:?}"),
+            },
+            other => panic!("unexpected message {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarize_below_threshold_is_untouched() {
+        let summarizer = MockCompletionModel::new();
+        let trimmed = HistoryPolicy::summarize(1_000, 4, summarizer.clone())
+            .apply(
+                None,
+                &Message::user("prompt"),
+                history(),
+                &fixed_estimator(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(trimmed, history());
+        assert!(summarizer.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_summarize_replaces_oldest_messages() {
+        let summarizer = MockCompletionModel::new().with_text("The user asked about A.");
+        let policy = HistoryPolicy::summarize(50, 4, summarizer.clone());
+
+        let compacted = policy
+            .apply(
+                None,
+                &Message::user("prompt"),
+                history(),
+                &fixed_estimator(),
+            )
+            .await
+            .unwrap();
+
+        // The first 4 messages are replaced by a summary in their place
+        assert_eq!(compacted.len(), history().len() - 3);
+        assert_eq!(
+            compacted[0],
+            Message::user(format!("{SUMMARY_TAG}\nThe user asked about A."))
+        );
+        assert_eq!(compacted[1..], history()[4..]);
+
+        assert_eq!(
+            summarizer.requests()[0].preamble.as_deref(),
+            Some(SUMMARIZER_PREAMBLE)
+        );
+        let transcript = sent_text(&summarizer, 0);
+        assert!(transcript.contains("User: task"));
+        assert!(transcript.contains("Tool result (a): result"));
+        assert!(!transcript.contains("follow up"));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_does_not_split_tool_pairs() {
+        let summarizer = MockCompletionModel::new().with_text("Summary");
+        let compacted = HistoryPolicy::summarize(50, 2, summarizer)
+            .apply(
+                None,
+                &Message::user("prompt"),
+                history(),
+                &fixed_estimator(),
+            )
+            .await
+            .unwrap();
+
+        // Summarizing 2 messages would split the first tool call from its result
+        assert_eq!(compacted[1..], history()[3..]);
+        assert_pairs_intact(&compacted);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_summaries_do_not_nest() {
+        let summarizer = MockCompletionModel::new()
+            .with_text("The user asked about A.")
+            .with_text("The user asked about A, then B and C.");
+        let policy = HistoryPolicy::summarize(50, 4, summarizer.clone());
+        let prompt = Message::user("prompt");
+
+        let once = policy
+            .apply(None, &prompt, history(), &fixed_estimator())
+            .await
+            .unwrap();
+        let twice = policy
+            .apply(None, &prompt, once, &fixed_estimator())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            twice,
+            vec![
+                Message::user(format!(
+                    "{SUMMARY_TAG}\nThe user asked about A, then B and C."
+                )),
+                Message::assistant("second answer"),
+                Message::user("another question"),
+            ]
+        );
+
+        // The previous summary is passed to the summarizer as context, without its tag
+        let transcript = sent_text(&summarizer, 1);
+        assert!(transcript.contains("Summary of the conversation so far: The user asked about A."));
+        assert!(!transcript.contains(SUMMARY_TAG));
+    }
+
+    fn session() -> Vec<Message> {
+        vec![
+            Message::user("optimize the coating"),
+            Message::Assistant {
+                id: Some("msg-1".to_string()),
+                content: OneOrMany::many(vec![
+                    AssistantContent::Reasoning(crate::message::Reasoning::new(
+                        "Need the phase diagram first.",
+                    )),
+                    AssistantContent::ToolCall(ToolCall {
+                        id: "call-1".to_string(),
+                        call_id: None,
+                        function: ToolFunction {
+                            name: "submit_task".to_string(),
+                            arguments: serde_json::json!({"composition": {"Al": 0.3}}),
+                        },
+                    }),
+                ])
+                .unwrap(),
+            },
+            tool_result("call-1"),
+            Message::assistant("Waiting for the lab results."),
+        ]
+    }
+
+    #[test]
+    fn test_save_and_load_json_round_trip() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("history.json");
+
+        save_json(&path, &session()).unwrap();
+        assert_eq!(load_json(&path).unwrap(), session());
+
+        HistoryFile::new(session())
+            .agent("optimizer")
+            .model("qwen-plus")
+            .save(&path)
+            .unwrap();
+        let file = HistoryFile::load(&path).unwrap();
+        assert_eq!(file.schema_version, HISTORY_SCHEMA_VERSION);
+        assert_eq!(file.agent.as_deref(), Some("optimizer"));
+        assert_eq!(file.model.as_deref(), Some("qwen-plus"));
+        assert_eq!(file.messages, session());
+    }
+
+    #[test]
+    fn test_load_json_rejects_corrupt_and_mismatched_files() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("history.json");
+
+        std::fs::write(&path, "{ not json").unwrap();
+        let err = load_json(&path).unwrap_err();
+        assert!(matches!(err, HistoryError::Corrupt { .. }), "{err:?}");
+        assert!(err.to_string().contains("invalid JSON"), "{err}");
+
+        std::fs::write(&path, r#"{"messages": []}"#).unwrap();
+        let err = load_json(&path).unwrap_err();
+        assert!(
+            err.to_string().contains("missing `schema_version`"),
+            "{err}"
+        );
+
+        std::fs::write(&path, r#"{"schema_version": 99, "messages": []}"#).unwrap();
+        let err = load_json(&path).unwrap_err();
+        assert!(
+            matches!(err, HistoryError::UnsupportedVersion { found: 99, .. }),
+            "{err:?}"
+        );
+
+        std::fs::write(
+            &path,
+            r#"{"schema_version": 1, "created_at": "2025-01-01T00:00:00Z", "messages": 3}"#,
+        )
+        .unwrap();
+        let err = load_json(&path).unwrap_err();
+        assert!(matches!(err, HistoryError::Corrupt { .. }), "{err:?}");
+
+        let err = load_json(dir.path().join("missing.json")).unwrap_err();
+        assert!(matches!(err, HistoryError::Io { .. }), "{err:?}");
+    }
+}
@@ -0,0 +1,516 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    OneOrMany,
+    completion::{CompletionModel, CompletionRequest, CompletionResponse, Usage},
+    message::{AssistantContent, ToolCall},
+    wasm_compat::{WasmBoxedFuture, WasmCompatSend, WasmCompatSync},
+};
+
+use super::Agent;
+
+/// A layer wrapping every turn of an agent: its completion request, the model's response and the
+/// tool calls it triggers, for both [prompt](crate::completion::Prompt) and
+/// [streaming](crate::streaming::StreamingPrompt) requests.
+///
+/// Layers are applied in the order they were registered with
+/// [AgentBuilder::layer](super::AgentBuilder::layer), like an onion: the `before_*` hooks of the
+/// first layer run first, and its `after_*` hooks run last. Every hook does nothing by default.
+///
+/// In streaming mode, [after_completion](Self::after_completion) runs once the model's stream
+/// ended, with the aggregated response.
+///
+/// # Example
+/// ```rust,ignore
+/// struct Temperature(f64);
+///
+/// impl AgentMiddleware for Temperature {
+///     fn before_completion<'a>(
+///         &'a self,
+///         request: &'a mut CompletionRequest,
+///     ) -> WasmBoxedFuture<'a, ()> {
+///         request.temperature = Some(self.0);
+///         Box::pin(async {})
+///     }
+/// }
+///
+/// let agent = AgentBuilder::new(model)
+///     .layer(LoggingLayer)
+///     .layer(Temperature(0.2))
+///     .build();
+/// ```
+pub trait AgentMiddleware: WasmCompatSend + WasmCompatSync {
+    /// Called before a completion request is sent to the model, and may change it.
+    fn before_completion<'a>(
+        &'a self,
+        _request: &'a mut CompletionRequest,
+    ) -> WasmBoxedFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    /// Called with the model's response (without the provider's raw response) and the time the
+    /// model took to answer.
+    fn after_completion<'a>(
+        &'a self,
+        _response: &'a CompletionResponse<()>,
+        _latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    /// Called before a tool call is executed.
+    fn before_tool_call<'a>(&'a self, _tool_call: &'a ToolCall) -> WasmBoxedFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    /// Called with the result sent to the model for a tool call (an error returned by the tool
+    /// being reported as its result), and the time the call took.
+    fn after_tool_call<'a>(
+        &'a self,
+        _tool_call: &'a ToolCall,
+        _result: &'a str,
+        _latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+impl<M> Agent<M>
+where
+    M: CompletionModel,
+{
+    /// Pass `request` through the `before_completion` hook of every layer.
+    pub(crate) async fn before_completion(&self, request: &mut CompletionRequest) {
+        for layer in &self.middleware {
+            layer.before_completion(request).await;
+        }
+    }
+
+    /// Pass the model's response through the `after_completion` hook of every layer.
+    pub(crate) async fn after_completion(
+        &self,
+        choice: &OneOrMany<AssistantContent>,
+        usage: Usage,
+        latency: Duration,
+    ) {
+        if self.middleware.is_empty() {
+            return;
+        }
+
+        let response = CompletionResponse {
+            choice: choice.clone(),
+            usage,
+            raw_response: (),
+        };
+        for layer in self.middleware.iter().rev() {
+            layer.after_completion(&response, latency).await;
+        }
+    }
+
+    pub(crate) async fn before_tool_call(&self, tool_call: &ToolCall) {
+        for layer in &self.middleware {
+            layer.before_tool_call(tool_call).await;
+        }
+    }
+
+    pub(crate) async fn after_tool_call(
+        &self,
+        tool_call: &ToolCall,
+        result: &str,
+        latency: Duration,
+    ) {
+        for layer in self.middleware.iter().rev() {
+            layer.after_tool_call(tool_call, result, latency).await;
+        }
+    }
+}
+
+/// Logs every completion request, response and tool call with [tracing], at the `info` level.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoggingLayer;
+
+impl AgentMiddleware for LoggingLayer {
+    fn before_completion<'a>(
+        &'a self,
+        request: &'a mut CompletionRequest,
+    ) -> WasmBoxedFuture<'a, ()> {
+        let tools: Vec<_> = request
+            .tools
+            .iter()
+            .map(|tool| tool.name.as_str())
+            .collect();
+        tracing::info!(
+            target: "rig::agent::middleware",
+            messages = request.chat_history.len(),
+            documents = request.documents.len(),
+            ?tools,
+            "Sending completion request"
+        );
+        Box::pin(async {})
+    }
+
+    fn after_completion<'a>(
+        &'a self,
+        response: &'a CompletionResponse<()>,
+        latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        let tool_calls: Vec<_> = response
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::ToolCall(tool_call) => Some(tool_call.function.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        tracing::info!(
+            target: "rig::agent::middleware",
+            latency_ms = latency.as_millis() as u64,
+            input_tokens = response.usage.input_tokens,
+            output_tokens = response.usage.output_tokens,
+            ?tool_calls,
+            "Received completion response"
+        );
+        Box::pin(async {})
+    }
+
+    fn before_tool_call<'a>(&'a self, tool_call: &'a ToolCall) -> WasmBoxedFuture<'a, ()> {
+        tracing::info!(
+            target: "rig::agent::middleware",
+            tool = %tool_call.function.name,
+            arguments = %tool_call.function.arguments,
+            "Calling tool"
+        );
+        Box::pin(async {})
+    }
+
+    fn after_tool_call<'a>(
+        &'a self,
+        tool_call: &'a ToolCall,
+        result: &'a str,
+        latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        tracing::info!(
+            target: "rig::agent::middleware",
+            tool = %tool_call.function.name,
+            latency_ms = latency.as_millis() as u64,
+            result_len = result.len(),
+            "Tool call finished"
+        );
+        Box::pin(async {})
+    }
+}
+
+/// Upper bounds of the buckets of a [LatencyHistogram], in milliseconds.
+pub const LATENCY_BUCKETS_MS: [u64; 8] = [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// A histogram of latencies, bucketed by [LATENCY_BUCKETS_MS]. The last bucket counts the
+/// latencies above the largest bound.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// Number of latencies in each bucket, one more than there are bounds
+    pub buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    /// Number of recorded latencies
+    pub count: u64,
+    /// Sum of the recorded latencies
+    pub sum: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += latency;
+    }
+
+    /// The average recorded latency, if any was recorded.
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.sum / self.count as u32)
+    }
+}
+
+/// The metrics collected by a [MetricsLayer].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentMetrics {
+    /// Number of completion requests answered by the model
+    pub completions: u64,
+    /// Token usage summed over all completions
+    pub usage: Usage,
+    /// Latencies of the completion requests
+    pub completion_latency: LatencyHistogram,
+    /// Number of calls of each tool
+    pub tool_calls: BTreeMap<String, u64>,
+    /// Latencies of the calls of each tool
+    pub tool_latency: BTreeMap<String, LatencyHistogram>,
+}
+
+/// Counts completions, tokens and tool calls, and records their latencies in histograms.
+///
+/// Clones share the same metrics, so a clone can be kept to read them while the agent runs.
+///
+/// # Example
+/// ```rust,ignore
+/// let metrics = MetricsLayer::new();
+/// let agent = AgentBuilder::new(model).layer(metrics.clone()).build();
+///
+/// agent.prompt("Design a coating").multi_turn(5).await?;
+/// println!("{:?}", metrics.snapshot().tool_calls);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MetricsLayer(Arc<Mutex<AgentMetrics>>);
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metrics collected so far.
+    pub fn snapshot(&self) -> AgentMetrics {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl AgentMiddleware for MetricsLayer {
+    fn after_completion<'a>(
+        &'a self,
+        response: &'a CompletionResponse<()>,
+        latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        let mut metrics = self.0.lock().unwrap();
+        metrics.completions += 1;
+        metrics.usage += response.usage;
+        metrics.completion_latency.record(latency);
+        Box::pin(async {})
+    }
+
+    fn after_tool_call<'a>(
+        &'a self,
+        tool_call: &'a ToolCall,
+        _result: &'a str,
+        latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        let mut metrics = self.0.lock().unwrap();
+        let name = &tool_call.function.name;
+        *metrics.tool_calls.entry(name.clone()).or_default() += 1;
+        metrics
+            .tool_latency
+            .entry(name.clone())
+            .or_default()
+            .record(latency);
+        Box::pin(async {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{AgentBuilder, stream_collect},
+        completion::{Prompt, ToolDefinition},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[derive(Deserialize)]
+    struct AddArgs {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Math error")]
+    struct MathError;
+
+    struct Adder;
+
+    impl Tool for Adder {
+        const NAME: &'static str = "add";
+        type Error = MathError;
+        type Args = AddArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: "add".to_string(),
+                description: "Add x and y together".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x + args.y)
+        }
+    }
+
+    /// Records its hook invocations, prefixed by its name, in a shared log
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn record(&self, event: String) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {event}", self.name));
+        }
+    }
+
+    impl AgentMiddleware for Recorder {
+        fn before_completion<'a>(
+            &'a self,
+            request: &'a mut CompletionRequest,
+        ) -> WasmBoxedFuture<'a, ()> {
+            self.record(format!("before_completion({})", request.chat_history.len()));
+            // The second layer sees what the first one changed
+            request.temperature = Some(request.temperature.unwrap_or(0.0) + 0.5);
+            Box::pin(async {})
+        }
+
+        fn after_completion<'a>(
+            &'a self,
+            response: &'a CompletionResponse<()>,
+            _latency: Duration,
+        ) -> WasmBoxedFuture<'a, ()> {
+            Box::pin(async move {
+                let content = match response.choice.first() {
+                    AssistantContent::Text(text) => text.text,
+                    AssistantContent::ToolCall(tool_call) => tool_call.function.name,
+                    AssistantContent::Reasoning(_) => "reasoning".to_string(),
+                };
+                self.record(format!("after_completion({content})"));
+            })
+        }
+
+        fn before_tool_call<'a>(&'a self, tool_call: &'a ToolCall) -> WasmBoxedFuture<'a, ()> {
+            self.record(format!("before_tool_call({})", tool_call.function.name));
+            Box::pin(async {})
+        }
+
+        fn after_tool_call<'a>(
+            &'a self,
+            tool_call: &'a ToolCall,
+            result: &'a str,
+            _latency: Duration,
+        ) -> WasmBoxedFuture<'a, ()> {
+            self.record(format!(
+                "after_tool_call({} = {result})",
+                tool_call.function.name
+            ));
+            Box::pin(async {})
+        }
+    }
+
+    fn layered_agent(
+        log: &Arc<Mutex<Vec<String>>>,
+    ) -> (Agent<MockCompletionModel>, MockCompletionModel) {
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "add", json!({"x": 2, "y": 3}))
+            .with_text("5");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Adder)
+            .layer(Recorder {
+                name: "A",
+                log: log.clone(),
+            })
+            .layer(Recorder {
+                name: "B",
+                log: log.clone(),
+            })
+            .build();
+        (agent, model)
+    }
+
+    const EXPECTED_ORDER: [&str; 12] = [
+        "A before_completion(1)",
+        "B before_completion(1)",
+        "B after_completion(add)",
+        "A after_completion(add)",
+        "A before_tool_call(add)",
+        "B before_tool_call(add)",
+        "B after_tool_call(add = 5)",
+        "A after_tool_call(add = 5)",
+        "A before_completion(3)",
+        "B before_completion(3)",
+        "B after_completion(5)",
+        "A after_completion(5)",
+    ];
+
+    #[tokio::test]
+    async fn test_layers_run_in_registration_order() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let (agent, model) = layered_agent(&log);
+
+        let response = agent.prompt("Add 2 and 3").multi_turn(2).await.unwrap();
+
+        assert_eq!(response, "5");
+        assert_eq!(*log.lock().unwrap(), EXPECTED_ORDER);
+        assert_eq!(model.requests()[0].temperature, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_layers_run_in_streaming_mode() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let (agent, model) = layered_agent(&log);
+
+        let (_, final_response) =
+            stream_collect(agent.stream_prompt("Add 2 and 3").multi_turn(2), |_| {})
+                .await
+                .unwrap();
+
+        assert_eq!(final_response.response(), "5");
+        assert_eq!(*log.lock().unwrap(), EXPECTED_ORDER);
+        assert_eq!(model.requests()[1].temperature, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_layer_counts_turns_and_tool_calls() {
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "add", json!({"x": 2, "y": 3}))
+            .with_text("5")
+            .with_usage(Usage {
+                input_tokens: 10,
+                output_tokens: 2,
+                total_tokens: 12,
+            });
+        let metrics = MetricsLayer::new();
+        let agent = AgentBuilder::new(model)
+            .tool(Adder)
+            .layer(metrics.clone())
+            .build();
+
+        agent.prompt("Add 2 and 3").multi_turn(2).await.unwrap();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.completions, 2);
+        assert_eq!(snapshot.usage.total_tokens, 24);
+        assert_eq!(snapshot.completion_latency.count, 2);
+        assert_eq!(snapshot.tool_calls.get("add"), Some(&1));
+        assert_eq!(snapshot.tool_latency["add"].count, 1);
+    }
+
+    #[test]
+    fn test_latency_histogram_buckets() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_millis(50));
+        histogram.record(Duration::from_millis(70));
+        histogram.record(Duration::from_secs(60));
+
+        assert_eq!(histogram.buckets, [1, 1, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.mean(), Some(Duration::from_millis(20_040)));
+    }
+}
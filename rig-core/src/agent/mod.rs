@@ -106,16 +106,56 @@
 //! let response = agent.prompt("What does \"glarb-glarb\" mean?").await
 //!     .expect("Failed to prompt the agent");
 //! ```
+pub(crate) mod approval;
+#[cfg(feature = "axum")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum")))]
+pub mod axum;
+mod budget;
 mod builder;
 mod completion;
+mod deferred_tools;
+mod fallback;
+pub mod history;
+mod middleware;
+mod observer;
 pub(crate) mod prompt_request;
+mod replay;
 mod tool;
+mod tool_hook;
+mod tool_output;
+pub mod tool_policy;
+pub mod transcript;
+mod usage;
+mod validation;
+mod workflow;
 
 pub use crate::message::Text;
+pub use approval::{ApprovalDecision, ApprovalHandle};
+pub use budget::{BudgetLimit, TokenPrice};
 pub use builder::{AgentBuilder, AgentBuilderSimple};
 pub use completion::Agent;
+pub use deferred_tools::ToolProviderError;
+pub use fallback::CompletionRetryPolicy;
+pub use history::{HistoryError, HistoryPolicy, SUMMARY_TAG, TokenEstimator, estimate_tokens};
+pub use middleware::{
+    AgentMetrics, AgentMiddleware, LATENCY_BUCKETS_MS, LatencyHistogram, LoggingLayer, MetricsLayer,
+};
+pub use observer::{AgentEvent, AgentObserver};
 pub use prompt_request::streaming::{
-    FinalResponse, MultiTurnStreamItem, StreamingPromptRequest, stream_to_stdout,
+    FinalResponse, MultiTurnStreamItem, PrintOptions, StreamEvent, StreamingError,
+    StreamingPromptRequest, collect_stream_to_messages, stream_collect, stream_to_channel,
+    stream_to_stdout, stream_to_stdout_verbose, stream_to_stdout_with, stream_to_writer,
 };
 pub use prompt_request::{CancelSignal, PromptRequest, PromptResponse};
 pub use prompt_request::{PromptHook, StreamingPromptHook};
+pub use replay::{
+    RecordedRequest, RecordedResponse, RecordingLayer, ReplayEntry, ReplayError, ReplayModel,
+    ReplayStreamingResponse,
+};
+pub use tool::{AgentTool, AgentToolArgs};
+pub use tool_hook::{HookDecision, ToolCallInfo, ToolHooks, ToolResultInfo};
+pub use tool_output::{ToolOutputPolicy, ToolOutputSlice, ToolOutputStore, ToolOutputStrategy};
+pub use tool_policy::{ToolRetries, ToolRetry, ToolRetryPolicy, ToolTimeouts};
+pub use usage::{UsageAccumulator, UsageBreakdown};
+pub use validation::AnswerValidator;
+pub use workflow::{StageRecord, Workflow, WorkflowError};
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::ToolRetry,
+    completion::Usage,
+    message::ToolCall,
+    wasm_compat::{WasmCompatSend, WasmCompatSync},
+};
+
+/// A lifecycle event of an agent's multi-turn loop, passed to every [AgentObserver] of the agent.
+///
+/// Turns are numbered from 1. A turn is one completion request to the model, followed by the
+/// execution of the tools it called.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// A completion request is about to be sent to the model.
+    TurnStarted { turn: usize },
+    /// The model called a tool, which is about to be executed.
+    ToolCallIssued { turn: usize, tool_call: ToolCall },
+    /// A tool call failed and is about to be retried (see
+    /// [ToolRetryPolicy](crate::agent::ToolRetryPolicy)).
+    ToolCallRetried { turn: usize, retry: ToolRetry },
+    /// A tool call finished (an error returned by the tool is reported as its result).
+    ToolResultReceived {
+        turn: usize,
+        id: String,
+        call_id: Option<String>,
+        result: String,
+    },
+    /// The model's response and all tool calls of the turn have been handled.
+    TurnCompleted { turn: usize, usage: Usage },
+    /// The model answered without calling tools, ending the run. `usage` is summed over all turns.
+    FinalResponse { response: String, usage: Usage },
+}
+
+/// Receives the [AgentEvent]s of an agent's runs, for both [prompt](crate::completion::Prompt)
+/// and [streaming](crate::streaming::StreamingPrompt) requests.
+///
+/// Unlike tracing spans, events are delivered in-process and in order, which makes them easy to
+/// forward to eg. a live dashboard. Unlike [PromptHook](crate::agent::PromptHook)s, observers are
+/// attached to the agent itself and cannot cancel the run. Any `Fn(&AgentEvent)` closure is an
+/// observer.
+///
+/// # Example
+/// ```rust,ignore
+/// let (tx, rx) = std::sync::mpsc::channel();
+///
+/// let agent = AgentBuilder::new(model)
+///     .observer(move |event: &AgentEvent| {
+///         let _ = tx.send(event.clone());
+///     })
+///     .build();
+/// ```
+pub trait AgentObserver: WasmCompatSend + WasmCompatSync {
+    fn on_event(&self, event: &AgentEvent);
+}
+
+impl<F> AgentObserver for F
+where
+    F: Fn(&AgentEvent) + WasmCompatSend + WasmCompatSync,
+{
+    fn on_event(&self, event: &AgentEvent) {
+        self(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{Agent, AgentBuilder, stream_collect},
+        completion::{Prompt, ToolDefinition},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[derive(Deserialize)]
+    struct AddArgs {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Math error")]
+    struct MathError;
+
+    struct Adder;
+
+    impl Tool for Adder {
+        const NAME: &'static str = "add";
+        type Error = MathError;
+        type Args = AddArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: "add".to_string(),
+                description: "Add x and y together".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "x": {"type": "number"},
+                        "y": {"type": "number"}
+                    },
+                    "required": ["x", "y"]
+                }),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x + args.y)
+        }
+    }
+
+    fn usage() -> Usage {
+        Usage {
+            input_tokens: 10,
+            output_tokens: 2,
+            total_tokens: 12,
+        }
+    }
+
+    fn recording_agent() -> (Agent<MockCompletionModel>, Arc<Mutex<Vec<AgentEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "add", json!({"x": 1, "y": 2}))
+            .with_text("The sum is 3")
+            .with_usage(usage());
+        let agent = AgentBuilder::new(model)
+            .tool(Adder)
+            .observer(move |event: &AgentEvent| recorded.lock().unwrap().push(event.clone()))
+            .build();
+
+        (agent, events)
+    }
+
+    fn expected_events() -> Vec<AgentEvent> {
+        vec![
+            AgentEvent::TurnStarted { turn: 1 },
+            AgentEvent::ToolCallIssued {
+                turn: 1,
+                tool_call: ToolCall {
+                    id: "call_1".to_string(),
+                    call_id: None,
+                    function: crate::message::ToolFunction {
+                        name: "add".to_string(),
+                        arguments: json!({"x": 1, "y": 2}),
+                    },
+                },
+            },
+            AgentEvent::ToolResultReceived {
+                turn: 1,
+                id: "call_1".to_string(),
+                call_id: None,
+                result: "3".to_string(),
+            },
+            AgentEvent::TurnCompleted {
+                turn: 1,
+                usage: usage(),
+            },
+            AgentEvent::TurnStarted { turn: 2 },
+            AgentEvent::TurnCompleted {
+                turn: 2,
+                usage: usage(),
+            },
+            AgentEvent::FinalResponse {
+                response: "The sum is 3".to_string(),
+                usage: usage() + usage(),
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_observer_receives_prompt_events() {
+        let (agent, events) = recording_agent();
+
+        let response = agent.prompt("add 1 and 2").multi_turn(3).await.unwrap();
+
+        assert_eq!(response, "The sum is 3");
+        assert_eq!(*events.lock().unwrap(), expected_events());
+    }
+
+    #[tokio::test]
+    async fn test_observer_receives_streaming_events() {
+        let (agent, events) = recording_agent();
+
+        let (_, final_response) =
+            stream_collect(agent.stream_prompt("add 1 and 2").multi_turn(3), |_| {})
+                .await
+                .unwrap();
+
+        assert_eq!(final_response.response(), "The sum is 3");
+        assert_eq!(*events.lock().unwrap(), expected_events());
+    }
+}
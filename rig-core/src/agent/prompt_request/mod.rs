@@ -5,6 +5,7 @@ pub use streaming::StreamingPromptHook;
 use std::{
     future::IntoFuture,
     marker::PhantomData,
+    pin::pin,
     sync::{
         Arc,
         atomic::{AtomicBool, AtomicU64, Ordering},
@@ -12,18 +13,33 @@ use std::{
 };
 use tracing::{Instrument, span::Id};
 
-use futures::{StreamExt, stream};
+use futures::{
+    StreamExt,
+    future::{Either, select},
+    stream,
+};
+use tokio::sync::Notify;
 use tracing::info_span;
 
 use crate::{
     OneOrMany,
-    completion::{Completion, CompletionModel, Message, PromptError, Usage},
-    message::{AssistantContent, UserContent},
+    completion::{
+        Completion, CompletionModel, CompletionResponse, FinishReason, Message, PromptError, Usage,
+        ValidationAttempt,
+    },
+    message::{AssistantContent, ToolCall, UserContent},
     tool::ToolSetError,
     wasm_compat::{WasmBoxedFuture, WasmCompatSend, WasmCompatSync},
 };
 
-use super::Agent;
+use super::{
+    Agent, AgentEvent,
+    approval::approval_unavailable,
+    budget::{Budget, TokenPrice},
+    tool_policy::{ToolFilter, tool_not_allowed},
+    usage::{UsageAccumulator, UsageBreakdown},
+    validation::{AnswerValidation, repair_prompt},
+};
 
 pub trait PromptType {}
 pub struct Standard;
@@ -59,6 +75,20 @@ where
     state: PhantomData<S>,
     /// Optional per-request hook for events
     hook: Option<P>,
+    /// Signal cancelling the request
+    cancel_signal: CancelSignal,
+    /// Tools the request may use
+    tool_filter: ToolFilter,
+    /// Check of the final answer
+    validation: AnswerValidation,
+    /// Provider parameters merged over the agent's
+    additional_params: Option<serde_json::Value>,
+    /// Usage caps of the run
+    budget: Budget,
+    /// Whether answers cut off at the maximum output length are continued
+    auto_continue: bool,
+    /// Whether tool calls are returned to the caller instead of being executed
+    return_tool_calls: bool,
 }
 
 impl<'a, M> PromptRequest<'a, Standard, M, ()>
@@ -70,10 +100,17 @@ where
         Self {
             prompt: prompt.into(),
             chat_history: None,
-            max_depth: 0,
+            max_depth: agent.max_turns,
             agent,
             state: PhantomData,
             hook: None,
+            cancel_signal: CancelSignal::new(),
+            tool_filter: ToolFilter::default(),
+            validation: AnswerValidation::default(),
+            additional_params: None,
+            budget: Budget::default(),
+            auto_continue: false,
+            return_tool_calls: false,
         }
     }
 }
@@ -89,6 +126,9 @@ where
     /// Note: This changes the type of the response from `.send` to return a `PromptResponse` struct
     /// instead of a simple `String`. This is useful for tracking token usage across multiple turns
     /// of conversation.
+    ///
+    /// The response also holds the messages of the run, including the intermediate tool calls and
+    /// results, eg. to persist the conversation.
     pub fn extended_details(self) -> PromptRequest<'a, Extended, M, P> {
         PromptRequest {
             prompt: self.prompt,
@@ -97,6 +137,13 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: self.hook,
+            cancel_signal: self.cancel_signal,
+            tool_filter: self.tool_filter,
+            validation: self.validation,
+            additional_params: self.additional_params,
+            budget: self.budget,
+            auto_continue: self.auto_continue,
+            return_tool_calls: self.return_tool_calls,
         }
     }
     /// Set the maximum depth for multi-turn conversations (ie, the maximum number of turns an LLM can have calling tools before writing a text response).
@@ -109,6 +156,13 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: self.hook,
+            cancel_signal: self.cancel_signal,
+            tool_filter: self.tool_filter,
+            validation: self.validation,
+            additional_params: self.additional_params,
+            budget: self.budget,
+            auto_continue: self.auto_continue,
+            return_tool_calls: self.return_tool_calls,
         }
     }
 
@@ -121,6 +175,13 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: self.hook,
+            cancel_signal: self.cancel_signal,
+            tool_filter: self.tool_filter,
+            validation: self.validation,
+            additional_params: self.additional_params,
+            budget: self.budget,
+            auto_continue: self.auto_continue,
+            return_tool_calls: self.return_tool_calls,
         }
     }
 
@@ -136,29 +197,216 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: Some(hook),
+            cancel_signal: self.cancel_signal,
+            tool_filter: self.tool_filter,
+            validation: self.validation,
+            additional_params: self.additional_params,
+            budget: self.budget,
+            auto_continue: self.auto_continue,
+            return_tool_calls: self.return_tool_calls,
         }
     }
+
+    /// Cancel the request when `signal` is cancelled
+    pub fn with_cancellation(self, signal: CancelSignal) -> PromptRequest<'a, S, M, P> {
+        PromptRequest {
+            cancel_signal: signal,
+            ..self
+        }
+    }
+
+    /// Only let the model use the given tools during this request. The other tools are not sent
+    /// to the model, and calls to them are answered with a refusal instead of being executed.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let response = agent
+    ///     .prompt("What is the status of my tasks?")
+    ///     .allowed_tools(["calphamesh_get_task_status", "calphamesh_list_tasks"])
+    ///     .await?;
+    /// ```
+    pub fn allowed_tools(
+        mut self,
+        tool_names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> PromptRequest<'a, S, M, P> {
+        self.tool_filter.allow(tool_names);
+        self
+    }
+
+    /// Keep the model from using the given tools during this request, as with
+    /// [allowed_tools](Self::allowed_tools).
+    pub fn deny_tools(
+        mut self,
+        tool_names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> PromptRequest<'a, S, M, P> {
+        self.tool_filter.deny(tool_names);
+        self
+    }
+
+    /// Check the final answer with `validator`. A rejected answer is sent back to the model along
+    /// with the error returned by `validator`, up to [max_repair_attempts](Self::max_repair_attempts)
+    /// times (once by default). If the last answer is still rejected, the request fails with
+    /// [PromptError::ValidationError] holding every rejected answer.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let phases = agent
+    ///     .prompt("List the stable phases at 800 K as a JSON object")
+    ///     .validate(|answer| {
+    ///         serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(answer)
+    ///             .map(|_| ())
+    ///             .map_err(|e| format!("the answer must be a JSON object ({e})"))
+    ///     })
+    ///     .max_repair_attempts(2)
+    ///     .await?;
+    /// ```
+    pub fn validate(
+        mut self,
+        validator: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    ) -> PromptRequest<'a, S, M, P> {
+        self.validation.set_validator(Arc::new(validator));
+        self
+    }
+
+    /// Set how many times a final answer rejected by the [validator](Self::validate) is sent back
+    /// to the model. Repair turns do not count against the [multi_turn](Self::multi_turn) limit.
+    pub fn max_repair_attempts(mut self, attempts: usize) -> PromptRequest<'a, S, M, P> {
+        self.validation.set_max_repair_attempts(attempts);
+        self
+    }
+
+    /// Add provider-specific parameters to every completion request of this prompt, eg.
+    /// `json!({"enable_search": true})` for Qwen. They are merged into the agent's
+    /// [additional parameters](crate::agent::AgentBuilder::additional_params), taking precedence
+    /// over them on conflicting keys.
+    pub fn additional_params(mut self, params: serde_json::Value) -> PromptRequest<'a, S, M, P> {
+        self.additional_params = Some(params);
+        self
+    }
+
+    /// End the run with [PromptError::BudgetExceeded] once the agent and its sub-agents used more
+    /// than `tokens` tokens in total. The usage is checked after each turn, so the turn exceeding
+    /// the cap is completed (including its tool calls) but no further completion is requested.
+    pub fn max_total_tokens(mut self, tokens: u64) -> PromptRequest<'a, S, M, P> {
+        self.budget.set_max_total_tokens(tokens);
+        self
+    }
+
+    /// End the run with [PromptError::BudgetExceeded] once the estimated cost of the tokens used
+    /// by the agent and its sub-agents exceeds `cost`, as with
+    /// [max_total_tokens](Self::max_total_tokens). The cost is estimated with the
+    /// [token price](Self::token_price) of the request, and is not checked without one.
+    pub fn max_estimated_cost(mut self, cost: f64) -> PromptRequest<'a, S, M, P> {
+        self.budget.set_max_estimated_cost(cost);
+        self
+    }
+
+    /// Set the token price used to estimate the cost of the run, see
+    /// [max_estimated_cost](Self::max_estimated_cost).
+    pub fn token_price(mut self, price: TokenPrice) -> PromptRequest<'a, S, M, P> {
+        self.budget.set_token_price(price);
+        self
+    }
+
+    /// When the model's answer is cut off because it reached the maximum output length (as
+    /// reported by [CompletionModel::finish_reason]), ask it to continue where it stopped, as long
+    /// as the [multi_turn](Self::multi_turn) limit allows another turn. The returned answer is the
+    /// concatenation of the outputs.
+    pub fn auto_continue(mut self, enabled: bool) -> PromptRequest<'a, S, M, P> {
+        self.auto_continue = enabled;
+        self
+    }
+
+    /// Don't execute the tool calls of the model: the request stops at the first turn calling
+    /// tools, and the [PromptResponse] holds the calls in
+    /// [tool_calls](PromptResponse::tool_calls), eg. to let the user review or edit their
+    /// arguments. The assistant message with the calls is the last of the history (and of the
+    /// response's messages). Once the calls are executed, resume the run with
+    /// [Agent::continue_with_tool_results].
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let mut history = vec![];
+    /// let response = agent
+    ///     .prompt("Compute TiAlN at 1000 K")
+    ///     .with_history(&mut history)
+    ///     .return_tool_calls()
+    ///     .await?;
+    ///
+    /// let results = response
+    ///     .tool_calls
+    ///     .into_iter()
+    ///     .map(|call| {
+    ///         let output = run_after_review(&call);
+    ///         (call, output)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let answer = agent
+    ///     .continue_with_tool_results(&mut history, results)
+    ///     .await?;
+    /// ```
+    pub fn return_tool_calls(self) -> PromptRequest<'a, Extended, M, P> {
+        let mut request = self.extended_details();
+        request.return_tool_calls = true;
+        request
+    }
 }
 
-pub struct CancelSignal(Arc<AtomicBool>);
+/// Cancels a prompt request. Hooks receive the signal of their request, and a signal can be
+/// passed to a request with `with_cancellation` to abort it from the outside, eg. when the user
+/// closes the UI.
+///
+/// A cancelled request stops before its next completion or tool call, dropping any in-flight
+/// completion stream or tool call, and fails with [PromptError::PromptCancelled] carrying the
+/// conversation so far.
+#[derive(Clone)]
+pub struct CancelSignal(Arc<CancelState>);
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl Default for CancelSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl CancelSignal {
-    fn new() -> Self {
-        Self(Arc::new(AtomicBool::new(false)))
+    pub fn new() -> Self {
+        Self(Arc::new(CancelState::default()))
     }
 
     pub fn cancel(&self) {
-        self.0.store(true, Ordering::SeqCst);
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
     }
 
-    fn is_cancelled(&self) -> bool {
-        self.0.load(Ordering::SeqCst)
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the signal is cancelled.
+    pub async fn cancelled(&self) {
+        let notified = self.0.notify.notified();
+        let mut notified = pin!(notified);
+        // Register before checking the flag, so that a concurrent `cancel` cannot be missed
+        notified.as_mut().enable();
+
+        if !self.is_cancelled() {
+            notified.await;
+        }
     }
-}
 
-impl Clone for CancelSignal {
-    fn clone(&self) -> Self {
-        Self(self.0.clone())
+    /// Run `future` unless the signal is cancelled first, in which case `future` is dropped and
+    /// `None` is returned.
+    pub(crate) async fn or_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        match select(pin!(self.cancelled()), pin!(future)).await {
+            Either::Left(_) => None,
+            Either::Right((output, _)) => Some(output),
+        }
     }
 }
 
@@ -258,7 +506,16 @@ where
 #[derive(Debug, Clone)]
 pub struct PromptResponse {
     pub output: String,
+    /// Usage of the agent and of the sub-agents it called as tools
     pub total_usage: Usage,
+    /// Usage per turn of the agent and per sub-agent
+    pub usage_breakdown: UsageBreakdown,
+    /// The messages added to the conversation by the request, starting with the prompt and
+    /// including the intermediate tool calls and results
+    pub messages: Vec<Message>,
+    /// The tool calls the request stopped at without executing them, see
+    /// [PromptRequest::return_tool_calls]. Empty when the model answered.
+    pub tool_calls: Vec<ToolCall>,
 }
 
 impl PromptResponse {
@@ -266,8 +523,28 @@ impl PromptResponse {
         Self {
             output: output.into(),
             total_usage,
+            usage_breakdown: UsageBreakdown::default(),
+            messages: Vec::new(),
+            tool_calls: Vec::new(),
         }
     }
+
+    /// Set the usage breakdown of the response, adding the usage of its sub-agents to the total.
+    pub(crate) fn with_usage_breakdown(mut self, usage_breakdown: UsageBreakdown) -> Self {
+        self.total_usage += usage_breakdown.sub_agent_usage();
+        self.usage_breakdown = usage_breakdown;
+        self
+    }
+
+    pub(crate) fn with_messages(mut self, messages: Vec<Message>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    pub(crate) fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = tool_calls;
+        self
+    }
 }
 
 impl<M, P> PromptRequest<'_, Extended, M, P>
@@ -298,16 +575,22 @@ where
         } else {
             &mut vec![self.prompt.to_owned()]
         };
+        // Index of the prompt, where the messages added by this request start
+        let transcript_start = chat_history.len() - 1;
 
         if let Some(text) = self.prompt.rag_text() {
             agent_span.record("gen_ai.prompt", text);
         }
 
-        let cancel_sig = CancelSignal::new();
+        let cancel_sig = self.cancel_signal.clone();
 
         let mut current_max_depth = 0;
         let mut usage = Usage::new();
+        let usage_acc = UsageAccumulator::new();
         let current_span_id: AtomicU64 = AtomicU64::new(0);
+        let mut rejected_answers = Vec::new();
+        // Outputs of the turns cut off at the maximum output length, see `auto_continue`
+        let mut truncated_answer = String::new();
 
         // We need to do at least 2 loops for 1 roundtrip (user expects normal message)
         let last_prompt = loop {
@@ -316,11 +599,29 @@ where
                 .cloned()
                 .expect("there should always be at least one message in the chat history");
 
-            if current_max_depth > self.max_depth + 1 {
+            // Repair turns do not count against the turn limit
+            if current_max_depth > self.max_depth + 1 + rejected_answers.len() {
                 break prompt;
             }
 
+            if cancel_sig.is_cancelled() {
+                return Err(PromptError::prompt_cancelled(chat_history.to_vec()));
+            }
+
+            let run_usage = usage_acc.total_usage();
+            if let Some(limit) = self.budget.exceeded(&run_usage) {
+                tracing::warn!("The run exceeded {limit} after {current_max_depth} turns");
+                return Err(PromptError::budget_exceeded(
+                    limit,
+                    chat_history.to_vec(),
+                    run_usage,
+                ));
+            }
+
             current_max_depth += 1;
+            agent.notify(AgentEvent::TurnStarted {
+                turn: current_max_depth,
+            });
 
             if self.max_depth > 1 {
                 tracing::info!(
@@ -347,9 +648,13 @@ where
                 parent: &span,
                 "chat",
                 gen_ai.operation.name = "chat",
+                gen_ai.agent.name = agent.name(),
                 gen_ai.system_instructions = self.agent.preamble,
                 gen_ai.provider.name = tracing::field::Empty,
                 gen_ai.request.model = tracing::field::Empty,
+                gen_ai.request.temperature = tracing::field::Empty,
+                gen_ai.request.max_tokens = tracing::field::Empty,
+                gen_ai.request.top_p = tracing::field::Empty,
                 gen_ai.response.id = tracing::field::Empty,
                 gen_ai.response.model = tracing::field::Empty,
                 gen_ai.usage.output_tokens = tracing::field::Empty,
@@ -369,21 +674,43 @@ where
                 current_span_id.store(id.into_u64(), Ordering::SeqCst);
             };
 
-            let resp = agent
-                .completion(
-                    prompt.clone(),
-                    chat_history[..chat_history.len() - 1].to_vec(),
-                )
-                .await?
-                .send()
-                .instrument(chat_span.clone())
-                .await?;
+            let history = chat_history[..chat_history.len() - 1].to_vec();
+            let request = async {
+                let mut request = agent
+                    .completion(prompt.clone(), history)
+                    .await?
+                    .retain_tools(|tool| self.tool_filter.allows(&tool.name));
+                if let Some(params) = &self.additional_params {
+                    request = request.additional_params(params.clone());
+                }
+                agent
+                    .send_completion(request.build())
+                    .instrument(chat_span.clone())
+                    .await
+            };
+            let Some(resp) = cancel_sig.or_cancelled(request).await else {
+                return Err(PromptError::prompt_cancelled(chat_history.to_vec()));
+            };
+            let (mut resp, model_index) = resp?;
 
             usage += resp.usage;
+            usage_acc.record_turn(current_max_depth, resp.usage);
+            if model_index > 0 {
+                usage_acc.record_fallback(current_max_depth, model_index);
+            }
 
+            // The raw response is only available when the agent's own model answered
+            let finish_reason = resp.raw_response.as_ref().and_then(M::finish_reason);
             if let Some(ref hook) = self.hook {
-                hook.on_completion_response(&prompt, &resp, cancel_sig.clone())
-                    .await;
+                if let Some(raw_response) = resp.raw_response.take() {
+                    let response = CompletionResponse {
+                        choice: resp.choice.clone(),
+                        usage: resp.usage,
+                        raw_response,
+                    };
+                    hook.on_completion_response(&prompt, &response, cancel_sig.clone())
+                        .await;
+                }
                 if cancel_sig.is_cancelled() {
                     return Err(PromptError::prompt_cancelled(chat_history.to_vec()));
                 }
@@ -412,21 +739,98 @@ where
                     .collect::<Vec<_>>()
                     .join("\n");
 
+                let truncated = self.auto_continue && finish_reason == Some(FinishReason::Length);
+                if truncated && current_max_depth <= self.max_depth + rejected_answers.len() {
+                    tracing::info!("The answer was cut off, asking the model to continue");
+                    agent.notify(AgentEvent::TurnCompleted {
+                        turn: current_max_depth,
+                        usage: resp.usage,
+                    });
+                    truncated_answer.push_str(&merged_texts);
+                    chat_history.push(continue_prompt());
+                    continue;
+                }
+                let merged_texts = std::mem::take(&mut truncated_answer) + &merged_texts;
+
                 if self.max_depth > 1 {
                     tracing::info!("Depth reached: {}/{}", current_max_depth, self.max_depth);
                 }
 
+                if let Err(error) = self.validation.check(&merged_texts) {
+                    tracing::warn!("The answer was rejected by the validator: {error}");
+                    agent.notify(AgentEvent::TurnCompleted {
+                        turn: current_max_depth,
+                        usage: resp.usage,
+                    });
+                    rejected_answers.push(ValidationAttempt {
+                        answer: merged_texts,
+                        error: error.clone(),
+                    });
+
+                    if !self.validation.may_repair(rejected_answers.len()) {
+                        return Err(PromptError::validation_failed(
+                            rejected_answers,
+                            chat_history.to_vec(),
+                        ));
+                    }
+                    chat_history.push(repair_prompt(&error));
+                    continue;
+                }
+
                 agent_span.record("gen_ai.completion", &merged_texts);
                 agent_span.record("gen_ai.usage.input_tokens", usage.input_tokens);
                 agent_span.record("gen_ai.usage.output_tokens", usage.output_tokens);
 
+                agent.notify(AgentEvent::TurnCompleted {
+                    turn: current_max_depth,
+                    usage: resp.usage,
+                });
+                agent.notify(AgentEvent::FinalResponse {
+                    response: merged_texts.clone(),
+                    usage,
+                });
+
                 // If there are no tool calls, depth is not relevant, we can just return the merged text response.
-                return Ok(PromptResponse::new(merged_texts, usage));
+                return Ok(PromptResponse::new(merged_texts, usage)
+                    .with_usage_breakdown(usage_acc.breakdown())
+                    .with_messages(chat_history[transcript_start..].to_vec()));
+            }
+
+            let tool_calls: Vec<ToolCall> = tool_calls
+                .into_iter()
+                .filter_map(|content| match content {
+                    AssistantContent::ToolCall(tool_call) => Some(tool_call.clone()),
+                    _ => None,
+                })
+                .collect();
+
+            if self.return_tool_calls {
+                agent.notify(AgentEvent::TurnCompleted {
+                    turn: current_max_depth,
+                    usage: resp.usage,
+                });
+                let text = texts
+                    .into_iter()
+                    .filter_map(|content| match content {
+                        AssistantContent::Text(text) => Some(text.text.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                return Ok(PromptResponse::new(text, usage)
+                    .with_usage_breakdown(usage_acc.breakdown())
+                    .with_messages(chat_history[transcript_start..].to_vec())
+                    .with_tool_calls(tool_calls));
             }
 
+            // Up to `tool_concurrency` tool calls run at once; `buffered` keeps the results in the
+            // order of the calls.
             let hook = self.hook.clone();
+            let tool_usage = &usage_acc;
+            let tool_filter = &self.tool_filter;
             let tool_content = stream::iter(tool_calls)
-                .then(|choice| {
+                .map(|tool_call| {
                     let hook1 = hook.clone();
                     let hook2 = hook.clone();
 
@@ -455,87 +859,811 @@ where
                     };
 
                     async move {
-                        if let AssistantContent::ToolCall(tool_call) = choice {
-                            let tool_name = &tool_call.function.name;
-                            let args = tool_call.function.arguments.to_string();
-                            let tool_span = tracing::Span::current();
-                            tool_span.record("gen_ai.tool.name", tool_name);
-                            tool_span.record("gen_ai.tool.call.id", &tool_call.id);
-                            tool_span.record("gen_ai.tool.call.arguments", &args);
-                            if let Some(hook) = hook1 {
-                                hook.on_tool_call(tool_name, &args, cancel_sig1.clone())
-                                    .await;
-                                if cancel_sig1.is_cancelled() {
-                                    return Err(ToolSetError::Interrupted);
-                                }
-                            }
-                            let output =
-                                match agent.tool_server_handle.call_tool(tool_name, &args).await {
-                                    Ok(res) => res,
-                                    Err(e) => {
-                                        tracing::warn!("Error while executing tool: {e}");
-                                        e.to_string()
-                                    }
-                                };
-                            if let Some(hook) = hook2 {
-                                hook.on_tool_result(
-                                    tool_name,
-                                    &args,
-                                    &output.to_string(),
-                                    cancel_sig2.clone(),
-                                )
+                        let tool_name = &tool_call.function.name;
+                        let args = tool_call.function.arguments.to_string();
+                        let tool_span = tracing::Span::current();
+                        tool_span.record("gen_ai.tool.name", tool_name);
+                        tool_span.record("gen_ai.tool.call.id", &tool_call.id);
+                        tool_span.record("gen_ai.tool.call.arguments", &args);
+                        agent.notify(AgentEvent::ToolCallIssued {
+                            turn: current_max_depth,
+                            tool_call: tool_call.clone(),
+                        });
+                        if let Some(hook) = hook1 {
+                            hook.on_tool_call(tool_name, &args, cancel_sig1.clone())
                                 .await;
-
-                                if cancel_sig2.is_cancelled() {
-                                    return Err(ToolSetError::Interrupted);
-                                }
-                            }
-                            tool_span.record("gen_ai.tool.call.result", &output);
-                            tracing::info!(
-                                "executed tool {tool_name} with args {args}. result: {output}"
-                            );
-                            if let Some(call_id) = tool_call.call_id.clone() {
-                                Ok(UserContent::tool_result_with_call_id(
-                                    tool_call.id.clone(),
-                                    call_id,
-                                    OneOrMany::one(output.into()),
-                                ))
-                            } else {
-                                Ok(UserContent::tool_result(
-                                    tool_call.id.clone(),
-                                    OneOrMany::one(output.into()),
-                                ))
+                            if cancel_sig1.is_cancelled() {
+                                return Err(ToolSetError::Interrupted);
                             }
+                        }
+                        let output = if !tool_filter.allows(tool_name) {
+                            tool_not_allowed(tool_name)
+                        } else if agent.requires_approval(tool_name) {
+                            approval_unavailable(tool_name)
                         } else {
-                            unreachable!(
-                                "This should never happen as we already filtered for `ToolCall`"
+                            // Sub-agents running in the tool record their usage in `tool_usage`
+                            let call = agent.call_tool(&tool_call, |retry| {
+                                agent.notify(AgentEvent::ToolCallRetried {
+                                    turn: current_max_depth,
+                                    retry: retry.clone(),
+                                })
+                            });
+                            tool_usage.scope(call).await
+                        };
+                        if let Some(hook) = hook2 {
+                            hook.on_tool_result(
+                                tool_name,
+                                &args,
+                                &output.to_string(),
+                                cancel_sig2.clone(),
                             )
+                            .await;
+
+                            if cancel_sig2.is_cancelled() {
+                                return Err(ToolSetError::Interrupted);
+                            }
+                        }
+                        tool_span.record("gen_ai.tool.call.result", &output);
+                        agent.notify(AgentEvent::ToolResultReceived {
+                            turn: current_max_depth,
+                            id: tool_call.id.clone(),
+                            call_id: tool_call.call_id.clone(),
+                            result: output.clone(),
+                        });
+                        tracing::info!(
+                            "executed tool {tool_name} with args {args}. result: {output}"
+                        );
+                        if let Some(call_id) = tool_call.call_id.clone() {
+                            Ok(UserContent::tool_result_with_call_id(
+                                tool_call.id.clone(),
+                                call_id,
+                                OneOrMany::one(output.into()),
+                            ))
+                        } else {
+                            Ok(UserContent::tool_result(
+                                tool_call.id.clone(),
+                                OneOrMany::one(output.into()),
+                            ))
                         }
                     }
                     .instrument(tool_span)
                 })
-                .collect::<Vec<Result<UserContent, ToolSetError>>>()
-                .await
-                .into_iter()
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(|e| {
-                    if matches!(e, ToolSetError::Interrupted) {
-                        PromptError::prompt_cancelled(chat_history.to_vec())
-                    } else {
-                        e.into()
-                    }
-                })?;
+                .buffered(agent.tool_concurrency.max(1))
+                .collect::<Vec<Result<UserContent, ToolSetError>>>();
+            let tool_content = match cancel_sig.or_cancelled(tool_content).await {
+                Some(tool_content) => tool_content.into_iter().collect::<Result<Vec<_>, _>>(),
+                None => Err(ToolSetError::Interrupted),
+            };
+            let tool_content = match tool_content {
+                Ok(tool_content) => tool_content,
+                // Cancelled by the signal or by a hook: the transcript ends with the unanswered
+                // tool calls, which are dropped from the history so that it can be sent again
+                Err(ToolSetError::Interrupted) => {
+                    let transcript = chat_history.to_vec();
+                    chat_history.pop();
+                    return Err(PromptError::prompt_cancelled(transcript));
+                }
+                Err(e) => return Err(e.into()),
+            };
 
             chat_history.push(Message::User {
                 content: OneOrMany::many(tool_content).expect("There is atleast one tool call"),
             });
+
+            agent.notify(AgentEvent::TurnCompleted {
+                turn: current_max_depth,
+                usage: resp.usage,
+            });
         };
 
         // If we reach here, we never resolved the final tool call. We need to do ... something.
-        Err(PromptError::MaxDepthError {
-            max_depth: self.max_depth,
-            chat_history: Box::new(chat_history.clone()),
-            prompt: last_prompt,
+        Err(PromptError::max_depth(
+            self.max_depth,
+            chat_history.clone(),
+            last_prompt,
+            usage_acc.total_usage(),
+        ))
+    }
+}
+
+/// The message asking the model to continue an answer cut off at the maximum output length.
+pub(crate) fn continue_prompt() -> Message {
+    Message::user("Your answer was cut off. Continue exactly where you stopped, without repeating.")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{AgentBuilder, AgentEvent, MultiTurnStreamItem, StreamingError, stream_collect},
+        completion::{Prompt, ToolDefinition},
+        message::{ToolCall, ToolFunction, ToolResultContent},
+        streaming::{StreamedAssistantContent, StreamingPrompt},
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    /// Tracks how many tool calls are running at the same time.
+    #[derive(Default)]
+    struct Concurrency {
+        running: AtomicU64,
+        max_running: AtomicU64,
+    }
+
+    #[derive(Deserialize)]
+    struct SleepArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Sleep error")]
+    struct SleepError;
+
+    /// A tool that sleeps for `millis` and returns it.
+    struct Sleeper {
+        name: &'static str,
+        millis: u64,
+        concurrency: Arc<Concurrency>,
+    }
+
+    impl Tool for Sleeper {
+        const NAME: &'static str = "sleep";
+        type Error = SleepError;
+        type Args = SleepArgs;
+        type Output = u64;
+
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: self.name.to_string(),
+                description: format!("Sleep for {}ms", self.millis),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            let running = self.concurrency.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.concurrency
+                .max_running
+                .fetch_max(running, Ordering::SeqCst);
+
+            tokio::time::sleep(Duration::from_millis(self.millis)).await;
+
+            self.concurrency.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(self.millis)
+        }
+    }
+
+    fn tool_call(id: &str, name: &str) -> AssistantContent {
+        AssistantContent::ToolCall(ToolCall {
+            id: id.to_string(),
+            call_id: None,
+            function: ToolFunction {
+                name: name.to_string(),
+                arguments: json!({}),
+            },
         })
     }
+
+    fn parallel_agent(
+        max_concurrency: usize,
+    ) -> (
+        Agent<MockCompletionModel>,
+        MockCompletionModel,
+        Arc<Concurrency>,
+    ) {
+        let concurrency = Arc::new(Concurrency::default());
+        let model = MockCompletionModel::new()
+            .with_turn(vec![
+                tool_call("call_slow", "slow"),
+                tool_call("call_fast", "fast"),
+            ])
+            .with_text("done");
+
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Sleeper {
+                name: "slow",
+                millis: 100,
+                concurrency: concurrency.clone(),
+            })
+            .tool(Sleeper {
+                name: "fast",
+                millis: 10,
+                concurrency: concurrency.clone(),
+            })
+            .parallel_tool_calls(max_concurrency)
+            .build();
+
+        (agent, model, concurrency)
+    }
+
+    /// The tool result ids sent back to the model in its second request, in order.
+    fn tool_result_order(model: &MockCompletionModel) -> Vec<(String, String)> {
+        let requests = model.requests();
+        requests[1]
+            .chat_history
+            .iter()
+            .flat_map(|message| match message {
+                Message::User { content } => content.iter().cloned().collect(),
+                _ => vec![],
+            })
+            .filter_map(|content| match content {
+                UserContent::ToolResult(result) => match result.content.first() {
+                    ToolResultContent::Text(text) => Some((result.id, text.text)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn expected_order() -> Vec<(String, String)> {
+        vec![
+            ("call_slow".to_string(), "100".to_string()),
+            ("call_fast".to_string(), "10".to_string()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_parallel_tool_calls_keep_call_order() {
+        let (agent, model, concurrency) = parallel_agent(2);
+
+        let response = agent.prompt("sleep").multi_turn(3).await.unwrap();
+
+        assert_eq!(response, "done");
+        assert_eq!(concurrency.max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(tool_result_order(&model), expected_order());
+    }
+
+    #[tokio::test]
+    async fn test_streaming_parallel_tool_calls_keep_call_order() {
+        let (agent, model, concurrency) = parallel_agent(2);
+
+        let (messages, _) = stream_collect(agent.stream_prompt("sleep").multi_turn(3), |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(concurrency.max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(tool_result_order(&model), expected_order());
+        assert_eq!(messages.last(), Some(&Message::assistant("done")));
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_sequential_by_default() {
+        let (agent, model, concurrency) = parallel_agent(1);
+
+        agent.prompt("sleep").multi_turn(3).await.unwrap();
+
+        assert_eq!(concurrency.max_running.load(Ordering::SeqCst), 1);
+        assert_eq!(tool_result_order(&model), expected_order());
+    }
+
+    fn sleeper(name: &'static str, millis: u64) -> Sleeper {
+        Sleeper {
+            name,
+            millis,
+            concurrency: Arc::new(Concurrency::default()),
+        }
+    }
+
+    fn cancelled_history(error: &PromptError) -> Vec<Message> {
+        match error {
+            PromptError::PromptCancelled { chat_history } => chat_history.to_vec(),
+            other => panic!("expected a cancelled prompt, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_between_turns() {
+        let signal = CancelSignal::new();
+        let observer_signal = signal.clone();
+        let model = MockCompletionModel::new()
+            .with_turn(vec![tool_call("call_1", "fast")])
+            .with_text("done");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(sleeper("fast", 1))
+            .observer(move |event: &AgentEvent| {
+                if matches!(event, AgentEvent::TurnCompleted { .. }) {
+                    observer_signal.cancel();
+                }
+            })
+            .build();
+
+        let error = agent
+            .prompt("sleep")
+            .multi_turn(3)
+            .with_cancellation(signal)
+            .await
+            .unwrap_err();
+
+        assert_eq!(model.requests().len(), 1);
+        let history = cancelled_history(&error);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0], Message::user("sleep"));
+        assert!(matches!(
+            &history[2],
+            Message::User { content } if matches!(content.first(), UserContent::ToolResult(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_during_tool_call_cleans_up_history() {
+        let signal = CancelSignal::new();
+        let model = MockCompletionModel::new()
+            .with_turn(vec![tool_call("call_1", "slow")])
+            .with_text("done");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(sleeper("slow", 10_000))
+            .build();
+
+        let canceller = signal.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            canceller.cancel();
+        });
+
+        let mut history = Vec::new();
+        let error = agent
+            .prompt("sleep")
+            .with_history(&mut history)
+            .multi_turn(3)
+            .with_cancellation(signal)
+            .await
+            .unwrap_err();
+
+        // The transcript ends with the unanswered tool call, which is not kept in the history
+        let transcript = cancelled_history(&error);
+        assert_eq!(transcript.len(), 2);
+        assert!(matches!(&transcript[1], Message::Assistant { .. }));
+        assert_eq!(history, vec![Message::user("sleep")]);
+        assert_eq!(model.requests().len(), 1);
+    }
+
+    /// A hook cancelling the request when a tool is called.
+    #[derive(Clone)]
+    struct CancelOnToolCall;
+
+    impl<M: CompletionModel> PromptHook<M> for CancelOnToolCall {
+        async fn on_tool_call(&self, _tool_name: &str, _args: &str, cancel_sig: CancelSignal) {
+            cancel_sig.cancel();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_from_hook_cleans_up_history() {
+        let model = MockCompletionModel::new()
+            .with_turn(vec![tool_call("call_1", "fast")])
+            .with_text("done");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(sleeper("fast", 1))
+            .build();
+
+        let mut history = Vec::new();
+        let error = agent
+            .prompt("sleep")
+            .with_history(&mut history)
+            .multi_turn(3)
+            .with_hook(CancelOnToolCall)
+            .await
+            .unwrap_err();
+
+        // Same state as when the request is cancelled by its signal during the tool call
+        let transcript = cancelled_history(&error);
+        assert_eq!(transcript.len(), 2);
+        assert!(matches!(&transcript[1], Message::Assistant { .. }));
+        assert_eq!(history, vec![Message::user("sleep")]);
+        assert_eq!(model.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_mid_stream() {
+        let signal = CancelSignal::new();
+        let model = MockCompletionModel::new().with_turn(vec![
+            AssistantContent::text("The liquidus is "),
+            AssistantContent::text("at 650 C."),
+        ]);
+        let agent = AgentBuilder::new(model).build();
+
+        let mut stream = agent
+            .stream_prompt("liquidus?")
+            .with_cancellation(signal.clone())
+            .await;
+
+        let mut texts = Vec::new();
+        let mut error = None;
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(
+                    text,
+                ))) => {
+                    texts.push(text.text);
+                    signal.cancel();
+                }
+                Ok(_) => {}
+                Err(e) => error = Some(e),
+            }
+        }
+
+        assert_eq!(texts, vec!["The liquidus is "]);
+        let Some(StreamingError::Prompt(error)) = error else {
+            panic!("expected the stream to be cancelled");
+        };
+        assert_eq!(
+            cancelled_history(&error),
+            vec![
+                Message::user("liquidus?"),
+                Message::assistant("The liquidus is ")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extended_details_returns_transcript() {
+        let (agent, _, _) = parallel_agent(2);
+        let mut history = vec![Message::user("earlier"), Message::assistant("noted")];
+
+        let response = agent
+            .prompt("run the tools")
+            .with_history(&mut history)
+            .multi_turn(3)
+            .extended_details()
+            .await
+            .unwrap();
+
+        let tool_result = |id: &str, text: &str| {
+            UserContent::tool_result(id, OneOrMany::one(ToolResultContent::text(text)))
+        };
+        let expected = vec![
+            Message::user("run the tools"),
+            Message::Assistant {
+                id: None,
+                content: OneOrMany::many(vec![
+                    tool_call("call_slow", "slow"),
+                    tool_call("call_fast", "fast"),
+                ])
+                .unwrap(),
+            },
+            Message::User {
+                content: OneOrMany::many(vec![
+                    tool_result("call_slow", "100"),
+                    tool_result("call_fast", "10"),
+                ])
+                .unwrap(),
+            },
+            Message::assistant("done"),
+        ];
+        assert_eq!(response.output, "done");
+        assert_eq!(response.messages, expected);
+        assert_eq!(history[2..], expected[..]);
+    }
+
+    fn requested_tools(model: &MockCompletionModel) -> Vec<String> {
+        model.requests()[0]
+            .tools
+            .iter()
+            .map(|tool| tool.name.clone())
+            .collect()
+    }
+
+    fn expected_filtered_results() -> Vec<(String, String)> {
+        vec![
+            ("call_slow".to_string(), tool_not_allowed("slow")),
+            ("call_fast".to_string(), "10".to_string()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_denied_tools_are_hidden_and_refused() {
+        let (agent, model, concurrency) = parallel_agent(1);
+
+        agent
+            .prompt("sleep")
+            .multi_turn(3)
+            .deny_tools(["slow"])
+            .await
+            .unwrap();
+
+        assert_eq!(requested_tools(&model), ["fast"]);
+        assert_eq!(tool_result_order(&model), expected_filtered_results());
+        assert_eq!(concurrency.max_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_tool_filter() {
+        let (agent, model, _) = parallel_agent(1);
+
+        stream_collect(
+            agent
+                .stream_prompt("sleep")
+                .multi_turn(3)
+                .allowed_tools(["fast", "slow"])
+                .deny_tools(["slow"]),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(requested_tools(&model), ["fast"]);
+        assert_eq!(tool_result_order(&model), expected_filtered_results());
+    }
+
+    fn json_object(answer: &str) -> Result<(), String> {
+        match serde_json::from_str(answer) {
+            Ok(serde_json::Value::Object(_)) => Ok(()),
+            _ => Err("the answer must be a JSON object".to_string()),
+        }
+    }
+
+    fn repaired_transcript() -> Vec<Message> {
+        vec![
+            Message::user("list the phases"),
+            Message::assistant("FCC and BCC"),
+            repair_prompt("the answer must be a JSON object"),
+            Message::assistant(r#"{"phases": ["FCC", "BCC"]}"#),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_rejected_answer_is_repaired() {
+        let model = MockCompletionModel::new()
+            .with_text("FCC and BCC")
+            .with_text(r#"{"phases": ["FCC", "BCC"]}"#);
+        let agent = AgentBuilder::new(model.clone()).build();
+
+        let response = agent
+            .prompt("list the phases")
+            .validate(json_object)
+            .extended_details()
+            .await
+            .unwrap();
+
+        assert_eq!(response.output, r#"{"phases": ["FCC", "BCC"]}"#);
+        assert_eq!(response.messages, repaired_transcript());
+        assert_eq!(model.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_rejected_answer_is_repaired() {
+        let model = MockCompletionModel::new()
+            .with_text("FCC and BCC")
+            .with_text(r#"{"phases": ["FCC", "BCC"]}"#);
+        let agent = AgentBuilder::new(model.clone()).build();
+
+        let mut rejections = Vec::new();
+        let (messages, final_response) = stream_collect(
+            agent.stream_prompt("list the phases").validate(json_object),
+            |item| {
+                if let MultiTurnStreamItem::AnswerRejected { answer, error } = item {
+                    rejections.push((answer.clone(), error.clone()));
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(final_response.response(), r#"{"phases": ["FCC", "BCC"]}"#);
+        assert_eq!(
+            rejections,
+            [(
+                "FCC and BCC".to_string(),
+                "the answer must be a JSON object".to_string()
+            )]
+        );
+        assert_eq!(messages, repaired_transcript());
+    }
+
+    #[tokio::test]
+    async fn test_validation_error_holds_every_attempt() {
+        let model = MockCompletionModel::new()
+            .with_text("FCC")
+            .with_text("FCC and BCC")
+            .with_text("FCC, BCC and liquid");
+        let agent = AgentBuilder::new(model.clone()).build();
+
+        let error = agent
+            .prompt("list the phases")
+            .validate(json_object)
+            .max_repair_attempts(1)
+            .await
+            .unwrap_err();
+
+        let (attempts, chat_history) = match error {
+            PromptError::ValidationError {
+                attempts,
+                chat_history,
+            } => (attempts, chat_history),
+            other => panic!("expected a validation error, got {other:?}"),
+        };
+        let answers = attempts
+            .iter()
+            .map(|attempt| attempt.answer.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(answers, ["FCC", "FCC and BCC"]);
+        assert!(
+            attempts
+                .iter()
+                .all(|attempt| attempt.error == "the answer must be a JSON object")
+        );
+        assert_eq!(chat_history.len(), 4);
+        assert_eq!(model.requests().len(), 2);
+    }
+
+    fn agent_with_params(model: &MockCompletionModel) -> Agent<MockCompletionModel> {
+        AgentBuilder::new(model.clone())
+            .additional_params(json!({"top_p": 0.8, "enable_search": true}))
+            .build()
+    }
+
+    fn request_params() -> serde_json::Value {
+        json!({"top_p": 0.5, "seed": 42})
+    }
+
+    fn merged_params() -> Option<serde_json::Value> {
+        Some(json!({"top_p": 0.5, "enable_search": true, "seed": 42}))
+    }
+
+    #[tokio::test]
+    async fn test_request_params_take_precedence_over_agent_params() {
+        let model = MockCompletionModel::new();
+        let agent = agent_with_params(&model);
+
+        agent.prompt("hi").await.unwrap();
+        agent
+            .prompt("hi")
+            .additional_params(request_params())
+            .await
+            .unwrap();
+
+        let requests = model.requests();
+        assert_eq!(
+            requests[0].additional_params,
+            Some(json!({"top_p": 0.8, "enable_search": true}))
+        );
+        assert_eq!(requests[1].additional_params, merged_params());
+    }
+
+    #[tokio::test]
+    async fn test_streaming_request_params_take_precedence_over_agent_params() {
+        let model = MockCompletionModel::new();
+        let agent = agent_with_params(&model);
+
+        stream_collect(
+            agent
+                .stream_prompt("hi")
+                .additional_params(request_params()),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(model.requests()[0].additional_params, merged_params());
+    }
+
+    /// Records the agent name of every span created with a `gen_ai.agent.name` field, along with
+    /// the span name.
+    #[derive(Clone, Default)]
+    struct AgentNameCapture(Arc<std::sync::Mutex<Vec<(String, String)>>>);
+
+    struct AgentNameVisitor<'a>(&'a mut Option<String>);
+
+    impl tracing::field::Visit for AgentNameVisitor<'_> {
+        fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            if field.name() == "gen_ai.agent.name" {
+                *self.0 = Some(value.to_string());
+            }
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for AgentNameCapture {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut agent_name = None;
+            attrs.record(&mut AgentNameVisitor(&mut agent_name));
+            if let Some(agent_name) = agent_name {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((attrs.metadata().name().to_string(), agent_name));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_spans_record_agent_name() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let capture = AgentNameCapture::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+        let agent = AgentBuilder::new(MockCompletionModel::new())
+            .name("thermo_expert")
+            .build();
+        agent.prompt("hi").await.unwrap();
+        stream_collect(agent.stream_prompt("hi"), |_| {})
+            .await
+            .unwrap();
+
+        let spans = capture.0.lock().unwrap();
+        for span_name in ["chat", "chat_streaming"] {
+            assert!(
+                spans.contains(&(span_name.to_string(), "thermo_expert".to_string())),
+                "no {span_name} span recorded the agent name: {spans:?}"
+            );
+        }
+    }
+
+    /// An agent submitting a task once, then answering.
+    fn submitting_agent() -> (Agent<MockCompletionModel>, MockCompletionModel) {
+        let model = MockCompletionModel::new()
+            .with_turn(vec![
+                AssistantContent::text("Submitting the task."),
+                tool_call("call_1", "calphamesh_submit_point_task"),
+            ])
+            .with_text("task submitted");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(sleeper("calphamesh_submit_point_task", 1))
+            .build();
+        (agent, model)
+    }
+
+    #[tokio::test]
+    async fn test_returned_tool_calls_resume_like_automatic_execution() {
+        let (automatic, automatic_model) = submitting_agent();
+        let expected = automatic.prompt("submit").multi_turn(2).await.unwrap();
+
+        let (manual, manual_model) = submitting_agent();
+        let mut history = Vec::new();
+        let response = manual
+            .prompt("submit")
+            .with_history(&mut history)
+            .return_tool_calls()
+            .await
+            .unwrap();
+
+        assert_eq!(response.output, "Submitting the task.");
+        assert_eq!(response.messages, history);
+        assert!(matches!(history.last(), Some(Message::Assistant { .. })));
+        assert_eq!(manual_model.requests().len(), 1);
+        let [call] = response.tool_calls.as_slice() else {
+            panic!("expected one tool call, got {:?}", response.tool_calls);
+        };
+        assert_eq!(call.function.name, "calphamesh_submit_point_task");
+
+        // The caller executes the call, as the agent's tool would have
+        let answer = manual
+            .continue_with_tool_results(&mut history, [(call.clone(), "1".to_string())])
+            .await
+            .unwrap();
+
+        assert_eq!(answer, expected);
+        assert_eq!(
+            manual_model.requests()[1].chat_history,
+            automatic_model.requests()[1].chat_history
+        );
+        assert!(matches!(history.last(), Some(Message::Assistant { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_return_tool_calls_returns_answers() {
+        let model = MockCompletionModel::new().with_text("no tools needed");
+        let agent = AgentBuilder::new(model).build();
+
+        let response = agent.prompt("hello").return_tool_calls().await.unwrap();
+
+        assert_eq!(response.output, "no tools needed");
+        assert!(response.tool_calls.is_empty());
+    }
 }
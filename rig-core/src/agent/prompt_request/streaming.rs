@@ -2,24 +2,35 @@ use crate::{
     OneOrMany,
     agent::CancelSignal,
     completion::GetTokenUsage,
-    message::{AssistantContent, Reasoning, ToolResult, ToolResultContent, UserContent},
+    message::{AssistantContent, Reasoning, ToolCall, ToolResult, ToolResultContent, UserContent},
     streaming::{StreamedAssistantContent, StreamedUserContent, StreamingCompletion},
     wasm_compat::{WasmBoxedFuture, WasmCompatSend},
 };
-use futures::{Stream, StreamExt};
+use futures::{
+    Stream, StreamExt,
+    io::{AsyncWrite, AsyncWriteExt},
+};
 use serde::{Deserialize, Serialize};
-use std::{pin::Pin, sync::Arc};
-use tokio::sync::RwLock;
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+use tokio::sync::{RwLock, mpsc};
 use tracing::info_span;
 use tracing_futures::Instrument;
 
 use crate::{
-    agent::Agent,
-    completion::{CompletionError, CompletionModel, PromptError},
+    agent::{
+        Agent, AgentEvent, ApprovalDecision, ApprovalHandle, BudgetLimit, ToolRetry,
+        UsageAccumulator, UsageBreakdown,
+        budget::{Budget, TokenPrice},
+        tool_policy::{ToolFilter, tool_not_allowed},
+        validation::{AnswerValidation, repair_prompt},
+    },
+    completion::{CompletionError, CompletionModel, FinishReason, PromptError, ValidationAttempt},
     message::{Message, Text},
     tool::ToolSetError,
 };
 
+use super::continue_prompt;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub type StreamingResult<R> =
     Pin<Box<dyn Stream<Item = Result<MultiTurnStreamItem<R>, StreamingError>> + Send>>;
@@ -36,6 +47,14 @@ pub enum MultiTurnStreamItem<R> {
     StreamAssistantItem(StreamedAssistantContent<R>),
     /// A streamed user content item (mostly for tool results).
     StreamUserItem(StreamedUserContent),
+    /// A failed tool call that is being retried. The model never sees these.
+    ToolRetry(ToolRetry),
+    /// A tool call awaiting approval. The stream is paused until it is answered through the
+    /// request's [ApprovalHandle].
+    PendingApproval { call: ToolCall },
+    /// The answer streamed so far was rejected by the request's validator, and the model is asked
+    /// to answer again.
+    AnswerRejected { answer: String, error: String },
     /// The final result from the stream.
     FinalResponse(FinalResponse),
 }
@@ -45,6 +64,8 @@ pub enum MultiTurnStreamItem<R> {
 pub struct FinalResponse {
     response: String,
     aggregated_usage: crate::completion::Usage,
+    #[serde(default)]
+    usage_breakdown: UsageBreakdown,
 }
 
 impl FinalResponse {
@@ -52,6 +73,7 @@ impl FinalResponse {
         Self {
             response: String::new(),
             aggregated_usage: crate::completion::Usage::new(),
+            usage_breakdown: UsageBreakdown::default(),
         }
     }
 
@@ -59,9 +81,20 @@ impl FinalResponse {
         &self.response
     }
 
+    /// Usage of the agent's own completion requests, summed over every turn.
     pub fn usage(&self) -> crate::completion::Usage {
         self.aggregated_usage
     }
+
+    /// Usage of the agent and of the sub-agents it called as tools.
+    pub fn total_usage(&self) -> crate::completion::Usage {
+        self.aggregated_usage + self.usage_breakdown.sub_agent_usage()
+    }
+
+    /// Usage per turn of the agent and per sub-agent.
+    pub fn usage_breakdown(&self) -> &UsageBreakdown {
+        &self.usage_breakdown
+    }
 }
 
 impl<R> MultiTurnStreamItem<R> {
@@ -73,6 +106,7 @@ impl<R> MultiTurnStreamItem<R> {
         Self::FinalResponse(FinalResponse {
             response: response.to_string(),
             aggregated_usage,
+            usage_breakdown: UsageBreakdown::default(),
         })
     }
 }
@@ -111,6 +145,20 @@ where
     agent: Arc<Agent<M>>,
     /// Optional per-request hook for events
     hook: Option<P>,
+    /// Answers the tool calls awaiting approval
+    approvals: ApprovalHandle,
+    /// Signal cancelling the request
+    cancel_signal: CancelSignal,
+    /// Tools the request may use
+    tool_filter: ToolFilter,
+    /// Check of the final answer
+    validation: AnswerValidation,
+    /// Provider parameters merged over the agent's
+    additional_params: Option<serde_json::Value>,
+    /// Usage caps of the run
+    budget: Budget,
+    /// Whether answers cut off at the maximum output length are continued
+    auto_continue: bool,
 }
 
 impl<M, P> StreamingPromptRequest<M, P>
@@ -124,9 +172,16 @@ where
         Self {
             prompt: prompt.into(),
             chat_history: None,
-            max_depth: 0,
+            max_depth: agent.max_turns,
             agent,
             hook: None,
+            approvals: ApprovalHandle::default(),
+            cancel_signal: CancelSignal::new(),
+            tool_filter: ToolFilter::default(),
+            validation: AnswerValidation::default(),
+            additional_params: None,
+            budget: Budget::default(),
+            auto_continue: false,
         }
     }
 
@@ -154,9 +209,103 @@ where
             max_depth: self.max_depth,
             agent: self.agent,
             hook: Some(hook),
+            approvals: self.approvals,
+            cancel_signal: self.cancel_signal,
+            tool_filter: self.tool_filter,
+            validation: self.validation,
+            additional_params: self.additional_params,
+            budget: self.budget,
+            auto_continue: self.auto_continue,
         }
     }
 
+    /// Cancel the request when `signal` is cancelled. The stream then yields a
+    /// [`PromptError::PromptCancelled`] error carrying the conversation so far, and ends.
+    pub fn with_cancellation(mut self, signal: CancelSignal) -> Self {
+        self.cancel_signal = signal;
+        self
+    }
+
+    /// Only let the model use the given tools during this request. The other tools are not sent
+    /// to the model, and calls to them are answered with a refusal instead of being executed.
+    pub fn allowed_tools(
+        mut self,
+        tool_names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.tool_filter.allow(tool_names);
+        self
+    }
+
+    /// Keep the model from using the given tools during this request, as with
+    /// [allowed_tools](Self::allowed_tools).
+    pub fn deny_tools(mut self, tool_names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tool_filter.deny(tool_names);
+        self
+    }
+
+    /// Check the final answer with `validator`, as with
+    /// [PromptRequest::validate](crate::agent::PromptRequest::validate). The answer is checked once
+    /// fully streamed: a rejected answer is followed by an [AnswerRejected](MultiTurnStreamItem::AnswerRejected)
+    /// item and the answer of the repair turn, and the final response only holds the accepted answer.
+    pub fn validate(
+        mut self,
+        validator: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.validation.set_validator(Arc::new(validator));
+        self
+    }
+
+    /// Set how many times a final answer rejected by the [validator](Self::validate) is sent back
+    /// to the model. Repair turns do not count against the [multi_turn](Self::multi_turn) limit.
+    pub fn max_repair_attempts(mut self, attempts: usize) -> Self {
+        self.validation.set_max_repair_attempts(attempts);
+        self
+    }
+
+    /// Add provider-specific parameters to every completion request of this prompt, taking
+    /// precedence over the agent's, as with
+    /// [PromptRequest::additional_params](crate::agent::PromptRequest::additional_params).
+    pub fn additional_params(mut self, params: serde_json::Value) -> Self {
+        self.additional_params = Some(params);
+        self
+    }
+
+    /// End the run once the agent and its sub-agents used more than `tokens` tokens in total, as
+    /// with [PromptRequest::max_total_tokens](crate::agent::PromptRequest::max_total_tokens). The
+    /// stream then ends with a [`PromptError::BudgetExceeded`] error.
+    pub fn max_total_tokens(mut self, tokens: u64) -> Self {
+        self.budget.set_max_total_tokens(tokens);
+        self
+    }
+
+    /// End the run once the estimated cost of its tokens exceeds `cost`, as with
+    /// [PromptRequest::max_estimated_cost](crate::agent::PromptRequest::max_estimated_cost).
+    pub fn max_estimated_cost(mut self, cost: f64) -> Self {
+        self.budget.set_max_estimated_cost(cost);
+        self
+    }
+
+    /// Set the token price used to estimate the cost of the run, see
+    /// [max_estimated_cost](Self::max_estimated_cost).
+    pub fn token_price(mut self, price: TokenPrice) -> Self {
+        self.budget.set_token_price(price);
+        self
+    }
+
+    /// Continue answers cut off at the maximum output length, as with
+    /// [PromptRequest::auto_continue](crate::agent::PromptRequest::auto_continue). The
+    /// continuations are streamed as part of the same answer.
+    pub fn auto_continue(mut self, enabled: bool) -> Self {
+        self.auto_continue = enabled;
+        self
+    }
+
+    /// The handle approving or rejecting the tool calls of this request that require approval
+    /// (see [AgentBuilder::requires_approval](crate::agent::AgentBuilder::requires_approval)).
+    pub fn approvals(&self) -> ApprovalHandle {
+        self.approvals.clone()
+    }
+
     #[cfg_attr(feature = "worker", worker::send)]
     async fn send(self) -> StreamingResult<M::StreamingResponse> {
         let agent_span = if tracing::Span::current().is_disabled() {
@@ -195,22 +344,45 @@ where
         let mut max_depth_reached = false;
 
         let mut aggregated_usage = crate::completion::Usage::new();
+        let usage_acc = UsageAccumulator::new();
 
-        let cancel_signal = CancelSignal::new();
+        let cancel_signal = self.cancel_signal.clone();
 
         Box::pin(async_stream::stream! {
             let _guard = agent_span.enter();
             let mut current_prompt = prompt.clone();
             let mut did_call_tool = false;
+            let mut rejected_answers = Vec::new();
+            // Outputs of the turns cut off at the maximum output length, see `auto_continue`
+            let mut truncated_answer = String::new();
 
             'outer: loop {
-                if current_max_depth > self.max_depth + 1 {
+                // Repair turns do not count against the turn limit
+                if current_max_depth > self.max_depth + 1 + rejected_answers.len() {
                     last_prompt_error = current_prompt.rag_text().unwrap_or_default();
                     max_depth_reached = true;
                     break;
                 }
 
+                if cancel_signal.is_cancelled() {
+                    let mut history = chat_history.read().await.to_vec();
+                    history.push(current_prompt.clone());
+                    yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(history).into()));
+                    break 'outer;
+                }
+
+                let run_usage = usage_acc.total_usage();
+                if let Some(limit) = self.budget.exceeded(&run_usage) {
+                    tracing::warn!("The run exceeded {limit} after {current_max_depth} turns");
+                    let mut history = chat_history.read().await.to_vec();
+                    history.push(current_prompt.clone());
+                    yield Err(StreamingError::Prompt(PromptError::budget_exceeded(limit, history, run_usage).into()));
+                    break 'outer;
+                }
+
                 current_max_depth += 1;
+                agent.notify(AgentEvent::TurnStarted { turn: current_max_depth });
+                let mut turn_usage = crate::completion::Usage::new();
 
                 if self.max_depth > 1 {
                     tracing::info!(
@@ -230,6 +402,7 @@ where
 
                     if cancel_signal.is_cancelled() {
                         yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()));
+                        break 'outer;
                     }
                 }
 
@@ -238,9 +411,13 @@ where
                     parent: tracing::Span::current(),
                     "chat_streaming",
                     gen_ai.operation.name = "chat",
+                    gen_ai.agent.name = agent.name(),
                     gen_ai.system_instructions = &agent.preamble,
                     gen_ai.provider.name = tracing::field::Empty,
                     gen_ai.request.model = tracing::field::Empty,
+                    gen_ai.request.temperature = tracing::field::Empty,
+                    gen_ai.request.max_tokens = tracing::field::Empty,
+                    gen_ai.request.top_p = tracing::field::Empty,
                     gen_ai.response.id = tracing::field::Empty,
                     gen_ai.response.model = tracing::field::Empty,
                     gen_ai.usage.output_tokens = tracing::field::Empty,
@@ -249,21 +426,45 @@ where
                     gen_ai.output.messages = tracing::field::Empty,
                 );
 
-                let mut stream = tracing::Instrument::instrument(
-                    agent
+                let mut request = agent
                     .stream_completion(current_prompt.clone(), (*chat_history.read().await).clone())
                     .await?
-                    .stream(), chat_stream_span
+                    .retain_tools(|tool| self.tool_filter.allows(&tool.name));
+                if let Some(params) = &self.additional_params {
+                    request = request.additional_params(params.clone());
+                }
+                let started = std::time::Instant::now();
+                let (mut stream, model_index) = tracing::Instrument::instrument(
+                    agent.stream_completion_with_fallback(request.build()),
+                    chat_stream_span,
                 )
-
                 .await?;
+                if model_index > 0 {
+                    usage_acc.record_fallback(current_max_depth, model_index);
+                }
 
                 chat_history.write().await.push(current_prompt.clone());
 
                 let mut tool_calls = vec![];
                 let mut tool_results = vec![];
+                let mut pending_tool_calls = vec![];
+                let mut truncated = false;
+
+                loop {
+                    let Some(content) = cancel_signal.or_cancelled(stream.next()).await else {
+                        // Stop the provider stream, keeping the partial response in the transcript
+                        stream.cancel();
+                        let mut history = chat_history.read().await.to_vec();
+                        if is_text_response && !last_text_response.is_empty() {
+                            history.push(Message::assistant(&last_text_response));
+                        }
+                        yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(history).into()));
+                        break 'outer;
+                    };
+                    let Some(content) = content else {
+                        break;
+                    };
 
-                while let Some(content) = stream.next().await {
                     match content {
                         Ok(StreamedAssistantContent::Text(text)) => {
                             if !is_text_response {
@@ -275,76 +476,18 @@ where
                                 hook.on_text_delta(&text.text, &last_text_response, cancel_signal.clone()).await;
                                 if cancel_signal.is_cancelled() {
                                     yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()));
+                                    break 'outer;
                                 }
                             }
                             yield Ok(MultiTurnStreamItem::stream_item(StreamedAssistantContent::Text(text)));
                             did_call_tool = false;
                         },
                         Ok(StreamedAssistantContent::ToolCall(tool_call)) => {
-                            let tool_span = info_span!(
-                                parent: tracing::Span::current(),
-                                "execute_tool",
-                                gen_ai.operation.name = "execute_tool",
-                                gen_ai.tool.type = "function",
-                                gen_ai.tool.name = tracing::field::Empty,
-                                gen_ai.tool.call.id = tracing::field::Empty,
-                                gen_ai.tool.call.arguments = tracing::field::Empty,
-                                gen_ai.tool.call.result = tracing::field::Empty
-                            );
-
                             yield Ok(MultiTurnStreamItem::stream_item(StreamedAssistantContent::ToolCall(tool_call.clone())));
+                            agent.notify(AgentEvent::ToolCallIssued { turn: current_max_depth, tool_call: tool_call.clone() });
 
-                            let tc_result = async {
-                                let tool_span = tracing::Span::current();
-                                if let Some(ref hook) = self.hook {
-                                    hook.on_tool_call(&tool_call.function.name, &tool_call.function.arguments.to_string(), cancel_signal.clone()).await;
-                                    if cancel_signal.is_cancelled() {
-                                        return Err((StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()), None as Option<(String, String)>));
-                                    }
-                                }
-
-                                tool_span.record("gen_ai.tool.name", &tool_call.function.name);
-                                tool_span.record("gen_ai.tool.call.arguments", tool_call.function.arguments.to_string());
-
-                                let tool_result = match
-                                agent.tool_server_handle.call_tool(&tool_call.function.name, &tool_call.function.arguments.to_string()).await {
-                                    Ok(thing) => thing,
-                                    Err(e) => {
-                                        tracing::warn!("Error while calling tool: {e}");
-                                        e.to_string()
-                                    }
-                                };
-
-                                tool_span.record("gen_ai.tool.call.result", &tool_result);
-
-                                if let Some(ref hook) = self.hook {
-                                    hook.on_tool_result(&tool_call.function.name, &tool_call.function.arguments.to_string(), &tool_result.to_string(), cancel_signal.clone())
-                                    .await;
-
-                                    if cancel_signal.is_cancelled() {
-                                        return Err((StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()), None as Option<(String, String)>));
-                                    }
-                                }
-
-                                let tool_call_id = tool_call.id.clone();
-                                let tool_call_msg = AssistantContent::ToolCall(tool_call.clone());
-
-                                tool_calls.push(tool_call_msg);
-                                tool_results.push((tool_call.id.clone(), tool_call.call_id.clone(), tool_result.clone()));
-
-                                did_call_tool = true;
-                                Ok(tool_result)
-                            }.instrument(tool_span).await;
-
-                            match tc_result {
-                                Ok(text) => {
-                                    let tr = ToolResult { id: tool_call.id, call_id: tool_call.call_id, content: OneOrMany::one(ToolResultContent::Text(Text { text })) };
-                                    yield Ok(MultiTurnStreamItem::StreamUserItem(StreamedUserContent::ToolResult(tr)));
-                                }
-                                Err(e) => {
-                                    yield Err(e);
-                                }
-                            }
+                            pending_tool_calls.push(tool_call);
+                            did_call_tool = true;
                         },
                         Ok(StreamedAssistantContent::ToolCallDelta { id, delta }) => {
                             if let Some(ref hook) = self.hook {
@@ -353,6 +496,7 @@ where
 
                                 if cancel_signal.is_cancelled() {
                                     yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()));
+                                    break 'outer;
                                 }
                             }
                         }
@@ -367,23 +511,35 @@ where
                             did_call_tool = false;
                         },
                         // 处理工具结果（不应该从提供商流中到达这里，只是为了完整性）
-                        Ok(StreamedAssistantContent::ToolResult { id: _, result: _ }) => {
+                        Ok(StreamedAssistantContent::ToolResult { .. }) => {
                             // 工具结果应该在 Agent 层处理，不应该从提供商流中直接到达
                             // 这里只是为了编译完整性，实际不应该执行
                         },
                         Ok(StreamedAssistantContent::Final(final_resp)) => {
-                            if let Some(usage) = final_resp.token_usage() { aggregated_usage += usage; };
+                            if let Some(usage) = final_resp.token_usage() {
+                                aggregated_usage += usage;
+                                turn_usage += usage;
+                            };
+                            // Only the final response of the agent's own model is of its type
+                            let final_resp = final_resp.into_model();
+                            truncated = self.auto_continue
+                                && final_resp.as_ref().and_then(M::streaming_finish_reason) == Some(FinishReason::Length);
                             if is_text_response {
                                 if let Some(ref hook) = self.hook {
-                                    hook.on_stream_completion_response_finish(&prompt, &final_resp, cancel_signal.clone()).await;
+                                    if let Some(final_resp) = &final_resp {
+                                        hook.on_stream_completion_response_finish(&prompt, final_resp, cancel_signal.clone()).await;
+                                    }
 
                                     if cancel_signal.is_cancelled() {
                                         yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()));
+                                        break 'outer;
                                     }
                                 }
 
                                 tracing::Span::current().record("gen_ai.completion", &last_text_response);
-                                yield Ok(MultiTurnStreamItem::stream_item(StreamedAssistantContent::Final(final_resp)));
+                                if let Some(final_resp) = final_resp {
+                                    yield Ok(MultiTurnStreamItem::stream_item(StreamedAssistantContent::Final(final_resp)));
+                                }
                                 is_text_response = false;
                             }
                         }
@@ -394,6 +550,83 @@ where
                     }
                 }
 
+                agent.after_completion(&stream.choice, turn_usage, started.elapsed()).await;
+
+                // Ask for the approval of the tool calls requiring it, one at a time
+                let mut rejections = HashMap::new();
+                for tool_call in &pending_tool_calls {
+                    let tool_name = &tool_call.function.name;
+                    if !self.tool_filter.allows(tool_name) {
+                        rejections.insert(tool_call.id.clone(), tool_not_allowed(tool_name));
+                        continue;
+                    }
+                    if !agent.requires_approval(tool_name) {
+                        continue;
+                    }
+
+                    let decision = self.approvals.register(&tool_call.id);
+                    yield Ok(MultiTurnStreamItem::PendingApproval { call: tool_call.clone() });
+                    match cancel_signal.or_cancelled(decision).await {
+                        Some(Ok(ApprovalDecision::Rejected(reason))) => {
+                            rejections.insert(tool_call.id.clone(), reason);
+                        }
+                        Some(_) => {}
+                        None => {
+                            yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()));
+                            break 'outer;
+                        }
+                    }
+                }
+
+                // Run the tool calls of this turn, up to `tool_concurrency` at a time. The results
+                // are handled in call order, regardless of which tool finishes first.
+                let hook = self.hook.as_ref();
+                let (tool_agent, tool_history, tool_cancel_signal, turn, rejections, tool_usage) = (&agent, &chat_history, cancel_signal.clone(), current_max_depth, &rejections, &usage_acc);
+                let tool_outcomes = futures::stream::iter(pending_tool_calls)
+                    .map(move |tool_call| {
+                        let cancel_signal = tool_cancel_signal.clone();
+                        async move {
+                            let result = match rejections.get(&tool_call.id) {
+                                Some(reason) => Ok((reason.clone(), Vec::new())),
+                                None => tool_usage.scope(execute_tool_call(tool_agent, hook, &tool_call, turn, cancel_signal, tool_history)).await,
+                            };
+                            (tool_call, result)
+                        }
+                    })
+                    .buffered(agent.tool_concurrency.max(1))
+                    .collect::<Vec<_>>();
+                let Some(tool_outcomes) = cancel_signal.or_cancelled(tool_outcomes).await else {
+                    yield Err(StreamingError::Prompt(PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into()));
+                    break 'outer;
+                };
+
+                for (tool_call, result) in tool_outcomes {
+                    match result {
+                        Ok((text, retries)) => {
+                            for retry in retries {
+                                yield Ok(MultiTurnStreamItem::ToolRetry(retry));
+                            }
+                            tool_calls.push(AssistantContent::ToolCall(tool_call.clone()));
+                            tool_results.push((tool_call.id.clone(), tool_call.call_id.clone(), text.clone()));
+
+                            agent.notify(AgentEvent::ToolResultReceived {
+                                turn: current_max_depth,
+                                id: tool_call.id.clone(),
+                                call_id: tool_call.call_id.clone(),
+                                result: text.clone(),
+                            });
+                            let tr = ToolResult { id: tool_call.id, call_id: tool_call.call_id, content: OneOrMany::one(ToolResultContent::Text(Text { text })) };
+                            yield Ok(MultiTurnStreamItem::StreamUserItem(StreamedUserContent::tool_result(tr, tool_call.function.name)));
+                        }
+                        Err(e) => {
+                            yield Err(e);
+                        }
+                    }
+                }
+
+                usage_acc.record_turn(current_max_depth, turn_usage);
+                agent.notify(AgentEvent::TurnCompleted { turn: current_max_depth, usage: turn_usage });
+
                 // Add (parallel) tool calls to chat history
                 if !tool_calls.is_empty() {
                     chat_history.write().await.push(Message::Assistant {
@@ -428,28 +661,148 @@ where
                     None => unreachable!("Chat history should never be empty at this point"),
                 };
 
+                if !did_call_tool && truncated && current_max_depth <= self.max_depth + rejected_answers.len() {
+                    tracing::info!("The answer was cut off, asking the model to continue");
+                    chat_history.write().await.extend([current_prompt.clone(), Message::assistant(&last_text_response)]);
+                    truncated_answer.push_str(&last_text_response);
+                    current_prompt = continue_prompt();
+                    continue;
+                }
+                if !did_call_tool && !truncated_answer.is_empty() {
+                    last_text_response = std::mem::take(&mut truncated_answer) + &last_text_response;
+                }
+
+                if !did_call_tool && let Err(error) = self.validation.check(&last_text_response) {
+                    tracing::warn!("The answer was rejected by the validator: {error}");
+                    chat_history.write().await.extend([current_prompt.clone(), Message::assistant(&last_text_response)]);
+                    rejected_answers.push(ValidationAttempt {
+                        answer: last_text_response.clone(),
+                        error: error.clone(),
+                    });
+
+                    if !self.validation.may_repair(rejected_answers.len()) {
+                        let history = chat_history.read().await.to_vec();
+                        yield Err(StreamingError::Prompt(PromptError::validation_failed(std::mem::take(&mut rejected_answers), history).into()));
+                        break;
+                    }
+
+                    yield Ok(MultiTurnStreamItem::AnswerRejected { answer: last_text_response.clone(), error: error.clone() });
+                    current_prompt = repair_prompt(&error);
+                    continue;
+                }
+
                 if !did_call_tool {
                     let current_span = tracing::Span::current();
                     current_span.record("gen_ai.usage.input_tokens", aggregated_usage.input_tokens);
                     current_span.record("gen_ai.usage.output_tokens", aggregated_usage.output_tokens);
                     tracing::info!("Agent multi-turn stream finished");
-                    yield Ok(MultiTurnStreamItem::final_response(&last_text_response, aggregated_usage));
+                    agent.notify(AgentEvent::FinalResponse {
+                        response: last_text_response.clone(),
+                        usage: aggregated_usage,
+                    });
+                    yield Ok(MultiTurnStreamItem::FinalResponse(FinalResponse {
+                        response: last_text_response.clone(),
+                        aggregated_usage,
+                        usage_breakdown: usage_acc.breakdown(),
+                    }));
                     break;
                 }
             }
 
             if max_depth_reached {
-                yield Err(Box::new(PromptError::MaxDepthError {
-                    max_depth: self.max_depth,
-                    chat_history: Box::new((*chat_history.read().await).clone()),
-                    prompt: last_prompt_error.clone().into(),
-                }).into());
+                // The pending prompt (the last tool results) is part of the history to resume from
+                let mut history = (*chat_history.read().await).clone();
+                history.push(current_prompt.clone());
+
+                yield Err(Box::new(PromptError::max_depth(
+                    self.max_depth,
+                    history,
+                    last_prompt_error.clone().into(),
+                    usage_acc.total_usage(),
+                )).into());
             }
 
         })
     }
 }
 
+/// Execute one tool call of a streaming turn, running the hook's tool callbacks around it.
+/// Returns the tool output along with the failed attempts that were retried.
+async fn execute_tool_call<M, P>(
+    agent: &Agent<M>,
+    hook: Option<&P>,
+    tool_call: &ToolCall,
+    turn: usize,
+    cancel_signal: CancelSignal,
+    chat_history: &RwLock<Vec<Message>>,
+) -> Result<(String, Vec<ToolRetry>), StreamingError>
+where
+    M: CompletionModel,
+    P: StreamingPromptHook<M>,
+{
+    let tool_span = info_span!(
+        parent: tracing::Span::current(),
+        "execute_tool",
+        gen_ai.operation.name = "execute_tool",
+        gen_ai.tool.type = "function",
+        gen_ai.tool.name = tracing::field::Empty,
+        gen_ai.tool.call.id = tracing::field::Empty,
+        gen_ai.tool.call.arguments = tracing::field::Empty,
+        gen_ai.tool.call.result = tracing::field::Empty
+    );
+
+    async {
+        let tool_span = tracing::Span::current();
+        let args = tool_call.function.arguments.to_string();
+
+        if let Some(hook) = hook {
+            hook.on_tool_call(&tool_call.function.name, &args, cancel_signal.clone())
+                .await;
+            if cancel_signal.is_cancelled() {
+                return Err(StreamingError::Prompt(
+                    PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into(),
+                ));
+            }
+        }
+
+        tool_span.record("gen_ai.tool.name", &tool_call.function.name);
+        tool_span.record("gen_ai.tool.call.arguments", &args);
+
+        let mut retries = Vec::new();
+        let tool_result = agent
+            .call_tool(tool_call, |retry| {
+                agent.notify(AgentEvent::ToolCallRetried {
+                    turn,
+                    retry: retry.clone(),
+                });
+                retries.push(retry.clone());
+            })
+            .await;
+
+        tool_span.record("gen_ai.tool.call.result", &tool_result);
+
+        if let Some(hook) = hook {
+            hook.on_tool_result(
+                &tool_call.function.name,
+                &args,
+                &tool_result,
+                cancel_signal.clone(),
+            )
+            .await;
+
+            if cancel_signal.is_cancelled() {
+                return Err(StreamingError::Prompt(
+                    PromptError::prompt_cancelled(chat_history.read().await.to_vec()).into(),
+                ));
+            }
+        }
+
+        Ok((tool_result, retries))
+    }
+    .instrument(tool_span)
+    .await
+}
+
 impl<M, P> IntoFuture for StreamingPromptRequest<M, P>
 where
     M: CompletionModel + 'static,
@@ -465,52 +818,605 @@ where
     }
 }
 
+/// What [stream_to_stdout_with] prints, and how.
+///
+/// The default prints everything, uncolored and untruncated, like [stream_to_stdout].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintOptions {
+    /// Print the tool calls (with their raw JSON arguments) and tool retries
+    pub show_tool_calls: bool,
+    /// Print the tool results
+    pub show_tool_results: bool,
+    /// Print the model's reasoning
+    pub show_reasoning: bool,
+    /// Color the output with ANSI escape codes
+    pub color: bool,
+    /// Elide tool results longer than this many bytes
+    pub truncate_tool_output: Option<usize>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            show_tool_calls: true,
+            show_tool_results: true,
+            show_reasoning: true,
+            color: false,
+            truncate_tool_output: None,
+        }
+    }
+}
+
+impl PrintOptions {
+    /// Print the answer text only.
+    pub fn text_only() -> Self {
+        Self {
+            show_tool_calls: false,
+            show_tool_results: false,
+            show_reasoning: false,
+            ..Default::default()
+        }
+    }
+
+    /// Print everything inline and colored, to follow what (multi-)agent runs are doing.
+    pub fn verbose() -> Self {
+        Self {
+            color: true,
+            ..Default::default()
+        }
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_DIM: &str = "\x1b[2m";
+const ANSI_CYAN: &str = "\x1b[36m";
+const ANSI_GREEN: &str = "\x1b[32m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+
 /// helper function to stream a completion selfuest to stdout
 pub async fn stream_to_stdout<R>(
     stream: &mut StreamingResult<R>,
+) -> Result<FinalResponse, std::io::Error> {
+    stream_to_stdout_with(stream, PrintOptions::default()).await
+}
+
+/// Like [stream_to_stdout], printing the stream according to `options`.
+///
+/// # Example
+/// ```rust,ignore
+/// let options = PrintOptions {
+///     show_tool_calls: false,
+///     color: true,
+///     truncate_tool_output: Some(500),
+///     ..Default::default()
+/// };
+/// let res = stream_to_stdout_with(&mut stream, options).await?;
+/// ```
+pub async fn stream_to_stdout_with<R>(
+    stream: &mut StreamingResult<R>,
+    options: PrintOptions,
+) -> Result<FinalResponse, std::io::Error> {
+    write_stream(stream, &mut std::io::stdout(), options).await
+}
+
+/// Like [stream_to_stdout], also printing the tool calls, tool results and reasoning inline, see
+/// [PrintOptions::verbose].
+pub async fn stream_to_stdout_verbose<R>(
+    stream: &mut StreamingResult<R>,
+) -> Result<FinalResponse, std::io::Error> {
+    stream_to_stdout_with(stream, PrintOptions::verbose()).await
+}
+
+// Render the stream to `out`, flushing after every item
+async fn write_stream<R, W: std::io::Write>(
+    stream: &mut StreamingResult<R>,
+    out: &mut W,
+    options: PrintOptions,
 ) -> Result<FinalResponse, std::io::Error> {
     let mut final_res = FinalResponse::empty();
-    print!("Response: ");
+    write!(out, "Response: ")?;
     while let Some(content) = stream.next().await {
-        match content {
-            Ok(MultiTurnStreamItem::StreamItem(StreamedAssistantContent::ToolCall(tool_call))) => {
-                println!("\n[Tool call] {}: {}({})",
+        if let Some(res) = write_item(out, content, &options)? {
+            final_res = res;
+        }
+        out.flush()?;
+    }
+
+    Ok(final_res)
+}
+
+/// Like [stream_to_stdout], writing the stream to an async writer (e.g. an HTTP response body),
+/// flushed after every item.
+pub async fn stream_to_writer<R, W: AsyncWrite + Unpin>(
+    stream: &mut StreamingResult<R>,
+    mut writer: W,
+) -> Result<FinalResponse, std::io::Error> {
+    let options = PrintOptions::default();
+    let mut final_res = FinalResponse::empty();
+    let mut chunk = Vec::new();
+
+    writer.write_all(b"Response: ").await?;
+    while let Some(content) = stream.next().await {
+        chunk.clear();
+        if let Some(res) = write_item(&mut chunk, content, &options)? {
+            final_res = res;
+        }
+        writer.write_all(&chunk).await?;
+        writer.flush().await?;
+    }
+
+    Ok(final_res)
+}
+
+// Render a single stream item, returning the final response once it arrives
+fn write_item<R, W: std::io::Write>(
+    out: &mut W,
+    content: Result<MultiTurnStreamItem<R>, StreamingError>,
+    options: &PrintOptions,
+) -> Result<Option<FinalResponse>, std::io::Error> {
+    let paint = |color: &'static str| if options.color { color } else { "" };
+    let reset = paint(ANSI_RESET);
+
+    match content {
+        Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::ToolCall(
+            tool_call,
+        ))) => {
+            if options.show_tool_calls {
+                writeln!(
+                    out,
+                    "\n{}[Tool call] {}: {}({}){reset}",
+                    paint(ANSI_CYAN),
                     tool_call.id,
                     tool_call.function.name,
-                    tool_call.function.arguments);
-                println!("Response: ");
-                std::io::Write::flush(&mut std::io::stdout()).unwrap();
+                    tool_call.function.arguments
+                )?;
+                writeln!(out, "Response: ")?;
+            }
+        }
+        Ok(MultiTurnStreamItem::StreamUserItem(StreamedUserContent::ToolResult {
+            tool_result,
+            tool_name,
+        })) => {
+            if options.show_tool_results {
+                let text = tool_result_text(&tool_result);
+                writeln!(
+                    out,
+                    "\n{}[Tool Result] {}: {tool_name} -> {}{reset}",
+                    paint(ANSI_GREEN),
+                    tool_result.id,
+                    truncate_output(&text, options.truncate_tool_output)
+                )?;
+                write!(out, "Response: ")?;
             }
+        }
+        Ok(MultiTurnStreamItem::PendingApproval { call }) => {
+            // Nothing can answer approvals here: the stream stays paused until the call is
+            // answered through the request's approval handle, eg. from another task.
+            writeln!(
+                out,
+                "\n{}[Approval required] {}: {}({}){reset}",
+                paint(ANSI_YELLOW),
+                call.id,
+                call.function.name,
+                call.function.arguments
+            )?;
+        }
+        Ok(MultiTurnStreamItem::AnswerRejected { error, .. }) => {
+            writeln!(
+                out,
+                "\n{}[Answer rejected] {error}{reset}",
+                paint(ANSI_YELLOW)
+            )?;
+            write!(out, "Response: ")?;
+        }
+        Ok(MultiTurnStreamItem::ToolRetry(retry)) => {
+            if options.show_tool_calls {
+                writeln!(
+                    out,
+                    "\n{}[Tool retry] {}: {} attempt {} failed: {}{reset}",
+                    paint(ANSI_YELLOW),
+                    retry.id,
+                    retry.tool_name,
+                    retry.attempt,
+                    retry.error
+                )?;
+                write!(out, "Response: ")?;
+            }
+        }
+        Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(Text {
+            text,
+        }))) => {
+            write!(out, "{text}")?;
+        }
+        Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Reasoning(
+            Reasoning { reasoning, .. },
+        ))) => {
+            if options.show_reasoning {
+                write!(out, "{}{}{reset}", paint(ANSI_DIM), reasoning.join("\n"))?;
+            }
+        }
+        Ok(MultiTurnStreamItem::FinalResponse(res)) => {
+            return Ok(Some(res));
+        }
+        Err(err) => {
+            writeln!(out, "\nError: {err}")?;
+        }
+        _ => {}
+    }
 
-            Ok(MultiTurnStreamItem::StreamItem(StreamedAssistantContent::ToolResult { id, result })) => {
-                println!("\n[Tool Result] {}: {}", id, result);
-                print!("Response: ");
-                std::io::Write::flush(&mut std::io::stdout()).unwrap();
+    Ok(None)
+}
+
+// Elide the end of a tool output longer than `limit` bytes, keeping whole characters
+fn truncate_output(text: &str, limit: Option<usize>) -> std::borrow::Cow<'_, str> {
+    match limit {
+        Some(limit) if text.len() > limit => {
+            let mut end = limit;
+            while !text.is_char_boundary(end) {
+                end -= 1;
             }
-            Ok(MultiTurnStreamItem::StreamItem(StreamedAssistantContent::Text(Text { text }))) => {
-                // println!("\n[text]");
-                print!("{}", text);
-                std::io::Write::flush(&mut std::io::stdout()).unwrap();
+            format!("{}… ({} more bytes)", &text[..end], text.len() - end).into()
+        }
+        _ => text.into(),
+    }
+}
+
+// Concatenate the text parts of a tool result for display
+fn tool_result_text(tool_result: &ToolResult) -> String {
+    tool_result
+        .content
+        .iter()
+        .filter_map(|content| match content {
+            ToolResultContent::Text(Text { text }) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A serializable summary of a stream item, for forwarding a stream to clients, e.g. over
+/// server-sent events or a WebSocket. See [stream_to_channel].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum StreamEvent {
+    /// A chunk of the response text
+    Text { text: String },
+    /// A tool call made by the model
+    ToolCall {
+        id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        call_id: Option<String>,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    /// The result of a tool call, as text
+    ToolResult {
+        id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        call_id: Option<String>,
+        name: String,
+        content: String,
+    },
+    /// A chunk of the model's reasoning
+    Reasoning { reasoning: String },
+    /// A tool call awaiting approval
+    ApprovalRequired {
+        id: String,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    /// An answer rejected by the request's validator, followed by the answer of a repair turn
+    AnswerRejected { error: String },
+    /// The run exceeded one of the usage caps of its request and ended
+    BudgetExceeded {
+        limit: BudgetLimit,
+        usage: crate::completion::Usage,
+    },
+    /// The end of the stream
+    Final {
+        response: String,
+        usage: crate::completion::Usage,
+    },
+    /// An error ending the stream
+    Error { message: String },
+}
+
+impl StreamEvent {
+    /// The `type` tag of the event, as serialized
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            StreamEvent::Text { .. } => "text",
+            StreamEvent::ToolCall { .. } => "tool_call",
+            StreamEvent::ToolResult { .. } => "tool_result",
+            StreamEvent::Reasoning { .. } => "reasoning",
+            StreamEvent::ApprovalRequired { .. } => "approval_required",
+            StreamEvent::AnswerRejected { .. } => "answer_rejected",
+            StreamEvent::BudgetExceeded { .. } => "budget_exceeded",
+            StreamEvent::Final { .. } => "final",
+            StreamEvent::Error { .. } => "error",
+        }
+    }
+
+    /// Whether the event ends the stream
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            StreamEvent::Final { .. }
+                | StreamEvent::BudgetExceeded { .. }
+                | StreamEvent::Error { .. }
+        )
+    }
+
+    pub(crate) fn from_item<R>(
+        item: Result<MultiTurnStreamItem<R>, StreamingError>,
+    ) -> Option<Self> {
+        let event = match item {
+            Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(text))) => {
+                StreamEvent::Text { text: text.text }
             }
+            Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::ToolCall(
+                tool_call,
+            ))) => StreamEvent::ToolCall {
+                id: tool_call.id,
+                call_id: tool_call.call_id,
+                name: tool_call.function.name,
+                arguments: tool_call.function.arguments,
+            },
             Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Reasoning(
-                Reasoning { reasoning, .. },
-            ))) => {
-                // println!("\n[thinking]");
-                let reasoning = reasoning.join("\n");
-                print!("{}", reasoning);
-                std::io::Write::flush(&mut std::io::stdout()).unwrap();
+                reasoning,
+            ))) => StreamEvent::Reasoning {
+                reasoning: reasoning.reasoning.join("\n"),
+            },
+            Ok(MultiTurnStreamItem::StreamUserItem(StreamedUserContent::ToolResult {
+                tool_result,
+                tool_name,
+            })) => StreamEvent::ToolResult {
+                content: tool_result_text(&tool_result),
+                id: tool_result.id,
+                call_id: tool_result.call_id,
+                name: tool_name,
+            },
+            Ok(MultiTurnStreamItem::PendingApproval { call }) => StreamEvent::ApprovalRequired {
+                id: call.id,
+                name: call.function.name,
+                arguments: call.function.arguments,
+            },
+            Ok(MultiTurnStreamItem::AnswerRejected { error, .. }) => {
+                StreamEvent::AnswerRejected { error }
+            }
+            Ok(MultiTurnStreamItem::FinalResponse(res)) => StreamEvent::Final {
+                usage: res.total_usage(),
+                response: res.response,
+            },
+            Err(StreamingError::Prompt(err)) => match *err {
+                PromptError::BudgetExceeded { limit, usage, .. } => {
+                    StreamEvent::BudgetExceeded { limit, usage }
+                }
+                err => StreamEvent::Error {
+                    message: StreamingError::Prompt(Box::new(err)).to_string(),
+                },
+            },
+            Err(err) => StreamEvent::Error {
+                message: err.to_string(),
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+// Number of events buffered by [stream_to_channel] before the stream waits for the receiver
+const STREAM_EVENT_BUFFER: usize = 64;
+
+/// Drive the stream on a background task, sending its items as [StreamEvent]s.
+///
+/// The channel closes once the stream ends. Dropping the receiver stops the stream. The task is
+/// spawned on tokio, or with `wasm_bindgen_futures` on wasm (requires the `worker` feature).
+///
+/// # Example
+/// ```rust,ignore
+/// let stream = agent.stream_prompt("Design a coating").multi_turn(5).await;
+/// let mut events = stream_to_channel(stream);
+/// while let Some(event) = events.recv().await {
+///     sse_sender.send(serde_json::to_string(&event)?).await?;
+/// }
+/// ```
+pub fn stream_to_channel<R>(mut stream: StreamingResult<R>) -> mpsc::Receiver<StreamEvent>
+where
+    R: WasmCompatSend + 'static,
+{
+    let (sender, receiver) = mpsc::channel(STREAM_EVENT_BUFFER);
+    let forward = async move {
+        while let Some(item) = stream.next().await {
+            let Some(event) = StreamEvent::from_item(item) else {
+                continue;
+            };
+            if sender.send(event).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    #[cfg(not(target_family = "wasm"))]
+    tokio::spawn(forward);
+
+    #[cfg(all(feature = "worker", target_family = "wasm"))]
+    wasm_bindgen_futures::spawn_local(forward);
+
+    receiver
+}
+
+/// Send a streaming prompt request and collect the conversation it produces.
+///
+/// Every stream item is passed to `sink` as it arrives (e.g. to print live output). Once the
+/// stream ends, returns the messages added to the conversation, starting with the prompt, and the
+/// [FinalResponse]. The messages follow the order the agent uses internally: for each turn an
+/// assistant message (reasoning, text and tool calls), then one user message per tool result in
+/// the order of the calls (with `call_id` preserved), and finally the assistant's text answer, if
+/// it produced one.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::agent::{MultiTurnStreamItem, stream_collect};
+/// use rig::streaming::{StreamedAssistantContent, StreamingChat};
+///
+/// let (messages, final_response) = stream_collect(
+///     agent.stream_chat("Predict the hardness", history.clone()).multi_turn(10),
+///     |item| {
+///         if let MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(text)) = item {
+///             print!("{}", text.text);
+///         }
+///     },
+/// )
+/// .await?;
+///
+/// history.extend(messages);
+/// ```
+pub async fn stream_collect<M, P, F>(
+    request: StreamingPromptRequest<M, P>,
+    sink: F,
+) -> Result<(Vec<Message>, FinalResponse), StreamingError>
+where
+    M: CompletionModel + 'static,
+    <M as CompletionModel>::StreamingResponse: WasmCompatSend + GetTokenUsage,
+    P: StreamingPromptHook<M> + 'static,
+    F: FnMut(&MultiTurnStreamItem<M::StreamingResponse>),
+{
+    let prompt = request.prompt.clone();
+    let mut stream = request.await;
+    let (generated, final_response) = collect_stream_to_messages(&mut stream, sink).await?;
+
+    let mut messages = Vec::with_capacity(generated.len() + 1);
+    messages.push(prompt);
+    messages.extend(generated);
+
+    Ok((messages, final_response))
+}
+
+/// Consume a multi-turn stream, passing every item to `sink`, and rebuild the messages the
+/// agent produced (excluding the prompt). See [stream_collect] for the message layout.
+pub async fn collect_stream_to_messages<R, F>(
+    stream: &mut StreamingResult<R>,
+    mut sink: F,
+) -> Result<(Vec<Message>, FinalResponse), StreamingError>
+where
+    F: FnMut(&MultiTurnStreamItem<R>),
+{
+    let mut collector = MessageCollector::default();
+    let mut final_response = FinalResponse::empty();
+
+    while let Some(item) = stream.next().await {
+        let item = item?;
+        sink(&item);
+
+        match item {
+            MultiTurnStreamItem::StreamAssistantItem(content) => collector.push_assistant(content),
+            MultiTurnStreamItem::StreamUserItem(StreamedUserContent::ToolResult {
+                tool_result,
+                ..
+            }) => collector.tool_results.push(tool_result),
+            MultiTurnStreamItem::AnswerRejected { error, .. } => {
+                collector.push_repair_prompt(&error)
             }
-            Ok(MultiTurnStreamItem::FinalResponse(res)) => {
-                final_res = res;
+            MultiTurnStreamItem::ToolRetry(_) | MultiTurnStreamItem::PendingApproval { .. } => {}
+            MultiTurnStreamItem::FinalResponse(response) => final_response = response,
+        }
+    }
+
+    Ok((collector.finish(), final_response))
+}
+
+/// Groups streamed items into per-turn assistant messages followed by their tool results.
+#[derive(Default)]
+struct MessageCollector {
+    messages: Vec<Message>,
+    assistant: Vec<AssistantContent>,
+    tool_results: Vec<ToolResult>,
+}
+
+impl MessageCollector {
+    fn push_assistant<R>(&mut self, content: StreamedAssistantContent<R>) {
+        let content = match content {
+            StreamedAssistantContent::Text(text) if text.text.is_empty() => return,
+            StreamedAssistantContent::Text(text) => AssistantContent::Text(text),
+            StreamedAssistantContent::Reasoning(reasoning) => {
+                AssistantContent::Reasoning(reasoning)
             }
-            Err(err) => {
-                eprintln!("Error: {err}");
+            // The tools of a turn only run once all its calls were streamed, so a call after tool
+            // results starts the next turn.
+            StreamedAssistantContent::ToolCall(tool_call) => {
+                if !self.tool_results.is_empty() {
+                    self.flush();
+                }
+                self.assistant.push(AssistantContent::ToolCall(tool_call));
+                return;
             }
-            _ => {}
+            StreamedAssistantContent::ToolCallDelta { .. }
+            | StreamedAssistantContent::ToolResult { .. }
+            | StreamedAssistantContent::Final(_) => return,
+        };
+
+        // Text or reasoning after tool results belongs to the next turn
+        if !self.tool_results.is_empty() {
+            self.flush();
+        }
+
+        match (self.assistant.last_mut(), content) {
+            (Some(AssistantContent::Text(last)), AssistantContent::Text(text)) => {
+                last.text.push_str(&text.text);
+            }
+            (Some(AssistantContent::Reasoning(last)), AssistantContent::Reasoning(reasoning)) => {
+                let merged = last.reasoning.concat() + &reasoning.reasoning.concat();
+                last.reasoning = vec![merged];
+                if reasoning.signature.is_some() {
+                    last.signature = reasoning.signature;
+                }
+            }
+            (_, content) => self.assistant.push(content),
         }
     }
 
-    Ok(final_res)
+    /// End the turn of a rejected answer with the prompt asking the model to repair it.
+    fn push_repair_prompt(&mut self, error: &str) {
+        self.flush();
+        self.messages.push(repair_prompt(error));
+    }
+
+    fn flush(&mut self) {
+        let assistant = std::mem::take(&mut self.assistant);
+
+        // Results follow the order of their calls, whatever order they arrived in. Results
+        // without a matching call keep their place after the others.
+        let position = |result: &ToolResult| {
+            assistant.iter().position(|content| match content {
+                AssistantContent::ToolCall(call) => match (&call.call_id, &result.call_id) {
+                    (Some(call_id), Some(result_call_id)) => call_id == result_call_id,
+                    _ => call.id == result.id,
+                },
+                _ => false,
+            })
+        };
+        self.tool_results
+            .sort_by_key(|result| position(result).unwrap_or(usize::MAX));
+
+        if let Ok(content) = OneOrMany::many(assistant) {
+            self.messages.push(Message::Assistant { id: None, content });
+        }
+
+        self.messages.extend(
+            self.tool_results
+                .drain(..)
+                .map(|tool_result| Message::User {
+                    content: OneOrMany::one(UserContent::ToolResult(tool_result)),
+                }),
+        );
+    }
+
+    fn finish(mut self) -> Vec<Message> {
+        self.flush();
+        self.messages
+    }
 }
 
 // dead code allowed because of functions being left empty to allow for users to not have to implement every single function
@@ -588,3 +1494,598 @@ where
 }
 
 impl<M> StreamingPromptHook<M> for () where M: CompletionModel {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::AgentBuilder,
+        completion::ToolDefinition,
+        message::{ToolCall, ToolFunction},
+        streaming::StreamingChat,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct AddArgs {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Math error")]
+    struct MathError;
+
+    struct Adder;
+
+    impl Tool for Adder {
+        const NAME: &'static str = "add";
+        type Error = MathError;
+        type Args = AddArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: "add".to_string(),
+                description: "Add x and y together".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "x": {"type": "number"},
+                        "y": {"type": "number"}
+                    },
+                    "required": ["x", "y"]
+                }),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x + args.y)
+        }
+    }
+
+    fn add_call(id: &str, call_id: Option<&str>, x: i32, y: i32) -> AssistantContent {
+        AssistantContent::ToolCall(ToolCall {
+            id: id.to_string(),
+            call_id: call_id.map(str::to_string),
+            function: ToolFunction {
+                name: "add".to_string(),
+                arguments: json!({"x": x, "y": y}),
+            },
+        })
+    }
+
+    fn tool_result_message(id: &str, call_id: Option<&str>, text: &str) -> Message {
+        Message::User {
+            content: OneOrMany::one(UserContent::ToolResult(ToolResult {
+                id: id.to_string(),
+                call_id: call_id.map(str::to_string),
+                content: OneOrMany::one(ToolResultContent::text(text)),
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_collect_text_only() {
+        let agent = AgentBuilder::new(MockCompletionModel::new().with_turn(vec![
+            AssistantContent::text("Hello, "),
+            AssistantContent::text("world"),
+        ]))
+        .build();
+
+        let mut items = 0;
+        let (messages, final_response) =
+            stream_collect(agent.stream_chat("hi", vec![]), |_| items += 1)
+                .await
+                .unwrap();
+
+        assert_eq!(
+            messages,
+            vec![Message::user("hi"), Message::assistant("Hello, world")]
+        );
+        assert_eq!(final_response.response(), "Hello, world");
+        assert!(items > 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_collect_orders_tool_turns() {
+        let model = MockCompletionModel::new()
+            .with_turn(vec![
+                AssistantContent::Reasoning(Reasoning::new("Let me ")),
+                AssistantContent::Reasoning(Reasoning::new("add.")),
+                add_call("call_a", Some("c1"), 1, 2),
+                add_call("call_b", None, 3, 4),
+            ])
+            .with_text("The sums are 3 and 7");
+        let agent = AgentBuilder::new(model).tool(Adder).build();
+
+        let (messages, final_response) = stream_collect(
+            agent.stream_chat("add things", vec![]).multi_turn(3),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            messages,
+            vec![
+                Message::user("add things"),
+                Message::Assistant {
+                    id: None,
+                    content: OneOrMany::many(vec![
+                        AssistantContent::Reasoning(Reasoning::new("Let me add.")),
+                        add_call("call_a", Some("c1"), 1, 2),
+                        add_call("call_b", None, 3, 4),
+                    ])
+                    .unwrap(),
+                },
+                tool_result_message("call_a", Some("c1"), "3"),
+                tool_result_message("call_b", None, "7"),
+                Message::assistant("The sums are 3 and 7"),
+            ]
+        );
+        assert_eq!(final_response.response(), "The sums are 3 and 7");
+    }
+
+    #[tokio::test]
+    async fn test_collect_stream_interleaves_tool_results_by_call() {
+        let result = |id: &str, call_id: Option<&str>, text: &str| {
+            Ok(MultiTurnStreamItem::StreamUserItem(
+                StreamedUserContent::tool_result(
+                    ToolResult {
+                        id: id.to_string(),
+                        call_id: call_id.map(str::to_string),
+                        content: OneOrMany::one(ToolResultContent::text(text)),
+                    },
+                    "add",
+                ),
+            ))
+        };
+        let call = |id: &str, call_id: Option<&str>, x, y| {
+            let AssistantContent::ToolCall(call) = add_call(id, call_id, x, y) else {
+                unreachable!()
+            };
+            Ok(MultiTurnStreamItem::stream_item(
+                StreamedAssistantContent::ToolCall(call),
+            ))
+        };
+        // Two calls in the first turn, answered out of order, then a second tool-only turn
+        let items: Vec<Result<MultiTurnStreamItem<()>, StreamingError>> = vec![
+            call("call_a", Some("c1"), 1, 2),
+            call("call_b", Some("c2"), 3, 4),
+            result("call_b", Some("c2"), "7"),
+            result("call_a", Some("c1"), "3"),
+            call("call_c", None, 5, 6),
+            result("call_c", None, "11"),
+            Ok(MultiTurnStreamItem::stream_item(
+                StreamedAssistantContent::text("Done"),
+            )),
+        ];
+        let mut stream: StreamingResult<()> = Box::pin(futures::stream::iter(items));
+
+        let (messages, _) = collect_stream_to_messages(&mut stream, |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messages,
+            vec![
+                Message::Assistant {
+                    id: None,
+                    content: OneOrMany::many(vec![
+                        add_call("call_a", Some("c1"), 1, 2),
+                        add_call("call_b", Some("c2"), 3, 4),
+                    ])
+                    .unwrap(),
+                },
+                tool_result_message("call_a", Some("c1"), "3"),
+                tool_result_message("call_b", Some("c2"), "7"),
+                Message::Assistant {
+                    id: None,
+                    content: OneOrMany::one(add_call("call_c", None, 5, 6)),
+                },
+                tool_result_message("call_c", None, "11"),
+                Message::assistant("Done"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_collect_empty_final_text() {
+        let model = MockCompletionModel::new()
+            .with_turn(vec![add_call("call_a", None, 1, 1)])
+            .with_text("");
+        let agent = AgentBuilder::new(model).tool(Adder).build();
+
+        let (messages, final_response) =
+            stream_collect(agent.stream_chat("add", vec![]).multi_turn(3), |_| {})
+                .await
+                .unwrap();
+
+        assert_eq!(
+            messages,
+            vec![
+                Message::user("add"),
+                Message::Assistant {
+                    id: None,
+                    content: OneOrMany::one(add_call("call_a", None, 1, 1)),
+                },
+                tool_result_message("call_a", None, "2"),
+            ]
+        );
+        assert_eq!(final_response.response(), "");
+    }
+
+    fn always_calling_tools() -> MockCompletionModel {
+        (0..10)
+            .fold(MockCompletionModel::new(), |model, i| {
+                model.with_turn(vec![add_call(&format!("call_{i}"), None, i, 1)])
+            })
+            .with_usage(crate::completion::Usage {
+                input_tokens: 10,
+                output_tokens: 2,
+                total_tokens: 12,
+            })
+    }
+
+    fn expected_max_depth_history(turns: i32) -> Vec<Message> {
+        let mut history = vec![Message::user("keep adding")];
+        for i in 0..turns {
+            history.push(Message::Assistant {
+                id: None,
+                content: OneOrMany::one(add_call(&format!("call_{i}"), None, i, 1)),
+            });
+            history.push(tool_result_message(
+                &format!("call_{i}"),
+                None,
+                &(i + 1).to_string(),
+            ));
+        }
+        history
+    }
+
+    fn assert_max_depth_error(err: &PromptError, turns: i32) {
+        let PromptError::MaxDepthError {
+            max_depth,
+            chat_history,
+            usage,
+            last_tool_calls,
+            ..
+        } = err
+        else {
+            panic!("expected MaxDepthError, got {err:?}");
+        };
+
+        assert_eq!(*max_depth, 2);
+        assert_eq!(**chat_history, expected_max_depth_history(turns));
+        assert_eq!(err.chat_history(), Some(chat_history.as_slice()));
+        assert_eq!(usage.total_tokens, 12 * turns as u64);
+        assert_eq!(last_tool_calls.len(), 1);
+        assert_eq!(last_tool_calls[0].id, format!("call_{}", turns - 1));
+    }
+
+    #[tokio::test]
+    async fn test_max_depth_error_keeps_history() {
+        use crate::completion::Prompt;
+
+        let agent = AgentBuilder::new(always_calling_tools())
+            .tool(Adder)
+            .build();
+
+        let err = agent.prompt("keep adding").multi_turn(2).await.unwrap_err();
+
+        assert_max_depth_error(&err, 4);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_max_depth_error_keeps_history() {
+        let agent = AgentBuilder::new(always_calling_tools())
+            .tool(Adder)
+            .build();
+
+        let mut stream = agent.stream_chat("keep adding", vec![]).multi_turn(2).await;
+        let mut last_item = None;
+        while let Some(item) = stream.next().await {
+            last_item = Some(item);
+        }
+
+        // The stream ends with the max depth error
+        match last_item {
+            Some(Err(StreamingError::Prompt(err))) => assert_max_depth_error(&err, 4),
+            _ => panic!("expected the stream to end with a MaxDepthError"),
+        }
+    }
+
+    fn thinking_adder() -> Agent<MockCompletionModel> {
+        let model = MockCompletionModel::new()
+            .with_turn(vec![
+                AssistantContent::Reasoning(Reasoning::new("thinking")),
+                add_call("call_1", None, 1, 2),
+            ])
+            .with_text("The sum is 3");
+        AgentBuilder::new(model).tool(Adder).build()
+    }
+
+    async fn render(options: PrintOptions) -> String {
+        let agent = thinking_adder();
+        let mut stream = agent.stream_chat("add", vec![]).multi_turn(3).await;
+        let mut out = Vec::new();
+
+        let final_response = write_stream(&mut stream, &mut out, options).await.unwrap();
+
+        assert_eq!(final_response.response(), "The sum is 3");
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_default_options() {
+        let output = render(PrintOptions::default()).await;
+
+        assert_eq!(
+            output,
+            "Response: thinking\n[Tool call] call_1: add({\"x\":1,\"y\":2})\nResponse: \n\
+             \n[Tool Result] call_1: add -> 3\nResponse: The sum is 3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_hides_everything_but_text() {
+        let options = PrintOptions {
+            show_tool_calls: false,
+            show_tool_results: false,
+            show_reasoning: false,
+            ..Default::default()
+        };
+
+        assert_eq!(render(options).await, "Response: The sum is 3");
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_color() {
+        let output = render(PrintOptions {
+            color: true,
+            ..Default::default()
+        })
+        .await;
+
+        assert!(output.contains(&format!("{ANSI_DIM}thinking{ANSI_RESET}")));
+        assert!(output.contains(&format!("{ANSI_CYAN}[Tool call] call_1")));
+        assert!(output.contains(&format!(
+            "{ANSI_GREEN}[Tool Result] call_1: add -> 3{ANSI_RESET}"
+        )));
+        assert!(!render(PrintOptions::default()).await.contains('\x1b'));
+    }
+
+    // A run with reasoning, a retried tool call and its result
+    fn fixture_stream() -> StreamingResult<()> {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            call_id: None,
+            function: ToolFunction {
+                name: "search".to_string(),
+                arguments: json!({"q": "rig"}),
+            },
+        };
+        let items: Vec<Result<MultiTurnStreamItem<()>, StreamingError>> = vec![
+            Ok(MultiTurnStreamItem::stream_item(
+                StreamedAssistantContent::Reasoning(Reasoning::new("planning")),
+            )),
+            Ok(MultiTurnStreamItem::stream_item(
+                StreamedAssistantContent::ToolCall(call),
+            )),
+            Ok(MultiTurnStreamItem::ToolRetry(ToolRetry {
+                id: "call_1".to_string(),
+                call_id: None,
+                tool_name: "search".to_string(),
+                attempt: 1,
+                error: "timeout".to_string(),
+            })),
+            Ok(MultiTurnStreamItem::StreamUserItem(
+                StreamedUserContent::tool_result(
+                    ToolResult {
+                        id: "call_1".to_string(),
+                        call_id: None,
+                        content: OneOrMany::one(ToolResultContent::text("found")),
+                    },
+                    "search",
+                ),
+            )),
+            Ok(MultiTurnStreamItem::stream_item(
+                StreamedAssistantContent::text("Done"),
+            )),
+            Ok(MultiTurnStreamItem::final_response(
+                "Done",
+                crate::completion::Usage::new(),
+            )),
+        ];
+        Box::pin(futures::stream::iter(items))
+    }
+
+    async fn render_fixture(options: PrintOptions) -> String {
+        let mut stream = fixture_stream();
+        let mut out = Vec::new();
+
+        let final_response = write_stream(&mut stream, &mut out, options).await.unwrap();
+
+        assert_eq!(final_response.response(), "Done");
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_verbose_fixture() {
+        assert_eq!(
+            render_fixture(PrintOptions::verbose()).await,
+            format!(
+                "Response: {ANSI_DIM}planning{ANSI_RESET}\n\
+                 {ANSI_CYAN}[Tool call] call_1: search({{\"q\":\"rig\"}}){ANSI_RESET}\n\
+                 Response: \n\n\
+                 {ANSI_YELLOW}[Tool retry] call_1: search attempt 1 failed: timeout{ANSI_RESET}\n\
+                 Response: \n\
+                 {ANSI_GREEN}[Tool Result] call_1: search -> found{ANSI_RESET}\n\
+                 Response: Done"
+            )
+        );
+        assert_eq!(
+            render_fixture(PrintOptions::text_only()).await,
+            "Response: Done"
+        );
+    }
+
+    #[test]
+    fn test_truncate_output() {
+        assert_eq!(truncate_output("short", Some(10)), "short");
+        assert_eq!(truncate_output("exactly10!", Some(10)), "exactly10!");
+        assert_eq!(
+            truncate_output("a long tool output", None),
+            "a long tool output"
+        );
+        assert_eq!(
+            truncate_output("a long tool output", Some(6)),
+            "a long… (12 more bytes)"
+        );
+        // "é" is two bytes: never cut inside it
+        assert_eq!(
+            truncate_output("café au lait", Some(4)),
+            "caf… (10 more bytes)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_to_writer_matches_stdout_output() {
+        let agent = thinking_adder();
+        let mut stream = agent.stream_chat("add", vec![]).multi_turn(3).await;
+        let mut out = Vec::new();
+
+        let final_response = stream_to_writer(&mut stream, &mut out).await.unwrap();
+
+        assert_eq!(final_response.response(), "The sum is 3");
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            render(PrintOptions::default()).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_to_writer_writes_errors() {
+        let agent = AgentBuilder::new(
+            MockCompletionModel::new().with_stream_error("503 Service Unavailable"),
+        )
+        .build();
+        let mut stream = agent.stream_chat("hi", vec![]).await;
+        let mut out = Vec::new();
+
+        stream_to_writer(&mut stream, &mut out).await.unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Response: \nError: CompletionError: ProviderError: 503 Service Unavailable\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_to_channel_events() {
+        let agent = thinking_adder();
+        let stream = agent.stream_chat("add", vec![]).multi_turn(3).await;
+
+        let mut receiver = stream_to_channel(stream);
+        let mut events = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Reasoning {
+                    reasoning: "thinking".to_string()
+                },
+                StreamEvent::ToolCall {
+                    id: "call_1".to_string(),
+                    call_id: None,
+                    name: "add".to_string(),
+                    arguments: json!({"x": 1, "y": 2}),
+                },
+                StreamEvent::ToolResult {
+                    id: "call_1".to_string(),
+                    call_id: None,
+                    name: "add".to_string(),
+                    content: "3".to_string(),
+                },
+                StreamEvent::Text {
+                    text: "The sum is 3".to_string()
+                },
+                StreamEvent::Final {
+                    response: "The sum is 3".to_string(),
+                    usage: crate::completion::Usage::new(),
+                },
+            ]
+        );
+
+        let serialized: Vec<_> = events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect();
+        assert_eq!(
+            serialized[1],
+            json!({"type": "tool_call", "id": "call_1", "name": "add", "arguments": {"x": 1, "y": 2}})
+        );
+        let types: Vec<_> = serialized
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            ["reasoning", "tool_call", "tool_result", "text", "final"]
+        );
+        for (event, value) in events.iter().zip(&serialized) {
+            assert_eq!(event.event_type(), value["type"]);
+        }
+        for (event, value) in events.iter().zip(serialized) {
+            assert_eq!(
+                &serde_json::from_value::<StreamEvent>(value).unwrap(),
+                event
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streamed_tool_results_carry_call_id_and_tool_name() {
+        let model = MockCompletionModel::new()
+            .with_turn(vec![
+                add_call("call_a", Some("c1"), 1, 2),
+                add_call("call_b", None, 3, 4),
+            ])
+            .with_text("The sums are 3 and 7");
+        let agent = AgentBuilder::new(model).tool(Adder).build();
+
+        let mut results = Vec::new();
+        stream_collect(
+            agent.stream_chat("add things", vec![]).multi_turn(3),
+            |item| {
+                if let MultiTurnStreamItem::StreamUserItem(content) = item {
+                    results.push((
+                        content.id().to_string(),
+                        content.call_id().map(str::to_string),
+                        content.tool_name().to_string(),
+                    ));
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                (
+                    "call_a".to_string(),
+                    Some("c1".to_string()),
+                    "add".to_string()
+                ),
+                ("call_b".to_string(), None, "add".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,534 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_stream::stream;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    OneOrMany,
+    completion::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, Document,
+        GetTokenUsage, ToolDefinition, Usage,
+    },
+    message::{AssistantContent, Message, ToolCall, ToolChoice},
+    streaming::{RawStreamingChoice, StreamingCompletionResponse, StreamingResult},
+    wasm_compat::WasmBoxedFuture,
+};
+
+use super::AgentMiddleware;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("Failed to access recording `{}`: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Recording `{}` is corrupt at line {line}: {reason}", path.display())]
+    Corrupt {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+}
+
+/// A completion request as written to a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub preamble: Option<String>,
+    pub chat_history: Vec<Message>,
+    pub documents: Vec<Document>,
+    pub tools: Vec<ToolDefinition>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    pub tool_choice: Option<ToolChoice>,
+    pub additional_params: Option<serde_json::Value>,
+}
+
+impl From<&CompletionRequest> for RecordedRequest {
+    fn from(request: &CompletionRequest) -> Self {
+        Self {
+            preamble: request.preamble.clone(),
+            chat_history: request.chat_history.iter().cloned().collect(),
+            documents: request.documents.clone(),
+            tools: request.tools.clone(),
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            tool_choice: request.tool_choice.clone(),
+            additional_params: request.additional_params.clone(),
+        }
+    }
+}
+
+/// A model response as written to a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub choice: OneOrMany<AssistantContent>,
+    pub usage: Usage,
+}
+
+/// A line of a recording written by [RecordingLayer].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplayEntry {
+    /// A completion request and the model's response to it
+    Completion {
+        request: Box<RecordedRequest>,
+        response: RecordedResponse,
+    },
+    /// A tool call and the result sent back to the model
+    ToolCall { tool_call: ToolCall, result: String },
+}
+
+/// Records every completion request, model response and tool call of an agent to a JSONL file,
+/// one [ReplayEntry] per line, so the run can be replayed with [ReplayModel].
+///
+/// Register it as the last layer, so that it records the requests as the model receives them.
+/// Failures to write the recording are logged and do not fail the run.
+///
+/// # Example
+/// ```rust,ignore
+/// let agent = client
+///     .agent(qwen::QWEN_PLUS)
+///     .tool(SubmitCoatingTask)
+///     .layer(RecordingLayer::new("coating_run.jsonl")?)
+///     .build();
+/// agent.prompt("Design a TiAlN coating").multi_turn(5).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordingLayer(Arc<Mutex<Recording>>);
+
+#[derive(Debug)]
+struct Recording {
+    path: PathBuf,
+    file: File,
+    /// The request awaiting the model's response
+    pending: Option<RecordedRequest>,
+}
+
+impl RecordingLayer {
+    /// Record to `path`, replacing any existing file.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::create(&path).map_err(|source| ReplayError::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+        Ok(Self(Arc::new(Mutex::new(Recording {
+            path,
+            file,
+            pending: None,
+        }))))
+    }
+}
+
+impl Recording {
+    fn write(&mut self, entry: &ReplayEntry) {
+        let result = serde_json::to_vec(entry)
+            .map_err(std::io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                self.file.write_all(&line)?;
+                self.file.flush()
+            });
+
+        if let Err(e) = result {
+            tracing::warn!(
+                target: "rig::agent::replay",
+                path = %self.path.display(),
+                "Failed to write to the recording: {e}"
+            );
+        }
+    }
+}
+
+impl AgentMiddleware for RecordingLayer {
+    fn before_completion<'a>(
+        &'a self,
+        request: &'a mut CompletionRequest,
+    ) -> WasmBoxedFuture<'a, ()> {
+        self.0.lock().unwrap().pending = Some(RecordedRequest::from(&*request));
+        Box::pin(async {})
+    }
+
+    fn after_completion<'a>(
+        &'a self,
+        response: &'a CompletionResponse<()>,
+        _latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        let mut recording = self.0.lock().unwrap();
+        if let Some(request) = recording.pending.take() {
+            recording.write(&ReplayEntry::Completion {
+                request: Box::new(request),
+                response: RecordedResponse {
+                    choice: response.choice.clone(),
+                    usage: response.usage,
+                },
+            });
+        }
+        Box::pin(async {})
+    }
+
+    fn after_tool_call<'a>(
+        &'a self,
+        tool_call: &'a ToolCall,
+        result: &'a str,
+        _latency: Duration,
+    ) -> WasmBoxedFuture<'a, ()> {
+        self.0.lock().unwrap().write(&ReplayEntry::ToolCall {
+            tool_call: tool_call.clone(),
+            result: result.to_string(),
+        });
+        Box::pin(async {})
+    }
+}
+
+/// Final streaming response yielded by [ReplayModel].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayStreamingResponse {
+    pub usage: Usage,
+}
+
+impl GetTokenUsage for ReplayStreamingResponse {
+    fn token_usage(&self) -> Option<Usage> {
+        Some(self.usage)
+    }
+}
+
+/// A completion model answering with the responses of a recording written by [RecordingLayer],
+/// in order, without any network access.
+///
+/// Every request must match the recorded one, otherwise the completion fails with a
+/// [CompletionError::ResponseError] showing the difference. Tools are not replayed: the agent
+/// runs them again, and a tool returning something else shows up as a divergence of the next
+/// request.
+///
+/// # Example
+/// ```rust,ignore
+/// let agent = AgentBuilder::new(ReplayModel::from_file("coating_run.jsonl")?)
+///     .tool(SubmitCoatingTask)
+///     .build();
+/// agent.prompt("Design a TiAlN coating").multi_turn(5).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReplayModel(Arc<Mutex<Replay>>);
+
+#[derive(Debug, Default)]
+struct Replay {
+    completions: VecDeque<(RecordedRequest, RecordedResponse)>,
+    /// Number of completions replayed so far
+    replayed: usize,
+}
+
+impl ReplayModel {
+    /// Replay the completions of `entries`, in order.
+    pub fn new(entries: impl IntoIterator<Item = ReplayEntry>) -> Self {
+        let completions = entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                ReplayEntry::Completion { request, response } => Some((*request, response)),
+                ReplayEntry::ToolCall { .. } => None,
+            })
+            .collect();
+
+        Self(Arc::new(Mutex::new(Replay {
+            completions,
+            replayed: 0,
+        })))
+    }
+
+    /// Replay a recording written by [RecordingLayer].
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| ReplayError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let mut entries = vec![];
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|source| ReplayError::Io {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).map_err(|e| ReplayError::Corrupt {
+                path: path.to_path_buf(),
+                line: index + 1,
+                reason: e.to_string(),
+            })?;
+            entries.push(entry);
+        }
+
+        Ok(Self::new(entries))
+    }
+
+    /// Number of recorded completions not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.0.lock().unwrap().completions.len()
+    }
+
+    fn next_response(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<RecordedResponse, CompletionError> {
+        let mut replay = self.0.lock().unwrap();
+        replay.replayed += 1;
+        let number = replay.replayed;
+
+        let (recorded, response) = replay.completions.pop_front().ok_or_else(|| {
+            CompletionError::ResponseError(format!(
+                "Replay exhausted: no recorded response left for completion {number}"
+            ))
+        })?;
+
+        let recorded = serde_json::to_value(recorded)?;
+        let actual = serde_json::to_value(RecordedRequest::from(request))?;
+        if recorded != actual {
+            return Err(CompletionError::ResponseError(format!(
+                "Replay diverged at completion {number}, the request differs from the recording:\n{}",
+                diff(
+                    &serde_json::to_string_pretty(&recorded)?,
+                    &serde_json::to_string_pretty(&actual)?
+                )
+            )));
+        }
+
+        Ok(response)
+    }
+}
+
+/// Number of unchanged lines shown around the changed ones.
+const DIFF_CONTEXT: usize = 2;
+
+/// A line diff of `recorded` and `actual`, showing the changed block between their common start
+/// and end, with removed lines prefixed by `-` and added lines by `+`.
+fn diff(recorded: &str, actual: &str) -> String {
+    let recorded: Vec<_> = recorded.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+
+    let prefix = recorded
+        .iter()
+        .zip(&actual)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = recorded[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let context_start = prefix.saturating_sub(DIFF_CONTEXT);
+    let context_end = (recorded.len() - suffix + DIFF_CONTEXT).min(recorded.len());
+
+    let mut out = vec![];
+    out.extend(
+        recorded[context_start..prefix]
+            .iter()
+            .map(|line| format!("  {line}")),
+    );
+    out.extend(
+        recorded[prefix..recorded.len() - suffix]
+            .iter()
+            .map(|line| format!("- {line}")),
+    );
+    out.extend(
+        actual[prefix..actual.len() - suffix]
+            .iter()
+            .map(|line| format!("+ {line}")),
+    );
+    out.extend(
+        recorded[recorded.len() - suffix..context_end]
+            .iter()
+            .map(|line| format!("  {line}")),
+    );
+    out.join("\n")
+}
+
+impl CompletionModel for ReplayModel {
+    type Response = ();
+    type StreamingResponse = ReplayStreamingResponse;
+    type Client = ();
+
+    fn make(_client: &Self::Client, _model: impl Into<String>) -> Self {
+        Self::default()
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        let response = self.next_response(&request)?;
+
+        Ok(CompletionResponse {
+            choice: response.choice,
+            usage: response.usage,
+            raw_response: (),
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<ReplayStreamingResponse>, CompletionError> {
+        let RecordedResponse { choice, usage } = self.next_response(&request)?;
+
+        let stream: StreamingResult<ReplayStreamingResponse> = Box::pin(stream! {
+            for content in choice {
+                match content {
+                    AssistantContent::Text(text) => {
+                        yield Ok(RawStreamingChoice::Message(text.text));
+                    }
+                    AssistantContent::ToolCall(tool_call) => {
+                        yield Ok(RawStreamingChoice::ToolCall {
+                            id: tool_call.id,
+                            call_id: tool_call.call_id,
+                            name: tool_call.function.name,
+                            arguments: tool_call.function.arguments,
+                        });
+                    }
+                    AssistantContent::Reasoning(reasoning) => {
+                        yield Ok(RawStreamingChoice::Reasoning {
+                            id: reasoning.id,
+                            reasoning: reasoning.reasoning.join(""),
+                            signature: reasoning.signature,
+                        });
+                    }
+                }
+            }
+            yield Ok(RawStreamingChoice::FinalResponse(ReplayStreamingResponse { usage }));
+        });
+
+        Ok(StreamingCompletionResponse::stream(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::AgentBuilder,
+        completion::{Prompt, PromptError},
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[derive(Deserialize)]
+    struct AddArgs {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Math error")]
+    struct MathError;
+
+    struct Adder;
+
+    impl Tool for Adder {
+        const NAME: &'static str = "add";
+        type Error = MathError;
+        type Args = AddArgs;
+        type Output = i32;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: "add".to_string(),
+                description: "Add x and y together".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x + args.y)
+        }
+    }
+
+    /// Record a run adding 2 and 3 with a tool, returning the recording.
+    async fn record(dir: &assert_fs::TempDir) -> PathBuf {
+        let path = dir.path().join("run.jsonl");
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "add", json!({"x": 2, "y": 3}))
+            .with_text("5");
+        let agent = AgentBuilder::new(model)
+            .preamble("You add numbers")
+            .tool(Adder)
+            .layer(RecordingLayer::new(&path).unwrap())
+            .build();
+
+        let response = agent.prompt("Add 2 and 3").multi_turn(2).await.unwrap();
+        assert_eq!(response, "5");
+        path
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = record(&dir).await;
+
+        let recording = std::fs::read_to_string(&path).unwrap();
+        let types: Vec<_> = recording
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["type"].clone())
+            .collect();
+        assert_eq!(types, ["completion", "tool_call", "completion"]);
+
+        let model = ReplayModel::from_file(&path).unwrap();
+        let agent = AgentBuilder::new(model.clone())
+            .preamble("You add numbers")
+            .tool(Adder)
+            .build();
+        let response = agent.prompt("Add 2 and 3").multi_turn(2).await.unwrap();
+
+        assert_eq!(response, "5");
+        assert_eq!(model.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_replay_detects_divergence() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = record(&dir).await;
+
+        let agent = AgentBuilder::new(ReplayModel::from_file(&path).unwrap())
+            .preamble("You add numbers")
+            .tool(Adder)
+            .build();
+        let error = agent.prompt("Add 2 and 4").multi_turn(2).await.unwrap_err();
+
+        let PromptError::CompletionError(CompletionError::ResponseError(message)) = error else {
+            panic!("unexpected error: {error}");
+        };
+        assert!(message.starts_with("Replay diverged at completion 1"));
+        let changed: Vec<_> = message
+            .lines()
+            .filter(|line| line.starts_with(['-', '+']))
+            .collect();
+        assert_eq!(changed.len(), 2);
+        assert!(changed[0].starts_with('-') && changed[0].contains("Add 2 and 3"));
+        assert!(changed[1].starts_with('+') && changed[1].contains("Add 2 and 4"));
+    }
+
+    #[test]
+    fn test_from_file_reports_corrupt_line() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("run.jsonl");
+        std::fs::write(&path, "\n{ not json\n").unwrap();
+
+        let error = ReplayModel::from_file(&path).unwrap_err();
+
+        assert!(matches!(error, ReplayError::Corrupt { line: 2, .. }));
+    }
+}
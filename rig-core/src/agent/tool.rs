@@ -1,10 +1,13 @@
+use std::sync::Arc;
+
 use crate::{
-    agent::Agent,
-    completion::{CompletionModel, Prompt, PromptError, ToolDefinition},
+    agent::{Agent, PromptResponse, UsageAccumulator},
+    completion::{CompletionModel, Message, Prompt, PromptError, ToolDefinition},
     tool::Tool,
 };
 use schemars::{JsonSchema, schema_for};
 use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AgentToolArgs {
@@ -41,10 +44,306 @@ impl<M: CompletionModel> Tool for Agent<M> {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        self.prompt(args.prompt).await
+        let response = self.prompt(args.prompt).extended_details().await?;
+        record_usage(&<Self as Tool>::name(self), &response);
+
+        Ok(response.output)
     }
 
     fn name(&self) -> String {
         self.name.clone().unwrap_or_else(|| Self::NAME.to_string())
     }
 }
+
+/// Wraps an [Agent] so it can be registered as a tool on another agent.
+///
+/// Unlike the blanket `Tool` implementation on [Agent], the tool name and description are
+/// stored per instance, so several sub-agents can be registered on the same orchestrator.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::{agent::AgentTool, providers::openai};
+///
+/// let openai = openai::Client::from_env();
+///
+/// let researcher = openai.agent("gpt-4o").preamble("You research topics.").build();
+/// let writer = openai.agent("gpt-4o").preamble("You write reports.").build();
+///
+/// let orchestrator = openai.agent("gpt-4o")
+///     .tool(researcher.into_tool("researcher", "Research a topic in depth").multi_turn(3))
+///     .tool(AgentTool::new(writer, "writer", "Write a report from research notes"))
+///     .build();
+/// ```
+pub struct AgentTool<M: CompletionModel> {
+    agent: Agent<M>,
+    name: String,
+    description: String,
+    multi_turn: Option<usize>,
+    history: Option<Arc<RwLock<Vec<Message>>>>,
+}
+
+impl<M: CompletionModel> AgentTool<M> {
+    /// Create a new agent tool with the given tool name and description
+    pub fn new(agent: Agent<M>, name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            agent,
+            name: name.into(),
+            description: description.into(),
+            multi_turn: None,
+            history: None,
+        }
+    }
+
+    /// Set the maximum number of tool-calling turns the wrapped agent may take per call.
+    /// Defaults to the [max_turns](crate::agent::AgentBuilder::max_turns) of the agent.
+    pub fn multi_turn(mut self, depth: usize) -> Self {
+        self.multi_turn = Some(depth);
+        self
+    }
+
+    /// Forward a shared chat history to the wrapped agent on every call.
+    ///
+    /// The history is read when the tool is called; the sub-agent's exchange is not written back.
+    pub fn with_history(mut self, history: Arc<RwLock<Vec<Message>>>) -> Self {
+        self.history = Some(history);
+        self
+    }
+}
+
+impl<M: CompletionModel> Agent<M> {
+    /// Wrap this agent in an [AgentTool] with the given tool name and description
+    pub fn into_tool(
+        self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> AgentTool<M> {
+        AgentTool::new(self, name, description)
+    }
+}
+
+impl<M: CompletionModel> Tool for AgentTool<M> {
+    const NAME: &'static str = "agent_tool";
+
+    type Error = PromptError;
+    type Args = AgentToolArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: serde_json::to_value(schema_for!(AgentToolArgs))
+                .expect("converting JSON schema to JSON value should never fail"),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let mut history = match &self.history {
+            Some(history) => history.read().await.clone(),
+            None => Vec::new(),
+        };
+
+        let response = self
+            .agent
+            .prompt(args.prompt)
+            .multi_turn(self.multi_turn.unwrap_or(self.agent.max_turns))
+            .with_history(&mut history)
+            .extended_details()
+            .await?;
+        record_usage(&self.name, &response);
+
+        Ok(response.output)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Add the usage of a sub-agent to the prompt request running it as a tool, if any.
+fn record_usage(name: &str, response: &PromptResponse) {
+    if let Some(usage) = UsageAccumulator::current() {
+        usage.record_agent(name, response.total_usage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        agent::AgentBuilder,
+        completion::message::{
+            AssistantContent, ToolCall, ToolFunction, ToolResultContent, UserContent,
+        },
+        test_utils::MockCompletionModel,
+        tool::ToolSet,
+    };
+
+    fn sub_agent(model: MockCompletionModel) -> Agent<MockCompletionModel> {
+        AgentBuilder::new(model).build()
+    }
+
+    #[tokio::test]
+    async fn test_two_agent_tools_are_distinct_in_toolset() {
+        let toolset = ToolSet::from_tools(vec![
+            sub_agent(MockCompletionModel::new()).into_tool("researcher", "Research a topic"),
+            sub_agent(MockCompletionModel::new()).into_tool("writer", "Write a report"),
+        ]);
+
+        assert!(toolset.contains("researcher"));
+        assert!(toolset.contains("writer"));
+
+        let mut definitions = toolset.get_tool_definitions().await.unwrap();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(definitions.len(), 2);
+        assert_eq!(definitions[0].name, "researcher");
+        assert_eq!(definitions[0].description, "Research a topic");
+        assert_eq!(definitions[1].name, "writer");
+        assert_eq!(definitions[1].description, "Write a report");
+    }
+
+    #[tokio::test]
+    async fn test_agent_tool_call_forwards_history() {
+        let model = MockCompletionModel::new().with_text("research notes");
+        let history = Arc::new(RwLock::new(vec![Message::user("Earlier question")]));
+        let tool = sub_agent(model.clone())
+            .into_tool("researcher", "Research a topic")
+            .with_history(history.clone());
+
+        let output = tool
+            .call(AgentToolArgs {
+                prompt: "Find sources".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(output, "research notes");
+
+        let requests = model.requests();
+        assert_eq!(requests.len(), 1);
+        let sent: Vec<Message> = requests[0].chat_history.clone().into_iter().collect();
+        assert_eq!(
+            sent,
+            vec![
+                Message::user("Earlier question"),
+                Message::user("Find sources")
+            ]
+        );
+        // The caller's history is left untouched
+        assert_eq!(history.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_orchestrator_routes_to_named_agent_tool() {
+        let researcher = MockCompletionModel::new().with_text("research notes");
+        let writer = MockCompletionModel::new().with_text("final report");
+        let orchestrator_model = MockCompletionModel::new()
+            .with_tool_call(
+                "call_1",
+                "writer",
+                serde_json::json!({"prompt": "Write it up"}),
+            )
+            .with_text("done");
+
+        let orchestrator = AgentBuilder::new(orchestrator_model.clone())
+            .tool(sub_agent(researcher.clone()).into_tool("researcher", "Research a topic"))
+            .tool(sub_agent(writer.clone()).into_tool("writer", "Write a report"))
+            .build();
+
+        let response = orchestrator
+            .prompt("Write a report")
+            .multi_turn(2)
+            .await
+            .unwrap();
+        assert_eq!(response, "done");
+
+        let tool_names: Vec<String> = orchestrator_model.requests()[0]
+            .tools
+            .iter()
+            .map(|tool| tool.name.clone())
+            .collect();
+        assert!(tool_names.contains(&"researcher".to_string()));
+        assert!(tool_names.contains(&"writer".to_string()));
+        assert_eq!(writer.requests().len(), 1);
+        assert!(researcher.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_orchestrator_runs_agent_tools_concurrently() {
+        let latency = Duration::from_millis(200);
+        let researcher = MockCompletionModel::new()
+            .with_text("research notes")
+            .with_latency(latency);
+        let writer = MockCompletionModel::new()
+            .with_text("final report")
+            .with_latency(latency);
+        let orchestrator_model = MockCompletionModel::new()
+            .with_turn(vec![
+                AssistantContent::ToolCall(ToolCall {
+                    id: "call_1".to_string(),
+                    call_id: None,
+                    function: ToolFunction {
+                        name: "researcher".to_string(),
+                        arguments: serde_json::json!({"prompt": "Find sources"}),
+                    },
+                }),
+                AssistantContent::ToolCall(ToolCall {
+                    id: "call_2".to_string(),
+                    call_id: None,
+                    function: ToolFunction {
+                        name: "writer".to_string(),
+                        arguments: serde_json::json!({"prompt": "Write it up"}),
+                    },
+                }),
+            ])
+            .with_text("done");
+
+        let orchestrator = AgentBuilder::new(orchestrator_model.clone())
+            .tool(sub_agent(researcher.clone()).into_tool("researcher", "Research a topic"))
+            .tool(sub_agent(writer.clone()).into_tool("writer", "Write a report"))
+            .parallel_tool_calls(2)
+            .build();
+
+        let start = Instant::now();
+        let response = orchestrator
+            .prompt("Write a report")
+            .multi_turn(2)
+            .await
+            .unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(response, "done");
+
+        // Run one after the other, the sub-agents would take at least twice their latency
+        assert!(
+            elapsed < latency * 2,
+            "sub-agents did not overlap: {elapsed:?}"
+        );
+        assert_eq!(researcher.requests().len(), 1);
+        assert_eq!(writer.requests().len(), 1);
+
+        // Results are fed back in the order of the calls, as JSON encoded tool outputs
+        let results: Vec<(String, String)> = orchestrator_model.requests()[1]
+            .chat_history
+            .iter()
+            .flat_map(|message| match message {
+                Message::User { content } => content.iter().cloned().collect(),
+                _ => vec![],
+            })
+            .filter_map(|content| match content {
+                UserContent::ToolResult(result) => match result.content.first() {
+                    ToolResultContent::Text(text) => Some((result.id, text.text)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                ("call_1".to_string(), "\"research notes\"".to_string()),
+                ("call_2".to_string(), "\"final report\"".to_string()),
+            ]
+        );
+    }
+}
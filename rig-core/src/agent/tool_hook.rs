@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::message::ToolCall;
+
+/// A tool call about to be executed by an agent, as seen by its
+/// [tool call hooks](crate::agent::AgentBuilder::on_tool_call).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallInfo {
+    /// The id of the tool call
+    pub id: String,
+    pub call_id: Option<String>,
+    pub name: String,
+    /// The arguments the tool will be called with
+    pub args: serde_json::Value,
+}
+
+impl From<&ToolCall> for ToolCallInfo {
+    fn from(tool_call: &ToolCall) -> Self {
+        Self {
+            id: tool_call.id.clone(),
+            call_id: tool_call.call_id.clone(),
+            name: tool_call.function.name.clone(),
+            args: tool_call.function.arguments.clone(),
+        }
+    }
+}
+
+/// What an agent should do with a tool call, as decided by a tool call hook.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookDecision {
+    /// Execute the tool call
+    Allow,
+    /// Do not execute the tool call: the message is sent to the model as the tool result instead
+    Deny(String),
+    /// Execute the tool call with these arguments instead
+    Mutate(serde_json::Value),
+}
+
+/// A tool call handled by an agent, as seen by its
+/// [tool result hooks](crate::agent::AgentBuilder::on_tool_result).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResultInfo {
+    /// The id of the tool call
+    pub id: String,
+    pub call_id: Option<String>,
+    pub name: String,
+    /// The arguments the tool was called with, after any mutation
+    pub args: serde_json::Value,
+    /// The tool result sent to the model
+    pub result: String,
+    /// Whether the call was denied by a tool call hook, in which case `result` is the denial
+    pub denied: bool,
+}
+
+pub type ToolCallHook = Arc<dyn Fn(&ToolCallInfo) -> HookDecision + Send + Sync>;
+pub type ToolResultHook = Arc<dyn Fn(&ToolResultInfo) + Send + Sync>;
+
+/// The tool hooks of an agent. Unlike a [PromptHook](crate::agent::PromptHook), which is attached
+/// to a single request, these apply to every `prompt` and `stream_prompt` run of the agent, and
+/// can veto or rewrite tool calls.
+///
+/// Tool call hooks run in registration order, each seeing the arguments as mutated by the
+/// previous ones. The first hook denying a call wins.
+#[derive(Clone, Default)]
+pub struct ToolHooks {
+    on_call: Vec<ToolCallHook>,
+    on_result: Vec<ToolResultHook>,
+}
+
+impl std::fmt::Debug for ToolHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolHooks")
+            .field("on_call", &self.on_call.len())
+            .field("on_result", &self.on_result.len())
+            .finish()
+    }
+}
+
+impl ToolHooks {
+    pub fn on_call(
+        mut self,
+        hook: impl Fn(&ToolCallInfo) -> HookDecision + Send + Sync + 'static,
+    ) -> Self {
+        self.on_call.push(Arc::new(hook));
+        self
+    }
+
+    pub fn on_result(mut self, hook: impl Fn(&ToolResultInfo) + Send + Sync + 'static) -> Self {
+        self.on_result.push(Arc::new(hook));
+        self
+    }
+
+    /// Run the tool call hooks on `info`, returning the combined decision.
+    pub(crate) fn decide(&self, info: &ToolCallInfo) -> HookDecision {
+        let mut info = std::borrow::Cow::Borrowed(info);
+        let mut mutated = false;
+
+        for hook in &self.on_call {
+            match hook(info.as_ref()) {
+                HookDecision::Allow => {}
+                HookDecision::Deny(reason) => return HookDecision::Deny(reason),
+                HookDecision::Mutate(args) => {
+                    info.to_mut().args = args;
+                    mutated = true;
+                }
+            }
+        }
+
+        if mutated {
+            HookDecision::Mutate(info.into_owned().args)
+        } else {
+            HookDecision::Allow
+        }
+    }
+
+    /// Pass `info` to every tool result hook.
+    pub(crate) fn result(&self, info: &ToolResultInfo) {
+        for hook in &self.on_result {
+            hook(info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{Agent, AgentBuilder, stream_collect},
+        completion::{Prompt, ToolDefinition},
+        message::{Message, ToolResultContent, UserContent},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[derive(Deserialize)]
+    struct SubmitArgs {
+        points: u32,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Submit error")]
+    struct SubmitError;
+
+    /// A tool submitting a task, whose cost is its number of points.
+    struct Submit {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Tool for Submit {
+        const NAME: &'static str = "calphamesh_submit_line_task";
+        type Error = SubmitError;
+        type Args = SubmitArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Submit a line task".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {"points": {"type": "integer"}},
+                    "required": ["points"]
+                }),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("submitted {} points", args.points))
+        }
+    }
+
+    const MAX_POINTS: u64 = 100;
+
+    fn limit_cost(info: &ToolCallInfo) -> HookDecision {
+        if !info.name.starts_with("calphamesh_submit_") {
+            return HookDecision::Allow;
+        }
+        match info.args["points"].as_u64() {
+            Some(points) if points > MAX_POINTS => HookDecision::Deny(format!(
+                "Denied: {points} points exceed the limit of {MAX_POINTS}"
+            )),
+            _ => HookDecision::Allow,
+        }
+    }
+
+    fn submit_agent(
+        points: u32,
+        hooks: ToolHooks,
+    ) -> (Agent<MockCompletionModel>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let model = MockCompletionModel::new()
+            .with_tool_call(
+                "call_1",
+                "calphamesh_submit_line_task",
+                json!({ "points": points }),
+            )
+            .with_text("done");
+        let agent = AgentBuilder::new(model)
+            .tool(Submit {
+                calls: calls.clone(),
+            })
+            .tool_hooks(hooks)
+            .build();
+
+        (agent, calls)
+    }
+
+    fn tool_results(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                Message::User { content } => match content.first() {
+                    UserContent::ToolResult(result) => match result.content.first() {
+                        ToolResultContent::Text(text) => Some(text.text),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_denied_tool_call_is_reported_to_model() {
+        let logged = Arc::new(Mutex::new(Vec::new()));
+        let log = logged.clone();
+        let hooks = ToolHooks::default()
+            .on_call(limit_cost)
+            .on_result(move |info: &ToolResultInfo| log.lock().unwrap().push(info.clone()));
+        let (agent, calls) = submit_agent(500, hooks);
+        let model = agent.model.clone();
+
+        agent.prompt("submit").multi_turn(3).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        let history: Vec<_> = model.requests()[1].chat_history.iter().cloned().collect();
+        assert_eq!(
+            tool_results(&history),
+            vec!["Denied: 500 points exceed the limit of 100"]
+        );
+
+        let logged = logged.lock().unwrap();
+        assert_eq!(logged.len(), 1);
+        assert!(logged[0].denied);
+    }
+
+    #[tokio::test]
+    async fn test_denied_tool_call_is_reported_to_model_when_streaming() {
+        let (agent, calls) = submit_agent(500, ToolHooks::default().on_call(limit_cost));
+
+        let (messages, _) = stream_collect(agent.stream_prompt("submit").multi_turn(3), |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            tool_results(&messages),
+            vec!["Denied: 500 points exceed the limit of 100"]
+        );
+    }
+
+    fn capping_hooks() -> ToolHooks {
+        ToolHooks::default()
+            .on_call(|info: &ToolCallInfo| {
+                let points = info.args["points"].as_u64().unwrap_or_default();
+                HookDecision::Mutate(json!({ "points": points.min(MAX_POINTS) }))
+            })
+            .on_call(limit_cost)
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_arguments_can_be_mutated() {
+        let (agent, calls) = submit_agent(500, capping_hooks());
+        let model = agent.model.clone();
+
+        agent.prompt("submit").multi_turn(3).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let history: Vec<_> = model.requests()[1].chat_history.iter().cloned().collect();
+        assert_eq!(tool_results(&history), vec!["\"submitted 100 points\""]);
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_arguments_can_be_mutated_when_streaming() {
+        let (agent, calls) = submit_agent(500, capping_hooks());
+
+        let (messages, _) = stream_collect(agent.stream_prompt("submit").multi_turn(3), |_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(tool_results(&messages), vec!["\"submitted 100 points\""]);
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use schemars::{JsonSchema, schema_for};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    completion::{CompletionModel, CompletionModelDyn, Message, ToolDefinition},
+    message::AssistantContent,
+    tool::{Tool, ToolDyn},
+};
+
+use super::deferred_tools::{DeferredTools, ToolProviderError};
+
+const SUMMARIZER_PREAMBLE: &str = "You condense the output of a tool for an AI assistant. Keep \
+every identifier, number, status and error the assistant may rely on, and drop repetitive data. \
+Reply with the condensed output only.";
+
+/// How an agent shrinks tool outputs longer than `max_chars` characters before sending them to
+/// the model, so that huge payloads (eg. simulation results) don't fill the context window of
+/// every later turn.
+///
+/// Tool hooks and observers still see the full output. Outputs of the `fetch_tool_output` tool
+/// itself are never shrunk.
+///
+/// # Example
+/// ```rust,ignore
+/// let agent = AgentBuilder::new(model)
+///     .tool(GetTaskResult)
+///     .tool_output_policy(ToolOutputPolicy::store_and_reference(4_000))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ToolOutputPolicy {
+    /// Length (in characters) above which outputs are shrunk
+    pub max_chars: usize,
+    /// How outputs are shrunk
+    pub strategy: ToolOutputStrategy,
+    store: ToolOutputStore,
+}
+
+/// How a [ToolOutputPolicy] shrinks a tool output that is too long.
+#[derive(Clone)]
+pub enum ToolOutputStrategy {
+    /// Keep the first `max_chars` characters, followed by a note saying how much was cut off.
+    Truncate,
+    /// Ask the model (usually a cheaper one) to condense the output. Falls back to truncating
+    /// when the model fails.
+    Summarize(Arc<dyn CompletionModelDyn>),
+    /// Keep the full output in the policy's [ToolOutputStore], and send the model its first
+    /// `max_chars` characters along with a reference to read the rest through the
+    /// `fetch_tool_output` tool, which is registered on the agent automatically.
+    StoreAndReference,
+}
+
+impl std::fmt::Debug for ToolOutputStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolOutputStrategy::Truncate => write!(f, "Truncate"),
+            ToolOutputStrategy::Summarize(_) => write!(f, "Summarize"),
+            ToolOutputStrategy::StoreAndReference => write!(f, "StoreAndReference"),
+        }
+    }
+}
+
+impl ToolOutputPolicy {
+    pub fn new(max_chars: usize, strategy: ToolOutputStrategy) -> Self {
+        Self {
+            max_chars,
+            strategy,
+            store: ToolOutputStore::default(),
+        }
+    }
+
+    /// Create a [ToolOutputStrategy::Truncate] policy.
+    pub fn truncate(max_chars: usize) -> Self {
+        Self::new(max_chars, ToolOutputStrategy::Truncate)
+    }
+
+    /// Create a [ToolOutputStrategy::Summarize] policy using `model` as the summarizer.
+    pub fn summarize<M>(max_chars: usize, model: M) -> Self
+    where
+        M: CompletionModel + 'static,
+    {
+        Self::new(max_chars, ToolOutputStrategy::Summarize(Arc::new(model)))
+    }
+
+    /// Create a [ToolOutputStrategy::StoreAndReference] policy.
+    pub fn store_and_reference(max_chars: usize) -> Self {
+        Self::new(max_chars, ToolOutputStrategy::StoreAndReference)
+    }
+
+    /// The store holding the full outputs of a [ToolOutputStrategy::StoreAndReference] policy.
+    pub fn store(&self) -> &ToolOutputStore {
+        &self.store
+    }
+
+    /// The `fetch_tool_output` tool reading this policy's store, for the strategies needing it.
+    pub(crate) fn deferred_tools(&self) -> Option<DeferredTools> {
+        let ToolOutputStrategy::StoreAndReference = self.strategy else {
+            return None;
+        };
+
+        let tool = FetchToolOutput {
+            store: self.store.clone(),
+            max_chars: self.max_chars,
+        };
+        Some(DeferredTools::new(move || {
+            let tool = tool.clone();
+            async move { Ok::<_, ToolProviderError>(vec![Box::new(tool) as Box<dyn ToolDyn>]) }
+        }))
+    }
+
+    /// Shrink the output of the tool named `tool_name` if it is too long.
+    pub(crate) async fn apply(&self, tool_name: &str, output: String) -> String {
+        let total_chars = output.chars().count();
+        if total_chars <= self.max_chars || tool_name == FetchToolOutput::NAME {
+            return output;
+        }
+
+        match &self.strategy {
+            ToolOutputStrategy::Truncate => truncate(&output, self.max_chars, total_chars),
+            ToolOutputStrategy::Summarize(summarizer) => {
+                match summarize(summarizer.as_ref(), &output).await {
+                    Ok(summary) => format!(
+                        "[Summary of the {total_chars} characters long output of {tool_name}]\n{summary}"
+                    ),
+                    Err(e) => {
+                        tracing::warn!("Failed to summarize the output of {tool_name}: {e}");
+                        truncate(&output, self.max_chars, total_chars)
+                    }
+                }
+            }
+            ToolOutputStrategy::StoreAndReference => {
+                let preview = output[..char_offset(&output, self.max_chars)].to_string();
+                let id = self.store.insert(output);
+                format!(
+                    "{preview}\n[Output of {tool_name} truncated: showing {} of {total_chars} \
+                     characters. The full output is stored as `{id}`; call {} with this id and a \
+                     character range to read the rest.]",
+                    self.max_chars,
+                    FetchToolOutput::NAME
+                )
+            }
+        }
+    }
+}
+
+/// The full tool outputs kept by a [ToolOutputStrategy::StoreAndReference] policy, by id.
+///
+/// Clones share the same outputs.
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutputStore {
+    outputs: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl ToolOutputStore {
+    /// Store `output`, returning its id.
+    pub fn insert(&self, output: String) -> String {
+        let mut outputs = self.outputs.lock().expect("tool output store poisoned");
+        let id = format!("tool_output_{}", outputs.len() + 1);
+        outputs.insert(id.clone(), output);
+        id
+    }
+
+    /// The output stored as `id`.
+    pub fn get(&self, id: &str) -> Option<String> {
+        self.outputs
+            .lock()
+            .expect("tool output store poisoned")
+            .get(id)
+            .cloned()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+struct FetchToolOutputArgs {
+    /// Id of the stored output, as given in the truncated tool result
+    id: String,
+    /// Index of the first character to read
+    #[serde(default)]
+    start: usize,
+    /// Index after the last character to read. Defaults to reading as much as allowed.
+    #[serde(default)]
+    end: Option<usize>,
+}
+
+/// A slice of a stored tool output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolOutputSlice {
+    pub id: String,
+    pub start: usize,
+    pub end: usize,
+    pub total_chars: usize,
+    pub text: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum FetchToolOutputError {
+    #[error("No tool output is stored as `{0}`")]
+    NotFound(String),
+}
+
+/// Reads character ranges of the outputs of a [ToolOutputStore], at most `max_chars` at a time.
+#[derive(Debug, Clone)]
+struct FetchToolOutput {
+    store: ToolOutputStore,
+    max_chars: usize,
+}
+
+impl Tool for FetchToolOutput {
+    const NAME: &'static str = "fetch_tool_output";
+
+    type Error = FetchToolOutputError;
+    type Args = FetchToolOutputArgs;
+    type Output = ToolOutputSlice;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Read a character range of a tool output that was too long to be shown in full. \
+                 Returns at most {} characters per call.",
+                self.max_chars
+            ),
+            parameters: serde_json::to_value(schema_for!(FetchToolOutputArgs))
+                .expect("converting JSON schema to JSON value should never fail"),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let output = self
+            .store
+            .get(&args.id)
+            .ok_or_else(|| FetchToolOutputError::NotFound(args.id.clone()))?;
+
+        let total_chars = output.chars().count();
+        let start = args.start.min(total_chars);
+        let end = args
+            .end
+            .unwrap_or(total_chars)
+            .clamp(start, total_chars)
+            .min(start + self.max_chars);
+        let text = output.chars().skip(start).take(end - start).collect();
+
+        Ok(ToolOutputSlice {
+            id: args.id,
+            start,
+            end,
+            total_chars,
+            text,
+        })
+    }
+}
+
+/// The byte offset of the character at index `chars` of `text` (or its length).
+fn char_offset(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(offset, _)| offset)
+}
+
+fn truncate(output: &str, max_chars: usize, total_chars: usize) -> String {
+    format!(
+        "{}\n[Truncated: showing {max_chars} of {total_chars} characters]",
+        &output[..char_offset(output, max_chars)]
+    )
+}
+
+async fn summarize(
+    summarizer: &dyn CompletionModelDyn,
+    output: &str,
+) -> Result<String, crate::completion::CompletionError> {
+    let response = summarizer
+        .completion_request(Message::user(output))
+        .preamble(SUMMARIZER_PREAMBLE.to_string())
+        .send()
+        .await?;
+
+    Ok(response
+        .choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::AgentBuilder,
+        completion::Prompt,
+        message::{ToolResultContent, UserContent},
+        test_utils::MockCompletionModel,
+    };
+
+    #[derive(Deserialize)]
+    struct GetResultArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Result error")]
+    struct GetResultError;
+
+    /// Returns a large result, like a finished CalphaMesh task
+    struct GetResult;
+
+    impl Tool for GetResult {
+        const NAME: &'static str = "get_result";
+        type Error = GetResultError;
+        type Args = GetResultArgs;
+        type Output = Vec<u32>;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Get the task result".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok((0..100).collect())
+        }
+    }
+
+    fn tool_results(history: &[Message]) -> Vec<String> {
+        history
+            .iter()
+            .filter_map(|message| match message {
+                Message::User { content } => match content.first() {
+                    UserContent::ToolResult(result) => match result.content.first() {
+                        ToolResultContent::Text(text) => Some(text.text),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_truncate_boundaries() {
+        let policy = ToolOutputPolicy::truncate(5);
+
+        assert_eq!(policy.apply("tool", "12345".to_string()).await, "12345");
+        assert_eq!(
+            policy.apply("tool", "123456".to_string()).await,
+            "12345\n[Truncated: showing 5 of 6 characters]"
+        );
+        // Lengths are counted in characters, not bytes
+        assert_eq!(policy.apply("tool", "αβγδε".to_string()).await, "αβγδε");
+        assert_eq!(
+            policy.apply("tool", "αβγδεζ".to_string()).await,
+            "αβγδε\n[Truncated: showing 5 of 6 characters]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_summarize_falls_back_to_truncating() {
+        let summarizer = MockCompletionModel::new()
+            .with_text("100 values from 0 to 99")
+            .with_error("overloaded");
+        let policy = ToolOutputPolicy::summarize(5, summarizer.clone());
+
+        assert_eq!(
+            policy.apply("get_result", "0123456789".to_string()).await,
+            "[Summary of the 10 characters long output of get_result]\n100 values from 0 to 99"
+        );
+        assert_eq!(
+            policy.apply("get_result", "0123456789".to_string()).await,
+            "01234\n[Truncated: showing 5 of 10 characters]"
+        );
+        let requests = summarizer.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].preamble.as_deref(), Some(SUMMARIZER_PREAMBLE));
+    }
+
+    #[tokio::test]
+    async fn test_store_and_reference_fetches_stored_output() {
+        let full_output = serde_json::to_string(&(0..100).collect::<Vec<u32>>()).unwrap();
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "get_result", json!({}))
+            .with_tool_call(
+                "call_2",
+                "fetch_tool_output",
+                json!({"id": "tool_output_1", "start": 5, "end": 1000}),
+            )
+            .with_text("done");
+        let policy = ToolOutputPolicy::store_and_reference(20);
+        let agent = AgentBuilder::new(model.clone())
+            .tool(GetResult)
+            .tool_output_policy(policy.clone())
+            .build();
+
+        let mut history = vec![];
+        agent
+            .prompt("Get the result")
+            .with_history(&mut history)
+            .multi_turn(3)
+            .await
+            .unwrap();
+
+        let results = tool_results(&history);
+        assert_eq!(
+            results[0],
+            format!(
+                "{}\n[Output of get_result truncated: showing 20 of {} characters. The full \
+                 output is stored as `tool_output_1`; call fetch_tool_output with this id and a \
+                 character range to read the rest.]",
+                &full_output[..20],
+                full_output.len()
+            )
+        );
+        assert_eq!(
+            policy.store().get("tool_output_1"),
+            Some(full_output.clone())
+        );
+
+        // The reference tool returns the stored payload, at most `max_chars` at a time
+        let slice: ToolOutputSlice = serde_json::from_str(&results[1]).unwrap();
+        assert_eq!(
+            slice,
+            ToolOutputSlice {
+                id: "tool_output_1".to_string(),
+                start: 5,
+                end: 25,
+                total_chars: full_output.len(),
+                text: full_output[5..25].to_string(),
+            }
+        );
+
+        let tools: Vec<_> = model.requests()[0]
+            .tools
+            .iter()
+            .map(|tool| tool.name.clone())
+            .collect();
+        assert!(tools.contains(&"fetch_tool_output".to_string()));
+    }
+}
@@ -0,0 +1,488 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use futures::future::{Either, select};
+use serde::{Deserialize, Serialize};
+
+use crate::tool::ToolError;
+
+/// How long an agent waits for its tools: an optional default timeout for every tool, overridden
+/// for individual tools by name.
+///
+/// A tool call that runs out of time is cancelled, and the model receives a
+/// [timeout error](tool_timeout_error) as the tool result, so that it can react to it (eg. by
+/// trying again later or with other arguments) instead of the whole run being aborted.
+///
+/// # Example
+/// ```rust,ignore
+/// let agent = AgentBuilder::new(model)
+///     .tool(SubmitPointTask)
+///     .tool(GetTaskStatus)
+///     .tool_timeout(Duration::from_secs(30))
+///     .tool_timeout_for("calphamesh_get_task_status", Duration::from_secs(120))
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ToolTimeouts {
+    default: Option<Duration>,
+    per_tool: HashMap<String, Duration>,
+}
+
+impl ToolTimeouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the timeout of every tool without a timeout of its own.
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default = Some(timeout);
+        self
+    }
+
+    /// Set the timeout of the tool named `tool_name`.
+    pub fn tool(mut self, tool_name: impl Into<String>, timeout: Duration) -> Self {
+        self.per_tool.insert(tool_name.into(), timeout);
+        self
+    }
+
+    /// The timeout applying to the tool named `tool_name`, if any.
+    pub fn timeout_for(&self, tool_name: &str) -> Option<Duration> {
+        self.per_tool.get(tool_name).copied().or(self.default)
+    }
+
+    /// Run `call` (an attempt at executing the tool `tool_name`) within its timeout. On expiry,
+    /// `call` is dropped and the timeout is returned as the error.
+    pub(crate) async fn run<F>(&self, tool_name: &str, call: F) -> Result<F::Output, Duration>
+    where
+        F: Future,
+    {
+        let Some(timeout) = self.timeout_for(tool_name) else {
+            return Ok(call.await);
+        };
+
+        match select(Box::pin(call), futures_timer::Delay::new(timeout)).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(timeout),
+        }
+    }
+}
+
+/// When to retry a tool call that failed, eg. because of a transient network error.
+///
+/// A failed call is attempted again, after a delay doubling on each attempt starting from
+/// `backoff`, as long as fewer than `max_attempts` attempts were made and `retry_if` accepts the
+/// error. Retries are invisible to the model: it only receives the final result, or the last
+/// error along with the number of attempts. They are reported to the agent's
+/// [observers](crate::agent::AgentObserver) and in streaming responses.
+///
+/// # Example
+/// ```rust,ignore
+/// let agent = AgentBuilder::new(model)
+///     .tool(GetTaskStatus)
+///     .tool_retry_policy(
+///         ToolRetryPolicy::new(4, Duration::from_millis(250))
+///             .retry_if(|err| err.to_string().contains("error sending request")),
+///     )
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ToolRetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: usize,
+    /// Delay before the first retry
+    pub backoff: Duration,
+    /// Whether a tool error is worth retrying
+    pub retry_if: fn(&ToolError) -> bool,
+}
+
+impl Default for ToolRetryPolicy {
+    fn default() -> Self {
+        Self::new(3, Duration::from_millis(500))
+    }
+}
+
+impl ToolRetryPolicy {
+    /// Retry every tool error, up to `max_attempts` attempts in total
+    pub fn new(max_attempts: usize, backoff: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff,
+            retry_if: |_| true,
+        }
+    }
+
+    /// Only retry the errors accepted by `retry_if`
+    pub fn retry_if(mut self, retry_if: fn(&ToolError) -> bool) -> Self {
+        self.retry_if = retry_if;
+        self
+    }
+
+    /// Whether the attempt number `attempt` (counting from 1), which failed with `error`,
+    /// should be retried.
+    pub fn should_retry(&self, attempt: usize, error: &ToolError) -> bool {
+        attempt < self.max_attempts && (self.retry_if)(error)
+    }
+
+    /// The delay before retrying the attempt number `attempt` (counting from 1).
+    pub fn delay(&self, attempt: usize) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1) as u32))
+    }
+}
+
+/// The retry policies of an agent's tools: an optional default policy, overridden for
+/// individual tools by name. Without a policy, failed tool calls are not retried.
+#[derive(Debug, Clone, Default)]
+pub struct ToolRetries {
+    default: Option<ToolRetryPolicy>,
+    per_tool: HashMap<String, ToolRetryPolicy>,
+}
+
+impl ToolRetries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the retry policy of every tool without a policy of its own.
+    pub fn default_policy(mut self, policy: ToolRetryPolicy) -> Self {
+        self.default = Some(policy);
+        self
+    }
+
+    /// Set the retry policy of the tool named `tool_name`.
+    pub fn tool(mut self, tool_name: impl Into<String>, policy: ToolRetryPolicy) -> Self {
+        self.per_tool.insert(tool_name.into(), policy);
+        self
+    }
+
+    /// The retry policy applying to the tool named `tool_name`, if any.
+    pub fn policy_for(&self, tool_name: &str) -> Option<&ToolRetryPolicy> {
+        self.per_tool.get(tool_name).or(self.default.as_ref())
+    }
+}
+
+/// A failed attempt at a tool call, which is about to be retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolRetry {
+    /// The id of the tool call
+    pub id: String,
+    pub call_id: Option<String>,
+    pub tool_name: String,
+    /// The number of the failed attempt, counting from 1
+    pub attempt: usize,
+    /// The error of the failed attempt
+    pub error: String,
+}
+
+/// The tools a single prompt request may use, set with `allowed_tools` and `deny_tools` on the
+/// request builders. Filtered out tools are neither sent to the model nor executed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ToolFilter {
+    allowed: Option<HashSet<String>>,
+    denied: HashSet<String>,
+}
+
+impl ToolFilter {
+    /// Only allow the given tools (on top of previously allowed ones).
+    pub(crate) fn allow(&mut self, tool_names: impl IntoIterator<Item = impl Into<String>>) {
+        self.allowed
+            .get_or_insert_with(HashSet::new)
+            .extend(tool_names.into_iter().map(Into::into));
+    }
+
+    /// Deny the given tools, even if they are allowed.
+    pub(crate) fn deny(&mut self, tool_names: impl IntoIterator<Item = impl Into<String>>) {
+        self.denied.extend(tool_names.into_iter().map(Into::into));
+    }
+
+    /// Whether the tool named `tool_name` may be used.
+    pub(crate) fn allows(&self, tool_name: &str) -> bool {
+        self.allowed
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(tool_name))
+            && !self.denied.contains(tool_name)
+    }
+}
+
+/// The tool result sent to the model when it calls the tool `tool_name`, which the request does
+/// not allow.
+pub(crate) fn tool_not_allowed(tool_name: &str) -> String {
+    format!("The tool `{tool_name}` is not available for this request. The call was not executed.")
+}
+
+/// The tool result sent to the model when the tool `tool_name` did not finish within `timeout`.
+pub fn tool_timeout_error(tool_name: &str, timeout: Duration) -> String {
+    serde_json::json!({
+        "error": "tool_timeout",
+        "tool": tool_name,
+        "timeout_secs": timeout.as_secs_f64(),
+        "message": format!(
+            "The tool `{tool_name}` did not finish within {timeout:?} and was cancelled."
+        ),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::{
+        agent::{Agent, AgentBuilder, MultiTurnStreamItem, stream_collect},
+        completion::{Prompt, ToolDefinition},
+        message::{Message, ToolResultContent, UserContent},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    #[derive(Deserialize)]
+    struct SleepArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Sleep error")]
+    struct SleepError;
+
+    /// A tool that sleeps for `millis` before answering.
+    struct Sleeper {
+        name: &'static str,
+        millis: u64,
+    }
+
+    impl Tool for Sleeper {
+        const NAME: &'static str = "sleep";
+        type Error = SleepError;
+        type Args = SleepArgs;
+        type Output = String;
+
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: self.name.to_string(),
+                description: format!("Sleep for {}ms", self.millis),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            futures_timer::Delay::new(Duration::from_millis(self.millis)).await;
+            Ok("awake".to_string())
+        }
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("connection reset")]
+    struct ConnectionReset;
+
+    /// A tool failing `failures` times before succeeding.
+    struct Flaky {
+        failures: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Tool for Flaky {
+        const NAME: &'static str = "flaky";
+        type Error = ConnectionReset;
+        type Args = SleepArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: "flaky".to_string(),
+                description: "Fetch the task status".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(ConnectionReset)
+            } else {
+                Ok("completed".to_string())
+            }
+        }
+    }
+
+    fn flaky_agent(
+        failures: usize,
+        policy: ToolRetryPolicy,
+    ) -> (Agent<MockCompletionModel>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "flaky", json!({}))
+            .with_text("The task is completed");
+        let agent = AgentBuilder::new(model)
+            .tool(Flaky {
+                failures,
+                calls: calls.clone(),
+            })
+            .tool_retry_policy(policy)
+            .build();
+
+        (agent, calls)
+    }
+
+    fn tool_result_text(message: &Message) -> Option<String> {
+        match message {
+            Message::User { content } => match content.first() {
+                UserContent::ToolResult(result) => match result.content.first() {
+                    ToolResultContent::Text(text) => Some(text.text),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_timeout_for_prefers_tool_override() {
+        let timeouts = ToolTimeouts::new()
+            .default_timeout(Duration::from_secs(5))
+            .tool("poll", Duration::from_secs(60));
+
+        assert_eq!(timeouts.timeout_for("poll"), Some(Duration::from_secs(60)));
+        assert_eq!(timeouts.timeout_for("submit"), Some(Duration::from_secs(5)));
+        assert_eq!(ToolTimeouts::new().timeout_for("poll"), None);
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_tool_result_is_sent_to_model() {
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "hang", json!({}))
+            .with_text("The tool timed out, I will try again later.");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Sleeper {
+                name: "hang",
+                millis: 60_000,
+            })
+            .tool_timeout(Duration::from_millis(20))
+            .build();
+
+        let response = agent.prompt("poll the task").multi_turn(3).await.unwrap();
+        assert_eq!(response, "The tool timed out, I will try again later.");
+
+        // The model saw a structured timeout error as the tool result
+        let requests = model.requests();
+        let prompt = requests[1].chat_history.iter().last().unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&tool_result_text(prompt).unwrap()).unwrap();
+        assert_eq!(result["error"], "tool_timeout");
+        assert_eq!(result["tool"], "hang");
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeout_can_be_overridden_per_tool() {
+        let model = MockCompletionModel::new()
+            .with_tool_call("call_1", "slow", json!({}))
+            .with_text("done");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(Sleeper {
+                name: "slow",
+                millis: 50,
+            })
+            .tool_timeout(Duration::from_millis(10))
+            .tool_timeout_for("slow", Duration::from_secs(10))
+            .build();
+
+        agent.prompt("sleep").multi_turn(3).await.unwrap();
+
+        let requests = model.requests();
+        let prompt = requests[1].chat_history.iter().last().unwrap();
+        assert_eq!(tool_result_text(prompt).as_deref(), Some("\"awake\""));
+    }
+
+    #[tokio::test]
+    async fn test_failed_tool_calls_are_retried() {
+        let (agent, calls) = flaky_agent(2, ToolRetryPolicy::new(3, Duration::from_millis(1)));
+        let model = agent.model.clone();
+
+        agent.prompt("check the task").multi_turn(3).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // The model only saw the successful result
+        let requests = model.requests();
+        let history = &requests[1].chat_history;
+        let results: Vec<_> = history.iter().filter_map(tool_result_text).collect();
+        assert_eq!(results, vec!["\"completed\"".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_tool_retries_are_streamed() {
+        let (agent, _) = flaky_agent(2, ToolRetryPolicy::new(3, Duration::from_millis(1)));
+
+        let mut items = Vec::new();
+        let (messages, _) = stream_collect(
+            agent.stream_prompt("check the task").multi_turn(3),
+            |item| items.push(item.clone()),
+        )
+        .await
+        .unwrap();
+
+        let attempts: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                MultiTurnStreamItem::ToolRetry(retry) => Some((retry.id.as_str(), retry.attempt)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(attempts, vec![("call_1", 1), ("call_1", 2)]);
+
+        let results: Vec<_> = messages.iter().filter_map(tool_result_text).collect();
+        assert_eq!(results, vec!["\"completed\"".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_last_error_is_reported_with_attempt_count() {
+        let (agent, calls) = flaky_agent(5, ToolRetryPolicy::new(2, Duration::from_millis(1)));
+        let model = agent.model.clone();
+
+        agent.prompt("check the task").multi_turn(3).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let requests = model.requests();
+        let result = requests[1]
+            .chat_history
+            .iter()
+            .find_map(tool_result_text)
+            .unwrap();
+        assert!(result.contains("connection reset"), "{result}");
+        assert!(result.ends_with("(after 2 attempts)"), "{result}");
+    }
+
+    #[tokio::test]
+    async fn test_errors_rejected_by_retry_if_are_not_retried() {
+        let policy = ToolRetryPolicy::new(3, Duration::from_millis(1))
+            .retry_if(|err| err.to_string().contains("timed out"));
+        let (agent, calls) = flaky_agent(1, policy);
+
+        agent.prompt("check the task").multi_turn(3).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        let policy = ToolRetryPolicy::new(4, Duration::from_millis(100));
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert!(policy.should_retry(3, &ToolError::JsonError(serde::de::Error::custom("x"))));
+        assert!(!policy.should_retry(4, &ToolError::JsonError(serde::de::Error::custom("x"))));
+    }
+}
@@ -0,0 +1,479 @@
+//! Shareable reports of a chat history, e.g. of a [Workflow](super::Workflow) run.
+//!
+//! Every message gets a role badge (`user`, `assistant`, or `tool` for messages that only carry
+//! tool results). Tool call arguments are pretty printed JSON in fenced code blocks, and long tool
+//! results are cut off with a note saying how much was left out.
+//!
+//! # Example
+//! ```rust,ignore
+//! use rig::agent::transcript::{self, TranscriptOptions};
+//!
+//! let options = TranscriptOptions {
+//!     title: Some("Coating design".to_string()),
+//!     ..Default::default()
+//! };
+//! std::fs::write("report.md", transcript::to_markdown(&history, &options))?;
+//! std::fs::write("report.html", transcript::to_html(&history, &options))?;
+//! ```
+use std::collections::HashMap;
+
+use crate::{
+    completion::{Message, Usage},
+    message::{AssistantContent, ToolResultContent, UserContent},
+};
+
+/// What [to_markdown] and [to_html] render, and how.
+///
+/// The default renders everything without a title, cutting tool results off after 2000 bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptOptions {
+    /// Heading of the report
+    pub title: Option<String>,
+    /// Render the model's reasoning
+    pub show_reasoning: bool,
+    /// Cut tool results longer than this many bytes off
+    pub max_tool_result_len: Option<usize>,
+    /// Rows of the token usage table at the end of the report, e.g. one per workflow stage
+    pub usage: Vec<(String, Usage)>,
+}
+
+impl Default for TranscriptOptions {
+    fn default() -> Self {
+        Self {
+            title: None,
+            show_reasoning: true,
+            max_tool_result_len: Some(2000),
+            usage: vec![],
+        }
+    }
+}
+
+/// Render `messages` as a Markdown document.
+pub fn to_markdown(messages: &[Message], options: &TranscriptOptions) -> String {
+    let mut blocks = vec![];
+    if let Some(title) = &options.title {
+        blocks.push(format!("# {title}"));
+    }
+
+    for (i, entry) in entries(messages, options).iter().enumerate() {
+        if i > 0 {
+            blocks.push("---".to_string());
+        }
+        blocks.push(format!("**`{}`**", entry.role.label()));
+
+        for part in &entry.parts {
+            match part {
+                Part::Text(text) => blocks.push(text.clone()),
+                Part::Reasoning(reasoning) => {
+                    let quoted = reasoning
+                        .lines()
+                        .map(|line| format!("> {line}").trim_end().to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    blocks.push(format!("> **Reasoning**\n>\n{quoted}"));
+                }
+                Part::ToolCall {
+                    id,
+                    name,
+                    arguments,
+                } => {
+                    blocks.push(format!("**Tool call** `{name}` (`{id}`)"));
+                    blocks.push(fenced("json", arguments));
+                }
+                Part::ToolResult {
+                    id,
+                    name,
+                    text,
+                    omitted,
+                } => {
+                    blocks.push(match name {
+                        Some(name) => format!("**Tool result** `{name}` (`{id}`)"),
+                        None => format!("**Tool result** (`{id}`)"),
+                    });
+                    blocks.push(fenced("text", text));
+                    if *omitted > 0 {
+                        blocks.push(format!("*{}*", truncation_note(*omitted)));
+                    }
+                }
+                Part::Attachment(kind) => blocks.push(format!("*[{kind} attachment]*")),
+            }
+        }
+    }
+
+    if !options.usage.is_empty() {
+        blocks.push("## Usage".to_string());
+        let mut table =
+            "| | Input tokens | Output tokens | Total tokens |\n|---|---:|---:|---:|".to_string();
+        for (label, usage) in &options.usage {
+            table.push_str(&format!(
+                "\n| {label} | {} | {} | {} |",
+                usage.input_tokens, usage.output_tokens, usage.total_tokens
+            ));
+        }
+        blocks.push(table);
+    }
+
+    blocks.join("\n\n") + "\n"
+}
+
+/// Render `messages` as a standalone HTML page.
+pub fn to_html(messages: &[Message], options: &TranscriptOptions) -> String {
+    let title = escape_html(options.title.as_deref().unwrap_or("Transcript"));
+    let mut lines = vec![
+        "<!DOCTYPE html>".to_string(),
+        "<html lang=\"en\">".to_string(),
+        "<head>".to_string(),
+        "<meta charset=\"utf-8\">".to_string(),
+        format!("<title>{title}</title>"),
+        format!("<style>\n{HTML_STYLE}</style>"),
+        "</head>".to_string(),
+        "<body>".to_string(),
+    ];
+    if options.title.is_some() {
+        lines.push(format!("<h1>{title}</h1>"));
+    }
+
+    for entry in entries(messages, options) {
+        let role = entry.role.label();
+        lines.push(format!("<section class=\"message {role}\">"));
+        lines.push(format!("<span class=\"badge {role}\">{role}</span>"));
+
+        for part in &entry.parts {
+            match part {
+                Part::Text(text) => {
+                    lines.push(format!("<div class=\"text\">{}</div>", escape_html(text)))
+                }
+                Part::Reasoning(reasoning) => lines.push(format!(
+                    "<blockquote class=\"reasoning\">{}</blockquote>",
+                    escape_html(reasoning)
+                )),
+                Part::ToolCall {
+                    id,
+                    name,
+                    arguments,
+                } => {
+                    lines.push(format!(
+                        "<div class=\"tool-call\"><strong>Tool call</strong> <code>{}</code> (<code>{}</code>)</div>",
+                        escape_html(name),
+                        escape_html(id)
+                    ));
+                    lines.push(format!(
+                        "<pre><code class=\"language-json\">{}</code></pre>",
+                        escape_html(arguments)
+                    ));
+                }
+                Part::ToolResult {
+                    id,
+                    name,
+                    text,
+                    omitted,
+                } => {
+                    let name = match name {
+                        Some(name) => format!(" <code>{}</code>", escape_html(name)),
+                        None => String::new(),
+                    };
+                    lines.push(format!(
+                        "<div class=\"tool-result\"><strong>Tool result</strong>{name} (<code>{}</code>)</div>",
+                        escape_html(id)
+                    ));
+                    lines.push(format!("<pre><code>{}</code></pre>", escape_html(text)));
+                    if *omitted > 0 {
+                        lines.push(format!(
+                            "<p class=\"note\">{}</p>",
+                            truncation_note(*omitted)
+                        ));
+                    }
+                }
+                Part::Attachment(kind) => {
+                    lines.push(format!("<p class=\"attachment\">[{kind} attachment]</p>"))
+                }
+            }
+        }
+
+        lines.push("</section>".to_string());
+    }
+
+    if !options.usage.is_empty() {
+        lines.push("<h2>Usage</h2>".to_string());
+        lines.push("<table class=\"usage\">".to_string());
+        lines.push(
+            "<tr><th></th><th>Input tokens</th><th>Output tokens</th><th>Total tokens</th></tr>"
+                .to_string(),
+        );
+        for (label, usage) in &options.usage {
+            lines.push(format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(label),
+                usage.input_tokens,
+                usage.output_tokens,
+                usage.total_tokens
+            ));
+        }
+        lines.push("</table>".to_string());
+    }
+
+    lines.push("</body>".to_string());
+    lines.push("</html>".to_string());
+    lines.join("\n") + "\n"
+}
+
+const HTML_STYLE: &str = "\
+body { font-family: sans-serif; max-width: 960px; margin: 2em auto; line-height: 1.5; }
+.message { border-top: 1px solid #ddd; padding: 0.5em 0; }
+.badge { display: inline-block; padding: 0 0.5em; border-radius: 4px; color: #fff; font-size: 0.8em; font-weight: bold; }
+.badge.user { background: #2563eb; }
+.badge.assistant { background: #16a34a; }
+.badge.tool { background: #9333ea; }
+.text, .reasoning { white-space: pre-wrap; }
+.reasoning, .note { color: #666; }
+pre { background: #f6f8fa; padding: 0.5em; overflow-x: auto; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ddd; padding: 0.25em 0.75em; text-align: right; }
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Role {
+    fn label(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+/// A message, reduced to what the renderers need.
+struct Entry {
+    role: Role,
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Reasoning(String),
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: String,
+    },
+    ToolResult {
+        id: String,
+        /// Name of the tool, if the call it answers is in the transcript
+        name: Option<String>,
+        text: String,
+        /// Number of bytes cut off
+        omitted: usize,
+    },
+    Attachment(&'static str),
+}
+
+fn entries(messages: &[Message], options: &TranscriptOptions) -> Vec<Entry> {
+    let mut tool_names = HashMap::new();
+
+    messages
+        .iter()
+        .map(|message| match message {
+            Message::User { content } => {
+                let role = if content
+                    .iter()
+                    .all(|content| matches!(content, UserContent::ToolResult(_)))
+                {
+                    Role::Tool
+                } else {
+                    Role::User
+                };
+                let parts = content
+                    .iter()
+                    .map(|content| match content {
+                        UserContent::Text(text) => Part::Text(text.text.clone()),
+                        UserContent::ToolResult(result) => {
+                            let text = result
+                                .content
+                                .iter()
+                                .map(|content| match content {
+                                    ToolResultContent::Text(text) => text.text.clone(),
+                                    ToolResultContent::Image(_) => "[image]".to_string(),
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            let (shown, omitted) = truncate(&text, options.max_tool_result_len);
+                            Part::ToolResult {
+                                id: result.id.clone(),
+                                name: tool_names.get(&result.id).cloned(),
+                                text: shown.to_string(),
+                                omitted,
+                            }
+                        }
+                        UserContent::Image(_) => Part::Attachment("image"),
+                        UserContent::Audio(_) => Part::Attachment("audio"),
+                        UserContent::Video(_) => Part::Attachment("video"),
+                        UserContent::Document(_) => Part::Attachment("document"),
+                    })
+                    .collect();
+                Entry { role, parts }
+            }
+            Message::Assistant { content, .. } => {
+                let parts = content
+                    .iter()
+                    .filter_map(|content| match content {
+                        AssistantContent::Text(text) => Some(Part::Text(text.text.clone())),
+                        AssistantContent::ToolCall(tool_call) => {
+                            tool_names
+                                .insert(tool_call.id.clone(), tool_call.function.name.clone());
+                            Some(Part::ToolCall {
+                                id: tool_call.id.clone(),
+                                name: tool_call.function.name.clone(),
+                                arguments: serde_json::to_string_pretty(
+                                    &tool_call.function.arguments,
+                                )
+                                .unwrap_or_else(|_| tool_call.function.arguments.to_string()),
+                            })
+                        }
+                        AssistantContent::Reasoning(reasoning) => {
+                            let reasoning = reasoning.reasoning.join("\n");
+                            (options.show_reasoning && !reasoning.is_empty())
+                                .then_some(Part::Reasoning(reasoning))
+                        }
+                    })
+                    .collect();
+                Entry {
+                    role: Role::Assistant,
+                    parts,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Cut `text` off after `limit` bytes (on a char boundary), returning the kept text and the number
+/// of bytes cut off.
+fn truncate(text: &str, limit: Option<usize>) -> (&str, usize) {
+    match limit {
+        Some(limit) if text.len() > limit => {
+            let mut end = limit;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            (&text[..end], text.len() - end)
+        }
+        _ => (text, 0),
+    }
+}
+
+fn truncation_note(omitted: usize) -> String {
+    format!("Truncated: {omitted} more bytes not shown.")
+}
+
+/// A fenced code block whose fence is longer than any run of backticks in `code`.
+fn fenced(language: &str, code: &str) -> String {
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{fence}{language}\n{code}\n{fence}")
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{OneOrMany, message::Reasoning};
+
+    const GOLDEN_MARKDOWN: &str = include_str!("../../tests/data/transcript/transcript.md");
+    const GOLDEN_HTML: &str = include_str!("../../tests/data/transcript/transcript.html");
+
+    /// A short coating design run: a prompt, a tool call with its (long) result, and the answer.
+    fn fixture() -> (Vec<Message>, TranscriptOptions) {
+        let messages = vec![
+            Message::user("Design a TiAlN coating reaching 3500 HV."),
+            Message::Assistant {
+                id: None,
+                content: OneOrMany::many(vec![
+                    AssistantContent::Reasoning(Reasoning::new(
+                        "Predict first,\nthen compare with the target.",
+                    )),
+                    AssistantContent::text("Let me predict its performance."),
+                    AssistantContent::tool_call(
+                        "call_1",
+                        "ml_performance_predictor",
+                        json!({"composition": {"Al": 0.5, "N": 0.5}, "structure": "monolayer"}),
+                    ),
+                ])
+                .unwrap(),
+            },
+            Message::User {
+                content: OneOrMany::one(UserContent::tool_result(
+                    "call_1",
+                    OneOrMany::one(ToolResultContent::text(
+                        "hardness: 3250 HV\nadhesion: 68.5 N\nwear rate: 1.2e-6 mm³/N·m",
+                    )),
+                )),
+            },
+            Message::assistant("Predicted hardness is 3250 HV, short of the target (< 3500 HV)."),
+        ];
+        let options = TranscriptOptions {
+            title: Some("TiAlN design".to_string()),
+            max_tool_result_len: Some(39),
+            usage: vec![(
+                "predict".to_string(),
+                Usage {
+                    input_tokens: 120,
+                    output_tokens: 45,
+                    total_tokens: 165,
+                },
+            )],
+            ..Default::default()
+        };
+        (messages, options)
+    }
+
+    #[test]
+    fn test_markdown_matches_golden_file() {
+        let (messages, options) = fixture();
+        assert_eq!(to_markdown(&messages, &options), GOLDEN_MARKDOWN);
+    }
+
+    #[test]
+    fn test_html_matches_golden_file() {
+        let (messages, options) = fixture();
+        assert_eq!(to_html(&messages, &options), GOLDEN_HTML);
+    }
+
+    #[test]
+    fn test_fence_outgrows_backticks_in_content() {
+        assert_eq!(fenced("text", "a ``` b"), "````text\na ``` b\n````");
+        assert_eq!(fenced("json", "{}"), "```json\n{}\n```");
+    }
+
+    #[test]
+    fn test_reasoning_can_be_hidden() {
+        let (messages, options) = fixture();
+        let options = TranscriptOptions {
+            show_reasoning: false,
+            ..options
+        };
+        let markdown = to_markdown(&messages, &options);
+        assert!(!markdown.contains("Reasoning"));
+        assert!(markdown.contains("Let me predict its performance."));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::completion::Usage;
+
+/// The token usage of a prompt request, broken down per turn of the agent and per sub-agent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageBreakdown {
+    /// Usage of the agent's own completion requests, one entry per turn
+    pub turns: Vec<Usage>,
+    /// Total usage of the sub-agents called as tools during the request, by name
+    pub sub_agents: BTreeMap<String, Usage>,
+    /// Turns (starting at 1) answered by one of the agent's fallback models, with the position
+    /// of that model in the fallback chain (starting at 1)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fallbacks: BTreeMap<usize, usize>,
+}
+
+impl UsageBreakdown {
+    /// Usage of the agent's own completion requests.
+    pub fn agent_usage(&self) -> Usage {
+        self.turns
+            .iter()
+            .fold(Usage::new(), |total, usage| total + *usage)
+    }
+
+    /// Usage of all sub-agents.
+    pub fn sub_agent_usage(&self) -> Usage {
+        self.sub_agents
+            .values()
+            .fold(Usage::new(), |total, usage| total + *usage)
+    }
+
+    /// Usage of the agent and all of its sub-agents.
+    pub fn total(&self) -> Usage {
+        self.agent_usage() + self.sub_agent_usage()
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: UsageAccumulator;
+}
+
+/// Collects the token usage of a prompt request, including the usage of sub-agents running
+/// inside its tool calls.
+///
+/// Every `prompt` and `stream_prompt` run of an agent creates an accumulator and makes it
+/// [current](Self::current) while its tools are running. [AgentTool](crate::agent::AgentTool)
+/// records the usage of its wrapped agent there; custom tools wrapping an agent can do the same:
+///
+/// ```rust,ignore
+/// async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+///     let response = self.agent.prompt(args.prompt).extended_details().await?;
+///     if let Some(usage) = UsageAccumulator::current() {
+///         usage.record_agent("researcher", response.total_usage);
+///     }
+///     Ok(response.output)
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UsageAccumulator(Arc<Mutex<UsageBreakdown>>);
+
+impl UsageAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The accumulator of the prompt request whose tool is currently running, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// Add the usage of the agent's completion request in `turn` (starting at 1).
+    pub fn record_turn(&self, turn: usize, usage: Usage) {
+        let mut breakdown = self.0.lock().unwrap();
+        let index = turn.max(1) - 1;
+        if breakdown.turns.len() <= index {
+            breakdown.turns.resize(index + 1, Usage::new());
+        }
+        breakdown.turns[index] += usage;
+    }
+
+    /// Record that `turn` (starting at 1) was answered by the fallback model at position `model`
+    /// (starting at 1) of the agent's fallback chain.
+    pub fn record_fallback(&self, turn: usize, model: usize) {
+        self.0.lock().unwrap().fallbacks.insert(turn, model);
+    }
+
+    /// Add the usage of the sub-agent named `name`.
+    pub fn record_agent(&self, name: &str, usage: Usage) {
+        let mut breakdown = self.0.lock().unwrap();
+        *breakdown.sub_agents.entry(name.to_string()).or_default() += usage;
+    }
+
+    /// Usage of the agent and all of its sub-agents so far.
+    pub fn total_usage(&self) -> Usage {
+        self.0.lock().unwrap().total()
+    }
+
+    /// The usage recorded so far.
+    pub fn breakdown(&self) -> UsageBreakdown {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Run `future` with this accumulator as the current one.
+    pub(crate) async fn scope<F: Future>(&self, future: F) -> F::Output {
+        CURRENT.scope(self.clone(), future).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::{Agent, AgentBuilder, AgentTool, StreamingError, stream_collect},
+        completion::{AssistantContent, Prompt, PromptError, ToolDefinition},
+        message::{ToolCall, ToolFunction},
+        streaming::StreamingPrompt,
+        test_utils::MockCompletionModel,
+        tool::Tool,
+    };
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+        }
+    }
+
+    fn tool_call(id: &str, name: &str, args: serde_json::Value) -> AssistantContent {
+        AssistantContent::ToolCall(ToolCall {
+            id: id.to_string(),
+            call_id: None,
+            function: ToolFunction {
+                name: name.to_string(),
+                arguments: args,
+            },
+        })
+    }
+
+    /// An orchestrator calling the `researcher` sub-agent twice, then answering. Every completion
+    /// of the orchestrator uses (10, 5) tokens, and every completion of the sub-agent (3, 2).
+    fn orchestrator() -> Agent<MockCompletionModel> {
+        let researcher_model = MockCompletionModel::new()
+            .with_text("notes 1")
+            .with_usage(usage(3, 2));
+        let researcher = AgentBuilder::new(researcher_model).build();
+
+        let model = MockCompletionModel::new()
+            .with_turn(vec![tool_call(
+                "call_1",
+                "researcher",
+                json!({"prompt": "research TiAlN"}),
+            )])
+            .with_turn(vec![tool_call(
+                "call_2",
+                "researcher",
+                json!({"prompt": "research AlCrN"}),
+            )])
+            .with_text("done")
+            .with_usage(usage(10, 5));
+        AgentBuilder::new(model)
+            .tool(AgentTool::new(researcher, "researcher", "Research a topic"))
+            .build()
+    }
+
+    fn expected_breakdown() -> UsageBreakdown {
+        UsageBreakdown {
+            turns: vec![usage(10, 5); 3],
+            sub_agents: BTreeMap::from([("researcher".to_string(), usage(6, 4))]),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_usage_includes_sub_agents() {
+        let agent = orchestrator();
+
+        let response = agent
+            .prompt("research coatings")
+            .multi_turn(3)
+            .extended_details()
+            .await
+            .unwrap();
+
+        assert_eq!(response.usage_breakdown, expected_breakdown());
+        assert_eq!(response.total_usage, usage(36, 19));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_usage_includes_sub_agents() {
+        let agent = orchestrator();
+
+        let (_, final_response) = stream_collect(
+            agent.stream_prompt("research coatings").multi_turn(3),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(final_response.usage(), usage(30, 15));
+        assert_eq!(final_response.usage_breakdown(), &expected_breakdown());
+        assert_eq!(final_response.total_usage(), usage(36, 19));
+    }
+
+    #[tokio::test]
+    async fn test_max_depth_usage_includes_sub_agents() {
+        let max_depth_usage = |error: PromptError| match error {
+            PromptError::MaxDepthError { usage, .. } => usage,
+            other => panic!("expected MaxDepthError, got {other:?}"),
+        };
+
+        // Both turns calling the researcher run before the limit is hit
+        let error = orchestrator()
+            .prompt("research coatings")
+            .multi_turn(0)
+            .await
+            .unwrap_err();
+        assert_eq!(max_depth_usage(error), usage(26, 14));
+
+        let error = stream_collect(
+            orchestrator()
+                .stream_prompt("research coatings")
+                .multi_turn(0),
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+        let StreamingError::Prompt(error) = error else {
+            panic!("expected MaxDepthError, got {error:?}");
+        };
+        assert_eq!(max_depth_usage(*error), usage(26, 14));
+    }
+
+    #[derive(Deserialize)]
+    struct ProbeArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Probe error")]
+    struct ProbeError;
+
+    /// A tool recording a fixed usage in the current accumulator, as a custom agent wrapper would.
+    struct Probe {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Tool for Probe {
+        const NAME: &'static str = "probe";
+        type Error = ProbeError;
+        type Args = ProbeArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Probe".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            if let Some(accumulator) = UsageAccumulator::current() {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                accumulator.record_agent("probe", usage(1, 1));
+            }
+            Ok("ok".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_tools_can_record_usage() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let model = MockCompletionModel::new()
+            .with_turn(vec![
+                tool_call("call_1", "probe", json!({})),
+                tool_call("call_2", "probe", json!({})),
+            ])
+            .with_text("done");
+        let agent = AgentBuilder::new(model)
+            .tool(Probe {
+                calls: calls.clone(),
+            })
+            .parallel_tool_calls(2)
+            .build();
+
+        let response = agent
+            .prompt("probe")
+            .multi_turn(3)
+            .extended_details()
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            response.usage_breakdown.sub_agents,
+            BTreeMap::from([("probe".to_string(), usage(2, 2))])
+        );
+        assert!(UsageAccumulator::current().is_none());
+    }
+}
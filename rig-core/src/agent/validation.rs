@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use crate::completion::Message;
+
+/// Checks the final answer of a prompt request, returning why it is rejected.
+/// See [PromptRequest::validate](crate::agent::PromptRequest::validate).
+pub type AnswerValidator = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+/// Number of times a rejected answer is sent back to the model, unless set on the request.
+const DEFAULT_MAX_REPAIR_ATTEMPTS: usize = 1;
+
+/// The validator of a prompt request, with the number of repairs it allows.
+#[derive(Clone, Default)]
+pub(crate) struct AnswerValidation {
+    validator: Option<AnswerValidator>,
+    max_repair_attempts: Option<usize>,
+}
+
+impl AnswerValidation {
+    pub(crate) fn set_validator(&mut self, validator: AnswerValidator) {
+        self.validator = Some(validator);
+    }
+
+    pub(crate) fn set_max_repair_attempts(&mut self, attempts: usize) {
+        self.max_repair_attempts = Some(attempts);
+    }
+
+    /// Check `answer`. Every answer is accepted when there is no validator.
+    pub(crate) fn check(&self, answer: &str) -> Result<(), String> {
+        match &self.validator {
+            Some(validator) => validator(answer),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the model may be asked to repair its answer after `rejected` rejected answers.
+    pub(crate) fn may_repair(&self, rejected: usize) -> bool {
+        rejected
+            <= self
+                .max_repair_attempts
+                .unwrap_or(DEFAULT_MAX_REPAIR_ATTEMPTS)
+    }
+}
+
+/// The message asking the model to fix an answer rejected with `error`.
+pub(crate) fn repair_prompt(error: &str) -> Message {
+    Message::user(format!(
+        "Your answer was rejected: {error}\nPlease answer again, fixing this problem."
+    ))
+}
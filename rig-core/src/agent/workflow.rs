@@ -0,0 +1,716 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::{
+        Agent, FinalResponse, MultiTurnStreamItem, StreamingError,
+        history::{self, HISTORY_SCHEMA_VERSION, HistoryError},
+        stream_collect,
+        transcript::{self, TranscriptOptions},
+    },
+    completion::{CompletionModel, GetTokenUsage, Message, Usage},
+    streaming::StreamingChat,
+    wasm_compat::WasmCompatSend,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorkflowError {
+    #[error("Stage `{stage}` failed: {source}")]
+    Stage {
+        stage: String,
+        #[source]
+        source: Box<StreamingError>,
+    },
+    #[error("IoError: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JsonError: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("HistoryError: {0}")]
+    History(#[from] HistoryError),
+}
+
+/// The outcome of a single workflow stage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageRecord {
+    /// Name the stage was run under.
+    pub name: String,
+    /// The final text response of the stage's agent.
+    pub response: String,
+    /// Token usage aggregated over every turn of the stage, including its sub-agents.
+    pub usage: Usage,
+    /// Number of messages the stage appended to the workflow history.
+    pub messages: usize,
+}
+
+/// A sequence of agent stages sharing one chat history.
+///
+/// Each stage streams an agent's response to a prompt, with the messages of all previous stages
+/// as chat history. The prompt and every message the agent produced (tool calls, tool results,
+/// reasoning and the final text) are then appended to the history for the next stage.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::agent::Workflow;
+///
+/// let mut workflow = Workflow::new().with_history(vec![Message::user(request)]);
+///
+/// workflow.run_stage("extract", &requirement_agent, "Extract the requirements.").await?;
+/// workflow.run_stage("predict", &prediction_agent, "Predict the performance.").await?;
+///
+/// println!("{}", workflow.summary());
+/// workflow.save("workflow.json")?;
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Workflow {
+    history: Vec<Message>,
+    stages: Vec<StageRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_turns: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branch: Option<Branch>,
+}
+
+/// Where a forked workflow branched off its parent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Branch {
+    name: String,
+    /// Number of stages the parent had run when the branch was forked.
+    forked_at: usize,
+}
+
+impl Workflow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start the workflow from an existing chat history.
+    pub fn with_history(mut self, history: Vec<Message>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Set the maximum number of turns each stage's agent may take (see
+    /// [StreamingPromptRequest::multi_turn](crate::agent::StreamingPromptRequest::multi_turn)).
+    /// Defaults to the [max_turns](crate::agent::AgentBuilder::max_turns) of each agent.
+    pub fn max_turns(mut self, depth: usize) -> Self {
+        self.max_turns = Some(depth);
+        self
+    }
+
+    /// The accumulated chat history of all stages run so far.
+    pub fn history(&self) -> &[Message] {
+        &self.history
+    }
+
+    /// Records of all stages run so far, in order.
+    pub fn stages(&self) -> &[StageRecord] {
+        &self.stages
+    }
+
+    /// The name of the branch, for workflows created with [Workflow::fork].
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_ref().map(|branch| branch.name.as_str())
+    }
+
+    /// Branch off the workflow, e.g. to try two strategies from the same intermediate state.
+    ///
+    /// The branch starts with a copy of the history and stage records (and so of the usage) so
+    /// far; stages run on it do not affect this workflow and vice versa. Forking a branch again
+    /// names the new branch `parent/name`. Bring the results back with
+    /// [Workflow::merge_summaries].
+    pub fn fork(&self, name: &str) -> Self {
+        let name = match self.branch() {
+            Some(parent) => format!("{parent}/{name}"),
+            None => name.to_string(),
+        };
+
+        Self {
+            branch: Some(Branch {
+                name,
+                forked_at: self.stages.len(),
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Append the conclusions of `branches` to the history, one user message per branch listing
+    /// the final response of every stage it ran since it was forked.
+    ///
+    /// Each branch is also recorded as a stage (named after the branch), carrying the usage of
+    /// the stages it ran so that [Workflow::total_usage] covers the work done on every branch.
+    pub fn merge_summaries<'a>(&mut self, branches: impl IntoIterator<Item = &'a Workflow>) {
+        for branch in branches {
+            let (name, forked_at) = match &branch.branch {
+                Some(Branch { name, forked_at }) => (name.as_str(), *forked_at),
+                None => ("main", 0),
+            };
+            let stages = branch.stages.get(forked_at..).unwrap_or_default();
+
+            let mut conclusion = format!("Conclusions of branch `{name}`:");
+            for stage in stages {
+                conclusion.push_str(&format!("\n- {}: {}", stage.name, stage.response));
+            }
+
+            self.stages.push(StageRecord {
+                name: name.to_string(),
+                response: conclusion.clone(),
+                usage: stages
+                    .iter()
+                    .fold(Usage::new(), |total, stage| total + stage.usage),
+                messages: 1,
+            });
+            self.history.push(Message::user(conclusion));
+        }
+    }
+
+    /// Token usage summed over all stages.
+    pub fn total_usage(&self) -> Usage {
+        self.stages
+            .iter()
+            .fold(Usage::new(), |total, stage| total + stage.usage)
+    }
+
+    /// Run a stage, appending its messages to the history. Returns the stage's final response.
+    pub async fn run_stage<M>(
+        &mut self,
+        name: &str,
+        agent: &Agent<M>,
+        prompt: &str,
+    ) -> Result<String, WorkflowError>
+    where
+        M: CompletionModel + 'static,
+        M::StreamingResponse: WasmCompatSend + GetTokenUsage,
+    {
+        self.run_stage_with(name, agent, prompt, |_| {}).await
+    }
+
+    /// Like [Workflow::run_stage], but passes every streamed item to `sink` (eg. to print it).
+    pub async fn run_stage_with<M, F>(
+        &mut self,
+        name: &str,
+        agent: &Agent<M>,
+        prompt: &str,
+        sink: F,
+    ) -> Result<String, WorkflowError>
+    where
+        M: CompletionModel + 'static,
+        M::StreamingResponse: WasmCompatSend + GetTokenUsage,
+        F: FnMut(&MultiTurnStreamItem<M::StreamingResponse>),
+    {
+        let output = self
+            .stream_stage(agent, prompt, sink)
+            .await
+            .map_err(|e| stage_error(name, e))?;
+
+        Ok(self.push_stage(name, output))
+    }
+
+    /// Run several stages concurrently against the current history.
+    ///
+    /// No stage sees the output of the others. Once all stages have finished, their messages are
+    /// appended in the order the stages were given, regardless of which finished first. If any
+    /// stage fails, the history is left untouched and the first error (in stage order) is returned.
+    pub async fn run_stages_parallel<M>(
+        &mut self,
+        stages: Vec<(&str, &Agent<M>, &str)>,
+    ) -> Result<Vec<String>, WorkflowError>
+    where
+        M: CompletionModel + 'static,
+        M::StreamingResponse: WasmCompatSend + GetTokenUsage,
+    {
+        let results = join_all(
+            stages
+                .iter()
+                .map(|(_, agent, prompt)| self.stream_stage(agent, prompt, |_| {})),
+        )
+        .await;
+
+        let mut outputs = Vec::with_capacity(results.len());
+        for ((name, _, _), result) in stages.iter().zip(results) {
+            outputs.push((*name, result.map_err(|e| stage_error(name, e))?));
+        }
+
+        Ok(outputs
+            .into_iter()
+            .map(|(name, output)| self.push_stage(name, output))
+            .collect())
+    }
+
+    /// A short human readable overview of the stages run so far.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} stage(s), {} message(s) in history",
+            self.stages.len(),
+            self.history.len()
+        );
+        if let Some(branch) = self.branch() {
+            summary = format!("Branch `{branch}`: {summary}");
+        }
+
+        for stage in &self.stages {
+            summary.push_str(&format!(
+                "\n- {}: {} message(s), {} tokens",
+                stage.name, stage.messages, stage.usage.total_tokens
+            ));
+        }
+
+        let total = self.total_usage();
+        summary.push_str(&format!(
+            "\nTotal usage: {} input / {} output / {} total tokens",
+            total.input_tokens, total.output_tokens, total.total_tokens
+        ));
+
+        summary
+    }
+
+    /// Write the history as a Markdown report, with a usage table of every stage. See
+    /// [transcript::to_markdown].
+    pub fn export_markdown(&self, path: impl AsRef<Path>) -> Result<(), WorkflowError> {
+        std::fs::write(
+            path,
+            transcript::to_markdown(&self.history, &self.report_options()),
+        )?;
+        Ok(())
+    }
+
+    /// Like [Workflow::export_markdown], but writes a standalone HTML page.
+    pub fn export_html(&self, path: impl AsRef<Path>) -> Result<(), WorkflowError> {
+        std::fs::write(
+            path,
+            transcript::to_html(&self.history, &self.report_options()),
+        )?;
+        Ok(())
+    }
+
+    fn report_options(&self) -> TranscriptOptions {
+        let mut usage: Vec<_> = self
+            .stages
+            .iter()
+            .map(|stage| (stage.name.clone(), stage.usage))
+            .collect();
+        usage.push(("Total".to_string(), self.total_usage()));
+
+        TranscriptOptions {
+            title: Some(match self.branch() {
+                Some(branch) => format!("Workflow report (branch `{branch}`)"),
+                None => "Workflow report".to_string(),
+            }),
+            usage,
+            ..Default::default()
+        }
+    }
+
+    /// Save the workflow (history and stage records) as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), WorkflowError> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Load a workflow previously written with [Workflow::save].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WorkflowError> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Save the workflow in the versioned format of [history::HistoryFile], so that a later
+    /// process can pick up where this one stopped with [Workflow::resume].
+    ///
+    /// Branches are written next to `path`, see [Workflow::checkpoint_path]. Returns the path
+    /// the checkpoint was written to.
+    pub fn checkpoint(&self, path: impl AsRef<Path>) -> Result<PathBuf, WorkflowError> {
+        let path = self.checkpoint_path(path);
+        let checkpoint = Checkpoint {
+            schema_version: HISTORY_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            workflow: self,
+        };
+
+        history::write_versioned(&path, &checkpoint)?;
+        Ok(path)
+    }
+
+    /// The file [Workflow::checkpoint] writes to for `path`: `path` itself, or for a branch,
+    /// `path` with the branch name inserted before the extension (`checkpoint.json` becomes
+    /// `checkpoint.strategy-a.json`), so that branches never overwrite each other's checkpoints.
+    pub fn checkpoint_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        let Some(branch) = self.branch() else {
+            return path.to_path_buf();
+        };
+
+        let branch: String = branch
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_name = match path.extension() {
+            Some(extension) => format!("{stem}.{branch}.{}", extension.to_string_lossy()),
+            None => format!("{stem}.{branch}"),
+        };
+
+        path.with_file_name(file_name)
+    }
+
+    /// Restore a workflow saved with [Workflow::checkpoint]. Fails with a descriptive
+    /// [HistoryError] if the file is corrupt or was written with another schema version.
+    pub fn resume(path: impl AsRef<Path>) -> Result<Self, WorkflowError> {
+        let checkpoint: Checkpoint<Workflow> = history::read_versioned(path.as_ref())?;
+        Ok(checkpoint.workflow)
+    }
+
+    async fn stream_stage<M, F>(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        sink: F,
+    ) -> Result<(Vec<Message>, FinalResponse), StreamingError>
+    where
+        M: CompletionModel + 'static,
+        M::StreamingResponse: WasmCompatSend + GetTokenUsage,
+        F: FnMut(&MultiTurnStreamItem<M::StreamingResponse>),
+    {
+        let mut request = agent.stream_chat(prompt, self.history.clone());
+        if let Some(max_turns) = self.max_turns {
+            request = request.multi_turn(max_turns);
+        }
+
+        stream_collect(request, sink).await
+    }
+
+    fn push_stage(
+        &mut self,
+        name: &str,
+        (messages, final_response): (Vec<Message>, FinalResponse),
+    ) -> String {
+        let response = final_response.response().to_string();
+
+        self.stages.push(StageRecord {
+            name: name.to_string(),
+            response: response.clone(),
+            usage: final_response.total_usage(),
+            messages: messages.len(),
+        });
+        self.history.extend(messages);
+
+        response
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<W> {
+    schema_version: u32,
+    created_at: DateTime<Utc>,
+    #[serde(flatten)]
+    workflow: W,
+}
+
+fn stage_error(stage: &str, error: StreamingError) -> WorkflowError {
+    WorkflowError::Stage {
+        stage: stage.to_string(),
+        source: Box::new(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        OneOrMany,
+        agent::AgentBuilder,
+        message::{AssistantContent, Reasoning},
+        test_utils::MockCompletionModel,
+    };
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+        }
+    }
+
+    fn agent(text: &str, usage: Usage) -> (Agent<MockCompletionModel>, MockCompletionModel) {
+        let model = MockCompletionModel::new().with_text(text).with_usage(usage);
+        (AgentBuilder::new(model.clone()).build(), model)
+    }
+
+    #[tokio::test]
+    async fn test_three_stage_workflow() {
+        let (extract, _) = agent("requirements", usage(10, 5));
+        let (predict, predict_model) = agent("prediction", usage(20, 7));
+        let (optimize, optimize_model) = agent("optimization", usage(30, 9));
+
+        let mut workflow = Workflow::new().with_history(vec![Message::user("request")]);
+        assert_eq!(
+            workflow
+                .run_stage("extract", &extract, "extract")
+                .await
+                .unwrap(),
+            "requirements"
+        );
+        workflow
+            .run_stage("predict", &predict, "predict")
+            .await
+            .unwrap();
+        workflow
+            .run_stage("optimize", &optimize, "optimize")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            workflow.history(),
+            &[
+                Message::user("request"),
+                Message::user("extract"),
+                Message::assistant("requirements"),
+                Message::user("predict"),
+                Message::assistant("prediction"),
+                Message::user("optimize"),
+                Message::assistant("optimization"),
+            ]
+        );
+
+        // Each stage sees the output of the stages before it
+        assert_eq!(predict_model.requests()[0].chat_history.len(), 4);
+        assert_eq!(optimize_model.requests()[0].chat_history.len(), 6);
+
+        let names: Vec<_> = workflow.stages().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["extract", "predict", "optimize"]);
+        assert_eq!(workflow.stages()[1].usage, usage(20, 7));
+        assert_eq!(workflow.total_usage(), usage(60, 21));
+        assert!(workflow.summary().contains("3 stage(s), 7 message(s)"));
+    }
+
+    #[tokio::test]
+    async fn test_parallel_stages_merge_in_stage_order() {
+        let (composition, composition_model) = agent("composition", usage(1, 1));
+        let (structure, structure_model) = agent("structure", usage(2, 2));
+
+        let mut workflow = Workflow::new().with_history(vec![Message::user("request")]);
+        let responses = workflow
+            .run_stages_parallel(vec![
+                ("structure", &structure, "optimize structure"),
+                ("composition", &composition, "optimize composition"),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(responses, ["structure", "composition"]);
+        assert_eq!(
+            workflow.history(),
+            &[
+                Message::user("request"),
+                Message::user("optimize structure"),
+                Message::assistant("structure"),
+                Message::user("optimize composition"),
+                Message::assistant("composition"),
+            ]
+        );
+
+        // Parallel stages only see the history from before the batch
+        assert_eq!(composition_model.requests()[0].chat_history.len(), 2);
+        assert_eq!(structure_model.requests()[0].chat_history.len(), 2);
+        assert_eq!(workflow.total_usage(), usage(3, 3));
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let (extract, _) = agent("requirements", usage(10, 5));
+        let mut workflow = Workflow::new();
+        workflow
+            .run_stage("extract", &extract, "extract")
+            .await
+            .unwrap();
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("workflow.json");
+        workflow.save(&path).unwrap();
+
+        let loaded = Workflow::load(&path).unwrap();
+        assert_eq!(loaded.history(), workflow.history());
+        assert_eq!(loaded.stages(), workflow.stages());
+    }
+
+    #[tokio::test]
+    async fn test_export_markdown() {
+        let (extract, _) = agent("requirements", usage(10, 5));
+        let (predict, _) = agent("prediction", usage(20, 7));
+        let mut workflow = Workflow::new();
+        workflow
+            .run_stage("extract", &extract, "extract")
+            .await
+            .unwrap();
+        workflow
+            .run_stage("predict", &predict, "predict")
+            .await
+            .unwrap();
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("report.md");
+        workflow.export_markdown(&path).unwrap();
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert!(report.starts_with("# Workflow report\n\n**`user`**\n\nextract\n"));
+        assert!(report.contains("**`assistant`**\n\nprediction\n"));
+        assert!(report.ends_with(
+            "| extract | 10 | 5 | 15 |\n| predict | 20 | 7 | 27 |\n| Total | 30 | 12 | 42 |\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_and_resume() {
+        let model = MockCompletionModel::new()
+            .with_turn(vec![
+                AssistantContent::Reasoning(Reasoning::new("Submit the task first.")),
+                AssistantContent::text("submitting"),
+            ])
+            .with_usage(usage(10, 5));
+        let agent = AgentBuilder::new(model).build();
+
+        let mut workflow = Workflow::new()
+            .with_history(vec![
+                Message::user("optimize the coating"),
+                Message::Assistant {
+                    id: None,
+                    content: OneOrMany::one(AssistantContent::tool_call(
+                        "call-1",
+                        "submit_task",
+                        serde_json::json!({"alloy": "AlCrN"}),
+                    )),
+                },
+                Message::tool_result("call-1", "task-42"),
+            ])
+            .max_turns(3);
+        workflow
+            .run_stage("submit", &agent, "submit")
+            .await
+            .unwrap();
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        workflow.checkpoint(&path).unwrap();
+
+        let resumed = Workflow::resume(&path).unwrap();
+        assert_eq!(resumed.history(), workflow.history());
+        assert_eq!(resumed.stages(), workflow.stages());
+        assert_eq!(resumed.max_turns, Some(3));
+
+        // Files from another schema version are rejected with a descriptive error
+        let mut json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        json["schema_version"] = serde_json::json!(HISTORY_SCHEMA_VERSION + 1);
+        std::fs::write(&path, json.to_string()).unwrap();
+
+        let err = Workflow::resume(&path).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                WorkflowError::History(HistoryError::UnsupportedVersion { .. })
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fork_and_merge_summaries() {
+        let (extract, _) = agent("requirements", usage(10, 5));
+        let (predict, _) = agent("prediction", usage(20, 7));
+        let (ductile, ductile_model) = agent("add more Al", usage(30, 9));
+        let (hard, hard_model) = agent("add more Cr", usage(40, 11));
+
+        let mut workflow = Workflow::new().with_history(vec![Message::user("request")]);
+        workflow
+            .run_stage("extract", &extract, "extract")
+            .await
+            .unwrap();
+        workflow
+            .run_stage("predict", &predict, "predict")
+            .await
+            .unwrap();
+        let parent_history = workflow.history().to_vec();
+
+        let mut branch_a = workflow.fork("ductility");
+        let mut branch_b = workflow.fork("hardness");
+        branch_a
+            .run_stage("optimize", &ductile, "optimize ductility")
+            .await
+            .unwrap();
+        branch_b
+            .run_stage("optimize", &hard, "optimize hardness")
+            .await
+            .unwrap();
+
+        // Branches start from the parent's state and don't see each other
+        assert_eq!(workflow.history(), parent_history);
+        assert_eq!(workflow.stages().len(), 2);
+        assert_eq!(ductile_model.requests()[0].chat_history.len(), 6);
+        assert_eq!(hard_model.requests()[0].chat_history.len(), 6);
+        assert_eq!(
+            branch_a.history().last(),
+            Some(&Message::assistant("add more Al"))
+        );
+        assert!(
+            !branch_b
+                .history()
+                .contains(&Message::assistant("add more Al"))
+        );
+        assert_eq!(branch_a.total_usage(), usage(60, 21));
+        assert_eq!(branch_a.branch(), Some("ductility"));
+        assert!(branch_a.summary().starts_with("Branch `ductility`"));
+        assert_eq!(branch_a.fork("again").branch(), Some("ductility/again"));
+
+        workflow.merge_summaries([&branch_a, &branch_b]);
+
+        assert_eq!(
+            &workflow.history()[parent_history.len()..],
+            &[
+                Message::user("Conclusions of branch `ductility`:\n- optimize: add more Al"),
+                Message::user("Conclusions of branch `hardness`:\n- optimize: add more Cr"),
+            ]
+        );
+        let names: Vec<_> = workflow.stages().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["extract", "predict", "ductility", "hardness"]);
+        assert_eq!(workflow.total_usage(), usage(100, 32));
+    }
+
+    #[tokio::test]
+    async fn test_branch_checkpoints() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        let workflow = Workflow::new().with_history(vec![Message::user("request")]);
+        let mut branch = workflow.fork("strategy a");
+        let (optimize, _) = agent("add more Al", usage(30, 9));
+        branch
+            .run_stage("optimize", &optimize, "optimize")
+            .await
+            .unwrap();
+
+        assert_eq!(workflow.checkpoint(&path).unwrap(), path);
+        let branch_path = branch.checkpoint(&path).unwrap();
+        assert_eq!(branch_path, dir.path().join("checkpoint.strategy-a.json"));
+        assert_eq!(
+            branch
+                .fork("b")
+                .checkpoint_path(dir.path().join("checkpoint")),
+            dir.path().join("checkpoint.strategy-a-b")
+        );
+
+        let resumed = Workflow::resume(&branch_path).unwrap();
+        assert_eq!(resumed.branch(), Some("strategy a"));
+        assert_eq!(resumed.history(), branch.history());
+        assert_eq!(
+            Workflow::resume(&path).unwrap().history(),
+            workflow.history()
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Prompt caching for completion models.
+//!
+//! [CachingCompletionModel] wraps any [CompletionModel] and memoizes its responses, so that
+//! re-sending an identical request (same preamble, history, documents, tools and parameters)
+//! does not call the provider again. This is mostly useful during development, where the same
+//! prompts are sent over and over.
+//!
+//! # Example
+//! ```rust,ignore
+//! use rig::completion::CachingCompletionModel;
+//! use rig::pipeline::FileCache;
+//! use rig::providers::openai;
+//!
+//! let client = openai::Client::from_env();
+//! let model = CachingCompletionModel::with_cache(
+//!     client.completion_model(openai::GPT_4O),
+//!     FileCache::new(".rig-cache"),
+//! );
+//! let agent = rig::agent::AgentBuilder::new(model).build();
+//! ```
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse, Usage};
+use crate::{
+    OneOrMany,
+    message::AssistantContent,
+    pipeline::cache::{Cache, MemoryCache, cache_key},
+    streaming::StreamingCompletionResponse,
+};
+
+/// Number of responses kept by the in-memory cache of [CachingCompletionModel::new].
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// A completion model answering repeated identical requests from a cache instead of calling
+/// the wrapped model again.
+///
+/// Only successful, non-streaming completions are cached; streaming requests always go to the
+/// wrapped model. Requests are keyed by a hash of their serialized form, which does not include
+/// the name of the wrapped model: do not share a persistent cache between different models.
+pub struct CachingCompletionModel<M> {
+    model: M,
+    cache: Arc<dyn Cache>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl<M> CachingCompletionModel<M> {
+    /// Wrap `model` with an in-memory cache of [DEFAULT_CACHE_CAPACITY] responses.
+    pub fn new(model: M) -> Self {
+        Self::with_cache(model, MemoryCache::new(DEFAULT_CACHE_CAPACITY))
+    }
+
+    /// Wrap `model`, storing its responses in `cache`, eg. a
+    /// [FileCache](crate::pipeline::FileCache) to reuse them between runs.
+    pub fn with_cache(model: M, cache: impl Cache + 'static) -> Self {
+        Self {
+            model,
+            cache: Arc::new(cache),
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The wrapped model.
+    pub fn inner(&self) -> &M {
+        &self.model
+    }
+
+    /// Number of completions answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of completions sent to the wrapped model.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+impl<M: Clone> Clone for CachingCompletionModel<M> {
+    fn clone(&self) -> Self {
+        Self {
+            model: self.model.clone(),
+            cache: self.cache.clone(),
+            hits: self.hits.clone(),
+            misses: self.misses.clone(),
+        }
+    }
+}
+
+/// A [CompletionResponse] as stored in the cache.
+#[derive(Serialize, Deserialize)]
+struct CachedResponse<T> {
+    choice: OneOrMany<AssistantContent>,
+    usage: Usage,
+    raw_response: T,
+}
+
+impl<M> CompletionModel for CachingCompletionModel<M>
+where
+    M: CompletionModel,
+{
+    type Response = M::Response;
+    type StreamingResponse = M::StreamingResponse;
+    type Client = M::Client;
+
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        Self::new(M::make(client, model))
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let key = serde_json::to_vec(&request)
+            .map(|bytes| cache_key(&bytes))
+            .inspect_err(|error| tracing::warn!("Completion request is not cacheable: {error}"))
+            .ok();
+
+        if let Some(cached) = key
+            .and_then(|key| self.cache.get(key))
+            .and_then(|value| serde_json::from_value::<CachedResponse<M::Response>>(value).ok())
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(CompletionResponse {
+                choice: cached.choice,
+                usage: cached.usage,
+                raw_response: cached.raw_response,
+            });
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let response = self.model.completion(request).await?;
+
+        if let Some(key) = key {
+            let cached = CachedResponse {
+                choice: response.choice.clone(),
+                usage: response.usage,
+                raw_response: &response.raw_response,
+            };
+            match serde_json::to_value(&cached) {
+                Ok(value) => self.cache.put(key, value),
+                Err(error) => tracing::warn!("Completion response is not cacheable: {error}"),
+            }
+        }
+
+        Ok(response)
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        self.model.stream(request).await
+    }
+
+    fn finish_reason(response: &Self::Response) -> Option<super::FinishReason> {
+        M::finish_reason(response)
+    }
+
+    fn streaming_finish_reason(response: &Self::StreamingResponse) -> Option<super::FinishReason> {
+        M::streaming_finish_reason(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::test_utils::MockCompletionModel;
+
+    #[tokio::test]
+    async fn test_caching_model_calls_inner_model_once() {
+        let inner = MockCompletionModel::new()
+            .with_text("first answer")
+            .with_text("second answer")
+            .with_text("streamed answer")
+            .with_text("streamed again");
+        let model = CachingCompletionModel::new(inner.clone());
+
+        for _ in 0..3 {
+            let response = model.completion_request("Hello").send().await.unwrap();
+            assert_eq!(
+                response.choice.first(),
+                AssistantContent::text("first answer")
+            );
+        }
+        assert_eq!(inner.requests().len(), 1);
+        assert_eq!((model.hits(), model.misses()), (2, 1));
+
+        // A different request is not answered from the cache
+        let response = model
+            .completion_request("Hello")
+            .temperature(0.5)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.choice.first(),
+            AssistantContent::text("second answer")
+        );
+        assert_eq!(inner.requests().len(), 2);
+
+        // Streaming requests bypass the cache
+        for _ in 0..2 {
+            let mut stream = model.completion_request("Hello").stream().await.unwrap();
+            while stream.next().await.is_some() {}
+        }
+        assert_eq!(inner.requests().len(), 4);
+        assert_eq!((model.hits(), model.misses()), (2, 2));
+    }
+}
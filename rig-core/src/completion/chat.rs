@@ -71,9 +71,9 @@ impl<M: CompletionModel + 'static> CliChat for AgentImpl<M> {
             };
 
             match chunk {
-                Ok(MultiTurnStreamItem::StreamItem(StreamedAssistantContent::Text(Text {
-                    text,
-                }))) => {
+                Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(
+                    Text { text },
+                ))) => {
                     print!("{}", text);
                     acc.push_str(&text);
                 }
@@ -0,0 +1,271 @@
+//! Provider fallback for completion models.
+//!
+//! [FallbackCompletionModel] holds an ordered list of completion models, possibly from
+//! different providers, and sends each request to the first one, falling back to the next
+//! models when it fails.
+//!
+//! # Example
+//! ```rust,ignore
+//! use rig::completion::FallbackCompletionModel;
+//! use rig::providers::{ollama, qwen};
+//!
+//! let model = FallbackCompletionModel::new(qwen::Client::from_env().completion_model(qwen::QWEN_PLUS))
+//!     .fallback(ollama::Client::new().completion_model("qwen2.5"));
+//! let agent = rig::agent::AgentBuilder::new(model).build();
+//! ```
+use std::sync::Arc;
+
+use futures::{StreamExt, stream};
+
+use super::{
+    CompletionError, CompletionModel, CompletionModelDyn, CompletionRequest, CompletionResponse,
+    GetTokenUsage,
+};
+use crate::{
+    client::FinalCompletionResponse, streaming::StreamingCompletionResponse,
+    wasm_compat::WasmCompatSend,
+};
+
+/// Errors of every model of a [FallbackCompletionModel] when none of them succeeded, returned
+/// as the source of a [CompletionError::ProviderApiError].
+#[derive(Debug, thiserror::Error)]
+#[error("All {} models failed: {}", errors.len(), format_errors(errors))]
+pub struct FallbackError {
+    /// The error of each model, in the order they were tried.
+    pub errors: Vec<CompletionError>,
+}
+
+fn format_errors(errors: &[CompletionError]) -> String {
+    errors
+        .iter()
+        .enumerate()
+        .map(|(index, error)| format!("[{index}] {error}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// A completion model trying an ordered list of models until one of them succeeds.
+///
+/// A request falls back to the next model on [retryable](CompletionError::is_retryable)
+/// errors. Other errors, such as a rejected request, are returned right away since the next
+/// models would most likely reject it too. When every model failed, the errors are aggregated
+/// in a [FallbackError].
+///
+/// Streaming requests fall back when the stream fails before its first chunk; errors occurring
+/// later are returned in the stream.
+///
+/// The raw responses of the models are not kept, since they can come from different providers.
+#[derive(Clone)]
+pub struct FallbackCompletionModel {
+    models: Vec<Arc<dyn CompletionModelDyn>>,
+}
+
+impl FallbackCompletionModel {
+    /// Create a fallback model sending requests to `model` first.
+    pub fn new<M, R>(model: M) -> Self
+    where
+        M: CompletionModel<StreamingResponse = R> + 'static,
+        R: Clone + Unpin + GetTokenUsage + 'static,
+    {
+        Self {
+            models: vec![Arc::new(model)],
+        }
+    }
+
+    /// Add `model` at the end of the list of models to try.
+    pub fn fallback<M, R>(mut self, model: M) -> Self
+    where
+        M: CompletionModel<StreamingResponse = R> + 'static,
+        R: Clone + Unpin + GetTokenUsage + 'static,
+    {
+        self.models.push(Arc::new(model));
+        self
+    }
+
+    /// Number of models in the list.
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    /// Whether to try the model after the one at `index`, which failed with `error`.
+    fn should_fall_back(&self, index: usize, error: &CompletionError) -> bool {
+        let fall_back = index + 1 < self.models.len() && error.is_retryable();
+        if fall_back {
+            tracing::warn!("Completion request failed on model {index}, falling back: {error}");
+        }
+        fall_back
+    }
+
+    fn aggregate(mut errors: Vec<CompletionError>) -> CompletionError {
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            CompletionError::ProviderApiError(Box::new(FallbackError { errors }))
+        }
+    }
+}
+
+impl CompletionModel for FallbackCompletionModel {
+    type Response = ();
+    type StreamingResponse = FinalCompletionResponse;
+    type Client = ();
+
+    /// **PANICS**: a fallback model is built from its models with [FallbackCompletionModel::new]
+    fn make(_: &Self::Client, _: impl Into<String>) -> Self {
+        panic!("Cannot create a fallback completion model from a client")
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let mut errors = Vec::new();
+        for (index, model) in self.models.iter().enumerate() {
+            match model.completion(request.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    let fall_back = self.should_fall_back(index, &error);
+                    errors.push(error);
+                    if !fall_back {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Err(Self::aggregate(errors))
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let mut errors = Vec::new();
+        for (index, model) in self.models.iter().enumerate() {
+            let response = match model.stream(request.clone()).await {
+                Ok(response) => first_item_checked(response).await,
+                Err(error) => Err(error),
+            };
+            match response {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    let fall_back = self.should_fall_back(index, &error);
+                    errors.push(error);
+                    if !fall_back {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Err(Self::aggregate(errors))
+    }
+}
+
+/// Wait for the first item of `response`, returning the error it failed with if the stream
+/// failed before producing anything. Otherwise, the returned response replays the first item.
+pub(crate) async fn first_item_checked<R>(
+    mut response: StreamingCompletionResponse<R>,
+) -> Result<StreamingCompletionResponse<R>, CompletionError>
+where
+    R: Clone + Unpin + GetTokenUsage + WasmCompatSend + 'static,
+{
+    let first = response.inner.next().await;
+    if let Some(Err(error)) = first {
+        return Err(error);
+    }
+
+    Ok(StreamingCompletionResponse::stream(Box::pin(
+        stream::iter(first).chain(response.inner),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::AgentBuilder, completion::Prompt, message::AssistantContent,
+        test_utils::MockCompletionModel,
+    };
+
+    #[tokio::test]
+    async fn test_fallback_model_uses_next_model_on_error() {
+        let primary = MockCompletionModel::new().with_error("503 Service Unavailable");
+        let secondary = MockCompletionModel::new().with_text("recovered");
+        let model = FallbackCompletionModel::new(primary.clone()).fallback(secondary.clone());
+
+        let response = CompletionModel::completion_request(&model, "Hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.choice.first(), AssistantContent::text("recovered"));
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(
+            secondary.requests()[0].chat_history,
+            primary.requests()[0].chat_history
+        );
+
+        // The fallback model can back an agent like any other model
+        let agent = AgentBuilder::new(model).build();
+        assert_eq!(agent.prompt("Hello").await.unwrap(), "mock response");
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(secondary.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_model_aggregates_errors() {
+        let model = FallbackCompletionModel::new(
+            MockCompletionModel::new().with_error("503 Service Unavailable"),
+        )
+        .fallback(MockCompletionModel::new().with_error("connection reset"));
+
+        let error = CompletionModel::completion_request(&model, "Hello")
+            .send()
+            .await
+            .unwrap_err();
+        let CompletionError::ProviderApiError(source) = &error else {
+            panic!("Expected the aggregated errors, got {error:?}");
+        };
+        let errors = &source.downcast_ref::<FallbackError>().unwrap().errors;
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            error.to_string(),
+            "ProviderError: All 2 models failed: [0] ProviderError: 503 Service Unavailable; \
+             [1] ProviderError: connection reset"
+        );
+
+        // Requests rejected by the first model are not sent to the next ones
+        let secondary = MockCompletionModel::new();
+        let model = FallbackCompletionModel::new(
+            MockCompletionModel::new().with_error("400 Bad Request: InvalidParameter"),
+        )
+        .fallback(secondary.clone());
+        assert!(matches!(
+            CompletionModel::completion_request(&model, "Hello")
+                .send()
+                .await,
+            Err(CompletionError::ProviderError(_))
+        ));
+        assert!(secondary.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fallback_model_streams_from_next_model() {
+        let primary = MockCompletionModel::new().with_stream_error("503 Service Unavailable");
+        let secondary = MockCompletionModel::new().with_text("streamed");
+        let model = FallbackCompletionModel::new(primary).fallback(secondary);
+
+        let mut stream = CompletionModel::completion_request(&model, "Hello")
+            .stream()
+            .await
+            .unwrap();
+        while let Some(item) = stream.next().await {
+            item.unwrap();
+        }
+        assert_eq!(stream.choice.first(), AssistantContent::text("streamed"));
+    }
+}
@@ -0,0 +1,260 @@
+//! Load balancing across several instances of a completion model.
+//!
+//! [LoadBalancedCompletionModel] spreads requests over several models of the same provider,
+//! typically the same model used with different API keys, so that their rate limits add up.
+//!
+//! # Example
+//! ```rust,ignore
+//! use rig::completion::LoadBalancedCompletionModel;
+//! use rig::providers::qwen;
+//!
+//! let model = LoadBalancedCompletionModel::new(
+//!     ["key-1", "key-2", "key-3"]
+//!         .map(|key| qwen::Client::new(key).completion_model(qwen::QWEN_PLUS)),
+//! );
+//! let agent = rig::agent::AgentBuilder::new(model).build();
+//! ```
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse};
+use crate::streaming::StreamingCompletionResponse;
+
+/// How long a rate limited model is avoided by default, see
+/// [LoadBalancedCompletionModel::rate_limit_cooldown].
+pub const DEFAULT_RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Slot {
+    /// Sequence number of the last request sent to the model, 0 if it was never used
+    last_used: u64,
+    /// The model answered `429 Too Many Requests` and is avoided until then
+    limited_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Slots {
+    slots: Vec<Slot>,
+    sequence: u64,
+}
+
+/// A completion model sending each request to the least recently used of several models.
+///
+/// When a model answers with `429 Too Many Requests`, the request is sent again to the next
+/// model, and the rate limited model is only used again once its cooldown elapsed, or when every
+/// model is rate limited. Other errors are returned as is.
+pub struct LoadBalancedCompletionModel<M> {
+    models: Vec<M>,
+    slots: Arc<Mutex<Slots>>,
+    cooldown: Duration,
+}
+
+impl<M: CompletionModel> LoadBalancedCompletionModel<M> {
+    /// Balance requests over `models`.
+    ///
+    /// # Panics
+    /// If `models` is empty.
+    pub fn new(models: impl IntoIterator<Item = M>) -> Self {
+        let models: Vec<M> = models.into_iter().collect();
+        assert!(
+            !models.is_empty(),
+            "a load balanced model needs at least one model"
+        );
+
+        Self {
+            slots: Arc::new(Mutex::new(Slots {
+                slots: vec![Slot::default(); models.len()],
+                sequence: 0,
+            })),
+            models,
+            cooldown: DEFAULT_RATE_LIMIT_COOLDOWN,
+        }
+    }
+
+    /// Avoid a model for `cooldown` after it answered `429 Too Many Requests`.
+    pub fn rate_limit_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// The balanced models.
+    pub fn models(&self) -> &[M] {
+        &self.models
+    }
+
+    /// The models in the order to try them for the next request: the models which are not rate
+    /// limited from the least to the most recently used, then the rate limited ones from the
+    /// first to the last to recover.
+    fn order(&self) -> Vec<usize> {
+        let slots = self.slots.lock().expect("load balancer lock poisoned");
+        let now = Instant::now();
+        let mut order: Vec<usize> = (0..self.models.len()).collect();
+        order.sort_by_key(|index| {
+            let slot = slots.slots[*index];
+            match slot.limited_until.filter(|until| *until > now) {
+                Some(until) => (true, Some(until), 0),
+                None => (false, None, slot.last_used),
+            }
+        });
+        order
+    }
+
+    fn mark_used(&self, index: usize) {
+        let mut slots = self.slots.lock().expect("load balancer lock poisoned");
+        slots.sequence += 1;
+        let sequence = slots.sequence;
+        slots.slots[index].last_used = sequence;
+    }
+
+    /// Record the result of a request sent to the model at `index`. Returns whether the request
+    /// was rate limited and should be sent to another model.
+    fn rate_limited<T>(&self, index: usize, result: &Result<T, CompletionError>) -> bool {
+        let limited = matches!(result, Err(error) if error.is_rate_limited());
+        let mut slots = self.slots.lock().expect("load balancer lock poisoned");
+        if limited {
+            tracing::warn!(
+                "Model {index} is rate limited, avoiding it for {:?}",
+                self.cooldown
+            );
+            slots.slots[index].limited_until = Some(Instant::now() + self.cooldown);
+        } else {
+            slots.slots[index].limited_until = None;
+        }
+        limited
+    }
+}
+
+impl<M: Clone> Clone for LoadBalancedCompletionModel<M> {
+    fn clone(&self) -> Self {
+        Self {
+            models: self.models.clone(),
+            slots: self.slots.clone(),
+            cooldown: self.cooldown,
+        }
+    }
+}
+
+impl<M> CompletionModel for LoadBalancedCompletionModel<M>
+where
+    M: CompletionModel,
+{
+    type Response = M::Response;
+    type StreamingResponse = M::StreamingResponse;
+    type Client = M::Client;
+
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        Self::new([M::make(client, model)])
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let order = self.order();
+        let last = order.len() - 1;
+        for (attempt, index) in order.into_iter().enumerate() {
+            self.mark_used(index);
+            let result = self.models[index].completion(request.clone()).await;
+            if !self.rate_limited(index, &result) || attempt == last {
+                return result;
+            }
+        }
+
+        unreachable!("a load balanced model has at least one model")
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let order = self.order();
+        let last = order.len() - 1;
+        for (attempt, index) in order.into_iter().enumerate() {
+            self.mark_used(index);
+            let result = self.models[index].stream(request.clone()).await;
+            if !self.rate_limited(index, &result) || attempt == last {
+                return result;
+            }
+        }
+
+        unreachable!("a load balanced model has at least one model")
+    }
+
+    fn finish_reason(response: &Self::Response) -> Option<super::FinishReason> {
+        M::finish_reason(response)
+    }
+
+    fn streaming_finish_reason(response: &Self::StreamingResponse) -> Option<super::FinishReason> {
+        M::streaming_finish_reason(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockCompletionModel;
+
+    #[tokio::test]
+    async fn test_requests_are_distributed() {
+        let models = [
+            MockCompletionModel::new(),
+            MockCompletionModel::new(),
+            MockCompletionModel::new(),
+        ];
+        let model = LoadBalancedCompletionModel::new(models.clone());
+
+        for _ in 0..6 {
+            model.completion_request("Hello").send().await.unwrap();
+        }
+        for model in &models {
+            assert_eq!(model.requests().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_model_is_avoided() {
+        let models = [
+            MockCompletionModel::new().with_error("429 Too Many Requests: Throttling"),
+            MockCompletionModel::new(),
+            MockCompletionModel::new(),
+        ];
+        let model = LoadBalancedCompletionModel::new(models.clone())
+            .rate_limit_cooldown(Duration::from_secs(60));
+
+        // The rate limited request is sent again to the next model
+        for _ in 0..5 {
+            model.completion_request("Hello").send().await.unwrap();
+        }
+        assert_eq!(models[0].requests().len(), 1);
+        assert_eq!(models[1].requests().len(), 3);
+        assert_eq!(models[2].requests().len(), 2);
+
+        // Other errors are not sent to another model
+        let failing = [
+            MockCompletionModel::new().with_error("500 Internal Server Error"),
+            MockCompletionModel::new(),
+        ];
+        let model = LoadBalancedCompletionModel::new(failing.clone());
+        assert!(model.completion_request("Hello").send().await.is_err());
+        assert!(failing[1].requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_every_model_rate_limited() {
+        let models = [
+            MockCompletionModel::new().with_error("429 Too Many Requests"),
+            MockCompletionModel::new().with_error("429 Too Many Requests"),
+        ];
+        let model = LoadBalancedCompletionModel::new(models.clone());
+
+        let error = model.completion_request("Hello").send().await.unwrap_err();
+        assert!(error.is_rate_limited());
+
+        // Every model is rate limited: the first to recover is used
+        model.completion_request("Hello").send().await.unwrap();
+        assert_eq!(models[0].requests().len(), 2);
+        assert_eq!(models[1].requests().len(), 1);
+    }
+}
@@ -1,5 +1,11 @@
+pub mod cache;
+pub mod fallback;
+pub mod load_balance;
 pub mod message;
 pub mod request;
 
+pub use cache::CachingCompletionModel;
+pub use fallback::{FallbackCompletionModel, FallbackError};
+pub use load_balance::LoadBalancedCompletionModel;
 pub use message::{AssistantContent, Message, MessageError};
 pub use request::*;
@@ -63,7 +63,8 @@
 //! For more information on how to use the completion functionality, refer to the documentation of
 //! the individual traits, structs, and enums defined in this module.
 
-use super::message::{AssistantContent, DocumentMediaType};
+use super::message::{AssistantContent, DocumentMediaType, ToolCall};
+use crate::agent::BudgetLimit;
 use crate::client::FinalCompletionResponse;
 use crate::client::completion::CompletionModelHandle;
 use crate::message::ToolChoice;
@@ -115,6 +116,70 @@ pub enum CompletionError {
     /// Error returned by the completion model provider
     #[error("ProviderError: {0}")]
     ProviderError(String),
+
+    #[cfg(not(target_family = "wasm"))]
+    /// Structured error returned by the completion model provider. The provider's error type is
+    /// kept as the error source, so callers can downcast it to read eg. the provider error code.
+    #[error("ProviderError: {0}")]
+    ProviderApiError(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[cfg(target_family = "wasm")]
+    /// Structured error returned by the completion model provider. The provider's error type is
+    /// kept as the error source, so callers can downcast it to read eg. the provider error code.
+    #[error("ProviderError: {0}")]
+    ProviderApiError(#[source] Box<dyn std::error::Error + 'static>),
+}
+
+impl CompletionError {
+    /// Whether the request may succeed when sent again, eg. to another model: transport errors
+    /// (connection failures, timeouts) and provider errors are retryable, unless the provider
+    /// answered with a client error status such as `400 Bad Request`. `408 Request Timeout` and
+    /// `429 Too Many Requests` are retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CompletionError::HttpError(_)
+            | CompletionError::ProviderError(_)
+            | CompletionError::ProviderApiError(_) => self.status().is_none_or(is_retryable_status),
+            _ => false,
+        }
+    }
+
+    /// Whether the provider rejected the request with `429 Too Many Requests`, eg. because the
+    /// rate limit of the API key was reached.
+    pub fn is_rate_limited(&self) -> bool {
+        self.status() == Some(http::StatusCode::TOO_MANY_REQUESTS)
+    }
+
+    /// The HTTP status the provider answered with, if known.
+    pub(crate) fn status(&self) -> Option<http::StatusCode> {
+        match self {
+            CompletionError::HttpError(
+                http_client::Error::InvalidStatusCode(status)
+                | http_client::Error::InvalidStatusCodeWithMessage(status, _),
+            ) => Some(*status),
+            CompletionError::ProviderError(message) => provider_message_status(message),
+            CompletionError::ProviderApiError(source) => {
+                provider_message_status(&source.to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_retryable_status(status: http::StatusCode) -> bool {
+    !status.is_client_error()
+        || status == http::StatusCode::REQUEST_TIMEOUT
+        || status == http::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Provider error messages start with the HTTP status when the provider answered with one,
+/// eg. `400 Bad Request: InvalidParameter: ...`.
+fn provider_message_status(message: &str) -> Option<http::StatusCode> {
+    message
+        .split_whitespace()
+        .next()
+        .and_then(|code| code.trim_end_matches(':').parse::<u16>().ok())
+        .and_then(|code| http::StatusCode::from_u16(code).ok())
 }
 
 /// Prompt errors
@@ -135,16 +200,54 @@ pub enum PromptError {
     /// The LLM tried to call too many tools during a multi-turn conversation.
     /// To fix this, you may either need to lower the amount of tools your model has access to (and then create other agents to share the tool load)
     /// or increase the amount of turns given in `.multi_turn()`.
+    ///
+    /// `chat_history` holds every message produced before the limit was hit (ending with the
+    /// results of `last_tool_calls`), so the run can be resumed with a higher limit by passing it
+    /// back as chat history. `usage` is the token usage of the agent and its sub-agents aggregated
+    /// over all turns, as in [PromptResponse::total_usage](crate::agent::PromptResponse::total_usage).
     #[error("MaxDepthError: (reached limit: {max_depth})")]
     MaxDepthError {
         max_depth: usize,
         chat_history: Box<Vec<Message>>,
         prompt: Message,
+        usage: Usage,
+        last_tool_calls: Vec<ToolCall>,
     },
 
     /// A prompting loop was cancelled.
     #[error("PromptCancelled")]
     PromptCancelled { chat_history: Box<Vec<Message>> },
+
+    /// The final answer was still rejected by the request's validator after the allowed repair
+    /// attempts. `attempts` holds every rejected answer, in order, and `chat_history` the
+    /// conversation up to the last one.
+    #[error(
+        "ValidationError: answer rejected {} times, last error: {}",
+        .attempts.len(),
+        .attempts.last().map_or("", |attempt| attempt.error.as_str())
+    )]
+    ValidationError {
+        attempts: Vec<ValidationAttempt>,
+        chat_history: Box<Vec<Message>>,
+    },
+
+    /// The usage of a multi-turn run exceeded one of the caps of its request, checked after each
+    /// turn. `chat_history` holds the conversation up to the turn that exceeded the cap, and
+    /// `usage` the usage of the agent and its sub-agents so far.
+    #[error("BudgetExceeded: the run exceeded {limit}")]
+    BudgetExceeded {
+        limit: BudgetLimit,
+        chat_history: Box<Vec<Message>>,
+        usage: Usage,
+    },
+}
+
+/// A final answer rejected by the validator of a prompt request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationAttempt {
+    pub answer: String,
+    /// Why the validator rejected the answer
+    pub error: String,
 }
 
 impl PromptError {
@@ -153,6 +256,74 @@ impl PromptError {
             chat_history: Box::new(chat_history),
         }
     }
+
+    pub(crate) fn max_depth(
+        max_depth: usize,
+        chat_history: Vec<Message>,
+        prompt: Message,
+        usage: Usage,
+    ) -> Self {
+        // The tool calls of the last assistant turn, whose results end the history
+        let last_tool_calls = chat_history
+            .iter()
+            .rev()
+            .find_map(|message| match message {
+                Message::Assistant { content, .. } => {
+                    let tool_calls: Vec<_> = content
+                        .iter()
+                        .filter_map(|content| match content {
+                            AssistantContent::ToolCall(tool_call) => Some(tool_call.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    (!tool_calls.is_empty()).then_some(tool_calls)
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        Self::MaxDepthError {
+            max_depth,
+            chat_history: Box::new(chat_history),
+            prompt,
+            usage,
+            last_tool_calls,
+        }
+    }
+
+    pub(crate) fn validation_failed(
+        attempts: Vec<ValidationAttempt>,
+        chat_history: Vec<Message>,
+    ) -> Self {
+        Self::ValidationError {
+            attempts,
+            chat_history: Box::new(chat_history),
+        }
+    }
+
+    pub(crate) fn budget_exceeded(
+        limit: BudgetLimit,
+        chat_history: Vec<Message>,
+        usage: Usage,
+    ) -> Self {
+        Self::BudgetExceeded {
+            limit,
+            chat_history: Box::new(chat_history),
+            usage,
+        }
+    }
+
+    /// The messages accumulated before the prompt loop stopped, if it stopped because it hit its
+    /// turn limit or budget, was cancelled or kept producing invalid answers.
+    pub fn chat_history(&self) -> Option<&[Message]> {
+        match self {
+            Self::MaxDepthError { chat_history, .. }
+            | Self::PromptCancelled { chat_history }
+            | Self::ValidationError { chat_history, .. }
+            | Self::BudgetExceeded { chat_history, .. } => Some(chat_history.as_slice()),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -288,6 +459,34 @@ where
     }
 }
 
+/// Why the model stopped generating a response, see [CompletionModel::finish_reason].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished its answer
+    Stop,
+    /// The answer was cut off because it reached the maximum output length
+    Length,
+    /// The model stopped to call tools
+    ToolCalls,
+    /// The answer was withheld by the provider's content filter
+    ContentFilter,
+    /// Any other reason reported by the provider
+    Other(String),
+}
+
+impl From<&str> for FinishReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "tool_calls" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+}
+
 /// Struct representing the token usage for a completion request.
 /// If tokens used are `0`, then the provider failed to supply token usage metrics.
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
@@ -375,6 +574,16 @@ pub trait CompletionModel: Clone + WasmCompatSend + WasmCompatSync {
     fn completion_request(&self, prompt: impl Into<Message>) -> CompletionRequestBuilder<Self> {
         CompletionRequestBuilder::new(self.clone(), prompt)
     }
+
+    /// The reason the model stopped generating `response`, for providers reporting it.
+    fn finish_reason(_response: &Self::Response) -> Option<FinishReason> {
+        None
+    }
+
+    /// The reason the model stopped generating a streamed response, from its final response.
+    fn streaming_finish_reason(_response: &Self::StreamingResponse) -> Option<FinishReason> {
+        None
+    }
 }
 
 pub trait CompletionModelDyn: WasmCompatSend + WasmCompatSync {
@@ -446,7 +655,7 @@ where
 }
 
 /// Struct representing a general completion request that can be sent to a completion model provider.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CompletionRequest {
     /// The preamble to be sent to the completion model provider
     pub preamble: Option<String>,
@@ -620,6 +829,12 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
             .fold(self, |builder, tool| builder.tool(tool))
     }
 
+    /// Keep only the tools matching `predicate` in the completion request.
+    pub fn retain_tools(mut self, predicate: impl FnMut(&ToolDefinition) -> bool) -> Self {
+        self.tools.retain(predicate);
+        self
+    }
+
     /// Adds additional parameters to the completion request.
     /// This can be used to set additional provider-specific parameters. For example,
     /// Cohere's completion models accept a `connectors` parameter that can be used to
@@ -810,4 +1025,30 @@ mod tests {
 
         assert_eq!(request.normalized_documents(), None);
     }
+
+    #[test]
+    fn test_retryable_errors() {
+        let status = |code: u16| http::StatusCode::from_u16(code).unwrap();
+
+        assert!(CompletionError::HttpError(http_client::Error::StreamEnded).is_retryable());
+        assert!(
+            CompletionError::HttpError(http_client::Error::InvalidStatusCode(status(503)))
+                .is_retryable()
+        );
+        assert!(
+            !CompletionError::HttpError(http_client::Error::InvalidStatusCode(status(401)))
+                .is_retryable()
+        );
+        assert!(CompletionError::ProviderError("500 Internal Server Error".into()).is_retryable());
+        assert!(
+            CompletionError::ProviderError("429 Too Many Requests: Throttling".into())
+                .is_retryable()
+        );
+        assert!(CompletionError::ProviderError("upstream overloaded".into()).is_retryable());
+        assert!(
+            !CompletionError::ProviderError("400 Bad Request: InvalidParameter: bad input".into())
+                .is_retryable()
+        );
+        assert!(!CompletionError::ResponseError("no choices".into()).is_retryable());
+    }
 }
@@ -35,9 +35,10 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
+    OneOrMany,
     agent::{Agent, AgentBuilder, AgentBuilderSimple},
     completion::{Completion, CompletionError, CompletionModel, ToolDefinition},
-    message::{AssistantContent, Message, ToolCall, ToolChoice, ToolFunction},
+    message::{AssistantContent, Message, ToolCall, ToolChoice},
     tool::Tool,
     wasm_compat::{WasmCompatSend, WasmCompatSync},
 };
@@ -77,6 +78,11 @@ where
     /// The function will retry the extraction if the initial attempt fails or
     /// if the model does not call the `submit` tool.
     ///
+    /// Models that answer in text rather than calling the `submit` tool (as Qwen models often
+    /// do) are supported by parsing a fenced or naked JSON object out of the text content.
+    /// Data that does not match the schema is fed back to the model for one correction
+    /// within the same attempt.
+    ///
     /// The number of retries is determined by the `retries` field on the Extractor struct.
     pub async fn extract(
         &self,
@@ -109,6 +115,11 @@ where
     /// The function will retry the extraction if the initial attempt fails or
     /// if the model does not call the `submit` tool.
     ///
+    /// Models that answer in text rather than calling the `submit` tool (as Qwen models often
+    /// do) are supported by parsing a fenced or naked JSON object out of the text content.
+    /// Data that does not match the schema is fed back to the model for one correction
+    /// within the same attempt.
+    ///
     /// The number of retries is determined by the `retries` field on the Extractor struct.
     pub async fn extract_with_chat_history(
         &self,
@@ -142,69 +153,145 @@ where
         text: impl Into<Message> + WasmCompatSend,
         messages: Vec<Message>,
     ) -> Result<T, ExtractionError> {
-        let response = self.agent.completion(text, messages).await?.send().await?;
-
-        if !response.choice.iter().any(|x| {
-            let AssistantContent::ToolCall(ToolCall {
-                function: ToolFunction { name, .. },
-                ..
-            }) = x
-            else {
-                return false;
-            };
-
-            name == SUBMIT_TOOL_NAME
-        }) {
-            tracing::warn!(
-                "The submit tool was not called. If this happens more than once, please ensure the model you are using is powerful enough to reliably call tools."
-            );
-        }
+        let prompt = text.into();
+        let response = self
+            .agent
+            .completion(prompt.clone(), messages.clone())
+            .await?
+            .send()
+            .await?;
+        let submission = Submission::from_choice(response.choice)?;
+
+        let error = match serde_json::from_value(submission.data().clone()) {
+            Ok(data) => return Ok(data),
+            Err(error) => error,
+        };
 
-        let arguments = response
-            .choice
-            .into_iter()
-            // We filter tool calls to look for submit tool calls
-            .filter_map(|content| {
-                if let AssistantContent::ToolCall(ToolCall {
-                    function: ToolFunction { arguments, name },
-                    ..
-                }) = content
-                {
-                    if name == SUBMIT_TOOL_NAME {
-                        Some(arguments)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
+        // Give the model a single chance to correct data that does not match the schema.
+        tracing::warn!(
+            "The extracted data does not match the schema: {error}. Asking the model to correct it."
+        );
+        let mut history = messages;
+        history.push(prompt);
+        let feedback = submission.into_feedback(&error, &mut history);
+        let response = self
+            .agent
+            .completion(feedback, history)
+            .await?
+            .send()
+            .await?;
+        let data = Submission::from_choice(response.choice)?.into_data();
+
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub async fn get_inner(&self) -> &Agent<M> {
+        &self.agent
+    }
+
+    pub async fn into_inner(self) -> Agent<M> {
+        self.agent
+    }
+}
+
+/// The raw data the model submitted, and where in the response it was found.
+enum Submission {
+    /// Arguments of a `submit` tool call.
+    ToolCall(ToolCall),
+    /// A JSON object embedded in the text content, for models that answer in text
+    /// instead of calling the `submit` tool.
+    Text {
+        text: String,
+        data: serde_json::Value,
+    },
+}
+
+impl Submission {
+    /// Prefers a `submit` tool call, falling back to a JSON object in the text content.
+    fn from_choice(choice: OneOrMany<AssistantContent>) -> Result<Self, ExtractionError> {
+        let mut submit_calls = vec![];
+        let mut texts = vec![];
+
+        for content in choice {
+            match content {
+                AssistantContent::ToolCall(call) if call.function.name == SUBMIT_TOOL_NAME => {
+                    submit_calls.push(call)
                 }
-            })
-            .collect::<Vec<_>>();
+                AssistantContent::Text(text) => texts.push(text.text),
+                _ => {}
+            }
+        }
 
-        if arguments.len() > 1 {
+        if submit_calls.len() > 1 {
             tracing::warn!(
-                "Multiple submit calls detected, using the last one. Providers / agents should only ensure one submit call."
+                "Multiple submit calls detected, using the first one. Providers / agents should only ensure one submit call."
             );
         }
 
-        let raw_data = if let Some(arg) = arguments.into_iter().next() {
-            arg
-        } else {
-            return Err(ExtractionError::NoData);
-        };
+        if let Some(call) = submit_calls.into_iter().next() {
+            return Ok(Submission::ToolCall(call));
+        }
+
+        tracing::warn!(
+            "The submit tool was not called, falling back to the text content. If this happens more than once, please ensure the model you are using is powerful enough to reliably call tools."
+        );
+
+        let text = texts.join("\n");
+        let data = json_from_text(&text).ok_or(ExtractionError::NoData)?;
 
-        Ok(serde_json::from_value(raw_data)?)
+        Ok(Submission::Text { text, data })
     }
 
-    pub async fn get_inner(&self) -> &Agent<M> {
-        &self.agent
+    fn data(&self) -> &serde_json::Value {
+        match self {
+            Submission::ToolCall(call) => &call.function.arguments,
+            Submission::Text { data, .. } => data,
+        }
     }
 
-    pub async fn into_inner(self) -> Agent<M> {
-        self.agent
+    fn into_data(self) -> serde_json::Value {
+        match self {
+            Submission::ToolCall(call) => call.function.arguments,
+            Submission::Text { data, .. } => data,
+        }
+    }
+
+    /// Records the rejected submission in `history` and returns the message asking the model
+    /// to correct it.
+    fn into_feedback(self, error: &serde_json::Error, history: &mut Vec<Message>) -> Message {
+        let feedback = format!(
+            "The submitted data does not match the required schema: {error}. Fix the data and call the `{SUBMIT_TOOL_NAME}` function again."
+        );
+
+        match self {
+            Submission::ToolCall(call) => {
+                let (id, call_id) = (call.id.clone(), call.call_id.clone());
+                history.push(call.into());
+                Message::tool_result_with_call_id(id, call_id, feedback)
+            }
+            Submission::Text { text, .. } => {
+                history.push(Message::assistant(text));
+                Message::user(feedback)
+            }
+        }
     }
 }
 
+/// Finds a JSON object in free-form text: the contents of a fenced code block if there is one,
+/// otherwise the outermost `{ ... }` span of the whole text.
+fn json_from_text(text: &str) -> Option<serde_json::Value> {
+    let fenced = text.split("```").skip(1).step_by(2);
+
+    fenced.chain(std::iter::once(text)).find_map(|candidate| {
+        let start = candidate.find('{')?;
+        let end = candidate.rfind('}')?;
+
+        serde_json::from_str(candidate.get(start..=end)?)
+            .ok()
+            .filter(serde_json::Value::is_object)
+    })
+}
+
 /// Builder for the Extractor
 pub struct ExtractorBuilder<M, T>
 where
@@ -318,3 +405,155 @@ where
         Ok(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{completion::CompletionResponse, providers::qwen, test_utils::MockCompletionModel};
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+    struct Person {
+        name: String,
+        age: u8,
+    }
+
+    /// Converts a DashScope assistant message into the content a Qwen model returns.
+    fn qwen_turn(message: serde_json::Value) -> Vec<AssistantContent> {
+        let response: qwen::CompletionResponse = serde_json::from_value(json!({
+            "request_id": "req-1",
+            "output": {
+                "choices": [{ "finish_reason": "stop", "message": message }]
+            },
+            "usage": { "input_tokens": 10, "output_tokens": 5, "total_tokens": 15 }
+        }))
+        .unwrap();
+        let response: CompletionResponse<qwen::CompletionResponse> = response.try_into().unwrap();
+
+        response.choice.into_iter().collect()
+    }
+
+    fn qwen_submit(arguments: &str) -> Vec<AssistantContent> {
+        qwen_turn(json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{
+                "id": "call_1",
+                "index": 0,
+                "type": "function",
+                "function": { "name": SUBMIT_TOOL_NAME, "arguments": arguments }
+            }]
+        }))
+    }
+
+    fn qwen_text(content: &str) -> Vec<AssistantContent> {
+        qwen_turn(json!({ "role": "assistant", "content": content }))
+    }
+
+    fn jane() -> Person {
+        Person {
+            name: "Jane".to_string(),
+            age: 30,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extracts_from_submit_tool_call() {
+        let model =
+            MockCompletionModel::new().with_turn(qwen_submit(r#"{"name":"Jane","age":30}"#));
+        let extractor = ExtractorBuilder::<_, Person>::new(model.clone()).build();
+
+        let person = extractor.extract("Jane is 30.").await.unwrap();
+
+        assert_eq!(person, jane());
+        let requests = model.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].tools[0].name, SUBMIT_TOOL_NAME);
+    }
+
+    #[tokio::test]
+    async fn test_extracts_fenced_json_from_text() {
+        let model = MockCompletionModel::new().with_turn(qwen_text(
+            "Here is the data:\n```json\n{\"name\": \"Jane\", \"age\": 30}\n```\nLet me know if you need more.",
+        ));
+        let extractor = ExtractorBuilder::<_, Person>::new(model.clone()).build();
+
+        assert_eq!(extractor.extract("Jane is 30.").await.unwrap(), jane());
+        assert_eq!(model.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_extracts_naked_json_from_text() {
+        let model = MockCompletionModel::new().with_turn(qwen_text(
+            "The person is {\"name\": \"Jane\", \"age\": 30} based on the text.",
+        ));
+        let extractor = ExtractorBuilder::<_, Person>::new(model.clone()).build();
+
+        assert_eq!(extractor.extract("Jane is 30.").await.unwrap(), jane());
+    }
+
+    #[tokio::test]
+    async fn test_text_without_json_is_no_data() {
+        let model = MockCompletionModel::new().with_turn(qwen_text("Jane is thirty."));
+        let extractor = ExtractorBuilder::<_, Person>::new(model.clone()).build();
+
+        let result = extractor.extract("Jane is 30.").await;
+
+        assert!(matches!(result, Err(ExtractionError::NoData)));
+        assert_eq!(model.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_submission_is_fed_back_as_tool_result() {
+        let model = MockCompletionModel::new()
+            .with_turn(qwen_submit(r#"{"name":"Jane","age":"thirty"}"#))
+            .with_turn(qwen_submit(r#"{"name":"Jane","age":30}"#));
+        let extractor = ExtractorBuilder::<_, Person>::new(model.clone()).build();
+
+        assert_eq!(extractor.extract("Jane is 30.").await.unwrap(), jane());
+
+        let requests = model.requests();
+        assert_eq!(requests.len(), 2);
+        let history = requests[1].chat_history.iter().collect::<Vec<_>>();
+        assert_eq!(history.len(), 3);
+        assert!(matches!(
+            history[1],
+            Message::Assistant { content, .. }
+                if matches!(content.first(), AssistantContent::ToolCall(call) if call.id == "call_1")
+        ));
+        let Message::User { content } = history[2] else {
+            panic!("expected the feedback to be a user message");
+        };
+        let crate::message::UserContent::ToolResult(result) = content.first() else {
+            panic!("expected the feedback to be a tool result");
+        };
+        assert_eq!(result.id, "call_1");
+        let crate::message::ToolResultContent::Text(text) = result.content.first() else {
+            panic!("expected a text tool result");
+        };
+        assert!(text.text.contains("invalid type"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_text_is_retried_only_once() {
+        let model = MockCompletionModel::new()
+            .with_turn(qwen_text(r#"{"name": "Jane"}"#))
+            .with_turn(qwen_text(r#"{"name": "Jane", "age": "thirty"}"#))
+            .with_turn(qwen_text(r#"{"name": "Jane", "age": 30}"#));
+        let extractor = ExtractorBuilder::<_, Person>::new(model.clone()).build();
+
+        let result = extractor.extract("Jane is 30.").await;
+
+        assert!(matches!(
+            result,
+            Err(ExtractionError::DeserializationError(_))
+        ));
+        let requests = model.requests();
+        assert_eq!(requests.len(), 2);
+        let feedback = requests[1].chat_history.iter().last().unwrap();
+        assert!(matches!(
+            feedback,
+            Message::User { content }
+                if matches!(content.first(), crate::message::UserContent::Text(text) if text.text.contains("missing field `age`"))
+        ));
+    }
+}
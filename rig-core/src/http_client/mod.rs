@@ -264,3 +264,44 @@ impl HttpClientExt for reqwest::Client {
         }
     }
 }
+
+/// A shared client, so that many models created from one provider client reuse the same
+/// connection state instead of cloning the underlying client.
+impl<C> HttpClientExt for std::sync::Arc<C>
+where
+    C: HttpClientExt + ?Sized,
+{
+    fn send<T, U>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        T: Into<Bytes>,
+        T: WasmCompatSend,
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        (**self).send(req)
+    }
+
+    fn send_multipart<U>(
+        &self,
+        req: Request<Form>,
+    ) -> impl Future<Output = Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        (**self).send_multipart(req)
+    }
+
+    fn send_streaming<T>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = Result<StreamingResponse>> + WasmCompatSend
+    where
+        T: Into<Bytes>,
+    {
+        (**self).send_streaming(req)
+    }
+}
@@ -131,6 +131,8 @@ pub mod prelude;
 pub mod providers;
 
 pub mod streaming;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod tool;
 pub mod tools;
 pub mod transcription;
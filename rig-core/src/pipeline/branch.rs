@@ -0,0 +1,206 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::wasm_compat::{WasmBoxedFuture, WasmCompatSend, WasmCompatSync};
+
+use super::Op;
+
+/// Object safe version of [Op], implemented by every op, so that ops of different types can be
+/// stored together, e.g. as the routes of a [Branch].
+pub trait BoxedOp<In, Out>: WasmCompatSend + WasmCompatSync {
+    fn call_boxed<'a>(&'a self, input: In) -> WasmBoxedFuture<'a, Out>
+    where
+        In: 'a;
+}
+
+impl<T> BoxedOp<T::Input, T::Output> for T
+where
+    T: Op,
+{
+    fn call_boxed<'a>(&'a self, input: T::Input) -> WasmBoxedFuture<'a, T::Output>
+    where
+        T::Input: 'a,
+    {
+        Box::pin(self.call(input))
+    }
+}
+
+/// A boxed op taking `In` and returning `Out`, see [boxed].
+pub type OpBox<In, Out> = Box<dyn BoxedOp<In, Out>>;
+
+/// Box `op`, erasing its type, so that ops of different types with the same input and output
+/// can be used as the routes of a [Branch].
+pub fn boxed<T>(op: T) -> OpBox<T::Input, T::Output>
+where
+    T: Op + 'static,
+{
+    Box::new(op)
+}
+
+pub struct Branch<F, K, In, Out> {
+    key: F,
+    routes: HashMap<K, OpBox<In, Out>>,
+    default: OpBox<In, Out>,
+}
+
+impl<F, K, In, Out> Branch<F, K, In, Out> {
+    pub(crate) fn new(key: F, routes: HashMap<K, OpBox<In, Out>>, default: OpBox<In, Out>) -> Self {
+        Self {
+            key,
+            routes,
+            default,
+        }
+    }
+}
+
+impl<F, K, In, Out> Op for Branch<F, K, In, Out>
+where
+    F: Fn(&In) -> K + WasmCompatSend + WasmCompatSync,
+    K: Eq + Hash + WasmCompatSend + WasmCompatSync,
+    In: WasmCompatSend + WasmCompatSync,
+    Out: WasmCompatSend + WasmCompatSync,
+{
+    type Input = In;
+    type Output = Out;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let route = self
+            .routes
+            .get(&(self.key)(&input))
+            .unwrap_or(&self.default);
+        route.call_boxed(input).await
+    }
+}
+
+/// Create a new branch operation.
+///
+/// The op computes the key of its input with `key`, and passes the input on to the route of
+/// that key, or to `default` when no route matches. All routes share the input and output types
+/// of the op.
+///
+/// # Example
+/// ```rust,ignore
+/// use std::collections::HashMap;
+/// use rig::pipeline::{self, Op, boxed, map};
+///
+/// let routes = HashMap::from([
+///     (true, boxed(map(|x: i32| x / 2))),
+///     (false, boxed(map(|x: i32| 3 * x + 1))),
+/// ]);
+/// let collatz = pipeline::branch(|x: &i32| x % 2 == 0, routes, boxed(pipeline::passthrough()));
+///
+/// assert_eq!(collatz.call(6).await, 3);
+/// assert_eq!(collatz.call(3).await, 10);
+/// ```
+pub fn branch<F, K, In, Out>(
+    key: F,
+    routes: HashMap<K, OpBox<In, Out>>,
+    default: OpBox<In, Out>,
+) -> Branch<F, K, In, Out>
+where
+    F: Fn(&In) -> K + WasmCompatSend + WasmCompatSync,
+    K: Eq + Hash + WasmCompatSend + WasmCompatSync,
+    In: WasmCompatSend + WasmCompatSync,
+    Out: WasmCompatSend + WasmCompatSync,
+{
+    Branch::new(key, routes, default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::AgentBuilder,
+        completion::PromptError,
+        message::Message,
+        pipeline::{self, agent_ops::prompt, map},
+        test_utils::MockCompletionModel,
+    };
+
+    #[derive(Debug, PartialEq, Eq, Hash)]
+    enum Topic {
+        Coating,
+        Alloy,
+    }
+
+    fn classify(question: &str) -> Option<Topic> {
+        if question.contains("coating") {
+            Some(Topic::Coating)
+        } else if question.contains("alloy") {
+            Some(Topic::Alloy)
+        } else {
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn test_branch_routes_inputs_to_agents() {
+        let coating = MockCompletionModel::new().with_text("coating answer");
+        let alloy = MockCompletionModel::new().with_text("alloy answer");
+        let general = MockCompletionModel::new().with_text("general answer");
+
+        let routes = HashMap::from([
+            (
+                Some(Topic::Coating),
+                boxed(prompt(AgentBuilder::new(coating.clone()).build())),
+            ),
+            (
+                Some(Topic::Alloy),
+                boxed(prompt(AgentBuilder::new(alloy.clone()).build())),
+            ),
+        ]);
+        let pipeline = pipeline::new()
+            .map(|question: String| question.trim().to_string())
+            .branch(
+                |question: &String| classify(question),
+                routes,
+                boxed(prompt(AgentBuilder::new(general.clone()).build())),
+            )
+            .map(|answer: Result<String, PromptError>| answer.unwrap());
+
+        assert_eq!(
+            pipeline
+                .call("Which coating resists wear?".to_string())
+                .await,
+            "coating answer"
+        );
+        assert_eq!(
+            pipeline
+                .call("Which alloy is the lightest?".to_string())
+                .await,
+            "alloy answer"
+        );
+        assert_eq!(pipeline.call("Hello".to_string()).await, "general answer");
+
+        for (model, question) in [
+            (coating, "Which coating resists wear?"),
+            (alloy, "Which alloy is the lightest?"),
+            (general, "Hello"),
+        ] {
+            let requests = model.requests();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(
+                requests[0].chat_history.iter().last(),
+                Some(&Message::user(question))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_branch_unifies_route_types() {
+        let routes = HashMap::from([
+            ("double", boxed(map(|x: i32| x * 2))),
+            (
+                "delayed",
+                boxed(pipeline::then(|x: i32| async move { x + 100 })),
+            ),
+        ]);
+        let op = branch(
+            |x: &i32| if *x > 10 { "delayed" } else { "double" },
+            routes,
+            boxed(pipeline::passthrough()),
+        );
+
+        assert_eq!(op.call(3).await, 6);
+        assert_eq!(op.call(20).await, 120);
+    }
+}
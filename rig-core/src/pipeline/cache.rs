@@ -0,0 +1,320 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::wasm_compat::{WasmCompatSend, WasmCompatSync};
+
+use super::Op;
+
+/// Storage for the outputs of a [Cached] op, keyed by a hash of the serialized input. Also used
+/// as the checkpoint store of [Op::call_with_checkpoints].
+///
+/// Values are stored as JSON so that a cache does not depend on the types of the op it is
+/// attached to.
+pub trait Cache: WasmCompatSend + WasmCompatSync {
+    fn get(&self, key: u64) -> Option<serde_json::Value>;
+
+    fn put(&self, key: u64, value: serde_json::Value);
+}
+
+impl<C: Cache> Cache for Arc<C> {
+    fn get(&self, key: u64) -> Option<serde_json::Value> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: u64, value: serde_json::Value) {
+        (**self).put(key, value)
+    }
+}
+
+/// In-memory cache keeping the `capacity` most recently used entries.
+pub struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<LruEntries>,
+}
+
+#[derive(Default)]
+struct LruEntries {
+    values: HashMap<u64, serde_json::Value>,
+    /// Keys from the least to the most recently used
+    order: VecDeque<u64>,
+}
+
+impl LruEntries {
+    fn touch(&mut self, key: u64) {
+        self.order.retain(|k| *k != key);
+        self.order.push_back(key);
+    }
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Mutex::new(LruEntries::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("cache lock poisoned")
+            .values
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get(&self, key: u64) -> Option<serde_json::Value> {
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        let value = entries.values.get(&key).cloned()?;
+        entries.touch(key);
+        Some(value)
+    }
+
+    fn put(&self, key: u64, value: serde_json::Value) {
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        entries.values.insert(key, value);
+        entries.touch(key);
+
+        while entries.values.len() > self.capacity {
+            let Some(oldest) = entries.order.pop_front() else {
+                break;
+            };
+            entries.values.remove(&oldest);
+        }
+    }
+}
+
+/// Cache storing each entry as a JSON file in a directory, so that it survives between runs.
+///
+/// I/O errors are logged and treated as cache misses.
+pub struct FileCache {
+    dir: PathBuf,
+}
+
+impl FileCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.json"))
+    }
+}
+
+impl Cache for FileCache {
+    fn get(&self, key: u64) -> Option<serde_json::Value> {
+        let content = std::fs::read(self.path(key)).ok()?;
+        match serde_json::from_slice(&content) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                tracing::warn!("Ignoring unreadable pipeline cache entry {key:016x}: {error}");
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: u64, value: serde_json::Value) {
+        let result = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(self.path(key), value.to_string()));
+        if let Err(error) = result {
+            tracing::warn!("Failed to write pipeline cache entry {key:016x}: {error}");
+        }
+    }
+}
+
+/// Stable 64-bit FNV-1a hash, used as the cache key so that file-backed entries remain valid
+/// across builds (unlike [std::collections::hash_map::DefaultHasher]).
+pub(crate) fn cache_key(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub struct Cached<Op, C> {
+    op: Op,
+    cache: C,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<Op, C> Cached<Op, C> {
+    pub(crate) fn new(op: Op, cache: C) -> Self {
+        Self {
+            op,
+            cache,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of calls answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of calls which ran the inner op.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+impl<T, C> Op for Cached<T, C>
+where
+    T: Op,
+    T::Input: Serialize,
+    T::Output: Serialize + DeserializeOwned,
+    C: Cache,
+{
+    type Input = T::Input;
+    type Output = T::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let key = match serde_json::to_vec(&input) {
+            Ok(bytes) => cache_key(&bytes),
+            Err(error) => {
+                tracing::warn!("Pipeline op input is not cacheable: {error}");
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return self.op.call(input).await;
+            }
+        };
+
+        if let Some(output) = self
+            .cache
+            .get(key)
+            .and_then(|value| serde_json::from_value(value).ok())
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return output;
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let output = self.op.call(input).await;
+        match serde_json::to_value(&output) {
+            Ok(value) => self.cache.put(key, value),
+            Err(error) => tracing::warn!("Pipeline op output is not cacheable: {error}"),
+        }
+        output
+    }
+}
+
+/// Create a new caching operation: calls of `op` with an input it was already called with are
+/// answered from `cache` instead of running `op` again. See [Op::cached].
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::pipeline::{self, Op, MemoryCache, cached};
+///
+/// let op = cached(pipeline::map(|x: i32| x * 2), MemoryCache::new(100));
+///
+/// assert_eq!(op.call(21).await, 42);
+/// assert_eq!(op.call(21).await, 42);
+/// assert_eq!((op.hits(), op.misses()), (1, 1));
+/// ```
+pub fn cached<T, C>(op: T, cache: C) -> Cached<T, C>
+where
+    T: Op,
+    T::Input: Serialize,
+    T::Output: Serialize + DeserializeOwned,
+    C: Cache,
+{
+    Cached::new(op, cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::pipeline::{self, map};
+
+    struct Counter {
+        calls: AtomicUsize,
+    }
+
+    impl Op for Counter {
+        type Input = String;
+        type Output = Vec<String>;
+
+        async fn call(&self, input: Self::Input) -> Self::Output {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            input.split(' ').map(str::to_string).collect()
+        }
+    }
+
+    fn counter() -> Counter {
+        Counter {
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_skips_identical_inputs() {
+        let op = cached(counter(), MemoryCache::new(10));
+
+        assert_eq!(op.call("a b".to_string()).await, vec!["a", "b"]);
+        assert_eq!(op.call("a b".to_string()).await, vec!["a", "b"]);
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 1);
+
+        assert_eq!(op.call("c".to_string()).await, vec!["c"]);
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 2);
+        assert_eq!((op.hits(), op.misses()), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_evicts_least_recently_used() {
+        let cache = Arc::new(MemoryCache::new(2));
+        let op = cached(counter(), cache.clone());
+
+        op.call("a".to_string()).await;
+        op.call("b".to_string()).await;
+        // Using "a" makes "b" the least recently used entry
+        op.call("a".to_string()).await;
+        op.call("c".to_string()).await;
+        assert_eq!(cache.len(), 2);
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 3);
+
+        op.call("a".to_string()).await;
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 3);
+        op.call("b".to_string()).await;
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_file_cache_persists_between_ops() {
+        let dir = assert_fs::TempDir::new().unwrap();
+
+        let op = cached(counter(), FileCache::new(dir.path().join("cache")));
+        op.call("a b".to_string()).await;
+        assert_eq!(op.misses(), 1);
+
+        let op = cached(counter(), FileCache::new(dir.path().join("cache")));
+        assert_eq!(op.call("a b".to_string()).await, vec!["a", "b"]);
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 0);
+        assert_eq!(op.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cached_in_pipeline() {
+        let op = pipeline::new()
+            .map(|x: i32| x + 1)
+            .chain(map(|x: i32| x * 2))
+            .cached(MemoryCache::new(10));
+
+        assert_eq!(op.call(1).await, 4);
+        assert_eq!(op.call(1).await, 4);
+        assert_eq!((op.hits(), op.misses()), (1, 1));
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    fmt::{Debug, Display},
+    future::Future,
+    sync::Arc,
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::wasm_compat::{WasmCompatSend, WasmCompatSync};
+
+use super::{
+    cache::{Cache, cache_key},
+    named::{Named, TryNamed},
+    op,
+};
+
+tokio::task_local! {
+    static CHECKPOINTS: Arc<dyn Cache>;
+}
+
+/// Run `op` on `input` with `store` as the checkpoint store, see [Op::call_with_checkpoints].
+pub(crate) fn call_with_checkpoints<T, C>(
+    op: &T,
+    store: C,
+    input: T::Input,
+) -> impl Future<Output = T::Output>
+where
+    T: op::Op,
+    C: Cache + 'static,
+{
+    CHECKPOINTS.scope(Arc::new(store), op.call(input))
+}
+
+/// The checkpoint store of the current run and the key of the stage `name` called on `input`,
+/// if the run has a store.
+fn checkpoint(name: &str, input: &impl Serialize) -> Option<(Arc<dyn Cache>, u64)> {
+    let store = CHECKPOINTS.try_with(Arc::clone).ok()?;
+    match serde_json::to_vec(&(name, input)) {
+        Ok(bytes) => Some((store, cache_key(&bytes))),
+        Err(error) => {
+            tracing::warn!("Input of pipeline stage {name} cannot be checkpointed: {error}");
+            None
+        }
+    }
+}
+
+fn restore<T: DeserializeOwned>(store: &dyn Cache, key: u64, name: &str) -> Option<T> {
+    let output = serde_json::from_value(store.get(key)?).ok()?;
+    tracing::info!(target: "rig::pipeline", "Resuming pipeline stage {name} from its checkpoint");
+    Some(output)
+}
+
+fn save(store: &dyn Cache, key: u64, name: &str, output: &impl Serialize) {
+    match serde_json::to_value(output) {
+        Ok(value) => store.put(key, value),
+        Err(error) => {
+            tracing::warn!("Output of pipeline stage {name} cannot be checkpointed: {error}")
+        }
+    }
+}
+
+/// A named stage whose output is saved to the checkpoint store of the run, so that a run
+/// resumed after a failure skips it. See [Named::checkpointed].
+pub struct Checkpointed<Stage> {
+    stage: Stage,
+}
+
+impl<Stage> Checkpointed<Stage> {
+    pub(crate) fn new(stage: Stage) -> Self {
+        Self { stage }
+    }
+
+    pub fn stage(&self) -> &Stage {
+        &self.stage
+    }
+}
+
+impl<Op> op::Op for Checkpointed<Named<Op>>
+where
+    Op: op::Op,
+    Op::Input: Debug + Serialize,
+    Op::Output: Debug + Serialize + DeserializeOwned,
+{
+    type Input = Op::Input;
+    type Output = Op::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let name = self.stage.name();
+        let Some((store, key)) = checkpoint(name, &input) else {
+            return self.stage.call(input).await;
+        };
+        if let Some(output) = restore(&*store, key, name) {
+            return output;
+        }
+
+        let output = self.stage.call(input).await;
+        save(&*store, key, name, &output);
+        output
+    }
+
+    fn named_stages(&self) -> usize {
+        self.stage.named_stages()
+    }
+}
+
+impl<Op, T, E> op::Op for Checkpointed<TryNamed<Op>>
+where
+    Op: op::Op<Output = Result<T, E>>,
+    Op::Input: Debug + Serialize,
+    T: Debug + Serialize + DeserializeOwned + WasmCompatSend + WasmCompatSync,
+    E: Display + WasmCompatSend + WasmCompatSync,
+{
+    type Input = Op::Input;
+    type Output = Op::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let name = self.stage.name();
+        let Some((store, key)) = checkpoint(name, &input) else {
+            return self.stage.call(input).await;
+        };
+        if let Some(output) = restore(&*store, key, name) {
+            return Ok(output);
+        }
+
+        let result = self.stage.call(input).await;
+        if let Ok(output) = &result {
+            save(&*store, key, name, output);
+        }
+        result
+    }
+
+    fn named_stages(&self) -> usize {
+        self.stage.named_stages()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::pipeline::{self, FileCache, MemoryCache, Op, TryOp, map};
+
+    /// Three checkpointed stages counting their calls. The last one panics when `crash` is set.
+    fn stages(
+        calls: Arc<[AtomicUsize; 3]>,
+        crash: bool,
+    ) -> impl Op<Input = i32, Output = i32> + 'static {
+        let (parse, score, report) = (calls.clone(), calls.clone(), calls);
+
+        pipeline::new()
+            .chain(
+                map(move |x: i32| {
+                    parse[0].fetch_add(1, Ordering::SeqCst);
+                    x + 1
+                })
+                .named("parse")
+                .checkpointed(),
+            )
+            .chain(
+                map(move |x: i32| {
+                    score[1].fetch_add(1, Ordering::SeqCst);
+                    x * 10
+                })
+                .named("score")
+                .checkpointed(),
+            )
+            .chain(
+                map(move |x: i32| {
+                    report[2].fetch_add(1, Ordering::SeqCst);
+                    if crash {
+                        panic!("crashed in the report stage");
+                    }
+                    x - 1
+                })
+                .named("report")
+                .checkpointed(),
+            )
+    }
+
+    fn counts(calls: &[AtomicUsize; 3]) -> [usize; 3] {
+        calls.each_ref().map(|calls| calls.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_resume_skips_completed_stages() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let calls = Arc::new([const { AtomicUsize::new(0) }; 3]);
+
+        let op = stages(calls.clone(), true);
+        let store = FileCache::new(dir.path().join("checkpoints"));
+        let crashed = tokio::spawn(async move { op.call_with_checkpoints(store, 1).await }).await;
+        assert!(crashed.unwrap_err().is_panic());
+        assert_eq!(counts(&calls), [1, 1, 1]);
+
+        // The resumed run only runs the stage which did not complete
+        let op = stages(calls.clone(), false);
+        let store = FileCache::new(dir.path().join("checkpoints"));
+        assert_eq!(op.call_with_checkpoints(store, 1).await, 19);
+        assert_eq!(counts(&calls), [1, 1, 2]);
+
+        // Other inputs are not resumed
+        let store = FileCache::new(dir.path().join("checkpoints"));
+        assert_eq!(op.call_with_checkpoints(store, 2).await, 29);
+        assert_eq!(counts(&calls), [2, 2, 3]);
+
+        // Without a checkpoint store, every stage runs
+        assert_eq!(op.call(1).await, 19);
+        assert_eq!(counts(&calls), [3, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_stages_are_not_checkpointed() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let op = map(move |x: i32| {
+            // Fails on the first call only
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(format!("{x} is not ready"))
+            } else {
+                Ok(x * 2)
+            }
+        })
+        .try_named("validate")
+        .checkpointed();
+        let store = Arc::new(MemoryCache::new(10));
+
+        assert!(op.call_with_checkpoints(store.clone(), 1).await.is_err());
+        assert_eq!(op.try_call(1).await, Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(store.is_empty());
+
+        assert_eq!(op.call_with_checkpoints(store.clone(), 1).await, Ok(2));
+        assert_eq!(op.call_with_checkpoints(store.clone(), 1).await, Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(store.len(), 1);
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use futures::{Stream, channel::mpsc};
+use tokio::task::JoinHandle;
+
+use super::Op;
+
+/// Progress of a pipeline run with [Op::call_with_events], reported by its
+/// [named](Op::named) stages.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineEvent {
+    StageStarted {
+        name: String,
+    },
+    StageFinished {
+        name: String,
+        duration: Duration,
+    },
+    /// A stage named with [try_named](super::TryOp::try_named) returned an error.
+    StageFailed {
+        name: String,
+        duration: Duration,
+        error: String,
+    },
+}
+
+impl PipelineEvent {
+    /// Name of the stage the event is about.
+    pub fn stage(&self) -> &str {
+        match self {
+            PipelineEvent::StageStarted { name }
+            | PipelineEvent::StageFinished { name, .. }
+            | PipelineEvent::StageFailed { name, .. } => name,
+        }
+    }
+}
+
+tokio::task_local! {
+    static EVENTS: mpsc::UnboundedSender<PipelineEvent>;
+}
+
+/// Send the event built by `event` to the listener of the current pipeline run, if any.
+pub(crate) fn emit(event: impl FnOnce() -> PipelineEvent) {
+    let _ = EVENTS.try_with(|events| events.unbounded_send(event()));
+}
+
+/// Spawn a run of `op` on `input`, see [Op::call_with_events].
+pub(crate) fn call_with_events<T>(
+    op: T,
+    input: T::Input,
+) -> (
+    impl Stream<Item = PipelineEvent> + Unpin,
+    JoinHandle<T::Output>,
+)
+where
+    T: Op + 'static,
+    T::Input: 'static,
+    T::Output: 'static,
+{
+    let (sender, events) = mpsc::unbounded();
+    let handle = tokio::spawn(EVENTS.scope(sender, async move { op.call(input).await }));
+    (events, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::pipeline::{self, TryOp, map, then};
+
+    #[tokio::test]
+    async fn test_call_with_events_reports_stages() {
+        let op = Arc::new(
+            pipeline::new()
+                .chain(map(|x: i32| x + 1).named("increment"))
+                .chain(
+                    then(|x: i32| async move {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        x * 10
+                    })
+                    .named("scale"),
+                )
+                .chain(
+                    map(|x: i32| {
+                        if x < 100 {
+                            Ok(x)
+                        } else {
+                            Err(format!("{x} is too large"))
+                        }
+                    })
+                    .try_named("validate"),
+                ),
+        );
+
+        let (events, handle) = op.clone().call_with_events(1);
+        let events: Vec<PipelineEvent> = events.collect().await;
+        assert_eq!(handle.await.unwrap(), Ok(20));
+
+        let stages: Vec<(&str, &str)> = events
+            .iter()
+            .map(|event| {
+                let kind = match event {
+                    PipelineEvent::StageStarted { .. } => "started",
+                    PipelineEvent::StageFinished { .. } => "finished",
+                    PipelineEvent::StageFailed { .. } => "failed",
+                };
+                (event.stage(), kind)
+            })
+            .collect();
+        assert_eq!(
+            stages,
+            vec![
+                ("increment", "started"),
+                ("increment", "finished"),
+                ("scale", "started"),
+                ("scale", "finished"),
+                ("validate", "started"),
+                ("validate", "finished"),
+            ]
+        );
+        match &events[3] {
+            PipelineEvent::StageFinished { duration, .. } => {
+                assert!(*duration >= Duration::from_millis(20))
+            }
+            event => panic!("Expected the scale stage to finish, got {event:?}"),
+        }
+
+        let (events, handle) = op.call_with_events(10);
+        let events: Vec<PipelineEvent> = events.collect().await;
+        assert_eq!(handle.await.unwrap(), Err("110 is too large".to_string()));
+        assert_eq!(events.len(), 6);
+        match &events[5] {
+            PipelineEvent::StageFailed { name, error, .. } => {
+                assert_eq!(name, "validate");
+                assert_eq!(error, "110 is too large");
+            }
+            event => panic!("Expected the validate stage to fail, got {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_without_listener() {
+        let op = map(|x: i32| x + 1).named("increment");
+
+        assert_eq!(op.call(1).await, 2);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::wasm_compat::{WasmCompatSend, WasmCompatSync};
+
+use super::Op;
+
+/// Result of a [LoopWhile] op: the last value produced and the number of times the body ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopOutput<T> {
+    pub value: T,
+    pub iterations: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoopError<T> {
+    /// The condition still held after `iterations` runs of the body. Only returned when the op
+    /// was configured with [LoopWhile::error_on_max_iters].
+    #[error("Loop condition still held after the maximum of {iterations} iterations")]
+    MaxIterations { value: T, iterations: usize },
+}
+
+pub struct LoopWhile<F, Body> {
+    cond: F,
+    body: Body,
+    max_iters: usize,
+    error_on_max_iters: bool,
+}
+
+impl<F, Body> LoopWhile<F, Body> {
+    pub(crate) fn new(cond: F, body: Body, max_iters: usize) -> Self {
+        Self {
+            cond,
+            body,
+            max_iters,
+            error_on_max_iters: false,
+        }
+    }
+
+    /// Return [LoopError::MaxIterations] instead of the last value when the iteration cap is
+    /// reached while the condition still holds.
+    pub fn error_on_max_iters(mut self) -> Self {
+        self.error_on_max_iters = true;
+        self
+    }
+}
+
+impl<F, Body> Op for LoopWhile<F, Body>
+where
+    F: Fn(&Body::Output) -> bool + WasmCompatSend + WasmCompatSync,
+    Body: Op<Output = <Body as Op>::Input>,
+{
+    type Input = Body::Input;
+    type Output = Result<LoopOutput<Body::Output>, LoopError<Body::Output>>;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let mut value = input;
+        let mut iterations = 0;
+
+        while (self.cond)(&value) {
+            if iterations == self.max_iters {
+                if self.error_on_max_iters {
+                    return Err(LoopError::MaxIterations { value, iterations });
+                }
+                break;
+            }
+            value = self.body.call(value).await;
+            iterations += 1;
+        }
+
+        Ok(LoopOutput { value, iterations })
+    }
+}
+
+/// Create a new loop operation.
+///
+/// The op feeds its input to `body`, and the output of `body` back into it, for as long as
+/// `cond` holds for the current value and at most `max_iters` times. The condition is checked
+/// before every run, so an input for which `cond` is false is returned as is.
+///
+/// The op returns the last value together with the number of iterations. By default, reaching
+/// `max_iters` is not an error; use [LoopWhile::error_on_max_iters] to make it one.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::pipeline::{self, Op, loop_while, map};
+///
+/// let op = loop_while(|x: &i32| *x < 100, map(|x: i32| x * 2), 10);
+///
+/// let result = op.call(3).await.unwrap();
+/// assert_eq!(result.value, 192);
+/// assert_eq!(result.iterations, 6);
+/// ```
+pub fn loop_while<F, Body>(cond: F, body: Body, max_iters: usize) -> LoopWhile<F, Body>
+where
+    F: Fn(&Body::Output) -> bool + WasmCompatSend + WasmCompatSync,
+    Body: Op<Output = <Body as Op>::Input>,
+{
+    LoopWhile::new(cond, body, max_iters)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::pipeline::{self, map};
+
+    struct Counter {
+        calls: AtomicUsize,
+    }
+
+    impl Op for Counter {
+        type Input = usize;
+        type Output = usize;
+
+        async fn call(&self, input: Self::Input) -> Self::Output {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            input + 1
+        }
+    }
+
+    fn counter() -> Counter {
+        Counter {
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_loop_while_stops_when_condition_fails() {
+        let op = loop_while(|x: &usize| *x < 3, counter(), 10);
+
+        let result = op.call(0).await.unwrap();
+        assert_eq!(
+            result,
+            LoopOutput {
+                value: 3,
+                iterations: 3
+            }
+        );
+        assert_eq!(op.body.calls.load(Ordering::SeqCst), 3);
+
+        let result = op.call(5).await.unwrap();
+        assert_eq!(
+            result,
+            LoopOutput {
+                value: 5,
+                iterations: 0
+            }
+        );
+        assert_eq!(op.body.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_loop_while_stops_at_max_iters() {
+        let op = loop_while(|_: &usize| true, counter(), 4);
+
+        let result = op.call(0).await.unwrap();
+        assert_eq!(
+            result,
+            LoopOutput {
+                value: 4,
+                iterations: 4
+            }
+        );
+        assert_eq!(op.body.calls.load(Ordering::SeqCst), 4);
+
+        let op = loop_while(|_: &usize| true, counter(), 4).error_on_max_iters();
+        match op.call(10).await {
+            Err(LoopError::MaxIterations { value, iterations }) => {
+                assert_eq!(value, 14);
+                assert_eq!(iterations, 4);
+            }
+            result => panic!("Expected MaxIterations, got {result:?}"),
+        }
+        assert_eq!(op.body.calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_loop_while_in_pipeline() {
+        let op = pipeline::new()
+            .map(|x: i32| x + 1)
+            .loop_while(|x: &i32| *x % 5 != 0, map(|x: i32| x + 1), 10)
+            .map(|result: Result<LoopOutput<i32>, LoopError<i32>>| result.unwrap().iterations);
+
+        assert_eq!(op.call(1).await, 3);
+        assert_eq!(op.call(4).await, 0);
+    }
+}
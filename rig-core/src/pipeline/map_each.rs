@@ -0,0 +1,222 @@
+use futures::{StreamExt, stream};
+
+use super::{Op, TryOp};
+
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to process {} element(s)", errors.len())]
+pub struct MapEachError<E> {
+    /// The errors of the failed elements, with their index in the input, in input order.
+    pub errors: Vec<(usize, E)>,
+}
+
+pub struct MapEach<T> {
+    op: T,
+    concurrency: usize,
+}
+
+impl<T> MapEach<T> {
+    pub(crate) fn new(op: T, concurrency: usize) -> Self {
+        Self {
+            op,
+            concurrency: concurrency.max(1),
+        }
+    }
+}
+
+impl<T> Op for MapEach<T>
+where
+    T: Op,
+{
+    type Input = Vec<T::Input>;
+    type Output = Vec<T::Output>;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let mut outputs: Vec<(usize, T::Output)> = stream::iter(input.into_iter().enumerate())
+            .map(|(index, input)| async move { (index, self.op.call(input).await) })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        outputs.sort_by_key(|(index, _)| *index);
+        outputs.into_iter().map(|(_, output)| output).collect()
+    }
+}
+
+pub struct TryMapEach<T> {
+    op: T,
+    concurrency: usize,
+}
+
+impl<T> TryMapEach<T> {
+    pub(crate) fn new(op: T, concurrency: usize) -> Self {
+        Self {
+            op,
+            concurrency: concurrency.max(1),
+        }
+    }
+}
+
+impl<T> Op for TryMapEach<T>
+where
+    T: TryOp,
+{
+    type Input = Vec<T::Input>;
+    type Output = Result<Vec<T::Output>, MapEachError<T::Error>>;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let mut results: Vec<_> = stream::iter(input.into_iter().enumerate())
+            .map(|(index, input)| async move { (index, self.op.try_call(input).await) })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        results.sort_by_key(|(index, _)| *index);
+
+        let mut outputs = Vec::with_capacity(results.len());
+        let mut errors = Vec::new();
+        for (index, result) in results {
+            match result {
+                Ok(output) => outputs.push(output),
+                Err(error) => errors.push((index, error)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(outputs)
+        } else {
+            Err(MapEachError { errors })
+        }
+    }
+}
+
+/// Create a new op applying `op` to every element of its `Vec` input, running up to
+/// `concurrency` elements at once. The outputs are in the order of the input elements.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::pipeline::{self, Op, map_each};
+///
+/// let op = map_each(pipeline::map(|x: i32| x * 2), 3);
+///
+/// let result = op.call(vec![1, 2, 3, 4]).await;
+/// assert_eq!(result, vec![2, 4, 6, 8]);
+/// ```
+pub fn map_each<T>(op: T, concurrency: usize) -> MapEach<T>
+where
+    T: Op,
+{
+    MapEach::new(op, concurrency)
+}
+
+/// Create a new op applying the fallible `op` to every element of its `Vec` input, running up
+/// to `concurrency` elements at once. Every element is processed even when some fail; the op
+/// then returns a [MapEachError] with the error of each failed element.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::pipeline::{self, TryOp, try_map_each};
+///
+/// let op = try_map_each(
+///     pipeline::map(|x: i32| if x % 2 == 0 { Ok(x) } else { Err("x is odd") }),
+///     3,
+/// );
+///
+/// assert_eq!(op.try_call(vec![2, 4]).await.unwrap(), vec![2, 4]);
+/// assert_eq!(op.try_call(vec![2, 3]).await.unwrap_err().errors, vec![(1, "x is odd")]);
+/// ```
+pub fn try_map_each<T>(op: T, concurrency: usize) -> TryMapEach<T>
+where
+    T: TryOp,
+{
+    TryMapEach::new(op, concurrency)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::pipeline::{self, then};
+
+    /// Tracks how many elements are processed at the same time.
+    #[derive(Default)]
+    struct Concurrency {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    /// An op scoring an element, finishing later elements first and failing on `fail_on`.
+    fn score(
+        concurrency: Arc<Concurrency>,
+        fail_on: Option<usize>,
+    ) -> impl Op<Input = usize, Output = Result<String, String>> {
+        then(move |x: usize| {
+            let concurrency = concurrency.clone();
+            async move {
+                let running = concurrency.running.fetch_add(1, Ordering::SeqCst) + 1;
+                concurrency.max_running.fetch_max(running, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5 * (10 - x as u64))).await;
+                concurrency.running.fetch_sub(1, Ordering::SeqCst);
+
+                if Some(x) == fail_on {
+                    Err(format!("cannot score {x}"))
+                } else {
+                    Ok(format!("score {x}"))
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_map_each_keeps_order() {
+        let concurrency = Arc::new(Concurrency::default());
+        let op = map_each(score(concurrency.clone(), Some(4)), 3);
+
+        let results = op.call((0..10).collect()).await;
+        let expected: Vec<Result<String, String>> = (0..10)
+            .map(|x| {
+                if x == 4 {
+                    Err("cannot score 4".to_string())
+                } else {
+                    Ok(format!("score {x}"))
+                }
+            })
+            .collect();
+        assert_eq!(results, expected);
+        assert_eq!(concurrency.max_running.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_try_map_each_collects_errors() {
+        let concurrency = Arc::new(Concurrency::default());
+        let op = try_map_each(score(concurrency.clone(), Some(4)), 3);
+
+        let error = op.try_call((0..10).collect()).await.unwrap_err();
+        assert_eq!(error.errors, vec![(4, "cannot score 4".to_string())]);
+        assert_eq!(error.to_string(), "Failed to process 1 element(s)");
+        assert_eq!(concurrency.max_running.load(Ordering::SeqCst), 3);
+
+        let op = try_map_each(score(concurrency.clone(), None), 3);
+        let outputs = op.try_call((0..10).collect()).await.unwrap();
+        assert_eq!(
+            outputs,
+            (0..10).map(|x| format!("score {x}")).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_map_each_in_pipeline() {
+        let op = pipeline::new()
+            .map(|text: String| text.split(' ').map(str::to_string).collect::<Vec<_>>())
+            .map_each(pipeline::map(|word: String| word.len()), 2)
+            .map(|lengths: Vec<usize>| lengths.into_iter().sum::<usize>());
+
+        assert_eq!(op.call("pipelines fan out".to_string()).await, 15);
+    }
+}
@@ -88,16 +88,36 @@
 //! ```
 
 pub mod agent_ops;
+pub mod branch;
+pub mod cache;
+pub mod checkpoint;
+pub mod events;
+pub mod loop_while;
+pub mod map_each;
+pub mod named;
 pub mod op;
+pub mod rate_limit;
+pub mod retry;
+pub mod timeout;
+pub mod tool;
 pub mod try_op;
 #[macro_use]
 pub mod parallel;
 #[macro_use]
 pub mod conditional;
 
-use std::future::Future;
+use std::{collections::HashMap, future::Future, hash::Hash};
 
+pub use branch::{OpBox, boxed, branch};
+pub use cache::{Cache, FileCache, MemoryCache, cached};
+pub use events::PipelineEvent;
+pub use loop_while::{LoopError, LoopOutput, loop_while};
+pub use map_each::{MapEachError, map_each, try_map_each};
 pub use op::{Op, map, passthrough, then};
+pub use rate_limit::{ConcurrencyLimit, RateLimiter, max_concurrent, rate_limited};
+pub use retry::{RetryPolicy, retry};
+pub use timeout::{TimeoutError, timeout};
+pub use tool::PipelineTool;
 pub use try_op::TryOp;
 
 use crate::{completion, extractor::Extractor, vector_store};
@@ -277,6 +297,106 @@ impl<E> PipelineBuilder<E> {
     {
         agent_ops::Extract::new(extractor)
     }
+
+    /// Add a branch operation to the current pipeline. The branch operation computes a key from
+    /// its input with `key`, and passes the input on to the route of that key, or to `default`
+    /// when no route matches. All routes share the same input and output types; ops of different
+    /// types are unified by [boxing](boxed) them.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use std::collections::HashMap;
+    /// use rig::pipeline::{self, Op, boxed};
+    ///
+    /// let routes = HashMap::from([
+    ///     ("coating", boxed(pipeline::agent_ops::prompt(coating_agent))),
+    ///     ("alloy", boxed(pipeline::agent_ops::prompt(alloy_agent))),
+    /// ]);
+    ///
+    /// let pipeline = pipeline::new().branch(
+    ///     |question: &String| if question.contains("coating") { "coating" } else { "alloy" },
+    ///     routes,
+    ///     boxed(pipeline::agent_ops::prompt(default_agent)),
+    /// );
+    ///
+    /// let result = pipeline.call("Which coating resists wear?".to_string()).await;
+    /// ```
+    pub fn branch<F, K, In, Out>(
+        self,
+        key: F,
+        routes: HashMap<K, OpBox<In, Out>>,
+        default: OpBox<In, Out>,
+    ) -> branch::Branch<F, K, In, Out>
+    where
+        F: Fn(&In) -> K + Send + Sync,
+        K: Eq + Hash + Send + Sync,
+        In: Send + Sync,
+        Out: Send + Sync,
+        Self: Sized,
+    {
+        branch::Branch::new(key, routes, default)
+    }
+
+    /// Add a loop operation to the current pipeline. The loop operation feeds its input to
+    /// `body`, and the output of `body` back into it, while `cond` holds and at most `max_iters`
+    /// times. It returns the last value together with the number of iterations.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op};
+    ///
+    /// let pipeline = pipeline::new().loop_while(
+    ///     |draft: &String| draft.len() < 12,
+    ///     pipeline::map(|draft: String| format!("{draft}!")),
+    ///     20,
+    /// );
+    ///
+    /// let result = pipeline.call("Hello".to_string()).await.unwrap();
+    /// assert_eq!(result.value, "Hello!!!!!!!");
+    /// assert_eq!(result.iterations, 7);
+    /// ```
+    pub fn loop_while<F, Body>(
+        self,
+        cond: F,
+        body: Body,
+        max_iters: usize,
+    ) -> loop_while::LoopWhile<F, Body>
+    where
+        F: Fn(&Body::Output) -> bool + Send + Sync,
+        Body: Op<Output = <Body as Op>::Input>,
+        Self: Sized,
+    {
+        loop_while::LoopWhile::new(cond, body, max_iters)
+    }
+
+    /// Add an op applied to every element of the `Vec` input of the pipeline, running up to
+    /// `concurrency` elements at once. The outputs are in the order of the input elements.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op};
+    ///
+    /// let pipeline = pipeline::new().map_each(pipeline::agent_ops::extract(scorer), 3);
+    ///
+    /// let scores = pipeline.call(ideas).await;
+    /// ```
+    pub fn map_each<T>(self, op: T, concurrency: usize) -> map_each::MapEach<T>
+    where
+        T: Op,
+        Self: Sized,
+    {
+        map_each::MapEach::new(op, concurrency)
+    }
+
+    /// Same as `map_each` but for fallible ops: every element is processed, and the errors of
+    /// the failed ones are collected in a [MapEachError].
+    pub fn try_map_each<T>(self, op: T, concurrency: usize) -> map_each::TryMapEach<T>
+    where
+        T: TryOp,
+        Self: Sized,
+    {
+        map_each::TryMapEach::new(op, concurrency)
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -0,0 +1,341 @@
+use std::{
+    fmt::{Debug, Display},
+    time::Instant,
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{Instrument, field::Empty};
+
+use crate::wasm_compat::{WasmCompatSend, WasmCompatSync};
+
+use super::{
+    checkpoint::Checkpointed,
+    events::{self, PipelineEvent},
+    op,
+};
+
+/// Create the span of a named stage. Sizes and duration are recorded once the stage ran.
+fn stage_span(name: &str, index: usize) -> tracing::Span {
+    tracing::info_span!(
+        target: "rig::pipeline",
+        "pipeline_stage",
+        stage.name = name,
+        stage.index = index,
+        stage.input_size = Empty,
+        stage.output_size = Empty,
+        stage.duration_ms = Empty,
+        stage.error = Empty,
+    )
+}
+
+/// Size of a stage input or output: the length of its debug representation. Only computed when
+/// the span is recorded by a subscriber.
+fn record_size(span: &tracing::Span, field: &str, value: &impl Debug) {
+    if !span.is_disabled() {
+        span.record(field, format!("{value:?}").len());
+    }
+}
+
+pub struct Named<Op> {
+    op: Op,
+    name: String,
+    index: usize,
+}
+
+impl<Op: op::Op> Named<Op> {
+    pub(crate) fn new(op: Op, name: impl Into<String>) -> Self {
+        Self {
+            index: op.named_stages(),
+            op,
+            name: name.into(),
+        }
+    }
+}
+
+impl<Op> Named<Op> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Position of the stage among the named stages of its pipeline, starting from 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Save the output of the stage to the checkpoint store of runs started with
+    /// [call_with_checkpoints](op::Op::call_with_checkpoints), keyed by the stage name and its
+    /// input. Runs resumed with the same store skip the stage once it completed.
+    pub fn checkpointed(self) -> Checkpointed<Self>
+    where
+        Op: op::Op,
+        Op::Input: Serialize,
+        Op::Output: Serialize + DeserializeOwned,
+    {
+        Checkpointed::new(self)
+    }
+}
+
+impl<Op> op::Op for Named<Op>
+where
+    Op: op::Op,
+    Op::Input: Debug,
+    Op::Output: Debug,
+{
+    type Input = Op::Input;
+    type Output = Op::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let span = stage_span(&self.name, self.index);
+        record_size(&span, "stage.input_size", &input);
+
+        events::emit(|| PipelineEvent::StageStarted {
+            name: self.name.clone(),
+        });
+        let started = Instant::now();
+        let output = self.op.call(input).instrument(span.clone()).await;
+        let duration = started.elapsed();
+        span.record("stage.duration_ms", duration.as_millis() as u64);
+        record_size(&span, "stage.output_size", &output);
+        events::emit(|| PipelineEvent::StageFinished {
+            name: self.name.clone(),
+            duration,
+        });
+
+        output
+    }
+
+    fn named_stages(&self) -> usize {
+        self.op.named_stages() + 1
+    }
+}
+
+pub struct TryNamed<Op> {
+    op: Op,
+    name: String,
+    index: usize,
+}
+
+impl<Op: op::Op> TryNamed<Op> {
+    pub(crate) fn new(op: Op, name: impl Into<String>) -> Self {
+        Self {
+            index: op.named_stages(),
+            op,
+            name: name.into(),
+        }
+    }
+}
+
+impl<Op> TryNamed<Op> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Position of the stage among the named stages of its pipeline, starting from 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Like [Named::checkpointed], only saving the output of the stage when it succeeds.
+    pub fn checkpointed<T, E>(self) -> Checkpointed<Self>
+    where
+        Op: op::Op<Output = Result<T, E>>,
+        Op::Input: Serialize,
+        T: Serialize + DeserializeOwned,
+    {
+        Checkpointed::new(self)
+    }
+}
+
+impl<Op, T, E> op::Op for TryNamed<Op>
+where
+    Op: op::Op<Output = Result<T, E>>,
+    Op::Input: Debug,
+    T: Debug + WasmCompatSend + WasmCompatSync,
+    E: Display + WasmCompatSend + WasmCompatSync,
+{
+    type Input = Op::Input;
+    type Output = Op::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let span = stage_span(&self.name, self.index);
+        record_size(&span, "stage.input_size", &input);
+
+        events::emit(|| PipelineEvent::StageStarted {
+            name: self.name.clone(),
+        });
+        let started = Instant::now();
+        let result = self.op.call(input).instrument(span.clone()).await;
+        let duration = started.elapsed();
+        span.record("stage.duration_ms", duration.as_millis() as u64);
+        match &result {
+            Ok(output) => {
+                record_size(&span, "stage.output_size", output);
+                events::emit(|| PipelineEvent::StageFinished {
+                    name: self.name.clone(),
+                    duration,
+                });
+            }
+            Err(error) => {
+                span.record("stage.error", tracing::field::display(error));
+                events::emit(|| PipelineEvent::StageFailed {
+                    name: self.name.clone(),
+                    duration,
+                    error: error.to_string(),
+                });
+            }
+        }
+
+        result
+    }
+
+    fn named_stages(&self) -> usize {
+        self.op.named_stages() + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing_subscriber::{Layer, layer::SubscriberExt, registry::LookupSpan};
+
+    use crate::pipeline::{self, Op, TryOp};
+
+    #[derive(Debug, Default)]
+    struct CapturedSpan {
+        id: u64,
+        parent: Option<u64>,
+        fields: HashMap<String, String>,
+    }
+
+    impl CapturedSpan {
+        fn field(&self, name: &str) -> Option<&str> {
+            self.fields.get(name).map(String::as_str)
+        }
+    }
+
+    impl tracing::field::Visit for CapturedSpan {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.fields
+                .insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.fields
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    /// Captures the pipeline stage spans, with their fields and parent.
+    #[derive(Clone, Default)]
+    struct StageCapture(Arc<Mutex<Vec<CapturedSpan>>>);
+
+    impl StageCapture {
+        /// The captured stage with the given name.
+        fn stage<T>(&self, name: &str, f: impl FnOnce(&CapturedSpan) -> T) -> T {
+            let spans = self.0.lock().unwrap();
+            let span = spans
+                .iter()
+                .find(|span| span.field("stage.name") == Some(name))
+                .unwrap_or_else(|| panic!("No span for stage {name}"));
+            f(span)
+        }
+    }
+
+    impl<S> Layer<S> for StageCapture
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut span = CapturedSpan {
+                id: id.into_u64(),
+                parent: ctx
+                    .span(id)
+                    .and_then(|span| span.parent())
+                    .map(|parent| parent.id().into_u64()),
+                ..Default::default()
+            };
+            attrs.record(&mut span);
+            self.0.lock().unwrap().push(span);
+        }
+
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut spans = self.0.lock().unwrap();
+            if let Some(span) = spans.iter_mut().find(|span| span.id == id.into_u64()) {
+                values.record(span);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_named_stages_are_nested_spans() {
+        let capture = StageCapture::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+        let op = pipeline::new()
+            .map(|x: i32| x + 1)
+            .named("increment")
+            .map(|x: i32| x * 10)
+            .named("scale")
+            .map(|x: i32| format!("value: {x}"))
+            .named("format");
+        assert_eq!(op.index(), 2);
+        assert_eq!(op.call(1).await, "value: 20");
+
+        let format = capture.stage("format", |span| {
+            assert_eq!(span.field("stage.index"), Some("2"));
+            assert_eq!(span.field("stage.input_size"), Some("1"));
+            assert_eq!(span.field("stage.output_size"), Some("11"));
+            assert!(span.field("stage.duration_ms").is_some());
+            assert_eq!(span.field("stage.error"), None);
+            span.id
+        });
+        let scale = capture.stage("scale", |span| {
+            assert_eq!(span.field("stage.index"), Some("1"));
+            assert_eq!(span.parent, Some(format));
+            span.id
+        });
+        capture.stage("increment", |span| {
+            assert_eq!(span.field("stage.index"), Some("0"));
+            assert_eq!(span.field("stage.output_size"), Some("1"));
+            assert_eq!(span.parent, Some(scale));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_try_named_records_errors() {
+        let capture = StageCapture::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+        let op = pipeline::new()
+            .map(|x: i32| {
+                if x > 0 {
+                    Ok(x)
+                } else {
+                    Err("x is not positive")
+                }
+            })
+            .try_named("validate");
+
+        assert_eq!(op.call(-1).await, Err("x is not positive"));
+        capture.stage("validate", |span| {
+            assert_eq!(span.field("stage.error"), Some("x is not positive"));
+            assert_eq!(span.field("stage.output_size"), None);
+        });
+    }
+}
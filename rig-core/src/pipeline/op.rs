@@ -2,7 +2,7 @@ use crate::wasm_compat::*;
 #[allow(unused_imports)] // Needed since this is used in a macro rule
 use futures::join;
 use futures::stream;
-use std::future::Future;
+use std::{collections::HashMap, future::Future, hash::Hash};
 
 // ================================================================
 // Core Op trait
@@ -171,6 +171,294 @@ pub trait Op: WasmCompatSend + WasmCompatSync {
     {
         Sequential::new(self, Prompt::new(prompt))
     }
+
+    /// Chain a branch operation to the current op. The branch operation computes a key from the
+    /// output of the current op with `key`, and passes the output on to the route of that key,
+    /// or to `default` when no route matches. See [branch](super::branch::branch).
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use std::collections::HashMap;
+    /// use rig::pipeline::{self, Op, boxed};
+    ///
+    /// let routes = HashMap::from([
+    ///     ("coating", boxed(pipeline::agent_ops::prompt(coating_agent))),
+    ///     ("alloy", boxed(pipeline::agent_ops::prompt(alloy_agent))),
+    /// ]);
+    ///
+    /// let chain = pipeline::new()
+    ///     .map(|question: String| question)
+    ///     .branch(
+    ///         |question: &String| if question.contains("coating") { "coating" } else { "alloy" },
+    ///         routes,
+    ///         boxed(pipeline::agent_ops::prompt(default_agent)),
+    ///     );
+    ///
+    /// let result = chain.call("Which coating resists wear?".to_string()).await;
+    /// ```
+    fn branch<F, K, Out>(
+        self,
+        key: F,
+        routes: HashMap<K, OpBox<Self::Output, Out>>,
+        default: OpBox<Self::Output, Out>,
+    ) -> Sequential<Self, Branch<F, K, Self::Output, Out>>
+    where
+        F: Fn(&Self::Output) -> K + WasmCompatSend + WasmCompatSync,
+        K: Eq + Hash + WasmCompatSend + WasmCompatSync,
+        Out: WasmCompatSend + WasmCompatSync,
+        Self: Sized,
+    {
+        Sequential::new(self, Branch::new(key, routes, default))
+    }
+
+    /// Chain a loop operation to the current op. The loop operation feeds the output of the
+    /// current op to `body`, and the output of `body` back into it, while `cond` holds and at
+    /// most `max_iters` times. See [loop_while](super::loop_while::loop_while).
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op};
+    ///
+    /// let chain = pipeline::new()
+    ///     .map(|x: i32| x + 1)
+    ///     .loop_while(|x: &i32| *x < 100, pipeline::map(|x: i32| x * 2), 10);
+    ///
+    /// let result = chain.call(2).await.unwrap();
+    /// assert_eq!(result.value, 192);
+    /// assert_eq!(result.iterations, 6);
+    /// ```
+    fn loop_while<F, Body>(
+        self,
+        cond: F,
+        body: Body,
+        max_iters: usize,
+    ) -> Sequential<Self, LoopWhile<F, Body>>
+    where
+        F: Fn(&Self::Output) -> bool + WasmCompatSend + WasmCompatSync,
+        Body: Op<Input = Self::Output, Output = Self::Output>,
+        Self: Sized,
+    {
+        Sequential::new(self, LoopWhile::new(cond, body, max_iters))
+    }
+
+    /// Chain an op applied to every element of the `Vec` output of the current op, running up
+    /// to `concurrency` elements at once. See [map_each](super::map_each::map_each).
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op};
+    ///
+    /// let chain = pipeline::new()
+    ///     .map(|n: i32| (1..=n).collect::<Vec<_>>())
+    ///     .map_each(pipeline::map(|x: i32| x * x), 3);
+    ///
+    /// let result = chain.call(4).await;
+    /// assert_eq!(result, vec![1, 4, 9, 16]);
+    /// ```
+    fn map_each<T>(self, op: T, concurrency: usize) -> Sequential<Self, MapEach<T>>
+    where
+        T: Op,
+        Self: Op<Output = Vec<T::Input>> + Sized,
+    {
+        Sequential::new(self, MapEach::new(op, concurrency))
+    }
+
+    /// Same as `map_each` but for fallible ops: every element is processed, and the errors of
+    /// the failed ones are collected. See [try_map_each](super::map_each::try_map_each).
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op};
+    ///
+    /// let chain = pipeline::new()
+    ///     .map(|n: i32| (1..=n).collect::<Vec<_>>())
+    ///     .try_map_each(pipeline::map(|x: i32| if x % 2 == 0 { Ok(x) } else { Err("x is odd") }), 3);
+    ///
+    /// let result = chain.call(2).await;
+    /// assert_eq!(result.unwrap_err().errors, vec![(0, "x is odd")]);
+    /// ```
+    fn try_map_each<T>(self, op: T, concurrency: usize) -> Sequential<Self, TryMapEach<T>>
+    where
+        T: TryOp,
+        Self: Op<Output = Vec<T::Input>> + Sized,
+    {
+        Sequential::new(self, TryMapEach::new(op, concurrency))
+    }
+
+    /// Cache the outputs of the op in `cache`, so that calling it again with an input it was
+    /// already called with does not run it a second time. The input and output of the op must
+    /// be serializable. See [cached](super::cache::cached).
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, MemoryCache, Op};
+    ///
+    /// let chain = pipeline::new()
+    ///     .map(|x: i32| x + 1)
+    ///     .cached(MemoryCache::new(100));
+    ///
+    /// assert_eq!(chain.call(1).await, 2);
+    /// assert_eq!(chain.call(1).await, 2);
+    /// assert_eq!(chain.hits(), 1);
+    /// ```
+    fn cached<C>(self, cache: C) -> Cached<Self, C>
+    where
+        C: Cache,
+        Self::Input: Serialize,
+        Self::Output: Serialize + DeserializeOwned,
+        Self: Sized,
+    {
+        Cached::new(self, cache)
+    }
+
+    /// Run the current op in a `pipeline_stage` tracing span named `name`, recording the size
+    /// of its input and output (the length of their debug representation) and its duration.
+    ///
+    /// Named stages built on top of each other from [pipeline::new](super::new) are numbered
+    /// in order (`stage.index`), and their spans are nested. Use
+    /// [try_named](super::TryOp::try_named) to also record the error of fallible ops.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op};
+    ///
+    /// let chain = pipeline::new()
+    ///     .map(|x: i32| x + 1)
+    ///     .named("increment")
+    ///     .map(|x: i32| format!("Result: {x}!"))
+    ///     .named("format");
+    ///
+    /// assert_eq!(chain.index(), 1);
+    /// assert_eq!(chain.call(1).await, "Result: 2!");
+    /// ```
+    fn named(self, name: impl Into<String>) -> Named<Self>
+    where
+        Self::Input: std::fmt::Debug,
+        Self::Output: std::fmt::Debug,
+        Self: Sized,
+    {
+        Named::new(self, name)
+    }
+
+    /// Wait for `limiter` before each call of the current op. `limiter` is either a number of
+    /// calls per second, or a [RateLimiter] whose clones are shared by several ops so they
+    /// draw from the same budget.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::{parallel, pipeline::{self, Op, RateLimiter}};
+    ///
+    /// // At most 5 calls per second to the provider, across the three scorers
+    /// let limiter = RateLimiter::per_second(5);
+    /// let evaluation = pipeline::new().chain(parallel!(
+    ///     quality_scorer.rate_limited(limiter.clone()),
+    ///     sentiment_scorer.rate_limited(limiter.clone()),
+    ///     summary_scorer.rate_limited(limiter.clone()),
+    /// ));
+    ///
+    /// let evaluations = evaluation.batch_call(10, documents).await;
+    /// ```
+    fn rate_limited(self, limiter: impl Into<RateLimiter>) -> RateLimited<Self>
+    where
+        Self: Sized,
+    {
+        RateLimited::new(self, limiter.into())
+    }
+
+    /// Run at most `limit` calls of the current op at the same time. `limit` is either a
+    /// number of calls, or a [ConcurrencyLimit] whose clones are shared by several ops so they
+    /// run at most that many calls in total.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, ConcurrencyLimit, Op};
+    ///
+    /// let limit = ConcurrencyLimit::new(4);
+    /// let chain = pipeline::new()
+    ///     .chain(extract_op.max_concurrent(limit.clone()))
+    ///     .chain(summarize_op.max_concurrent(limit));
+    ///
+    /// let summaries = chain.batch_call(100, documents).await;
+    /// ```
+    fn max_concurrent(self, limit: impl Into<ConcurrencyLimit>) -> MaxConcurrent<Self>
+    where
+        Self: Sized,
+    {
+        MaxConcurrent::new(self, limit.into())
+    }
+
+    /// Run the op on `input` in a new task, reporting the progress of its [named](Op::named)
+    /// stages as a stream of [PipelineEvent]s. The stream ends when the run completes; its
+    /// output is returned by the task handle.
+    ///
+    /// Only the named stages running in the spawned task report events. Wrap the op in an
+    /// [Arc](std::sync::Arc) to run it more than once.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use futures::StreamExt;
+    /// use rig::pipeline::{self, Op, map};
+    ///
+    /// let op = pipeline::new()
+    ///     .chain(map(|x: i32| x + 1).named("increment"))
+    ///     .chain(map(|x: i32| x * 2).named("double"));
+    ///
+    /// let (mut events, handle) = op.call_with_events(1);
+    /// while let Some(event) = events.next().await {
+    ///     println!("{event:?}");
+    /// }
+    /// assert_eq!(handle.await.unwrap(), 4);
+    /// ```
+    fn call_with_events(
+        self,
+        input: Self::Input,
+    ) -> (
+        impl futures::Stream<Item = PipelineEvent> + Unpin,
+        tokio::task::JoinHandle<Self::Output>,
+    )
+    where
+        Self: Sized + 'static,
+        Self::Input: 'static,
+        Self::Output: 'static,
+    {
+        events::call_with_events(self, input)
+    }
+
+    /// Run the op on `input`, saving the output of its [checkpointed](super::named::Named::checkpointed)
+    /// stages to `store`. When the pipeline is run again on the same input with the same store,
+    /// typically a [FileCache](super::FileCache) after a crash, the stages which completed are
+    /// skipped and their saved output is used instead.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, FileCache, Op, map};
+    ///
+    /// let op = pipeline::new()
+    ///     .chain(map(load_corpus).named("load").checkpointed())
+    ///     .chain(scorer.named("score").checkpointed())
+    ///     .chain(map(write_report).named("report"));
+    ///
+    /// // Resumes from the last completed stage if a previous run died
+    /// let report = op.call_with_checkpoints(FileCache::new("checkpoints"), corpus_dir).await;
+    /// ```
+    fn call_with_checkpoints<C>(
+        &self,
+        store: C,
+        input: Self::Input,
+    ) -> impl Future<Output = Self::Output> + WasmCompatSend
+    where
+        C: Cache + 'static,
+        Self: Sized,
+    {
+        checkpoint::call_with_checkpoints(self, store, input)
+    }
+
+    /// Number of [named](Op::named) stages making up the op, used to number the stages of a
+    /// pipeline.
+    #[doc(hidden)]
+    fn named_stages(&self) -> usize {
+        0
+    }
 }
 
 impl<T: Op> Op for &T {
@@ -181,6 +469,24 @@ impl<T: Op> Op for &T {
     async fn call(&self, input: Self::Input) -> Self::Output {
         (*self).call(input).await
     }
+
+    fn named_stages(&self) -> usize {
+        (*self).named_stages()
+    }
+}
+
+impl<T: Op> Op for std::sync::Arc<T> {
+    type Input = T::Input;
+    type Output = T::Output;
+
+    #[inline]
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        (**self).call(input).await
+    }
+
+    fn named_stages(&self) -> usize {
+        (**self).named_stages()
+    }
 }
 
 // ================================================================
@@ -210,10 +516,26 @@ where
         let prev = self.prev.call(input).await;
         self.op.call(prev).await
     }
+
+    fn named_stages(&self) -> usize {
+        self.prev.named_stages() + self.op.named_stages()
+    }
 }
 
-use super::agent_ops::{Lookup, Prompt};
+use super::{
+    agent_ops::{Lookup, Prompt},
+    branch::{Branch, OpBox},
+    cache::{Cache, Cached},
+    checkpoint,
+    events::{self, PipelineEvent},
+    loop_while::LoopWhile,
+    map_each::{MapEach, TryMapEach},
+    named::Named,
+    rate_limit::{ConcurrencyLimit, MaxConcurrent, RateLimited, RateLimiter},
+    try_op::TryOp,
+};
 use crate::{completion, vector_store};
+use serde::{Serialize, de::DeserializeOwned};
 
 // ================================================================
 // Core Op implementations
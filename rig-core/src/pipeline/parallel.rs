@@ -428,4 +428,41 @@ mod tests {
         let result = pipeline.try_call(1).await;
         assert_eq!(result, Err("1 is the number!".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_parallel_macro_recovers_failed_branch() {
+        let pipeline = parallel!(
+            passthrough(),
+            map(|x: i32| Ok::<_, String>(x * 2)).unwrap_or(-1),
+            map(|x: i32| Err::<i32, _>(format!("{x} is the number!"))).unwrap_or(0),
+            map(|x: i32| Err::<String, _>(x)).unwrap_or_else(|x| format!("recovered {x}"))
+        );
+
+        let result = pipeline.call(1).await;
+        assert_eq!(result, (1, 2, 0, "recovered 1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_try_parallel_macro_recovers_failed_branch() {
+        // Branches with different error types are unified with `map_err`, failed ones recovered
+        // with `or_else`, so that only the remaining error fails the stage.
+        let pipeline = try_parallel!(
+            map(|x: i32| Ok::<_, String>(x)),
+            map(|x: i32| Err::<i32, _>(x)).map_err(|x| format!("error {x}")),
+            map(|x: i32| Err::<i32, _>(format!("{x} is the number!")))
+                .or_else(|_| async move { Ok::<_, String>(0) })
+        );
+
+        let result = pipeline.try_call(1).await;
+        assert_eq!(result, Err("error 1".to_string()));
+
+        let pipeline = try_parallel!(
+            map(|x: i32| Ok::<_, String>(x)),
+            map(|x: i32| Err::<i32, _>(format!("{x} is the number!")))
+                .or_else(|_| async move { Ok::<_, String>(0) })
+        );
+
+        let result = pipeline.try_call(1).await;
+        assert_eq!(result, Ok((1, 0)));
+    }
 }
@@ -0,0 +1,274 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Semaphore;
+
+use super::op;
+
+/// A budget of calls per unit of time, see [Op::rate_limited](super::Op::rate_limited).
+///
+/// Calls are spaced evenly: with 10 permits per second, a call starts at most every 100ms.
+/// Clones share the same budget, so ops rate limited with clones of one limiter are limited
+/// together, e.g. all the ops calling the same provider.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    /// Earliest start of the next call
+    next: Arc<Mutex<Instant>>,
+}
+
+impl RateLimiter {
+    /// Allow `permits` calls every `period`.
+    ///
+    /// # Panics
+    /// If `permits` is 0.
+    pub fn new(permits: u32, period: Duration) -> Self {
+        assert!(permits > 0, "a rate limiter needs at least one permit");
+
+        Self {
+            interval: period / permits,
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Allow `permits` calls per second.
+    pub fn per_second(permits: u32) -> Self {
+        Self::new(permits, Duration::from_secs(1))
+    }
+
+    /// Wait until a call can start.
+    pub async fn acquire(&self) {
+        let start = {
+            let mut next = self.next.lock().expect("rate limiter lock poisoned");
+            let start = (*next).max(Instant::now());
+            *next = start + self.interval;
+            start
+        };
+
+        let delay = start.saturating_duration_since(Instant::now());
+        if !delay.is_zero() {
+            futures_timer::Delay::new(delay).await;
+        }
+    }
+}
+
+impl From<u32> for RateLimiter {
+    fn from(permits_per_second: u32) -> Self {
+        Self::per_second(permits_per_second)
+    }
+}
+
+/// A maximum number of calls running at the same time, see
+/// [Op::max_concurrent](super::Op::max_concurrent).
+///
+/// Clones share the same permits, so ops limited with clones of one limit never run more than
+/// `n` calls at the same time in total.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimit {
+    /// Allow `n` concurrent calls.
+    ///
+    /// # Panics
+    /// If `n` is 0.
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "a concurrency limit needs at least one permit");
+
+        Self {
+            semaphore: Arc::new(Semaphore::new(n)),
+        }
+    }
+
+    /// Number of calls which can start right now.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+}
+
+impl From<usize> for ConcurrencyLimit {
+    fn from(n: usize) -> Self {
+        Self::new(n)
+    }
+}
+
+pub struct RateLimited<Op> {
+    op: Op,
+    limiter: RateLimiter,
+}
+
+impl<Op> RateLimited<Op> {
+    pub(crate) fn new(op: Op, limiter: RateLimiter) -> Self {
+        Self { op, limiter }
+    }
+
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+}
+
+impl<Op> op::Op for RateLimited<Op>
+where
+    Op: op::Op,
+{
+    type Input = Op::Input;
+    type Output = Op::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        self.limiter.acquire().await;
+        self.op.call(input).await
+    }
+
+    fn named_stages(&self) -> usize {
+        self.op.named_stages()
+    }
+}
+
+/// Create a new rate limited operation, waiting for `limiter` before each call of `op`.
+/// See [Op::rate_limited](super::Op::rate_limited).
+pub fn rate_limited<Op>(op: Op, limiter: impl Into<RateLimiter>) -> RateLimited<Op>
+where
+    Op: op::Op,
+{
+    RateLimited::new(op, limiter.into())
+}
+
+pub struct MaxConcurrent<Op> {
+    op: Op,
+    limit: ConcurrencyLimit,
+}
+
+impl<Op> MaxConcurrent<Op> {
+    pub(crate) fn new(op: Op, limit: ConcurrencyLimit) -> Self {
+        Self { op, limit }
+    }
+
+    pub fn limit(&self) -> &ConcurrencyLimit {
+        &self.limit
+    }
+}
+
+impl<Op> op::Op for MaxConcurrent<Op>
+where
+    Op: op::Op,
+{
+    type Input = Op::Input;
+    type Output = Op::Output;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let _permit = self
+            .limit
+            .semaphore
+            .acquire()
+            .await
+            .expect("concurrency limit semaphore is never closed");
+        self.op.call(input).await
+    }
+
+    fn named_stages(&self) -> usize {
+        self.op.named_stages()
+    }
+}
+
+/// Create a new operation running at most `limit` calls of `op` at the same time.
+/// See [Op::max_concurrent](super::Op::max_concurrent).
+pub fn max_concurrent<Op>(op: Op, limit: impl Into<ConcurrencyLimit>) -> MaxConcurrent<Op>
+where
+    Op: op::Op,
+{
+    MaxConcurrent::new(op, limit.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        parallel,
+        pipeline::{self, Op, then},
+    };
+
+    /// Records how many calls of the ops it instruments run at the same time, and when they
+    /// start.
+    #[derive(Clone, Default)]
+    struct Probe {
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+        starts: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    impl Probe {
+        fn op(&self, millis: u64) -> impl Op<Input = i32, Output = i32> {
+            let probe = self.clone();
+            then(move |x: i32| {
+                let probe = probe.clone();
+                async move {
+                    probe.starts.lock().unwrap().push(Instant::now());
+                    let running = probe.running.fetch_add(1, Ordering::SeqCst) + 1;
+                    probe.max_running.fetch_max(running, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(millis)).await;
+                    probe.running.fetch_sub(1, Ordering::SeqCst);
+                    x
+                }
+            })
+        }
+
+        fn max_running(&self) -> usize {
+            self.max_running.load(Ordering::SeqCst)
+        }
+
+        fn starts(&self) -> Vec<Instant> {
+            let mut starts = self.starts.lock().unwrap().clone();
+            starts.sort();
+            starts
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_is_shared_across_ops() {
+        let probe = Probe::default();
+        let limit = ConcurrencyLimit::new(2);
+        let op = pipeline::new().chain(parallel!(
+            probe.op(20).max_concurrent(limit.clone()),
+            probe.op(20).max_concurrent(limit.clone()),
+            probe.op(20).max_concurrent(limit.clone()),
+        ));
+
+        let outputs = op.batch_call(10, 0..10).await;
+        assert_eq!(outputs.len(), 10);
+        assert_eq!(probe.starts().len(), 30);
+        assert_eq!(probe.max_running(), 2);
+        assert_eq!(limit.available(), 2);
+
+        // A limit can also be given as a number of calls
+        let probe = Probe::default();
+        let op = probe.op(20).max_concurrent(5);
+        op.batch_call(10, 0..10).await;
+        assert_eq!(probe.max_running(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_spaces_calls() {
+        let probe = Probe::default();
+        let limiter = RateLimiter::per_second(50);
+        let op = pipeline::new().chain(parallel!(
+            probe.op(1).rate_limited(limiter.clone()),
+            probe.op(1).rate_limited(limiter.clone()),
+        ));
+
+        let started = Instant::now();
+        op.batch_call(3, 0..3).await;
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        // The n-th call starts at least 20ms after the previous one was allowed to
+        let starts = probe.starts();
+        assert_eq!(starts.len(), 6);
+        for (n, start) in starts.iter().enumerate() {
+            assert!(*start >= started + Duration::from_millis(20) * n as u32);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::wasm_compat::{WasmCompatSend, WasmCompatSync};
+
+use super::{op, try_op::TryOp};
+
+// Decides whether an error is retryable, see [RetryPolicy::retry_if]
+type RetryClassifier<E> = Arc<dyn Fn(&E) -> bool + Send + Sync>;
+
+/// When to call a failed op again, see [TryOp::retry].
+///
+/// An op is called again after a delay doubling on each attempt starting from `backoff`, as long
+/// as fewer than `max_attempts` attempts were made and the error is retryable. By default every
+/// error is retryable; use [RetryPolicy::retry_if] to only retry transient errors.
+pub struct RetryPolicy<E> {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: usize,
+    /// Delay before the first retry
+    pub backoff: Duration,
+    retry_if: Option<RetryClassifier<E>>,
+}
+
+impl<E> RetryPolicy<E> {
+    pub fn new(max_attempts: usize, backoff: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff,
+            retry_if: None,
+        }
+    }
+
+    /// Only retry the errors for which `classifier` returns true.
+    pub fn retry_if(mut self, classifier: impl Fn(&E) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_if = Some(Arc::new(classifier));
+        self
+    }
+
+    /// Whether the attempt number `attempt` (counting from 1), which failed with `error`,
+    /// should be retried.
+    pub fn should_retry(&self, attempt: usize, error: &E) -> bool {
+        attempt < self.max_attempts
+            && self
+                .retry_if
+                .as_ref()
+                .is_none_or(|classifier| classifier(error))
+    }
+
+    /// The delay before retrying the attempt number `attempt` (counting from 1).
+    pub fn delay(&self, attempt: usize) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1) as u32))
+    }
+}
+
+impl<E> Clone for RetryPolicy<E> {
+    fn clone(&self) -> Self {
+        Self {
+            max_attempts: self.max_attempts,
+            backoff: self.backoff,
+            retry_if: self.retry_if.clone(),
+        }
+    }
+}
+
+impl<E> std::fmt::Debug for RetryPolicy<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .field("retry_if", &self.retry_if.is_some())
+            .finish()
+    }
+}
+
+pub struct Retry<Op, E> {
+    op: Op,
+    policy: RetryPolicy<E>,
+}
+
+impl<Op, E> Retry<Op, E> {
+    pub(crate) fn new(op: Op, policy: RetryPolicy<E>) -> Self {
+        Self { op, policy }
+    }
+}
+
+impl<Op, E> op::Op for Retry<Op, E>
+where
+    Op: TryOp<Error = E>,
+    Op::Input: Clone,
+    E: WasmCompatSend + WasmCompatSync,
+{
+    type Input = Op::Input;
+    type Output = Result<Op::Output, Op::Error>;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let mut attempt = 1;
+        loop {
+            match self.op.try_call(input.clone()).await {
+                Ok(output) => return Ok(output),
+                Err(error) if self.policy.should_retry(attempt, &error) => {
+                    let delay = self.policy.delay(attempt);
+                    tracing::warn!(
+                        attempt,
+                        max_attempts = self.policy.max_attempts,
+                        "Pipeline op failed, retrying in {delay:?}"
+                    );
+                    futures_timer::Delay::new(delay).await;
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Create a new retry operation, calling `op` again according to `policy` when it fails.
+/// See [TryOp::retry].
+pub fn retry<Op>(op: Op, policy: RetryPolicy<Op::Error>) -> Retry<Op, Op::Error>
+where
+    Op: TryOp,
+    Op::Input: Clone,
+{
+    Retry::new(op, policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::pipeline::{TimeoutError, op::Op};
+
+    #[derive(Debug, PartialEq)]
+    enum FlakyError {
+        Transient,
+        Permanent,
+    }
+
+    /// An op failing with `error` for its first `failures` calls.
+    struct Flaky {
+        failures: usize,
+        error: fn() -> FlakyError,
+        delay: Duration,
+        calls: AtomicUsize,
+    }
+
+    impl Flaky {
+        fn new(failures: usize, error: fn() -> FlakyError) -> Self {
+            Self {
+                failures,
+                error,
+                delay: Duration::ZERO,
+                calls: AtomicUsize::new(0),
+            }
+        }
+
+        /// Hang for `delay` on the calls that fail instead of failing right away.
+        fn hanging(failures: usize, delay: Duration) -> Self {
+            Self {
+                delay,
+                ..Self::new(failures, || FlakyError::Transient)
+            }
+        }
+    }
+
+    impl Op for Flaky {
+        type Input = i32;
+        type Output = Result<i32, FlakyError>;
+
+        async fn call(&self, input: Self::Input) -> Self::Output {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call >= self.failures {
+                return Ok(input * 2);
+            }
+            if !self.delay.is_zero() {
+                tokio::time::sleep(self.delay).await;
+            }
+            Err((self.error)())
+        }
+    }
+
+    fn policy(max_attempts: usize) -> RetryPolicy<FlakyError> {
+        RetryPolicy::new(max_attempts, Duration::from_millis(1))
+            .retry_if(|error| *error == FlakyError::Transient)
+    }
+
+    #[tokio::test]
+    async fn test_retry_succeeds_after_transient_failures() {
+        let op = retry(Flaky::new(2, || FlakyError::Transient), policy(3));
+
+        assert_eq!(op.try_call(21).await, Ok(42));
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        // Permanent errors are not retried
+        let op = retry(Flaky::new(2, || FlakyError::Permanent), policy(3));
+        assert_eq!(op.try_call(21).await, Err(FlakyError::Permanent));
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 1);
+
+        // Transient errors are retried up to the maximum number of attempts
+        let op = retry(Flaky::new(5, || FlakyError::Transient), policy(3));
+        assert_eq!(op.try_call(21).await, Err(FlakyError::Transient));
+        assert_eq!(op.op.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        let policy = RetryPolicy::<FlakyError>::new(4, Duration::from_millis(100));
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert!(policy.should_retry(3, &FlakyError::Permanent));
+        assert!(!policy.should_retry(4, &FlakyError::Transient));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_then_retry() {
+        let op = Flaky::hanging(1, Duration::from_secs(5))
+            .timeout(Duration::from_millis(20))
+            .retry(
+                RetryPolicy::new(2, Duration::from_millis(1))
+                    .retry_if(|error: &TimeoutError<FlakyError>| error.is_elapsed()),
+            );
+
+        match op.try_call(21).await {
+            Ok(output) => assert_eq!(output, 42),
+            Err(error) => panic!("Expected the retried op to succeed, got {error:?}"),
+        }
+    }
+}
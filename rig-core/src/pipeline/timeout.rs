@@ -0,0 +1,92 @@
+use std::{pin::pin, time::Duration};
+
+use futures::future::{self, Either};
+
+use super::{op, try_op::TryOp};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TimeoutError<E> {
+    #[error("Op timed out after {0:?}")]
+    Elapsed(Duration),
+
+    #[error("{0}")]
+    Op(E),
+}
+
+impl<E> TimeoutError<E> {
+    /// Whether the op timed out, as opposed to failing on its own.
+    pub fn is_elapsed(&self) -> bool {
+        matches!(self, TimeoutError::Elapsed(_))
+    }
+}
+
+pub struct Timeout<Op> {
+    op: Op,
+    duration: Duration,
+}
+
+impl<Op> Timeout<Op> {
+    pub(crate) fn new(op: Op, duration: Duration) -> Self {
+        Self { op, duration }
+    }
+}
+
+impl<Op> op::Op for Timeout<Op>
+where
+    Op: TryOp,
+{
+    type Input = Op::Input;
+    type Output = Result<Op::Output, TimeoutError<Op::Error>>;
+
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        let call = pin!(self.op.try_call(input));
+        let delay = futures_timer::Delay::new(self.duration);
+
+        match future::select(call, delay).await {
+            Either::Left((result, _)) => result.map_err(TimeoutError::Op),
+            Either::Right(_) => Err(TimeoutError::Elapsed(self.duration)),
+        }
+    }
+}
+
+/// Create a new timeout operation, failing with [TimeoutError::Elapsed] when `op` does not
+/// complete within `duration`. See [TryOp::timeout].
+pub fn timeout<Op>(op: Op, duration: Duration) -> Timeout<Op>
+where
+    Op: TryOp,
+{
+    Timeout::new(op, duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::op::then;
+
+    fn slow_op(millis: u64) -> impl TryOp<Input = i32, Output = i32, Error = &'static str> {
+        then(move |x: i32| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            if x < 0 { Err("x is negative") } else { Ok(x) }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_timeout_elapsed() {
+        let op = timeout(slow_op(1000), Duration::from_millis(20));
+
+        let error = op.try_call(1).await.unwrap_err();
+        assert!(error.is_elapsed());
+        assert_eq!(error.to_string(), "Op timed out after 20ms");
+    }
+
+    #[tokio::test]
+    async fn test_timeout_passes_through_results() {
+        let op = slow_op(5).timeout(Duration::from_secs(5));
+
+        assert_eq!(op.try_call(1).await.unwrap(), 1);
+        match op.try_call(-1).await {
+            Err(TimeoutError::Op(error)) => assert_eq!(error, "x is negative"),
+            result => panic!("Expected the op's error, got {result:?}"),
+        }
+    }
+}
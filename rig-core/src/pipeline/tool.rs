@@ -0,0 +1,234 @@
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    completion::ToolDefinition,
+    tool::{Tool, ToolError},
+    wasm_compat::{WasmCompatSend, WasmCompatSync},
+};
+
+use super::TryOp;
+
+/// Wraps a pipeline so it can be registered as a tool on an agent.
+///
+/// The tool arguments are deserialized into the input of the pipeline, and its output is
+/// serialized back as the tool result. Errors of the pipeline are returned as
+/// [ToolError::ToolCallError], so the model sees why the call failed. Infallible pipelines can
+/// be wrapped by mapping their output to `Ok::<_, std::convert::Infallible>`.
+///
+/// # Example
+/// ```rust,ignore
+/// use rig::{parallel, pipeline::{self, PipelineTool, TryOp, agent_ops::extract}};
+///
+/// let evaluation = pipeline::new()
+///     .chain(parallel!(extract(quality_scorer), extract(sentiment_scorer)))
+///     .map(|(quality, sentiment)| Ok::<_, ExtractionError>(Evaluation { quality: quality?, sentiment: sentiment? }));
+///
+/// let orchestrator = openai.agent("gpt-4o")
+///     .tool(PipelineTool::new(
+///         "evaluate_article",
+///         "Score the quality and sentiment of an article",
+///         serde_json::to_value(schemars::schema_for!(String)).unwrap(),
+///         evaluation,
+///     ))
+///     .build();
+/// ```
+pub struct PipelineTool<P> {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+    pipeline: P,
+}
+
+impl<P> PipelineTool<P>
+where
+    P: TryOp,
+{
+    /// Create a new pipeline tool. `parameters` is the JSON schema of the tool arguments, which
+    /// must deserialize into the input of `pipeline`.
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+        pipeline: P,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+            pipeline,
+        }
+    }
+}
+
+impl<P> Tool for PipelineTool<P>
+where
+    P: TryOp,
+    P::Input: DeserializeOwned,
+    P::Output: Serialize,
+    P::Error: std::error::Error + WasmCompatSend + WasmCompatSync + 'static,
+{
+    const NAME: &'static str = "pipeline_tool";
+
+    type Error = ToolError;
+    type Args = P::Input;
+    type Output = P::Output;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: self.parameters.clone(),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        self.pipeline
+            .try_call(args)
+            .await
+            .map_err(|error| ToolError::ToolCallError(Box::new(error)))
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        agent::AgentBuilder,
+        completion::{
+            Prompt,
+            message::{Message, ToolResultContent, UserContent},
+        },
+        parallel,
+        pipeline::{self, Op, map},
+        test_utils::MockCompletionModel,
+    };
+
+    #[derive(Clone, Deserialize)]
+    struct Article {
+        title: String,
+        body: String,
+    }
+
+    #[derive(Debug, Serialize, PartialEq)]
+    struct Evaluation {
+        title_words: usize,
+        body_words: usize,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Article {0} is empty")]
+    struct EmptyArticle(String);
+
+    fn evaluation_tool() -> impl Tool<Args = Article, Output = Evaluation, Error = ToolError> {
+        let pipeline = pipeline::new()
+            .chain(parallel!(
+                map(|article: Article| article.title.split_whitespace().count()),
+                map(|article: Article| article.body.split_whitespace().count()),
+            ))
+            .map(|(title_words, body_words)| {
+                if body_words == 0 {
+                    Err(EmptyArticle("body".to_string()))
+                } else {
+                    Ok(Evaluation {
+                        title_words,
+                        body_words,
+                    })
+                }
+            });
+
+        PipelineTool::new(
+            "evaluate_article",
+            "Count the words of an article",
+            json!({
+                "type": "object",
+                "properties": {
+                    "title": {"type": "string"},
+                    "body": {"type": "string"}
+                }
+            }),
+            pipeline,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_tool_maps_errors() {
+        let tool = evaluation_tool();
+
+        let evaluation = tool
+            .call(Article {
+                title: "Pipelines as tools".to_string(),
+                body: "One tool call runs the whole evaluation".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            evaluation,
+            Evaluation {
+                title_words: 3,
+                body_words: 7
+            }
+        );
+
+        let error = tool
+            .call(Article {
+                title: "Empty".to_string(),
+                body: String::new(),
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ToolError::ToolCallError(_)));
+        assert_eq!(error.to_string(), "ToolCallError: Article body is empty");
+    }
+
+    #[tokio::test]
+    async fn test_agent_calls_pipeline_tool() {
+        let model = MockCompletionModel::new()
+            .with_tool_call(
+                "call_1",
+                "evaluate_article",
+                json!({"title": "Pipelines as tools", "body": "Scored in one call"}),
+            )
+            .with_text("done");
+        let agent = AgentBuilder::new(model.clone())
+            .tool(evaluation_tool())
+            .build();
+
+        let response = agent.prompt("Evaluate it").multi_turn(2).await.unwrap();
+        assert_eq!(response, "done");
+
+        let requests = model.requests();
+        let definition = &requests[0].tools[0];
+        assert_eq!(definition.name, "evaluate_article");
+        assert_eq!(
+            definition.parameters["properties"]["body"]["type"],
+            "string"
+        );
+
+        let results: Vec<String> = requests[1]
+            .chat_history
+            .iter()
+            .flat_map(|message| match message {
+                Message::User { content } => content.iter().cloned().collect(),
+                _ => vec![],
+            })
+            .filter_map(|content| match content {
+                UserContent::ToolResult(result) => match result.content.first() {
+                    ToolResultContent::Text(text) => Some(text.text),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            results,
+            vec![r#"{"title_words":3,"body_words":4}"#.to_string()]
+        );
+    }
+}
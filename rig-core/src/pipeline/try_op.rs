@@ -1,4 +1,4 @@
-use std::future::Future;
+use std::{future::Future, time::Duration};
 
 use futures::stream;
 #[allow(unused_imports)] // Needed since this is used in a macro rule
@@ -6,7 +6,12 @@ use futures::try_join;
 
 use crate::wasm_compat::{WasmCompatSend, WasmCompatSync};
 
-use super::op::{self};
+use super::{
+    named::TryNamed,
+    op::{self},
+    retry::{Retry, RetryPolicy},
+    timeout::Timeout,
+};
 
 // ================================================================
 // Core TryOp trait
@@ -188,6 +193,123 @@ pub trait TryOp: WasmCompatSend + WasmCompatSync {
     {
         TrySequential::new(self, op)
     }
+
+    /// Recover from errors of the current op by returning `value` instead, turning it into an
+    /// infallible op. This lets a failing branch of a [parallel!](crate::parallel) stage fall
+    /// back to a default without failing the whole stage.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op, TryOp};
+    ///
+    /// let op = pipeline::new()
+    ///     .map(|x: i32| if x % 2 == 0 { Ok(x) } else { Err("x is odd") })
+    ///     .unwrap_or(0);
+    ///
+    /// assert_eq!(op.call(2).await, 2);
+    /// assert_eq!(op.call(1).await, 0);
+    /// ```
+    fn unwrap_or(self, value: Self::Output) -> UnwrapOr<Self, Self::Output>
+    where
+        Self::Output: Clone,
+        Self: Sized,
+    {
+        UnwrapOr::new(self, value)
+    }
+
+    /// Recover from errors of the current op by computing a value from the error with `f`,
+    /// turning it into an infallible op.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, Op, TryOp};
+    ///
+    /// let op = pipeline::new()
+    ///     .map(|x: i32| if x % 2 == 0 { Ok(x.to_string()) } else { Err("x is odd") })
+    ///     .unwrap_or_else(|err| format!("Error: {err}"));
+    ///
+    /// assert_eq!(op.call(1).await, "Error: x is odd");
+    /// ```
+    fn unwrap_or_else<F>(self, f: F) -> UnwrapOrElse<Self, F>
+    where
+        F: Fn(Self::Error) -> Self::Output + WasmCompatSend + WasmCompatSync,
+        Self: Sized,
+    {
+        UnwrapOrElse::new(self, f)
+    }
+
+    /// Call the current op again when it fails, according to `policy`. The input of the op is
+    /// cloned for every attempt.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use std::time::Duration;
+    /// use rig::pipeline::{self, RetryPolicy, TryOp};
+    ///
+    /// let op = pipeline::new()
+    ///     .prompt(agent)
+    ///     .retry(
+    ///         RetryPolicy::new(3, Duration::from_millis(500))
+    ///             .retry_if(|err: &PromptError| !matches!(err, PromptError::MaxDepthError { .. })),
+    ///     );
+    ///
+    /// let result = op.try_call("What is the melting point of iron?").await?;
+    /// ```
+    fn retry(self, policy: RetryPolicy<Self::Error>) -> Retry<Self, Self::Error>
+    where
+        Self::Input: Clone,
+        Self: Sized,
+    {
+        Retry::new(self, policy)
+    }
+
+    /// Fail with [TimeoutError::Elapsed](super::TimeoutError::Elapsed) when the current op does
+    /// not complete within `duration`. Errors of the op itself are returned as
+    /// [TimeoutError::Op](super::TimeoutError::Op).
+    ///
+    /// Combined with [retry](TryOp::retry), each attempt gets its own timeout:
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use std::time::Duration;
+    /// use rig::pipeline::{self, RetryPolicy, TimeoutError, TryOp};
+    ///
+    /// let op = pipeline::new()
+    ///     .prompt(agent)
+    ///     .timeout(Duration::from_secs(30))
+    ///     .retry(RetryPolicy::new(3, Duration::from_secs(1)).retry_if(TimeoutError::is_elapsed));
+    ///
+    /// let result = op.try_call("What is the melting point of iron?").await?;
+    /// ```
+    fn timeout(self, duration: Duration) -> Timeout<Self>
+    where
+        Self: Sized,
+    {
+        Timeout::new(self, duration)
+    }
+
+    /// Same as [named](op::Op::named) but for fallible ops: the span also records the error
+    /// (`stage.error`) when the op fails, and the output size only when it succeeds.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use rig::pipeline::{self, TryOp};
+    ///
+    /// let op = pipeline::new()
+    ///     .map(|x: i32| if x > 0 { Ok(x) } else { Err("x is not positive") })
+    ///     .try_named("validate");
+    ///
+    /// assert_eq!(op.try_call(-1).await, Err("x is not positive"));
+    /// ```
+    fn try_named(self, name: impl Into<String>) -> TryNamed<Self>
+    where
+        Self: op::Op + Sized,
+        <Self as TryOp>::Input: std::fmt::Debug,
+        <Self as TryOp>::Output: std::fmt::Debug,
+        Self::Error: std::fmt::Display,
+    {
+        TryNamed::new(self, name)
+    }
 }
 
 impl<Op, T, E> TryOp for Op
@@ -319,6 +441,62 @@ where
     }
 }
 
+pub struct UnwrapOr<Op, T> {
+    op: Op,
+    value: T,
+}
+
+impl<Op, T> UnwrapOr<Op, T> {
+    pub(crate) fn new(op: Op, value: T) -> Self {
+        Self { op, value }
+    }
+}
+
+impl<Op, T> op::Op for UnwrapOr<Op, T>
+where
+    Op: TryOp<Output = T>,
+    T: Clone + WasmCompatSend + WasmCompatSync,
+{
+    type Input = Op::Input;
+    type Output = T;
+
+    #[inline]
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        match self.op.try_call(input).await {
+            Ok(output) => output,
+            Err(_) => self.value.clone(),
+        }
+    }
+}
+
+pub struct UnwrapOrElse<Op, F> {
+    op: Op,
+    f: F,
+}
+
+impl<Op, F> UnwrapOrElse<Op, F> {
+    pub(crate) fn new(op: Op, f: F) -> Self {
+        Self { op, f }
+    }
+}
+
+impl<Op, F> op::Op for UnwrapOrElse<Op, F>
+where
+    Op: TryOp,
+    F: Fn(Op::Error) -> Op::Output + WasmCompatSend + WasmCompatSync,
+{
+    type Input = Op::Input;
+    type Output = Op::Output;
+
+    #[inline]
+    async fn call(&self, input: Self::Input) -> Self::Output {
+        match self.op.try_call(input).await {
+            Ok(output) => output,
+            Err(err) => (self.f)(err),
+        }
+    }
+}
+
 pub struct TrySequential<Op1, Op2> {
     prev: Op1,
     op: Op2,
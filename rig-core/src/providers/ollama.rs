@@ -665,8 +665,10 @@ where
 
                         for tool_call in tool_calls {
                             tool_calls_final.push(tool_call.clone());
+                            // Ollama tool calls have no id: like non-streaming responses, use the
+                            // tool name, which is also what tool results are matched on
                             yield RawStreamingChoice::ToolCall {
-                                id: String::new(),
+                                id: tool_call.function.name.clone(),
                                 name: tool_call.function.name,
                                 arguments: tool_call.function.arguments,
                                 call_id: None,
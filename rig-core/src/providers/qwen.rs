@@ -21,14 +21,19 @@ use crate::http_client::sse::{Event, GenericEventSource};
 use crate::http_client::{self, HttpClientExt};
 // 导入标准库的 HashMap
 use std::collections::HashMap;
+use std::sync::Arc;
 // 导入跟踪模块
 use tracing::{Instrument, info_span};
 
 // 导入 Rig 核心类型
 use crate::{
-    client::{ClientBuilderError, CompletionClient, ProviderClient, VerifyClient, VerifyError},
+    client::{
+        ClientBuilderError, CompletionClient, EmbeddingsClient, ProviderClient, VerifyClient,
+        VerifyError,
+    },
     completion::{self, CompletionError, CompletionRequest, message, MessageError},
-    impl_conversion_traits, json_utils,
+    embeddings::{self, EmbeddingError},
+    json_utils,
 };
 
 // 导入序列化相关
@@ -37,12 +42,17 @@ use serde_json::json;
 
 // 导入 JSON 工具
 use crate::completion::GetTokenUsage;
+use crate::completion::message::MimeType;
 
 // ================================================================
 // 主 Qwen 客户端
 // ================================================================
 // 通义千问 API 基础 URL 常量
 const QWEN_API_BASE_URL: &str = "https://dashscope.aliyuncs.com/api/v1/services/aigc";
+// 文本生成接口的默认路径
+const QWEN_COMPLETION_PATH: &str = "text-generation/generation";
+/// 默认的客户端标识（作为 `User-Agent` 请求头发送）
+pub const DEFAULT_USER_AGENT: &str = concat!("rig-qwen/", env!("CARGO_PKG_VERSION"));
 
 // 客户端构建器结构体
 pub struct ClientBuilder<'a, T = reqwest::Client> {
@@ -50,6 +60,8 @@ pub struct ClientBuilder<'a, T = reqwest::Client> {
     api_key: &'a str,
     // 基础 URL
     base_url: &'a str,
+    // 客户端标识
+    user_agent: String,
     // HTTP 客户端
     http_client: T,
 }
@@ -66,6 +78,8 @@ where
             api_key,
             // 设置默认基础 URL
             base_url: QWEN_API_BASE_URL,
+            // 设置默认客户端标识
+            user_agent: DEFAULT_USER_AGENT.to_string(),
             // 初始化 HTTP 客户端
             http_client: T::default(),
         }
@@ -79,11 +93,19 @@ where
         self
     }
 
+    /// 设置客户端标识，作为 `User-Agent` 请求头随每个请求发送（默认为 [DEFAULT_USER_AGENT]），
+    /// 便于 DashScope 侧的问题排查与调用统计
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
     // 设置自定义 HTTP 客户端
     pub fn with_client<U>(self, http_client: U) -> ClientBuilder<'a, U> {
         ClientBuilder {
             api_key: self.api_key,
             base_url: self.base_url,
+            user_agent: self.user_agent,
             http_client,
         }
     }
@@ -96,21 +118,41 @@ where
             base_url: self.base_url.to_string(),
             // 转换 API 密钥为字符串
             api_key: self.api_key.to_string(),
-            // 设置 HTTP 客户端
-            http_client: self.http_client,
+            // 设置客户端标识
+            user_agent: self.user_agent,
+            // 共享 HTTP 客户端
+            http_client: Arc::new(self.http_client),
         })
     }
 }
 
-// 客户端结构体
-#[derive(Clone)]
+/// 通义千问客户端
+///
+/// HTTP 客户端保存在 [Arc] 中：克隆客户端（例如 [CompletionClient::completion_model]、
+/// [EmbeddingsClient::embedding_model] 为每个模型克隆一次）只会增加引用计数，
+/// 从同一客户端创建的所有模型共享同一个 HTTP 客户端及其连接池，
+/// 即使 `T` 是克隆代价较高的自定义客户端也不会被深拷贝。
 pub struct Client<T = reqwest::Client> {
     // 基础 URL（公开）
     pub base_url: String,
     // API 密钥
     api_key: String,
-    // HTTP 客户端
-    pub http_client: T,
+    // 客户端标识（User-Agent）
+    user_agent: String,
+    // HTTP 客户端（在克隆之间共享）
+    pub http_client: Arc<T>,
+}
+
+// 手动实现 Clone：只克隆 Arc，不要求也不调用 `T: Clone`
+impl<T> Clone for Client<T> {
+    fn clone(&self) -> Self {
+        Self {
+            base_url: self.base_url.clone(),
+            api_key: self.api_key.clone(),
+            user_agent: self.user_agent.clone(),
+            http_client: Arc::clone(&self.http_client),
+        }
+    }
 }
 
 // 为 Client 实现 Debug trait
@@ -123,6 +165,8 @@ where
         f.debug_struct("Client")
             // 输出基础 URL
             .field("base_url", &self.base_url)
+            // 输出客户端标识
+            .field("user_agent", &self.user_agent)
             // 输出 HTTP 客户端
             .field("http_client", &self.http_client)
             // 隐藏 API 密钥（安全考虑）
@@ -220,7 +264,10 @@ where
         let url = format!("{}/{}", self.base_url, path.trim_start_matches('/'));
 
         http_client::with_bearer_auth(
-            http_client::Request::builder().method(method).uri(url),
+            http_client::Request::builder()
+                .method(method)
+                .uri(url)
+                .header(http::header::USER_AGENT, &self.user_agent),
             &self.api_key,
         )
     }
@@ -231,6 +278,9 @@ impl<T> ProviderClient for Client<T>
 where
     T: HttpClientExt + Clone + std::fmt::Debug + Default + Send + 'static,
 {
+    // API 密钥
+    type Input = String;
+
     // 从环境变量创建客户端
     fn from_env() -> Self {
         // 获取 DASHSCOPE_API_KEY 环境变量
@@ -239,14 +289,8 @@ where
         Self::new(&api_key)
     }
 
-    // 从 ProviderValue 创建客户端
-    fn from_val(input: crate::client::ProviderValue) -> Self {
-        // 解构 ProviderValue
-        let crate::client::ProviderValue::Simple(api_key) = input else {
-            // 如果不是 Simple 类型，则恐慌
-            panic!("Incorrect provider value type")
-        };
-        // 创建新的客户端
+    // 从 API 密钥创建客户端
+    fn from_val(api_key: Self::Input) -> Self {
         Self::new(&api_key)
     }
 }
@@ -258,16 +302,31 @@ where
 {
     // 完成模型类型
     type CompletionModel = CompletionModel<T>;
+}
 
-    /// Creates a Qwen completion model with the given `model_name`.
-    // 使用给定的 `model_name` 创建通义千问完成模型
-    fn completion_model(&self, model_name: &str) -> CompletionModel<T> {
-        CompletionModel {
-            // 克隆客户端
-            client: self.clone(),
-            // 转换模型名称为字符串
-            model: model_name.to_string(),
-        }
+// 为 Client 实现 EmbeddingsClient trait
+impl<T> EmbeddingsClient for Client<T>
+where
+    T: HttpClientExt + Clone + std::fmt::Debug + Default + Send + 'static,
+{
+    // 嵌入模型类型
+    type EmbeddingModel = EmbeddingModel<T>;
+
+    /// Creates a Qwen embedding model with the given `model`.
+    // 使用给定的模型名称创建通义千问嵌入模型（已知模型自动推断维度）
+    fn embedding_model(&self, model: impl Into<String>) -> EmbeddingModel<T> {
+        let model = model.into();
+        let ndims = embedding_dimensions_from_identifier(&model).unwrap_or_default();
+        EmbeddingModel::new(self.clone(), model, ndims)
+    }
+
+    // 使用给定的模型名称和维度创建通义千问嵌入模型
+    fn embedding_model_with_ndims(
+        &self,
+        model: impl Into<String>,
+        ndims: usize,
+    ) -> EmbeddingModel<T> {
+        EmbeddingModel::new(self.clone(), model, ndims)
     }
 }
 
@@ -283,7 +342,7 @@ where
         // 通义千问 API 没有专门的验证端点
         // 我们可以发送一个简单的请求来验证 API 密钥
         let request = self
-            .post("text-generation/generation")?
+            .post(QWEN_COMPLETION_PATH)?
             .header("Content-Type", "application/json")
             .body(serde_json::to_vec(&json!({
                 "model": "qwen-turbo",
@@ -298,10 +357,10 @@ where
                 "parameters": {
                     "max_tokens": 1
                 }
-            })).map_err(|e| http_client::Error::from(http_client::Error::Instance(e.into())))?)
+            })).map_err(|e| http_client::Error::Instance(e.into()))?)
             .map_err(|e| VerifyError::ProviderError(e.to_string()))?;
 
-        let response = self.http_client.send(request).await.map_err(http_client::Error::from)?;
+        let response = self.http_client.send(request).await?;
 
         // 匹配响应状态码
         match response.status() {
@@ -317,15 +376,6 @@ where
     }
 }
 
-// 为 Client 实现转换 traits
-// 支持嵌入、转录、图像生成和音频生成
-impl_conversion_traits!(
-    AsEmbeddings,
-    AsTranscription,
-    AsImageGeneration,
-    AsAudioGeneration for Client<T>
-);
-
 // ================================================================
 // 通义千问完成 API
 // ================================================================
@@ -357,6 +407,50 @@ pub const QWEN3_MAX: &str = "qwen3-max";
 /// `qwq-plus` 深度推理模型
 // qwq-plus 深度推理模型常量
 pub const QWQ_PLUS: &str = "qwq-plus";
+/// `qwen-long` 长上下文模型
+// qwen-long 长上下文模型常量
+pub const QWEN_LONG: &str = "qwen-long";
+/// `qwen-vl-plus` 视觉理解模型
+// qwen-vl-plus 视觉理解模型常量
+pub const QWEN_VL_PLUS: &str = "qwen-vl-plus";
+/// `qwen-vl-max` 视觉理解模型
+// qwen-vl-max 视觉理解模型常量
+pub const QWEN_VL_MAX: &str = "qwen-vl-max";
+
+/// 已知模型的上下文窗口大小（以 token 计），未知模型返回 `None`
+///
+/// 可用于为 token 预算类功能自动设置上限：
+///
+/// ```rust,ignore
+/// let max_tokens = qwen::context_window(qwen::QWEN_TURBO).unwrap_or(32_768);
+/// let agent = client
+///     .agent(qwen::QWEN_TURBO)
+///     .history_policy(HistoryPolicy::TokenBudget {
+///         max_tokens,
+///         keep_system: true,
+///         keep_last_n: 6,
+///     })
+///     .build();
+/// ```
+pub fn context_window(model: &str) -> Option<usize> {
+    let tokens = match model {
+        QWEN_MAX => 32_768,
+        QWEN_MAX_LATEST | QWEN_PLUS | QWQ_PLUS => 131_072,
+        QWEN3_MAX => 262_144,
+        QWEN_PLUS_LATEST | QWEN_TURBO | QWEN_TURBO_LATEST | QWEN_FLASH => 1_000_000,
+        QWEN_LONG => 10_000_000,
+        _ => return None,
+    };
+
+    Some(tokens)
+}
+
+/// 是否为能理解图像的视觉模型（`qwen-vl-plus`、`qwen2.5-vl-72b-instruct` 等名称中带 `-vl` 的模型）
+///
+/// 视觉模型的消息内容可以是包含图像的内容数组，其他模型只接受文本。
+pub fn is_vision_model(model: &str) -> bool {
+    model.contains("-vl")
+}
 
 // API 错误响应结构体
 #[derive(Debug, Deserialize)]
@@ -365,6 +459,56 @@ struct ApiErrorResponse {
     code: String,
     // 错误消息
     message: String,
+    // 请求 ID
+    #[serde(default)]
+    request_id: Option<String>,
+}
+
+/// DashScope 返回的结构化错误
+///
+/// 以 [`CompletionError::ProviderApiError`] 的错误源（source）返回，因此经过 `anyhow`
+/// 等错误包装后仍可通过 `downcast_ref` 取回，或直接使用 [`dashscope_error_code`]。
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{}{code}: {message}", status.map(|status| format!("{status}: ")).unwrap_or_default())]
+pub struct QwenError {
+    // HTTP 状态码（流式响应中的错误事件没有状态码）
+    pub status: Option<http::StatusCode>,
+    // DashScope 错误代码，例如 `InvalidParameter`、`Throttling.RateQuota`
+    pub code: String,
+    // 错误消息
+    pub message: String,
+    // 请求 ID，便于向阿里云排查问题
+    pub request_id: Option<String>,
+}
+
+impl QwenError {
+    /// 从 [`CompletionError`] 中取出 DashScope 错误（如果有）
+    pub fn from_completion_error(err: &CompletionError) -> Option<&QwenError> {
+        match err {
+            CompletionError::ProviderApiError(source) => source.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// 从 [`CompletionError`] 中取出 DashScope 错误代码（如果有）
+///
+/// ```rust,ignore
+/// if let Some(code) = qwen::dashscope_error_code(&err) {
+///     if code.starts_with("Throttling") {
+///         // 稍后重试
+///     }
+/// }
+/// ```
+pub fn dashscope_error_code(err: &CompletionError) -> Option<&str> {
+    QwenError::from_completion_error(err).map(|err| err.code.as_str())
+}
+
+// 为 QwenError 实现转换到 CompletionError（保留结构化错误作为错误源）
+impl From<QwenError> for CompletionError {
+    fn from(err: QwenError) -> Self {
+        CompletionError::ProviderApiError(Box::new(err))
+    }
 }
 
 // API 响应枚举
@@ -389,11 +533,61 @@ enum ApiResponse<T> {
 impl From<ApiErrorResponse> for CompletionError {
     // 转换方法
     fn from(err: ApiErrorResponse) -> Self {
-        // 将错误消息包装为 ProviderError
-        CompletionError::ProviderError(format!("{}: {}", err.code, err.message))
+        // 包装为没有状态码的 QwenError
+        QwenError {
+            status: None,
+            code: err.code,
+            message: err.message,
+            request_id: err.request_id,
+        }
+        .into()
+    }
+}
+
+// 将 HTTP 客户端错误映射为 CompletionError：
+// - 服务端返回的非成功状态码（如 400、500）映射为 ProviderError
+// - 其余传输层错误（连接断开、超时等）保留为 HttpError，调用方可据此决定是否重试
+fn map_http_error(err: http_client::Error) -> CompletionError {
+    match err {
+        http_client::Error::InvalidStatusCodeWithMessage(status, body) => {
+            provider_error(status, &body)
+        }
+        http_client::Error::InvalidStatusCode(status) => {
+            CompletionError::ProviderError(status.to_string())
+        }
+        err => CompletionError::HttpError(err),
+    }
+}
+
+// 根据状态码和响应体构建提供商错误：
+// 能解析为 DashScope 错误结构时返回携带 QwenError 的 ProviderApiError，否则返回 ProviderError
+fn provider_error(status: http::StatusCode, body: &str) -> CompletionError {
+    match serde_json::from_str::<ApiErrorResponse>(body) {
+        Ok(err) => QwenError {
+            status: Some(status),
+            code: err.code,
+            message: err.message,
+            request_id: err.request_id,
+        }
+        .into(),
+        Err(_) => CompletionError::ProviderError(format!("{status}: {body}")),
     }
 }
 
+/// 返回结果的格式（`parameters.result_format`）
+///
+/// 大多数模型支持 `Message`（默认）；部分旧版文本模型只支持 `Text`，
+/// 此时响应中只有 `output.text` 而没有 `output.choices`，也不支持工具调用。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultFormat {
+    // 返回 output.choices[].message
+    #[default]
+    Message,
+    // 返回 output.text
+    Text,
+}
+
 /// The response shape from the Qwen API
 // 通义千问 API 的响应结构
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -409,8 +603,46 @@ pub struct CompletionResponse {
 // 输出结构体
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Output {
-    // 选择列表
+    // 选择列表（result_format 为 "message" 时返回）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub choices: Vec<Choice>,
+    // 生成的文本（result_format 为 "text" 时返回）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    // 结束原因（result_format 为 "text" 时返回）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    // 联网搜索信息（启用 enable_search 且 API 返回搜索来源时存在）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_info: Option<SearchInfo>,
+}
+
+// 联网搜索信息结构体
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SearchInfo {
+    // 搜索结果列表
+    #[serde(default)]
+    pub search_results: Vec<SearchResult>,
+}
+
+// 单条搜索结果结构体
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SearchResult {
+    // 结果序号（对应回答中的角标）
+    #[serde(default)]
+    pub index: Option<u32>,
+    // 网页标题
+    #[serde(default)]
+    pub title: Option<String>,
+    // 网页链接
+    #[serde(default)]
+    pub url: Option<String>,
+    // 站点名称
+    #[serde(default)]
+    pub site_name: Option<String>,
+    // 站点图标
+    #[serde(default)]
+    pub icon: Option<String>,
 }
 
 // 使用情况统计结构体
@@ -437,6 +669,15 @@ impl Usage {
             total_tokens: 0,
         }
     }
+
+    // 转换为通用的使用情况统计
+    fn to_completion_usage(&self) -> completion::Usage {
+        completion::Usage {
+            input_tokens: self.input_tokens as u64,
+            output_tokens: self.output_tokens as u64,
+            total_tokens: self.total_tokens as u64,
+        }
+    }
 }
 
 // 选择结构体
@@ -482,11 +723,46 @@ pub enum Message {
     ToolResult {
         // 工具调用 ID
         tool_call_id: String,
-        // 消息内容
-        content: String,
+        // 产生结果的工具名称（可选，用于区分同一轮中多个工具的结果）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        // 消息内容（视觉模型的图像结果为内容数组）
+        content: MessageContent,
     },
 }
 
+/// 消息内容：纯文本，或视觉模型使用的多模态内容数组
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum MessageContent {
+    // 纯文本
+    Text(String),
+    // 内容数组，如 `[{"image": "https://..."}, {"text": "..."}]`
+    Parts(Vec<ContentPart>),
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_owned())
+    }
+}
+
+/// 多模态内容数组中的一项
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum ContentPart {
+    // 文本
+    Text { text: String },
+    // 图像（URL 或 `data:` URL 形式的 base64 数据）
+    Image { image: String },
+}
+
 // Message 的实现
 impl Message {
     // 创建系统消息
@@ -498,7 +774,7 @@ impl Message {
     }
 }
 
-// 为 message::ToolResult 实现转换到 Message
+// 为 message::ToolResult 实现转换到 Message（非视觉模型，图像转换为占位符）
 impl From<message::ToolResult> for Message {
     // 转换方法
     fn from(tool_result: message::ToolResult) -> Self {
@@ -510,10 +786,60 @@ impl From<message::ToolResult> for Message {
             message::ToolResultContent::Image(_) => String::from("[Image]"),
         };
 
-        // 返回工具结果消息
+        // 返回工具结果消息（工具名称不在结果中，由 fill_tool_result_names 根据对应的工具调用补全）
         Message::ToolResult {
             tool_call_id: tool_result.id,
-            content,
+            name: None,
+            content: content.into(),
+        }
+    }
+}
+
+// 将工具结果转换为视觉模型的消息：包含图像时使用内容数组保留图像数据，否则与非视觉模型相同
+fn vision_tool_result(tool_result: message::ToolResult) -> Result<Message, MessageError> {
+    let has_image = tool_result
+        .content
+        .iter()
+        .any(|content| matches!(content, message::ToolResultContent::Image(_)));
+    if !has_image {
+        return Ok(Message::from(tool_result));
+    }
+
+    let parts = tool_result
+        .content
+        .into_iter()
+        .map(|content| match content {
+            message::ToolResultContent::Text(text) => Ok(ContentPart::Text { text: text.text }),
+            message::ToolResultContent::Image(image) => Ok(ContentPart::Image {
+                image: image_url(image)?,
+            }),
+        })
+        .collect::<Result<Vec<_>, MessageError>>()?;
+
+    Ok(Message::ToolResult {
+        tool_call_id: tool_result.id,
+        name: None,
+        content: MessageContent::Parts(parts),
+    })
+}
+
+// 根据历史中的工具调用，为缺少名称的工具结果消息补全工具名称
+fn fill_tool_result_names(messages: &mut [Message]) {
+    let mut tool_names = HashMap::new();
+
+    for message in messages {
+        match message {
+            Message::Assistant { tool_calls, .. } => {
+                for call in tool_calls.iter() {
+                    tool_names.insert(call.id.clone(), call.function.name.clone());
+                }
+            }
+            Message::ToolResult {
+                tool_call_id, name, ..
+            } if name.is_none() => {
+                *name = tool_names.get(tool_call_id).cloned();
+            }
+            _ => {}
         }
     }
 }
@@ -543,80 +869,109 @@ impl TryFrom<message::Message> for Vec<Message> {
     // 错误类型
     type Error = MessageError;
 
-    // 转换方法
+    // 转换方法（按非视觉模型转换）
     fn try_from(message: message::Message) -> Result<Self, Self::Error> {
-        match message {
-            // 用户消息
-            message::Message::User { content } => {
-                // 提取工具结果
-                let mut messages = vec![];
-
-                // 收集工具结果消息
-                let tool_results = content
-                    .clone()
-                    .into_iter()
-                    .filter_map(|content| match content {
-                        message::UserContent::ToolResult(tool_result) => {
-                            Some(Message::from(tool_result))
-                        }
-                        _ => None,
-                    })
-                    .collect::<Vec<_>>();
+        convert_message(message, false)
+    }
+}
 
-                // 添加工具结果到消息列表
-                messages.extend(tool_results);
+// 图像的 URL：URL 原样使用，base64 数据转换为 `data:` URL（需要媒体类型）
+fn image_url(image: message::Image) -> Result<String, MessageError> {
+    match image.data {
+        message::DocumentSourceKind::Url(url) => Ok(url),
+        message::DocumentSourceKind::Base64(data) => {
+            let media_type = image.media_type.ok_or_else(|| {
+                MessageError::ConversionError(
+                    "A media type is required to send a base64-encoded image".to_string(),
+                )
+            })?;
+            Ok(format!("data:{};base64,{data}", media_type.to_mime_type()))
+        }
+        other => Err(MessageError::ConversionError(format!(
+            "Unsupported image source for Qwen: {other:?}"
+        ))),
+    }
+}
 
-                // 提取文本消息
-                let text_messages = content
-                    .into_iter()
-                    .filter_map(|content| match content {
-                        message::UserContent::Text(text) => Some(Message::User {
-                            content: text.text,
-                        }),
-                        _ => None,
-                    })
-                    .collect::<Vec<_>>();
-                
-                // 添加文本消息到消息列表
-                messages.extend(text_messages);
-
-                // 返回消息列表
-                Ok(messages)
-            }
-            // 助手消息
-            message::Message::Assistant { content, .. } => {
-                let mut messages = vec![];
-                let mut text_content = String::new();
-                let mut tool_calls = vec![];
-
-                // 遍历内容
-                for item in content {
-                    match item {
-                        // 文本内容
-                        completion::AssistantContent::Text(text) => {
-                            text_content.push_str(&text.text);
-                        }
-                        // 工具调用
-                        completion::AssistantContent::ToolCall(call) => {
-                            tool_calls.push(ToolCall::from(call));
-                        }
-                        // 推理内容（暂不处理）
-                        _ => {}
+// 将通用消息转换为通义千问消息，`vision` 为真时图像工具结果保留为内容数组
+fn convert_message(message: message::Message, vision: bool) -> Result<Vec<Message>, MessageError> {
+    match message {
+        // 用户消息
+        message::Message::User { content } => {
+            let mut messages = vec![];
+
+            // 按原顺序转换内容：工具结果各自成为一条工具消息，
+            // 连续的文本部分合并为一条用户消息（部分 Qwen 模型难以处理连续的多条用户消息）。
+            // 文档（如 RAG 检索到的上下文）按文本处理
+            for item in content {
+                let text = match item {
+                    message::UserContent::ToolResult(tool_result) if vision => {
+                        messages.push(vision_tool_result(tool_result)?);
+                        continue;
+                    }
+                    message::UserContent::ToolResult(tool_result) => {
+                        messages.push(Message::from(tool_result));
+                        continue;
+                    }
+                    message::UserContent::Text(text) => text.text,
+                    message::UserContent::Document(message::Document {
+                        data: message::DocumentSourceKind::String(text),
+                        ..
+                    }) => text,
+                    // base64 或 URL 文档无法按文本发送
+                    message::UserContent::Document(message::Document { data, .. }) => {
+                        return Err(MessageError::ConversionError(format!(
+                            "Unsupported document source for Qwen: {data:?}"
+                        )));
                     }
+                    _ => continue,
+                };
+
+                match messages.last_mut() {
+                    Some(Message::User { content }) => {
+                        content.push('\n');
+                        content.push_str(&text);
+                    }
+                    _ => messages.push(Message::User { content: text }),
                 }
+            }
 
-                // 如果有内容或工具调用，添加助手消息
-                if !text_content.is_empty() || !tool_calls.is_empty() {
-                    messages.push(Message::Assistant {
-                        content: text_content,
-                        reasoning_content: None,
-                        tool_calls,
-                    });
+            // 返回消息列表
+            Ok(messages)
+        }
+        // 助手消息
+        message::Message::Assistant { content, .. } => {
+            let mut messages = vec![];
+            let mut text_content = String::new();
+            let mut tool_calls = vec![];
+
+            // 遍历内容
+            for item in content {
+                match item {
+                    // 文本内容
+                    completion::AssistantContent::Text(text) => {
+                        text_content.push_str(&text.text);
+                    }
+                    // 工具调用
+                    completion::AssistantContent::ToolCall(call) => {
+                        tool_calls.push(ToolCall::from(call));
+                    }
+                    // 推理内容（暂不处理）
+                    _ => {}
                 }
+            }
 
-                // 返回消息列表
-                Ok(messages)
+            // 如果有内容或工具调用，添加助手消息
+            if !text_content.is_empty() || !tool_calls.is_empty() {
+                messages.push(Message::Assistant {
+                    content: text_content,
+                    reasoning_content: None,
+                    tool_calls,
+                });
             }
+
+            // 返回消息列表
+            Ok(messages)
         }
     }
 }
@@ -624,7 +979,8 @@ impl TryFrom<message::Message> for Vec<Message> {
 // 工具调用结构体
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ToolCall {
-    // 工具调用 ID
+    // 工具调用 ID（接口可能省略，见 `tool_call_id`）
+    #[serde(default)]
     pub id: String,
     // 工具调用索引
     pub index: usize,
@@ -676,6 +1032,30 @@ impl From<crate::completion::ToolDefinition> for ToolDefinition {
     }
 }
 
+// 将 Rig 的工具选择转换为 DashScope 的 `tool_choice`
+// DashScope 只支持 "auto"、"none" 以及强制调用单个函数：要求调用工具时若只有一个候选工具
+// （如提取器的 `submit` 工具），则强制调用该函数，从而得到符合其 JSON Schema 的结构化输出；
+// 多个候选工具无法强制，退回 "auto"
+fn convert_tool_choice(
+    tool_choice: &message::ToolChoice,
+    tools: &[crate::completion::ToolDefinition],
+) -> serde_json::Value {
+    let forced = |name: &str| json!({"type": "function", "function": {"name": name}});
+
+    match tool_choice {
+        message::ToolChoice::Auto => json!("auto"),
+        message::ToolChoice::None => json!("none"),
+        message::ToolChoice::Required => match tools {
+            [tool] => forced(&tool.name),
+            _ => json!("auto"),
+        },
+        message::ToolChoice::Specific { function_names } => match function_names.as_slice() {
+            [name] => forced(name),
+            _ => json!("auto"),
+        },
+    }
+}
+
 // 为 CompletionResponse 实现转换到 completion::CompletionResponse
 impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionResponse> {
     // 错误类型
@@ -683,10 +1063,18 @@ impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionRe
 
     // 转换方法
     fn try_from(response: CompletionResponse) -> Result<Self, Self::Error> {
-        // 获取第一个选择
-        let choice = response.output.choices.first().ok_or_else(|| {
-            CompletionError::ResponseError("Response contained no choices".to_owned())
-        })?;
+        // text 格式的响应只包含 output.text
+        let Some(choice) = response.output.choices.first() else {
+            let text = response.output.text.as_deref().ok_or_else(|| {
+                CompletionError::ResponseError("Response contained no choices".to_owned())
+            })?;
+
+            return Ok(completion::CompletionResponse {
+                choice: crate::OneOrMany::one(completion::AssistantContent::text(text)),
+                usage: response.usage.to_completion_usage(),
+                raw_response: response,
+            });
+        };
 
         // 提取内容
         let content = match &choice.message {
@@ -699,12 +1087,12 @@ impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionRe
                 let mut result = vec![];
 
                 // 添加推理内容（如果有）
-                if let Some(reasoning) = reasoning_content {
-                    if !reasoning.is_empty() {
-                        result.push(completion::AssistantContent::Reasoning(
-                            message::Reasoning::new(reasoning)
-                        ));
-                    }
+                if let Some(reasoning) = reasoning_content
+                    && !reasoning.is_empty()
+                {
+                    result.push(completion::AssistantContent::Reasoning(
+                        message::Reasoning::new(reasoning)
+                    ));
                 }
 
                 // 添加文本内容
@@ -718,7 +1106,7 @@ impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionRe
                         .iter()
                         .map(|call| {
                             completion::AssistantContent::tool_call(
-                                &call.id,
+                                tool_call_id(Some(&call.id), call.index),
                                 &call.function.name,
                                 call.function.arguments.clone(),
                             )
@@ -734,11 +1122,7 @@ impl TryFrom<CompletionResponse> for completion::CompletionResponse<CompletionRe
         }?;
 
         // 构建使用情况统计
-        let usage = completion::Usage {
-            input_tokens: response.usage.input_tokens as u64,
-            output_tokens: response.usage.output_tokens as u64,
-            total_tokens: response.usage.total_tokens as u64,
-        };
+        let usage = response.usage.to_completion_usage();
 
         // 返回完成响应
         Ok(completion::CompletionResponse {
@@ -759,6 +1143,14 @@ pub struct CompletionModel<T = reqwest::Client> {
     pub client: Client<T>,
     // 模型名称
     pub model: String,
+    // 完成接口路径（相对于基础 URL）
+    pub endpoint_path: String,
+    // 模型级默认参数（合并到每个请求的 parameters 中）
+    pub default_params: Option<serde_json::Value>,
+    // 是否启用联网搜索（None 表示不写入该参数）
+    pub enable_search: Option<bool>,
+    // 返回结果的格式（写入 parameters.result_format）
+    pub result_format: ResultFormat,
 }
 
 // CompletionModel 的实现
@@ -766,6 +1158,148 @@ impl<T> CompletionModel<T>
 where
     T: HttpClientExt + Clone + std::fmt::Debug + Default + Send + 'static,
 {
+    /// 使用默认的 `text-generation/generation` 路径创建完成模型
+    pub fn new(client: Client<T>, model: impl Into<String>) -> Self {
+        Self {
+            client,
+            model: model.into(),
+            endpoint_path: QWEN_COMPLETION_PATH.to_string(),
+            default_params: None,
+            enable_search: None,
+            result_format: ResultFormat::default(),
+        }
+    }
+
+    /// 覆盖 `completion()` 和 `stream()` 请求的接口路径
+    ///
+    /// 适用于部署在其他 DashScope 路径下的模型，例如多模态生成：
+    /// ```
+    /// use rig::client::CompletionClient;
+    /// use rig::providers::qwen;
+    ///
+    /// let client = qwen::Client::new_with_api_key("your-api-key");
+    /// let model = client
+    ///     .completion_model("qwen-vl-plus")
+    ///     .with_endpoint_path("multimodal-generation/generation");
+    /// ```
+    pub fn with_endpoint_path(mut self, endpoint_path: impl Into<String>) -> Self {
+        self.endpoint_path = endpoint_path.into();
+        self
+    }
+
+    /// 设置模型级默认参数，合并到每个请求的 `parameters` 中
+    ///
+    /// 请求自身的温度、最大令牌数以及 `additional_params` 优先于这里的默认值：
+    /// ```
+    /// use rig::client::CompletionClient;
+    /// use rig::providers::qwen;
+    /// use serde_json::json;
+    ///
+    /// let client = qwen::Client::new_with_api_key("your-api-key");
+    /// let model = client
+    ///     .completion_model(qwen::QWEN_PLUS)
+    ///     .default_params(json!({"enable_search": true}));
+    /// ```
+    pub fn default_params(mut self, params: serde_json::Value) -> Self {
+        self.default_params = Some(params);
+        self
+    }
+
+    /// 启用或关闭联网搜索（写入 `parameters.enable_search`）
+    ///
+    /// 启用后 qwen-plus 等模型可以用网络搜索结果增强回答。API 返回的搜索来源
+    /// 保存在原始响应的 [`Output::search_info`] 中（需要同时通过 `default_params`
+    /// 或 `additional_params` 设置 `search_options.enable_source`）。
+    pub fn enable_search(mut self, enable_search: bool) -> Self {
+        self.enable_search = Some(enable_search);
+        self
+    }
+
+    /// 设置返回结果的格式（默认为 [`ResultFormat::Message`]）
+    ///
+    /// 仅支持 `"text"` 格式的旧版文本模型需要设置为 [`ResultFormat::Text`]，
+    /// 此时响应从 `output.text` 中读取。
+    pub fn result_format(mut self, result_format: ResultFormat) -> Self {
+        self.result_format = result_format;
+        self
+    }
+
+    // 构建指向完成接口的 POST 请求
+    fn post_completion(&self) -> http_client::Result<http_client::Builder> {
+        self.client.post(&self.endpoint_path)
+    }
+
+    // 创建或获取追踪 span，并记录请求中实际使用的采样参数
+    fn completion_span(
+        &self,
+        // 是否为流式请求
+        streaming: bool,
+        // 前言
+        preamble: Option<String>,
+        // 已构建的请求体
+        request: &serde_json::Value,
+    ) -> tracing::Span {
+        let input_messages =
+            serde_json::to_string(&request.get("input").and_then(|v| v.get("messages")))
+                .unwrap_or_default();
+
+        let span = if !tracing::Span::current().is_disabled() {
+            // 使用当前 span
+            tracing::Span::current()
+        } else if streaming {
+            // 创建新的流式信息 span
+            info_span!(
+                target: "rig::completions",
+                "chat_streaming",
+                gen_ai.operation.name = "chat_streaming",
+                gen_ai.provider.name = "qwen",
+                gen_ai.request.model = self.model,
+                gen_ai.request.temperature = tracing::field::Empty,
+                gen_ai.request.max_tokens = tracing::field::Empty,
+                gen_ai.request.top_p = tracing::field::Empty,
+                gen_ai.system_instructions = preamble,
+                gen_ai.response.id = tracing::field::Empty,
+                gen_ai.usage.output_tokens = tracing::field::Empty,
+                gen_ai.usage.input_tokens = tracing::field::Empty,
+                gen_ai.input.messages = input_messages,
+                gen_ai.output.messages = tracing::field::Empty,
+            )
+        } else {
+            // 创建新的信息 span
+            info_span!(
+                target: "rig::completions",
+                "chat",
+                gen_ai.operation.name = "chat",
+                gen_ai.provider.name = "qwen",
+                gen_ai.request.model = self.model,
+                gen_ai.request.temperature = tracing::field::Empty,
+                gen_ai.request.max_tokens = tracing::field::Empty,
+                gen_ai.request.top_p = tracing::field::Empty,
+                gen_ai.system_instructions = preamble,
+                gen_ai.response.id = tracing::field::Empty,
+                gen_ai.usage.output_tokens = tracing::field::Empty,
+                gen_ai.usage.input_tokens = tracing::field::Empty,
+                gen_ai.input.messages = input_messages,
+                gen_ai.output.messages = tracing::field::Empty,
+            )
+        };
+
+        // 记录合并后的采样参数（包括 additional_params 中的设置）
+        let parameters = request.get("parameters");
+        let param = |name: &str| parameters.and_then(|p| p.get(name));
+        if let Some(temperature) = param("temperature").and_then(|v| v.as_f64()) {
+            span.record("gen_ai.request.temperature", temperature);
+        }
+        if let Some(max_tokens) = param("max_tokens").and_then(|v| v.as_u64()) {
+            span.record("gen_ai.request.max_tokens", max_tokens);
+        }
+        if let Some(top_p) = param("top_p").and_then(|v| v.as_f64()) {
+            span.record("gen_ai.request.top_p", top_p);
+        }
+
+        span
+    }
+
     // 创建完成请求
     fn create_completion_request(
         &self,
@@ -788,16 +1322,18 @@ where
             .preamble
             .map_or_else(Vec::new, |preamble| vec![Message::system(&preamble)]);
 
-        // 转换并扩展剩余的历史记录
+        // 转换并扩展剩余的历史记录（视觉模型保留图像工具结果）
+        let vision = is_vision_model(&self.model);
         full_history.extend(
             partial_history
                 .into_iter()
-                .map(message::Message::try_into)
+                .map(|message| convert_message(message, vision))
                 .collect::<Result<Vec<Vec<Message>>, _>>()?
                 .into_iter()
                 .flatten()
                 .collect::<Vec<_>>(),
         );
+        fill_tool_result_names(&mut full_history);
 
         // 构建基础请求
         let mut request = json!({
@@ -806,15 +1342,36 @@ where
                 "messages": full_history
             },
             "parameters": {
-                "result_format": "message"
+                "result_format": self.result_format
             }
         });
 
+        // 合并模型级默认参数（优先级低于请求中的任何设置）
+        if let Some(defaults) = &self.default_params {
+            json_utils::merge_inplace(&mut request["parameters"], defaults.clone());
+        }
+
+        // 联网搜索开关
+        if let Some(enable_search) = self.enable_search {
+            request["parameters"]["enable_search"] = json!(enable_search);
+        }
+
         // 添加温度参数（如果有）
         if let Some(temperature) = completion_request.temperature {
             request["parameters"]["temperature"] = json!(temperature);
         }
 
+        // 添加最大生成令牌数（如果有）
+        if let Some(max_tokens) = completion_request.max_tokens {
+            request["parameters"]["max_tokens"] = json!(max_tokens);
+        }
+
+        // 添加工具选择（需在工具被转换前根据工具列表确定）
+        if let Some(tool_choice) = &completion_request.tool_choice {
+            request["parameters"]["tool_choice"] =
+                convert_tool_choice(tool_choice, &completion_request.tools);
+        }
+
         // 添加工具（如果有）
         if !completion_request.tools.is_empty() {
             request["parameters"]["tools"] = json!(
@@ -847,6 +1404,13 @@ where
     type Response = CompletionResponse;
     // 流式响应类型
     type StreamingResponse = StreamingCompletionResponse;
+    // 客户端类型
+    type Client = Client<T>;
+
+    // 使用给定的模型名称创建完成模型
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        Self::new(client.clone(), model)
+    }
 
     // 支持 worker 特性
     #[cfg_attr(feature = "worker", worker::send)]
@@ -867,25 +1431,7 @@ where
         let request = self.create_completion_request(completion_request)?;
 
         // 创建或获取追踪 span
-        let span = if tracing::Span::current().is_disabled() {
-            // 创建新的信息 span
-            info_span!(
-                target: "rig::completions",
-                "chat",
-                gen_ai.operation.name = "chat",
-                gen_ai.provider.name = "qwen",
-                gen_ai.request.model = self.model,
-                gen_ai.system_instructions = preamble,
-                gen_ai.response.id = tracing::field::Empty,
-                gen_ai.usage.output_tokens = tracing::field::Empty,
-                gen_ai.usage.input_tokens = tracing::field::Empty,
-                gen_ai.input.messages = serde_json::to_string(&request.get("input").and_then(|v| v.get("messages"))).unwrap_or_default(),
-                gen_ai.output.messages = tracing::field::Empty,
-            )
-        } else {
-            // 使用当前 span
-            tracing::Span::current()
-        };
+        let span = self.completion_span(false, preamble, &request);
 
         // 记录调试信息
         tracing::debug!("Qwen completion request: {request:?}");
@@ -897,14 +1443,19 @@ where
                 .map_err(|e| CompletionError::ResponseError(e.to_string()))?;
 
             // 构建请求
-            let req = self.client
-                .post("text-generation/generation")?
+            let req = self
+                .post_completion()?
                 .header("Content-Type", "application/json")
                 .body(body)
                 .map_err(|e| CompletionError::ResponseError(e.to_string()))?;
 
             // 发送请求
-            let response = self.client.http_client.send::<_, Vec<u8>>(req).await?;
+            let response = self
+                .client
+                .http_client
+                .send::<_, Vec<u8>>(req)
+                .await
+                .map_err(map_http_error)?;
 
             // 检查响应状态
             if response.status().is_success() {
@@ -938,7 +1489,8 @@ where
                 api_response.try_into()
             } else {
                 // 返回提供商错误
-                Err(CompletionError::ProviderError(http_client::text(response).await?))
+                let status = response.status();
+                Err(provider_error(status, &http_client::text(response).await?))
             }
         }
         // 应用追踪工具
@@ -977,46 +1529,278 @@ where
             .map_err(|e| CompletionError::ResponseError(e.to_string()))?;
 
         // 构建 HTTP 请求
-        let req = self.client
-            .post("text-generation/generation")?
+        let req = self
+            .post_completion()?
             .header("Content-Type", "application/json")
             .header("X-DashScope-SSE", "enable")
             .body(body)
             .map_err(|e| CompletionError::ResponseError(e.to_string()))?;
 
         // 创建或获取追踪 span
-        let span = if tracing::Span::current().is_disabled() {
-            // 创建新的信息 span
-            info_span!(
-                target: "rig::completions",
-                "chat_streaming",
-                gen_ai.operation.name = "chat_streaming",
-                gen_ai.provider.name = "qwen",
-                gen_ai.request.model = self.model,
-                gen_ai.system_instructions = preamble,
-                gen_ai.response.id = tracing::field::Empty,
-                gen_ai.usage.output_tokens = tracing::field::Empty,
-                gen_ai.usage.input_tokens = tracing::field::Empty,
-                gen_ai.input.messages = serde_json::to_string(&request.get("input").and_then(|v| v.get("messages"))).unwrap_or_default(),
-                gen_ai.output.messages = tracing::field::Empty,
-            )
-        } else {
-            // 使用当前 span
-            tracing::Span::current()
-        };
+        let span = self.completion_span(true, preamble, &request);
 
         // 使用追踪工具发送流式请求
         tracing::Instrument::instrument(send_qwen_streaming_request(self.client.http_client.clone(), req), span).await
     }
+
+    // 从响应中读取结束原因（"length" 表示输出被截断）
+    fn finish_reason(response: &CompletionResponse) -> Option<completion::FinishReason> {
+        let reason = match response.output.choices.first() {
+            Some(choice) => Some(choice.finish_reason.as_str()),
+            None => response.output.finish_reason.as_deref(),
+        };
+        parse_finish_reason(reason)
+    }
+
+    // 从流式最终响应中读取结束原因
+    fn streaming_finish_reason(
+        response: &StreamingCompletionResponse,
+    ) -> Option<completion::FinishReason> {
+        parse_finish_reason(response.finish_reason.as_deref())
+    }
+}
+
+// 解析结束原因，DashScope 在生成过程中返回字符串 "null"
+fn parse_finish_reason(reason: Option<&str>) -> Option<completion::FinishReason> {
+    reason
+        .filter(|reason| !reason.is_empty() && *reason != "null")
+        .map(completion::FinishReason::from)
 }
 
 // ================================================================
-// 流式处理
+// 通义千问嵌入 API
 // ================================================================
 
-// 流式工具调用结构体
-#[derive(Deserialize, Debug, Clone)]
-pub struct StreamingToolCall {
+/// `text-embedding-v1` 嵌入模型（1536 维）
+pub const TEXT_EMBEDDING_V1: &str = "text-embedding-v1";
+/// `text-embedding-v2` 嵌入模型（1536 维）
+pub const TEXT_EMBEDDING_V2: &str = "text-embedding-v2";
+/// `text-embedding-v3` 嵌入模型（默认 1024 维）
+pub const TEXT_EMBEDDING_V3: &str = "text-embedding-v3";
+/// `text-embedding-v4` 嵌入模型（默认 1024 维）
+pub const TEXT_EMBEDDING_V4: &str = "text-embedding-v4";
+
+// 嵌入接口路径（相对于 DashScope 的 services 根路径，而不是 aigc 路径）
+const QWEN_EMBEDDING_PATH: &str = "embeddings/text-embedding/text-embedding";
+
+// 根据模型名称推断嵌入维度
+fn embedding_dimensions_from_identifier(identifier: &str) -> Option<usize> {
+    match identifier {
+        TEXT_EMBEDDING_V1 | TEXT_EMBEDDING_V2 => Some(1_536),
+        TEXT_EMBEDDING_V3 | TEXT_EMBEDDING_V4 => Some(1_024),
+        _ => None,
+    }
+}
+
+// 根据模型名称推断单次请求允许的最大文本数（v3 及以后为 10，早期模型为 25）
+fn embedding_batch_size_from_identifier(identifier: &str) -> usize {
+    match identifier {
+        TEXT_EMBEDDING_V1 | TEXT_EMBEDDING_V2 => 25,
+        _ => 10,
+    }
+}
+
+// 嵌入响应结构体
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingResponse {
+    // 请求 ID
+    #[serde(default)]
+    pub request_id: Option<String>,
+    // 输出结果
+    pub output: EmbeddingOutput,
+    // 使用情况统计
+    #[serde(default)]
+    pub usage: EmbeddingUsage,
+}
+
+// 嵌入输出结构体
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingOutput {
+    // 嵌入结果列表（不保证与输入顺序一致，需按 text_index 排序）
+    pub embeddings: Vec<EmbeddingData>,
+}
+
+// 单条嵌入结果结构体
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingData {
+    // 对应输入文本在本批次中的索引
+    pub text_index: usize,
+    // 嵌入向量
+    pub embedding: Vec<f64>,
+}
+
+// 嵌入使用情况统计（多个批次时为累加值）
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct EmbeddingUsage {
+    // 总令牌数
+    #[serde(default)]
+    pub total_tokens: usize,
+}
+
+// 通义千问嵌入模型结构体
+#[derive(Clone, Debug)]
+pub struct EmbeddingModel<T = reqwest::Client> {
+    // 客户端
+    client: Client<T>,
+    // 模型名称
+    pub model: String,
+    // 嵌入维度（0 表示使用模型默认值）
+    ndims: usize,
+    // 单次请求的最大文本数，超过时自动分批
+    batch_size: usize,
+}
+
+impl<T> EmbeddingModel<T>
+where
+    T: HttpClientExt + Clone + std::fmt::Debug + Default + Send + 'static,
+{
+    /// 创建嵌入模型，单次请求的最大文本数根据模型名称推断
+    pub fn new(client: Client<T>, model: impl Into<String>, ndims: usize) -> Self {
+        let model = model.into();
+        Self {
+            client,
+            batch_size: embedding_batch_size_from_identifier(&model),
+            model,
+            ndims,
+        }
+    }
+
+    /// 覆盖单次请求的最大文本数（DashScope 对不同模型的限制不同）
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// 嵌入任意数量的文本，并返回所有批次累加的使用情况
+    ///
+    /// 输入会被拆分为不超过 `batch_size` 的批次依次请求，结果按输入顺序拼接。
+    pub async fn embed_texts_with_usage(
+        &self,
+        documents: Vec<String>,
+    ) -> Result<(Vec<embeddings::Embedding>, EmbeddingUsage), EmbeddingError> {
+        let mut results = Vec::with_capacity(documents.len());
+        let mut usage = EmbeddingUsage::default();
+
+        for batch in documents.chunks(self.batch_size) {
+            let response = self.embed_batch(batch).await?;
+            usage.total_tokens += response.usage.total_tokens;
+
+            // 按 text_index 排序，恢复本批次的输入顺序
+            let mut data = response.output.embeddings;
+            data.sort_by_key(|embedding| embedding.text_index);
+
+            if data.len() != batch.len()
+                || data
+                    .iter()
+                    .enumerate()
+                    .any(|(i, embedding)| embedding.text_index != i)
+            {
+                return Err(EmbeddingError::ResponseError(
+                    "Response embeddings do not match the input texts".into(),
+                ));
+            }
+
+            results.extend(data.into_iter().zip(batch).map(|(embedding, document)| {
+                embeddings::Embedding {
+                    document: document.clone(),
+                    vec: embedding.embedding,
+                }
+            }));
+        }
+
+        tracing::info!(target: "rig", "Qwen embedding token usage: {:?}", usage);
+
+        Ok((results, usage))
+    }
+
+    // 嵌入接口的完整 URL（由 aigc 基础 URL 推导出 services 根路径）
+    fn embedding_url(&self) -> String {
+        let base_url = self.client.base_url.trim_end_matches('/');
+        let services_root = base_url.strip_suffix("/aigc").unwrap_or(base_url);
+        format!("{services_root}/{QWEN_EMBEDDING_PATH}")
+    }
+
+    // 发送单个批次的嵌入请求
+    async fn embed_batch(&self, batch: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let mut body = json!({
+            "model": self.model,
+            "input": {
+                "texts": batch
+            },
+            "parameters": {}
+        });
+
+        // 设置输出维度（仅 v3 及以后的模型支持）
+        if self.ndims > 0 && !matches!(self.model.as_str(), TEXT_EMBEDDING_V1 | TEXT_EMBEDDING_V2) {
+            body["parameters"]["dimension"] = json!(self.ndims);
+        }
+
+        let req = http_client::with_bearer_auth(
+            http_client::Request::builder()
+                .method(http_client::Method::POST)
+                .uri(self.embedding_url()),
+            &self.client.api_key,
+        )?
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(&body)?)
+        .map_err(|e| EmbeddingError::HttpError(e.into()))?;
+
+        let response = self.client.http_client.send::<_, Vec<u8>>(req).await?;
+
+        if response.status().is_success() {
+            let body: Vec<u8> = response.into_body().await?;
+            Ok(serde_json::from_slice(&body)?)
+        } else {
+            Err(EmbeddingError::ProviderError(
+                http_client::text(response).await?,
+            ))
+        }
+    }
+}
+
+// 为 EmbeddingModel 实现 rig 的 EmbeddingModel trait
+impl<T> embeddings::EmbeddingModel for EmbeddingModel<T>
+where
+    T: HttpClientExt + Clone + std::fmt::Debug + Default + Send + 'static,
+{
+    // 模型内部会自动分批，因此这里允许较大的文档数
+    const MAX_DOCUMENTS: usize = 1024;
+
+    type Client = Client<T>;
+
+    fn make(client: &Self::Client, model: impl Into<String>, ndims: Option<usize>) -> Self {
+        let model = model.into();
+        let ndims = ndims
+            .or(embedding_dimensions_from_identifier(&model))
+            .unwrap_or_default();
+
+        Self::new(client.clone(), model, ndims)
+    }
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    #[cfg_attr(feature = "worker", worker::send)]
+    async fn embed_texts(
+        &self,
+        documents: impl IntoIterator<Item = String>,
+    ) -> Result<Vec<embeddings::Embedding>, EmbeddingError> {
+        let (embeddings, _) = self
+            .embed_texts_with_usage(documents.into_iter().collect())
+            .await?;
+
+        Ok(embeddings)
+    }
+}
+
+// ================================================================
+// 流式处理
+// ================================================================
+
+// 流式工具调用结构体
+#[derive(Deserialize, Debug, Clone)]
+pub struct StreamingToolCall {
     // 工具调用 ID（可选）
     pub id: Option<String>,
     // 工具调用索引
@@ -1044,7 +1828,6 @@ struct StreamingChoice {
     // 消息内容（通义千问使用 message 而不是 delta）
     message: StreamingMessage,
     // 结束原因（可选）
-    #[allow(dead_code)]
     finish_reason: Option<String>,
 }
 
@@ -1068,6 +1851,9 @@ struct StreamingMessage {
 // 流式完成块结构体
 #[derive(Deserialize, Debug)]
 struct StreamingCompletionChunk {
+    // 请求 ID（DashScope 在每个 SSE 块中都会返回）
+    #[serde(default)]
+    request_id: Option<String>,
     // 输出结果
     output: StreamingOutput,
     // 使用情况统计（可选）
@@ -1077,17 +1863,61 @@ struct StreamingCompletionChunk {
 // 流式输出结构体
 #[derive(Deserialize, Debug)]
 struct StreamingOutput {
-    // 选择列表
+    // 选择列表（result_format 为 "message" 时返回）
+    #[serde(default)]
     choices: Vec<StreamingChoice>,
+    // 生成的文本（result_format 为 "text" 时返回）
+    #[serde(default)]
+    text: Option<String>,
+    // 结束原因（result_format 为 "text" 时返回）
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+impl StreamingOutput {
+    // 将 text 格式的块转换为等价的助手消息选择，使后续处理只需关注 choices
+    fn normalize(&mut self) {
+        if !self.choices.is_empty() {
+            return;
+        }
+
+        if let Some(text) = self.text.take() {
+            self.choices.push(StreamingChoice {
+                message: StreamingMessage {
+                    role: "assistant".to_string(),
+                    content: Some(text),
+                    reasoning_content: None,
+                    tool_calls: vec![],
+                },
+                finish_reason: self.finish_reason.take(),
+            });
+        }
+    }
 }
 
 // 流式完成响应结构体
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct StreamingCompletionResponse {
+    // 请求 ID（可用于向 DashScope 技术支持反馈问题时定位请求）
+    #[serde(default)]
+    pub request_id: Option<String>,
+    // 累积的完整推理内容（QwQ 等思考模型），可用于在后续轮次中重建历史
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+    // 结束原因（最后一个非 "null" 的 finish_reason，"length" 表示输出被截断）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
     // 使用情况统计
     pub usage: Usage,
 }
 
+impl StreamingCompletionResponse {
+    // 将累积的推理内容转换为通用的推理消息内容，便于追加到 chat_history
+    pub fn reasoning(&self) -> Option<message::Reasoning> {
+        self.reasoning_content.as_deref().map(message::Reasoning::new)
+    }
+}
+
 // 为 StreamingCompletionResponse 实现 GetTokenUsage trait
 impl GetTokenUsage for StreamingCompletionResponse {
     // 获取令牌使用情况
@@ -1106,6 +1936,73 @@ impl GetTokenUsage for StreamingCompletionResponse {
     }
 }
 
+// 确定工具调用 ID：接口省略 ID 或返回空 ID 时，按调用索引生成 `call_{index}`
+// 流式与非流式响应共用同一规则，保证工具结果总能通过 ID 与对应的工具调用关联
+fn tool_call_id(id: Option<&str>, index: usize) -> String {
+    match id {
+        Some(id) if !id.is_empty() => id.to_string(),
+        _ => format!("call_{index}"),
+    }
+}
+
+// 解析流式累积的工具调用参数
+// 空参数视为 `{}`；无法解析时尝试尽力修复（去除尾随逗号），仍失败则返回带原始参数的错误
+fn parse_tool_arguments(
+    id: &str,
+    name: &str,
+    arguments: &str,
+) -> Result<serde_json::Value, CompletionError> {
+    // 无参数工具的参数可能为空
+    if arguments.trim().is_empty() {
+        return Ok(json!({}));
+    }
+
+    let err = match serde_json::from_str(arguments) {
+        Ok(arguments_json) => return Ok(arguments_json),
+        Err(err) => err,
+    };
+
+    // 尽力修复后重试
+    if let Ok(arguments_json) = serde_json::from_str(&strip_trailing_commas(arguments)) {
+        tracing::warn!(id, name, arguments, "Repaired malformed tool call arguments: {err}");
+        return Ok(arguments_json);
+    }
+
+    tracing::warn!(id, name, arguments, "Couldn't parse tool call arguments: {err}");
+    Err(CompletionError::ResponseError(format!(
+        "Couldn't parse the arguments of tool call `{name}` ({id}): {err}. Raw arguments: {arguments}"
+    )))
+}
+
+// 去除 JSON 中 `}` 或 `]` 之前的尾随逗号（忽略字符串内的内容）
+fn strip_trailing_commas(json: &str) -> String {
+    let mut repaired = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in json.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == '}' || c == ']' {
+            let end = repaired.trim_end().len();
+            if repaired[..end].ends_with(',') {
+                repaired.remove(end - 1);
+            }
+        }
+        repaired.push(c);
+    }
+
+    repaired
+}
+
 // 发送通义千问流式请求
 pub async fn send_qwen_streaming_request<T>(
     // HTTP 客户端
@@ -1136,12 +2033,16 @@ where
     let stream = Box::pin(stream! {
         // 初始化最终使用情况统计
         let mut final_usage = Usage::new();
+        // 初始化请求 ID
+        let mut request_id: Option<String> = None;
         // 初始化文本响应累积器
         let mut text_response = String::new();
         // 初始化推理内容累积器
         let mut reasoning_response = String::new();
         // 初始化工具调用映射（索引 -> (ID, 名称, 参数)）
         let mut calls: HashMap<usize, (String, String, String)> = HashMap::new();
+        // 初始化结束原因
+        let mut finish_reason: Option<String> = None;
 
         // 循环处理 SSE 事件
         while let Some(event_result) = event_source.next().await {
@@ -1162,7 +2063,7 @@ where
 
                     // 解析流式完成块
                     let parsed = serde_json::from_str::<StreamingCompletionChunk>(&message.data);
-                    let Ok(data) = parsed else {
+                    let Ok(mut data) = parsed else {
                         // 解析失败，记录调试信息并继续
                         let err = parsed.unwrap_err();
                         tracing::warn!("Couldn't parse SSE payload: {}. Data: {}", err, message.data);
@@ -1170,40 +2071,54 @@ where
                     };
                     
                     tracing::debug!("Successfully parsed streaming chunk");
+                    data.output.normalize();
+
+                    // 首次收到请求 ID 时记录到 span
+                    if request_id.is_none()
+                        && let Some(id) = &data.request_id
+                    {
+                        span.record("gen_ai.response.id", id.as_str());
+                        request_id = Some(id.clone());
+                    }
 
                     // 处理第一个选择
                     if let Some(choice) = data.output.choices.first() {
                         let message = &choice.message;
 
+                        // 记录结束原因（生成过程中为 "null"）
+                        if let Some(reason) = choice.finish_reason.as_deref().filter(|reason| *reason != "null") {
+                            finish_reason = Some(reason.to_string());
+                        }
+
                         // 处理推理内容（QwQ 等思考模型）
-                        if let Some(reasoning) = &message.reasoning_content {
-                            if !reasoning.is_empty() {
-                                // 计算增量推理内容（incremental_output 模式下，API 返回累积文本）
-                                let incremental_reasoning = if reasoning.len() >= reasoning_response.len() {
-                                    // 当前推理内容长度 >= 累积长度，说明是累积文本，计算增量
-                                    let incremental = if reasoning.starts_with(&reasoning_response) {
-                                        &reasoning[reasoning_response.len()..]
-                                    } else {
-                                        // 如果内容不匹配，说明可能是新的响应，使用全部内容
-                                        reasoning
-                                    };
-                                    // 更新累积推理内容
-                                    reasoning_response = reasoning.clone();
-                                    incremental
+                        if let Some(reasoning) = &message.reasoning_content
+                            && !reasoning.is_empty()
+                        {
+                            // 计算增量推理内容（incremental_output 模式下，API 返回累积文本）
+                            let incremental_reasoning = if reasoning.len() >= reasoning_response.len() {
+                                // 当前推理内容长度 >= 累积长度，说明是累积文本，计算增量
+                                let incremental = if reasoning.starts_with(&reasoning_response) {
+                                    &reasoning[reasoning_response.len()..]
                                 } else {
-                                    // 当前推理内容长度 < 累积长度，说明这是增量文本片段
-                                    reasoning_response.push_str(reasoning);
+                                    // 如果内容不匹配，说明可能是新的响应，使用全部内容
                                     reasoning
                                 };
-                                
-                                // 只在有增量内容时生成推理内容结果
-                                if !incremental_reasoning.is_empty() {
-                                    yield Ok(crate::streaming::RawStreamingChoice::Reasoning {
-                                        reasoning: incremental_reasoning.to_string(),
-                                        id: None,
-                                        signature: None,
-                                    });
-                                }
+                                // 更新累积推理内容
+                                reasoning_response = reasoning.clone();
+                                incremental
+                            } else {
+                                // 当前推理内容长度 < 累积长度，说明这是增量文本片段
+                                reasoning_response.push_str(reasoning);
+                                reasoning
+                            };
+                            
+                            // 只在有增量内容时生成推理内容结果
+                            if !incremental_reasoning.is_empty() {
+                                yield Ok(crate::streaming::RawStreamingChoice::Reasoning {
+                                    reasoning: incremental_reasoning.to_string(),
+                                    id: None,
+                                    signature: None,
+                                });
                             }
                         }
 
@@ -1217,7 +2132,7 @@ where
                                     && function.arguments.is_empty()
                                 {
                                     // 获取 ID 和名称
-                                    let id = tool_call.id.clone().unwrap_or_default();
+                                    let id = tool_call_id(tool_call.id.as_deref(), tool_call.index);
                                     let name = function.name.clone().unwrap();
                                     // 插入到工具调用映射
                                     calls.insert(tool_call.index, (id, name, String::new()));
@@ -1257,20 +2172,24 @@ where
                                         }
                                         
                                         // 尝试从 ID 或索引创建工具调用映射
-                                        let id = tool_call.id.clone().unwrap_or_else(|| format!("call_{}", tool_call.index));
+                                        let id = tool_call_id(tool_call.id.as_deref(), tool_call.index);
                                         let name = function.name.clone().unwrap_or_else(|| String::from("unknown"));
                                         calls.insert(tool_call.index, (id, name, function.arguments.clone()));
                                     }
                                 }
                                 // 完整的工具调用（有 ID、函数名和完整参数）
                                 else if let (Some(id), Some(name)) = (&tool_call.id, &function.name) {
+                                    let id = &tool_call_id(Some(id), tool_call.index);
                                     // 获取参数
                                     let arguments_str = function.arguments.clone();
 
-                                    // 解析参数 JSON
-                                    let Ok(arguments_json) = serde_json::from_str::<serde_json::Value>(&arguments_str) else {
-                                        tracing::debug!("Couldn't parse tool call args '{}'", arguments_str);
-                                        continue;
+                                    // 解析参数 JSON（失败时向调用方报告错误，而不是静默丢弃）
+                                    let arguments_json = match parse_tool_arguments(id, name, &arguments_str) {
+                                        Ok(arguments_json) => arguments_json,
+                                        Err(err) => {
+                                            yield Err(err);
+                                            continue;
+                                        }
                                     };
 
                                     // 生成工具调用结果
@@ -1285,32 +2204,32 @@ where
                         }
 
                         // 处理文本内容
-                        if let Some(content) = &message.content {
-                            if !content.is_empty() {
-                                // 计算增量文本内容
-                                // 在 incremental_output=true 模式下，通义千问返回累积文本
-                                // 我们需要计算增量：新内容 = 当前累积内容 - 之前累积内容
-                                let incremental_text = if content.len() >= text_response.len() {
-                                    // 当前内容长度 >= 累积长度，说明是累积文本，计算增量
-                                    let incremental = if content.starts_with(&text_response) {
-                                        &content[text_response.len()..]
-                                    } else {
-                                        // 如果内容不匹配，说明可能是新的响应，使用全部内容
-                                        content
-                                    };
-                                    // 更新累积文本
-                                    text_response = content.clone();
-                                    incremental
+                        if let Some(content) = &message.content
+                            && !content.is_empty()
+                        {
+                            // 计算增量文本内容
+                            // 在 incremental_output=true 模式下，通义千问返回累积文本
+                            // 我们需要计算增量：新内容 = 当前累积内容 - 之前累积内容
+                            let incremental_text = if content.len() >= text_response.len() {
+                                // 当前内容长度 >= 累积长度，说明是累积文本，计算增量
+                                let incremental = if content.starts_with(&text_response) {
+                                    &content[text_response.len()..]
                                 } else {
-                                    // 当前内容长度 < 累积长度，说明这是增量文本片段
-                                    text_response.push_str(content);
+                                    // 如果内容不匹配，说明可能是新的响应，使用全部内容
                                     content
                                 };
-                                
-                                // 只在有增量内容时生成消息结果
-                                if !incremental_text.is_empty() {
-                                    yield Ok(crate::streaming::RawStreamingChoice::Message(incremental_text.to_string()));
-                                }
+                                // 更新累积文本
+                                text_response = content.clone();
+                                incremental
+                            } else {
+                                // 当前内容长度 < 累积长度，说明这是增量文本片段
+                                text_response.push_str(content);
+                                content
+                            };
+                            
+                            // 只在有增量内容时生成消息结果
+                            if !incremental_text.is_empty() {
+                                yield Ok(crate::streaming::RawStreamingChoice::Message(incremental_text.to_string()));
                             }
                         }
                     }
@@ -1329,8 +2248,8 @@ where
                 Err(err) => {
                     // 记录错误日志
                     tracing::error!(?err, "SSE error");
-                    // 生成错误结果
-                    yield Err(CompletionError::ResponseError(err.to_string()));
+                    // 生成错误结果（区分传输层错误与服务端错误）
+                    yield Err(map_http_error(err));
                     // 退出循环
                     break;
                 }
@@ -1343,9 +2262,13 @@ where
         let mut tool_calls = Vec::new();
         // 刷新累积的工具调用
         for (index, (id, name, arguments)) in calls {
-            // 解析参数 JSON
-            let Ok(arguments_json) = serde_json::from_str::<serde_json::Value>(&arguments) else {
-                continue;
+            // 解析参数 JSON（失败时向调用方报告错误，而不是静默丢弃）
+            let arguments_json = match parse_tool_arguments(&id, &name, &arguments) {
+                Ok(arguments_json) => arguments_json,
+                Err(err) => {
+                    yield Err(err);
+                    continue;
+                }
             };
 
             // 添加到工具调用列表
@@ -1368,14 +2291,17 @@ where
             });
         }
 
+        // 累积的推理内容（为空时不返回）
+        let reasoning_content = if reasoning_response.is_empty() {
+            None
+        } else {
+            Some(reasoning_response)
+        };
+
         // 构建助手消息
         let message = Message::Assistant {
             content: text_response,
-            reasoning_content: if reasoning_response.is_empty() {
-                None
-            } else {
-                Some(reasoning_response)
-            },
+            reasoning_content: reasoning_content.clone(),
             tool_calls
         };
 
@@ -1384,7 +2310,12 @@ where
 
         // 生成最终响应
         yield Ok(crate::streaming::RawStreamingChoice::FinalResponse(
-            StreamingCompletionResponse { usage: final_usage.clone() }
+            StreamingCompletionResponse {
+                request_id,
+                reasoning_content,
+                finish_reason,
+                usage: final_usage.clone(),
+            }
         ));
     });
 
@@ -1405,7 +2336,7 @@ mod tests {
     // 测试客户端构建器
     #[test]
     fn test_client_builder() {
-        let client = Client::builder("test-api-key")
+        let client = Client::<reqwest::Client>::builder("test-api-key")
             .base_url("https://test.api.com")
             .build()
             .unwrap();
@@ -1413,61 +2344,1861 @@ mod tests {
         assert_eq!(client.base_url, "https://test.api.com");
     }
 
-    // 测试消息序列化
+    // 测试每个请求都携带客户端标识
     #[test]
-    fn test_message_serialization() {
-        let message = Message::User {
-            content: "Hello".to_string(),
+    fn test_user_agent_header() {
+        let user_agent = |client: &Client| {
+            let req = client.post(QWEN_COMPLETION_PATH).unwrap();
+            req.headers_ref().unwrap()[http::header::USER_AGENT]
+                .to_str()
+                .unwrap()
+                .to_string()
         };
 
-        let json = serde_json::to_string(&message).unwrap();
-        assert!(json.contains("user"));
-        assert!(json.contains("Hello"));
+        let client = Client::new_with_api_key("test-api-key");
+        assert_eq!(user_agent(&client), DEFAULT_USER_AGENT);
+        assert!(DEFAULT_USER_AGENT.starts_with("rig-qwen/"));
+
+        let client = Client::<reqwest::Client>::builder("test-api-key")
+            .user_agent("calphamesh-agent/1.2")
+            .build()
+            .unwrap();
+        assert_eq!(user_agent(&client), "calphamesh-agent/1.2");
     }
 
-    // 测试工具调用序列化
+    // 测试已知模型的上下文窗口
     #[test]
-    fn test_tool_call_serialization() {
-        let tool_call = ToolCall {
-            id: "call_123".to_string(),
-            index: 0,
-            r#type: ToolType::Function,
-            function: Function {
-                name: "get_weather".to_string(),
-                arguments: json!({"location": "Beijing"}),
-            },
+    fn test_context_window() {
+        assert_eq!(context_window(QWEN_TURBO), Some(1_000_000));
+        assert_eq!(context_window(QWEN_LONG), Some(10_000_000));
+        assert_eq!(context_window(QWEN_MAX), Some(32_768));
+        assert_eq!(context_window("qwen-unknown"), None);
+    }
+
+    // 测试默认及自定义的完成接口 URL
+    #[test]
+    fn test_completion_endpoint_url() {
+        let client = Client::<reqwest::Client>::builder("test-api-key")
+            .base_url("https://test.api.com")
+            .build()
+            .unwrap();
+
+        let model = client.completion_model(QWEN_PLUS);
+        let req = model.post_completion().unwrap();
+        assert_eq!(
+            req.uri_ref().unwrap().to_string(),
+            "https://test.api.com/text-generation/generation"
+        );
+
+        let model = model.with_endpoint_path("/multimodal-generation/generation");
+        let req = model.post_completion().unwrap();
+        assert_eq!(
+            req.uri_ref().unwrap().to_string(),
+            "https://test.api.com/multimodal-generation/generation"
+        );
+    }
+
+    // 捕获 span 字段的测试 Layer
+    #[derive(Clone, Default)]
+    pub(super) struct SpanFieldCapture(
+        pub(super) std::sync::Arc<std::sync::Mutex<HashMap<String, String>>>,
+    );
+
+    impl tracing::field::Visit for SpanFieldCapture {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanFieldCapture {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            attrs.record(&mut self.clone());
+        }
+
+        fn on_record(
+            &self,
+            _id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    // 测试采样参数被记录到 span 中
+    #[test]
+    fn test_completion_span_records_sampling_params() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let model = Client::new_with_api_key("test-api-key").completion_model(QWEN_PLUS);
+        let request = model
+            .create_completion_request(CompletionRequest {
+                preamble: None,
+                chat_history: crate::OneOrMany::one(message::Message::user("Hello")),
+                documents: vec![],
+                tools: vec![],
+                temperature: Some(0.7),
+                max_tokens: Some(512),
+                tool_choice: None,
+                additional_params: Some(json!({"top_p": 0.8})),
+            })
+            .unwrap();
+
+        for streaming in [false, true] {
+            let capture = SpanFieldCapture::default();
+            let subscriber = tracing_subscriber::registry().with(capture.clone());
+            tracing::subscriber::with_default(subscriber, || {
+                let _span = model.completion_span(streaming, None, &request);
+            });
+
+            let fields = capture.0.lock().unwrap();
+            assert_eq!(fields["gen_ai.request.temperature"], "0.7");
+            assert_eq!(fields["gen_ai.request.max_tokens"], "512");
+            assert_eq!(fields["gen_ai.request.top_p"], "0.8");
+            assert_eq!(fields["gen_ai.request.model"], "\"qwen-plus\"");
+        }
+    }
+
+    // 测试模型级默认参数与请求参数的合并优先级
+    #[test]
+    fn test_default_params_merge_precedence() {
+        let model = Client::new_with_api_key("test-api-key")
+            .completion_model(QWEN_PLUS)
+            .default_params(json!({
+                "enable_search": true,
+                "top_p": 0.5,
+                "temperature": 0.1,
+            }));
+
+        let request = model
+            .create_completion_request(CompletionRequest {
+                preamble: None,
+                chat_history: crate::OneOrMany::one(message::Message::user("Hello")),
+                documents: vec![],
+                tools: vec![],
+                temperature: Some(0.7),
+                max_tokens: None,
+                tool_choice: None,
+                additional_params: Some(json!({"top_p": 0.9})),
+            })
+            .unwrap();
+
+        let parameters = &request["parameters"];
+        // 默认值在未被覆盖时保留
+        assert_eq!(parameters["enable_search"], json!(true));
+        assert_eq!(parameters["result_format"], json!("message"));
+        // 请求字段优先于默认值
+        assert_eq!(parameters["temperature"], json!(0.7));
+        // additional_params 优先于默认值
+        assert_eq!(parameters["top_p"], json!(0.9));
+    }
+
+    // 测试联网搜索参数的序列化
+    #[test]
+    fn test_enable_search_serialization() {
+        let request = |model: &CompletionModel| {
+            model
+                .create_completion_request(CompletionRequest {
+                    preamble: None,
+                    chat_history: crate::OneOrMany::one(message::Message::user("Hello")),
+                    documents: vec![],
+                    tools: vec![],
+                    temperature: None,
+                    max_tokens: None,
+                    tool_choice: None,
+                    additional_params: None,
+                })
+                .unwrap()
         };
 
-        let json = serde_json::to_string(&tool_call).unwrap();
-        assert!(json.contains("call_123"));
-        assert!(json.contains("get_weather"));
+        let model = Client::new_with_api_key("test-api-key").completion_model(QWEN_PLUS);
+        assert!(request(&model)["parameters"].get("enable_search").is_none());
+
+        let model = model.enable_search(true);
+        assert_eq!(request(&model)["parameters"]["enable_search"], json!(true));
+
+        let model = model.enable_search(false);
+        assert_eq!(request(&model)["parameters"]["enable_search"], json!(false));
     }
 
-    // 测试完成响应反序列化
+    // 测试搜索来源信息的反序列化
     #[test]
-    fn test_completion_response_deserialization() {
-        let data = r#"{
-            "status_code": 200,
-            "request_id": "test-request-id",
+    fn test_search_info_deserialization() {
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "request_id": "req-1",
             "output": {
                 "choices": [{
                     "finish_reason": "stop",
-                    "message": {
-                        "role": "assistant",
-                        "content": "你好！"
-                    }
-                }]
+                    "message": {"role": "assistant", "content": "Answer [1]"}
+                }],
+                "search_info": {
+                    "search_results": [{
+                        "index": 1,
+                        "title": "Example",
+                        "url": "https://example.com",
+                        "site_name": "Example Site",
+                        "icon": "https://example.com/icon.png"
+                    }]
+                }
             },
-            "usage": {
-                "input_tokens": 10,
-                "output_tokens": 5,
-                "total_tokens": 15
+            "usage": {"input_tokens": 1, "output_tokens": 2, "total_tokens": 3}
+        }))
+        .unwrap();
+
+        let search_info = response.output.search_info.unwrap();
+        assert_eq!(search_info.search_results.len(), 1);
+        assert_eq!(search_info.search_results[0].index, Some(1));
+        assert_eq!(
+            search_info.search_results[0].url.as_deref(),
+            Some("https://example.com")
+        );
+    }
+
+    // 返回固定 SSE 内容的测试 HTTP 客户端
+    #[derive(Clone)]
+    struct FixtureHttpClient {
+        // SSE 响应体
+        body: &'static str,
+    }
+
+    impl HttpClientExt for FixtureHttpClient {
+        fn send<T, U>(
+            &self,
+            _req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            T: Into<bytes::Bytes>,
+            T: crate::wasm_compat::WasmCompatSend,
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            std::future::ready(Err(http_client::Error::InvalidStatusCode(
+                http::StatusCode::NOT_IMPLEMENTED,
+            )))
+        }
+
+        fn send_multipart<U>(
+            &self,
+            _req: http::Request<reqwest::multipart::Form>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            std::future::ready(Err(http_client::Error::InvalidStatusCode(
+                http::StatusCode::NOT_IMPLEMENTED,
+            )))
+        }
+
+        fn send_streaming<T>(
+            &self,
+            _req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http_client::StreamingResponse>>
+        + crate::wasm_compat::WasmCompatSend
+        where
+            T: Into<bytes::Bytes>,
+        {
+            let body = self.body;
+            async move {
+                let stream: crate::http_client::sse::BoxedStream = Box::pin(futures::stream::iter(
+                    vec![Ok(bytes::Bytes::from_static(body.as_bytes()))],
+                ));
+                http::Response::builder()
+                    .status(http::StatusCode::OK)
+                    .header("Content-Type", "text/event-stream")
+                    .body(stream)
+                    .map_err(http_client::Error::Protocol)
             }
-        }"#;
+        }
+    }
 
-        let response: CompletionResponse = serde_json::from_str(data).unwrap();
-        assert_eq!(response.status_code, 200);
-        assert_eq!(response.usage.input_tokens, 10);
-        assert_eq!(response.usage.output_tokens, 5);
+    // 测试流式响应中的请求 ID 被记录到 span 并返回给调用方
+    #[tokio::test]
+    async fn test_streaming_request_id_recorded() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        const FIXTURE: &str = concat!(
+            "data: {\"request_id\":\"req-fixture-123\",\"output\":{\"choices\":[{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"finish_reason\":\"null\"}]}}\n\n",
+            "data: {\"request_id\":\"req-fixture-123\",\"output\":{\"choices\":[{\"message\":{\"role\":\"assistant\",\"content\":\"lo\"},\"finish_reason\":\"stop\"}]},\"usage\":{\"input_tokens\":3,\"output_tokens\":2,\"total_tokens\":5}}\n\n",
+        );
+
+        let capture = SpanFieldCapture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let model = Client::new_with_api_key("test-api-key").completion_model(QWEN_PLUS);
+        let request = json!({"model": QWEN_PLUS, "input": {"messages": []}, "parameters": {}});
+        let span = model.completion_span(true, None, &request);
+
+        let req = http::Request::post("https://test.api.com/text-generation/generation")
+            .body(Vec::new())
+            .unwrap();
+        let client = FixtureHttpClient { body: FIXTURE };
+        let mut stream = send_qwen_streaming_request(client, req)
+            .instrument(span)
+            .await
+            .unwrap();
+
+        while let Some(chunk) = stream.next().await {
+            chunk.unwrap();
+        }
+
+        let response = stream.response.expect("final response should be yielded");
+        assert_eq!(response.request_id.as_deref(), Some("req-fixture-123"));
+        assert_eq!(response.usage.total_tokens, 5);
+
+        let fields = capture.0.lock().unwrap();
+        assert_eq!(fields["gen_ai.response.id"], "\"req-fixture-123\"");
+    }
+
+    // 测试 QwQ 流式响应中累积的推理内容可从最终响应中恢复
+    #[tokio::test]
+    async fn test_streaming_reasoning_recoverable() {
+        const FIXTURE: &str = concat!(
+            "data: {\"request_id\":\"req-qwq\",\"output\":{\"choices\":[{\"message\":{\"role\":\"assistant\",\"content\":\"\",\"reasoning_content\":\"Let me \"},\"finish_reason\":\"null\"}]}}\n\n",
+            "data: {\"request_id\":\"req-qwq\",\"output\":{\"choices\":[{\"message\":{\"role\":\"assistant\",\"content\":\"\",\"reasoning_content\":\"think.\"},\"finish_reason\":\"null\"}]}}\n\n",
+            "data: {\"request_id\":\"req-qwq\",\"output\":{\"choices\":[{\"message\":{\"role\":\"assistant\",\"content\":\"42\"},\"finish_reason\":\"stop\"}]},\"usage\":{\"input_tokens\":3,\"output_tokens\":4,\"total_tokens\":7}}\n\n",
+        );
+
+        let req = http::Request::post("https://test.api.com/text-generation/generation")
+            .body(Vec::new())
+            .unwrap();
+        let client = FixtureHttpClient { body: FIXTURE };
+        let mut stream = send_qwen_streaming_request(client, req).await.unwrap();
+
+        let mut streamed_reasoning = String::new();
+        while let Some(chunk) = stream.next().await {
+            if let crate::streaming::StreamedAssistantContent::Reasoning(reasoning) = chunk.unwrap() {
+                streamed_reasoning.push_str(&reasoning.reasoning.join(""));
+            }
+        }
+
+        let response = stream.response.expect("final response should be yielded");
+        assert_eq!(response.reasoning_content.as_deref(), Some("Let me think."));
+        assert_eq!(streamed_reasoning, "Let me think.");
+        assert_eq!(
+            response.reasoning(),
+            Some(message::Reasoning::new("Let me think."))
+        );
+
+        // 无推理内容时不序列化该字段
+        let plain = StreamingCompletionResponse {
+            request_id: None,
+            reasoning_content: None,
+            finish_reason: None,
+            usage: Usage::new(),
+        };
+        assert!(!serde_json::to_string(&plain).unwrap().contains("reasoning_content"));
+    }
+
+    // 测试无法解析的工具调用参数会作为错误报告给调用方，可修复的参数会被修复
+    #[tokio::test]
+    async fn test_streaming_invalid_tool_arguments_reported() {
+        const FIXTURE: &str = concat!(
+            r#"data: {"request_id":"req-tools","output":{"choices":[{"message":{"role":"assistant","content":"","tool_calls":[{"index":0,"id":"call_a","type":"function","function":{"name":"get_weather","arguments":""}},{"index":1,"id":"call_b","type":"function","function":{"name":"get_time","arguments":""}}]},"finish_reason":"null"}]}}"#,
+            "\n\n",
+            r#"data: {"request_id":"req-tools","output":{"choices":[{"message":{"role":"assistant","content":"","tool_calls":[{"index":0,"function":{"arguments":"{\"location\": \"Beijing\""}},{"index":1,"function":{"arguments":"{\"city\": \"Shanghai\",}"}}]},"finish_reason":"null"}]}}"#,
+            "\n\n",
+            r#"data: {"request_id":"req-tools","output":{"choices":[{"message":{"role":"assistant","content":""},"finish_reason":"tool_calls"}]},"usage":{"input_tokens":3,"output_tokens":4,"total_tokens":7}}"#,
+            "\n\n",
+        );
+
+        let req = http::Request::post("https://test.api.com/text-generation/generation")
+            .body(Vec::new())
+            .unwrap();
+        let client = FixtureHttpClient { body: FIXTURE };
+        let mut stream = send_qwen_streaming_request(client, req).await.unwrap();
+
+        let mut errors = vec![];
+        let mut tool_calls = vec![];
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(crate::streaming::StreamedAssistantContent::ToolCall(tool_call)) => {
+                    tool_calls.push(tool_call)
+                }
+                Ok(_) => {}
+                Err(err) => errors.push(err.to_string()),
+            }
+        }
+
+        // 截断的参数不再被静默丢弃
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("get_weather"));
+        assert!(errors[0].contains(r#"{"location": "Beijing""#));
+
+        // 尾随逗号被修复
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.name, "get_time");
+        assert_eq!(tool_calls[0].function.arguments, json!({"city": "Shanghai"}));
+
+        assert!(stream.response.is_some());
+    }
+
+    // 测试尾随逗号的修复不影响字符串内容
+    #[test]
+    fn test_strip_trailing_commas() {
+        assert_eq!(
+            strip_trailing_commas(r#"{"a": [1, 2, ], "b": "x,}",}"#),
+            r#"{"a": [1, 2 ], "b": "x,}"}"#
+        );
+        assert_eq!(
+            parse_tool_arguments("call_1", "think", " ").unwrap(),
+            json!({})
+        );
+    }
+
+    // 总是返回固定错误的测试 HTTP 客户端：
+    // status 为 Some 时模拟服务端返回的错误状态码，为 None 时模拟连接断开
+    #[derive(Clone, Debug, Default)]
+    struct FailingHttpClient {
+        // 模拟的错误状态码
+        status: Option<http::StatusCode>,
+    }
+
+    impl FailingHttpClient {
+        // 构造本次请求应返回的错误
+        fn error(&self) -> http_client::Error {
+            match self.status {
+                Some(status) => http_client::Error::InvalidStatusCodeWithMessage(
+                    status,
+                    r#"{"code":"InvalidParameter","message":"bad input","request_id":"req-err"}"#
+                        .to_string(),
+                ),
+                None => http_client::Error::Instance(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "connection reset by peer",
+                ))),
+            }
+        }
+    }
+
+    impl HttpClientExt for FailingHttpClient {
+        fn send<T, U>(
+            &self,
+            _req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            T: Into<bytes::Bytes>,
+            T: crate::wasm_compat::WasmCompatSend,
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            let err = self.error();
+            async move { Err(err) }
+        }
+
+        fn send_multipart<U>(
+            &self,
+            _req: http::Request<reqwest::multipart::Form>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            let err = self.error();
+            async move { Err(err) }
+        }
+
+        fn send_streaming<T>(
+            &self,
+            _req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http_client::StreamingResponse>>
+        + crate::wasm_compat::WasmCompatSend
+        where
+            T: Into<bytes::Bytes>,
+        {
+            let err = self.error();
+            async move { Err(err) }
+        }
+    }
+
+    // 使用给定的测试 HTTP 客户端构建完成模型
+    fn failing_model(status: Option<http::StatusCode>) -> CompletionModel<FailingHttpClient> {
+        Client::<reqwest::Client>::builder("test-api-key")
+            .with_client(FailingHttpClient { status })
+            .build()
+            .unwrap()
+            .completion_model(QWEN_PLUS)
+    }
+
+    // 测试传输层错误映射为 HttpError，可由调用方重试
+    #[tokio::test]
+    async fn test_transport_error_is_http_error() {
+        use crate::completion::CompletionModel as _;
+
+        let model = failing_model(None);
+
+        let request = model.completion_request("Hello").build();
+        let err = model.completion(request).await.unwrap_err();
+        assert!(matches!(err, CompletionError::HttpError(_)), "{err:?}");
+
+        let request = model.completion_request("Hello").build();
+        let mut stream = model.stream(request).await.unwrap();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, CompletionError::HttpError(_)), "{err:?}");
+    }
+
+    // 测试服务端返回的错误状态码映射为 ProviderError，并包含状态码与错误信息
+    #[tokio::test]
+    async fn test_status_error_is_provider_error() {
+        use crate::completion::CompletionModel as _;
+
+        let model = failing_model(Some(http::StatusCode::BAD_REQUEST));
+
+        let request = model.completion_request("Hello").build();
+        let err = model.completion(request).await.unwrap_err();
+        assert!(
+            matches!(err, CompletionError::ProviderApiError(_)),
+            "{err:?}"
+        );
+        assert_eq!(
+            err.to_string(),
+            "ProviderError: 400 Bad Request: InvalidParameter: bad input"
+        );
+
+        let model = failing_model(Some(http::StatusCode::INTERNAL_SERVER_ERROR));
+        let request = model.completion_request("Hello").build();
+        let mut stream = model.stream(request).await.unwrap();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(
+            matches!(err, CompletionError::ProviderApiError(_)),
+            "{err:?}"
+        );
+    }
+
+    // 测试 DashScope 错误代码可从 CompletionError 以及 anyhow 错误链中取回
+    #[tokio::test]
+    async fn test_dashscope_error_code_is_recoverable() {
+        use crate::completion::CompletionModel as _;
+
+        let model = failing_model(Some(http::StatusCode::BAD_REQUEST));
+        let request = model.completion_request("Hello").build();
+        let err = model.completion(request).await.unwrap_err();
+
+        assert_eq!(dashscope_error_code(&err), Some("InvalidParameter"));
+        assert_eq!(
+            QwenError::from_completion_error(&err),
+            Some(&QwenError {
+                status: Some(http::StatusCode::BAD_REQUEST),
+                code: "InvalidParameter".to_string(),
+                message: "bad input".to_string(),
+                request_id: Some("req-err".to_string()),
+            })
+        );
+
+        // 经 anyhow 包装后，结构化错误仍在错误链中
+        let err = anyhow::Error::from(err).context("coating optimization failed");
+        let qwen_err = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<QwenError>())
+            .expect("QwenError should be in the error chain");
+        assert_eq!(qwen_err.code, "InvalidParameter");
+        assert!(format!("{err:#}").contains("InvalidParameter: bad input"));
+
+        // 传输层错误没有错误代码
+        let model = failing_model(None);
+        let request = model.completion_request("Hello").build();
+        let err = model.completion(request).await.unwrap_err();
+        assert_eq!(dashscope_error_code(&err), None);
+    }
+
+    // 测试 result_format 写入请求参数
+    #[test]
+    fn test_result_format_serialization() {
+        let client = Client::new_with_api_key("test-api-key");
+        let request = CompletionRequest {
+            preamble: None,
+            chat_history: crate::OneOrMany::one("Hello".into()),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            tool_choice: None,
+            additional_params: None,
+        };
+
+        let model = client.completion_model(QWEN_PLUS);
+        let json = model.create_completion_request(request.clone()).unwrap();
+        assert_eq!(json["parameters"]["result_format"], json!("message"));
+
+        let model = client
+            .completion_model(QWEN_PLUS)
+            .result_format(ResultFormat::Text);
+        let json = model.create_completion_request(request).unwrap();
+        assert_eq!(json["parameters"]["result_format"], json!("text"));
+    }
+
+    // 测试 message 格式响应的转换
+    #[test]
+    fn test_message_format_response() {
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "request_id": "req-message",
+            "output": {
+                "choices": [{
+                    "finish_reason": "stop",
+                    "message": {"role": "assistant", "content": "Hello!"}
+                }]
+            },
+            "usage": {"input_tokens": 3, "output_tokens": 2, "total_tokens": 5}
+        }))
+        .unwrap();
+
+        let response: completion::CompletionResponse<CompletionResponse> =
+            response.try_into().unwrap();
+        assert_eq!(
+            response.choice,
+            crate::OneOrMany::one(completion::AssistantContent::text("Hello!"))
+        );
+        assert_eq!(response.usage.total_tokens, 5);
+    }
+
+    // 测试 text 格式响应（只有 output.text）的转换
+    #[test]
+    fn test_text_format_response() {
+        let response: CompletionResponse = serde_json::from_value(json!({
+            "request_id": "req-text",
+            "output": {"text": "Hello!", "finish_reason": "stop"},
+            "usage": {"input_tokens": 3, "output_tokens": 2, "total_tokens": 5}
+        }))
+        .unwrap();
+        assert!(response.output.choices.is_empty());
+
+        let response: completion::CompletionResponse<CompletionResponse> =
+            response.try_into().unwrap();
+        assert_eq!(
+            response.choice,
+            crate::OneOrMany::one(completion::AssistantContent::text("Hello!"))
+        );
+        assert_eq!(response.usage.output_tokens, 2);
+        assert_eq!(response.raw_response.output.finish_reason.as_deref(), Some("stop"));
+    }
+
+    // 测试 text 格式的流式响应
+    #[tokio::test]
+    async fn test_streaming_text_format() {
+        const FIXTURE: &str = concat!(
+            "data: {\"request_id\":\"req-text\",\"output\":{\"text\":\"Hel\",\"finish_reason\":\"null\"}}\n\n",
+            "data: {\"request_id\":\"req-text\",\"output\":{\"text\":\"lo\",\"finish_reason\":\"stop\"},\"usage\":{\"input_tokens\":3,\"output_tokens\":2,\"total_tokens\":5}}\n\n",
+        );
+
+        let req = http::Request::post("https://test.api.com/text-generation/generation")
+            .body(Vec::new())
+            .unwrap();
+        let client = FixtureHttpClient { body: FIXTURE };
+        let mut stream = send_qwen_streaming_request(client, req).await.unwrap();
+
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            if let crate::streaming::StreamedAssistantContent::Text(chunk) = chunk.unwrap() {
+                text.push_str(&chunk.text);
+            }
+        }
+
+        assert_eq!(text, "Hello");
+        assert_eq!(stream.response.unwrap().usage.total_tokens, 5);
+    }
+
+    // 测试消息序列化
+    #[test]
+    fn test_message_serialization() {
+        let message = Message::User {
+            content: "Hello".to_string(),
+        };
+
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.contains("user"));
+        assert!(json.contains("Hello"));
+    }
+
+    // 测试多个文本部分的用户消息合并为一条用户消息，并保持与工具结果的相对顺序
+    #[test]
+    fn test_user_text_parts_are_merged() {
+        let user = |content: Vec<message::UserContent>| message::Message::User {
+            content: crate::OneOrMany::many(content).unwrap(),
+        };
+
+        let messages: Vec<Message> = user(vec![
+            message::UserContent::text("Compute the phase diagram"),
+            message::UserContent::text("of TiAlN at 1000 K"),
+        ])
+        .try_into()
+        .unwrap();
+        assert_eq!(
+            messages,
+            vec![Message::User {
+                content: "Compute the phase diagram\nof TiAlN at 1000 K".to_string(),
+            }]
+        );
+
+        let messages: Vec<Message> = user(vec![
+            message::UserContent::tool_result(
+                "call_1",
+                crate::OneOrMany::one(message::ToolResultContent::text("task 1")),
+            ),
+            message::UserContent::text("Now check"),
+            message::UserContent::text("the status"),
+        ])
+        .try_into()
+        .unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(
+            &messages[0],
+            Message::ToolResult { tool_call_id, .. } if tool_call_id == "call_1"
+        ));
+        assert_eq!(
+            messages[1],
+            Message::User {
+                content: "Now check\nthe status".to_string(),
+            }
+        );
+
+        // 文档按文本合并
+        let messages: Vec<Message> = user(vec![
+            message::UserContent::document("TiAlN: 3200 HV", None),
+            message::UserContent::text("Which coating is hardest?"),
+        ])
+        .try_into()
+        .unwrap();
+        assert_eq!(
+            messages,
+            vec![Message::User {
+                content: "TiAlN: 3200 HV\nWhich coating is hardest?".to_string(),
+            }]
+        );
+
+        // base64 与 URL 文档不能按文本发送
+        let base64_document = message::UserContent::Document(message::Document {
+            data: message::DocumentSourceKind::Base64("VGlBbE46IDMyMDAgSFY=".to_string()),
+            media_type: None,
+            additional_params: None,
+        });
+        let url_document = message::UserContent::document_url("https://example.com/tialn.pdf", None);
+        for document in [base64_document, url_document] {
+            let result: Result<Vec<Message>, _> =
+                user(vec![document, message::UserContent::text("Summarize")]).try_into();
+            assert!(matches!(result, Err(MessageError::ConversionError(_))));
+        }
+    }
+
+    // 测试工具结果消息携带产生结果的工具名称
+    #[test]
+    fn test_tool_result_message_includes_tool_name() {
+        let model = Client::new_with_api_key("test-api-key").completion_model(QWEN_PLUS);
+        let tool_result = |id: &str, text: &str| {
+            message::UserContent::tool_result(
+                id,
+                crate::OneOrMany::one(message::ToolResultContent::text(text)),
+            )
+        };
+        let history = vec![
+            message::Message::user("Compute both"),
+            message::Message::Assistant {
+                id: None,
+                content: crate::OneOrMany::many(vec![
+                    completion::AssistantContent::tool_call(
+                        "call_1",
+                        "calphamesh_submit_point_task",
+                        json!({}),
+                    ),
+                    completion::AssistantContent::tool_call(
+                        "call_2",
+                        "calphamesh_submit_line_task",
+                        json!({}),
+                    ),
+                ])
+                .unwrap(),
+            },
+            message::Message::User {
+                content: crate::OneOrMany::many(vec![
+                    tool_result("call_1", "task 1"),
+                    tool_result("call_2", "task 2"),
+                ])
+                .unwrap(),
+            },
+        ];
+
+        let request = model
+            .create_completion_request(CompletionRequest {
+                preamble: None,
+                chat_history: crate::OneOrMany::many(history).unwrap(),
+                documents: vec![],
+                tools: vec![],
+                temperature: None,
+                max_tokens: None,
+                tool_choice: None,
+                additional_params: None,
+            })
+            .unwrap();
+
+        let messages = request["input"]["messages"].as_array().unwrap();
+        assert_eq!(
+            messages[2],
+            json!({
+                "role": "tool",
+                "tool_call_id": "call_1",
+                "name": "calphamesh_submit_point_task",
+                "content": "task 1"
+            })
+        );
+        assert_eq!(messages[3]["name"], "calphamesh_submit_line_task");
+
+        // 没有对应工具调用时不序列化名称
+        let orphan = Message::from(message::ToolResult {
+            id: "call_3".to_string(),
+            call_id: None,
+            content: crate::OneOrMany::one(message::ToolResultContent::text("done")),
+        });
+        assert!(serde_json::to_value(&orphan).unwrap().get("name").is_none());
+    }
+
+    // 测试图像工具结果：视觉模型使用内容数组，其他模型使用占位符
+    #[test]
+    fn test_image_tool_result_by_model_class() {
+        let history = vec![
+            message::Message::user("Render the phase diagram"),
+            message::Message::Assistant {
+                id: None,
+                content: crate::OneOrMany::one(completion::AssistantContent::tool_call(
+                    "call_1",
+                    "render_phase_diagram",
+                    json!({}),
+                )),
+            },
+            message::Message::User {
+                content: crate::OneOrMany::one(message::UserContent::tool_result(
+                    "call_1",
+                    crate::OneOrMany::many(vec![
+                        message::ToolResultContent::text("Phase diagram of TiAlN"),
+                        message::ToolResultContent::image_base64(
+                            "iVBORw0KGgo=",
+                            Some(message::ImageMediaType::PNG),
+                            None,
+                        ),
+                    ])
+                    .unwrap(),
+                )),
+            },
+        ];
+        let tool_message = |model: &str| {
+            let request = Client::new_with_api_key("test-api-key")
+                .completion_model(model)
+                .create_completion_request(CompletionRequest {
+                    preamble: None,
+                    chat_history: crate::OneOrMany::many(history.clone()).unwrap(),
+                    documents: vec![],
+                    tools: vec![],
+                    temperature: None,
+                    max_tokens: None,
+                    tool_choice: None,
+                    additional_params: None,
+                })
+                .unwrap();
+            request["input"]["messages"][2].clone()
+        };
+
+        assert_eq!(
+            tool_message(QWEN_VL_PLUS),
+            json!({
+                "role": "tool",
+                "tool_call_id": "call_1",
+                "name": "render_phase_diagram",
+                "content": [
+                    {"text": "Phase diagram of TiAlN"},
+                    {"image": "data:image/png;base64,iVBORw0KGgo="}
+                ]
+            })
+        );
+        assert_eq!(tool_message(QWEN_PLUS)["content"], "Phase diagram of TiAlN");
+        assert!(is_vision_model("qwen2.5-vl-72b-instruct"));
+        assert!(!is_vision_model(QWEN_MAX));
+    }
+
+    // 测试工具调用序列化
+    #[test]
+    fn test_tool_call_serialization() {
+        let tool_call = ToolCall {
+            id: "call_123".to_string(),
+            index: 0,
+            r#type: ToolType::Function,
+            function: Function {
+                name: "get_weather".to_string(),
+                arguments: json!({"location": "Beijing"}),
+            },
+        };
+
+        let json = serde_json::to_string(&tool_call).unwrap();
+        assert!(json.contains("call_123"));
+        assert!(json.contains("get_weather"));
+    }
+
+    // 测试完成响应反序列化
+    #[test]
+    fn test_completion_response_deserialization() {
+        let data = r#"{
+            "status_code": 200,
+            "request_id": "test-request-id",
+            "output": {
+                "choices": [{
+                    "finish_reason": "stop",
+                    "message": {
+                        "role": "assistant",
+                        "content": "你好！"
+                    }
+                }]
+            },
+            "usage": {
+                "input_tokens": 10,
+                "output_tokens": 5,
+                "total_tokens": 15
+            }
+        }"#;
+
+        let response: CompletionResponse = serde_json::from_str(data).unwrap();
+        assert_eq!(response.usage.input_tokens, 10);
+        assert_eq!(response.usage.output_tokens, 5);
+    }
+
+    // 记录请求体的测试嵌入 HTTP 客户端：
+    // 按输入文本的索引生成向量，并以逆序返回以验证按 text_index 重新排序
+    #[derive(Clone, Debug, Default)]
+    struct EmbeddingHttpClient {
+        // 收到的所有请求体
+        requests: std::sync::Arc<std::sync::Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl HttpClientExt for EmbeddingHttpClient {
+        fn send<T, U>(
+            &self,
+            req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            T: Into<bytes::Bytes>,
+            T: crate::wasm_compat::WasmCompatSend,
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            let body: bytes::Bytes = req.into_body().into();
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+            let texts: Vec<String> =
+                serde_json::from_value(request["input"]["texts"].clone()).unwrap();
+            let embeddings: Vec<_> = texts
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, text)| {
+                    let index: f64 = text.trim_start_matches("doc-").parse().unwrap();
+                    json!({"text_index": i, "embedding": [index, 0.5]})
+                })
+                .collect();
+            let response = json!({
+                "request_id": "req-embedding",
+                "output": {"embeddings": embeddings},
+                "usage": {"total_tokens": texts.len() * 2}
+            });
+
+            self.requests.lock().unwrap().push(request);
+
+            async move {
+                let bytes = bytes::Bytes::from(serde_json::to_vec(&response).unwrap());
+                let body: http_client::LazyBody<U> = Box::pin(async move { Ok(U::from(bytes)) });
+                Ok(http::Response::builder().status(200).body(body).unwrap())
+            }
+        }
+
+        fn send_multipart<U>(
+            &self,
+            _req: http::Request<reqwest::multipart::Form>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            // 嵌入不使用 multipart 请求
+            std::future::ready(Err(http_client::Error::InvalidStatusCode(
+                http::StatusCode::NOT_IMPLEMENTED,
+            )))
+        }
+
+        fn send_streaming<T>(
+            &self,
+            _req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http_client::StreamingResponse>>
+        + crate::wasm_compat::WasmCompatSend
+        where
+            T: Into<bytes::Bytes>,
+        {
+            // 嵌入不使用流式请求
+            std::future::ready(Err(http_client::Error::InvalidStatusCode(
+                http::StatusCode::NOT_IMPLEMENTED,
+            )))
+        }
+    }
+
+    // 测试超过批次上限的输入会被拆分为多个请求，且结果按输入顺序拼接
+    #[tokio::test]
+    async fn test_embeddings_are_batched_in_order() {
+        use crate::embeddings::EmbeddingModel as _;
+
+        let http_client = EmbeddingHttpClient::default();
+        let model = Client::<reqwest::Client>::builder("test-api-key")
+            .with_client(http_client.clone())
+            .build()
+            .unwrap()
+            .embedding_model(TEXT_EMBEDDING_V4);
+        assert_eq!(model.ndims(), 1024);
+
+        let documents: Vec<String> = (0..30).map(|i| format!("doc-{i}")).collect();
+        let (embeddings, usage) = model
+            .embed_texts_with_usage(documents.clone())
+            .await
+            .unwrap();
+
+        // 30 条输入按每批 10 条拆分为 3 个请求
+        let requests = http_client.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 3);
+        for (batch, request) in requests.iter().enumerate() {
+            let texts = request["input"]["texts"].as_array().unwrap();
+            assert_eq!(texts.len(), 10);
+            assert_eq!(texts[0], json!(format!("doc-{}", batch * 10)));
+            assert_eq!(request["model"], json!(TEXT_EMBEDDING_V4));
+            assert_eq!(request["parameters"]["dimension"], json!(1024));
+        }
+
+        // 结果与输入一一对应且顺序一致
+        assert_eq!(embeddings.len(), 30);
+        for (i, embedding) in embeddings.iter().enumerate() {
+            assert_eq!(embedding.document, documents[i]);
+            assert_eq!(embedding.vec, vec![i as f64, 0.5]);
+        }
+
+        // 使用情况为所有批次之和
+        assert_eq!(usage.total_tokens, 60);
+
+        // 通过 EmbeddingModel trait 调用时同样自动分批
+        let embeddings = model.embed_texts(documents).await.unwrap();
+        assert_eq!(embeddings.len(), 30);
+        assert_eq!(http_client.requests.lock().unwrap().len(), 6);
+    }
+}
+
+// ================================================================
+// 端到端测试模块
+// 通过 MockHttpClient 回放录制的 DashScope 响应，无需网络访问及 API 密钥
+// ================================================================
+#[cfg(test)]
+mod end_to_end_tests {
+    use super::*;
+    use crate::completion::{CompletionModel as _, Prompt};
+    use crate::streaming::StreamingPrompt;
+    use crate::test_utils::MockHttpClient;
+
+    // 录制的 DashScope 响应
+    const COMPLETION_TEXT: &str = include_str!("../../tests/data/qwen/completion_text.json");
+    const COMPLETION_TOOL_CALL: &str =
+        include_str!("../../tests/data/qwen/completion_tool_call.json");
+    const STREAM_TEXT: &str = include_str!("../../tests/data/qwen/stream_text.sse");
+    const STREAM_TOOL_CALL: &str = include_str!("../../tests/data/qwen/stream_tool_call.sse");
+    const STREAM_TOOL_CALL_WITHOUT_ID: &str =
+        include_str!("../../tests/data/qwen/stream_tool_call_without_id.sse");
+    const ERROR_THROTTLING: &str = include_str!("../../tests/data/qwen/error_throttling.json");
+    const COMPLETION_TRUNCATED: &str =
+        include_str!("../../tests/data/qwen/completion_truncated.json");
+    const COMPLETION_CONTINUED: &str =
+        include_str!("../../tests/data/qwen/completion_continued.json");
+    const STREAM_TRUNCATED: &str = include_str!("../../tests/data/qwen/stream_truncated.sse");
+    const STREAM_CONTINUED: &str = include_str!("../../tests/data/qwen/stream_continued.sse");
+    const CONTINUED_ANSWER: &str = "Adding Cr to TiAlN raises the onset of spinodal decomposition and delays the formation of wurtzite AlN.";
+
+    // 使用给定的模拟 HTTP 客户端构建完成模型
+    fn mock_model(http_client: MockHttpClient) -> CompletionModel<MockHttpClient> {
+        Client::<reqwest::Client>::builder("test-api-key")
+            .with_client(http_client)
+            .build()
+            .unwrap()
+            .completion_model(QWEN_PLUS)
+    }
+
+    // 收集流式响应的文本、工具调用与错误
+    async fn collect_stream(
+        model: &CompletionModel<MockHttpClient>,
+        request: CompletionRequest,
+    ) -> (
+        String,
+        Vec<message::ToolCall>,
+        Vec<CompletionError>,
+        Option<StreamingCompletionResponse>,
+    ) {
+        let mut stream = model.stream(request).await.unwrap();
+        let (mut text, mut tool_calls, mut errors) = (String::new(), vec![], vec![]);
+
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(crate::streaming::StreamedAssistantContent::Text(chunk)) => {
+                    text.push_str(&chunk.text)
+                }
+                Ok(crate::streaming::StreamedAssistantContent::ToolCall(tool_call)) => {
+                    tool_calls.push(tool_call)
+                }
+                Ok(_) => {}
+                Err(err) => errors.push(err),
+            }
+        }
+
+        (text, tool_calls, errors, stream.response.clone())
+    }
+
+    // 测试非流式完成：请求的 URL、请求头、请求体以及响应的转换
+    #[tokio::test]
+    async fn test_completion() {
+        let http_client = MockHttpClient::new().with_body(COMPLETION_TEXT);
+        let model = mock_model(http_client.clone());
+
+        let request = model
+            .completion_request("Which structure does TiAlN keep at 1000 K?")
+            .preamble("You are a materials scientist.".to_string())
+            .build();
+        let response = model.completion(request).await.unwrap();
+
+        assert_eq!(
+            response.choice.first(),
+            completion::AssistantContent::text(
+                "TiAlN keeps the cubic B1 structure up to about 65 at.% AlN at 1000 K."
+            )
+        );
+        assert_eq!(response.usage.input_tokens, 39);
+        assert_eq!(response.usage.output_tokens, 22);
+        assert_eq!(
+            response.raw_response.request_id,
+            "7d1e2c3b-9a4f-9d8e-b6c5-2f3a4b5c6d7e"
+        );
+
+        let requests = http_client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].uri,
+            format!("{QWEN_API_BASE_URL}/{QWEN_COMPLETION_PATH}")
+        );
+        assert_eq!(
+            requests[0].headers[http::header::AUTHORIZATION],
+            "Bearer test-api-key"
+        );
+        let body = requests[0].json();
+        assert_eq!(body["model"], QWEN_PLUS);
+        assert_eq!(
+            body["input"]["messages"],
+            json!([
+                {"role": "system", "content": "You are a materials scientist."},
+                {"role": "user", "content": "Which structure does TiAlN keep at 1000 K?"}
+            ])
+        );
+    }
+
+    // 测试非流式完成返回的工具调用参数被解析为 JSON
+    #[tokio::test]
+    async fn test_completion_tool_call() {
+        let model = mock_model(MockHttpClient::new().with_body(COMPLETION_TOOL_CALL));
+
+        let request = model.completion_request("Compute TiAlN at 1000 K").build();
+        let response = model.completion(request).await.unwrap();
+
+        let tool_call = response
+            .choice
+            .iter()
+            .find_map(|content| match content {
+                completion::AssistantContent::ToolCall(tool_call) => Some(tool_call),
+                _ => None,
+            })
+            .expect("the response should contain a tool call");
+        assert_eq!(tool_call.id, "call_5f0d2a7c8b1e4c3a9e6d");
+        assert_eq!(tool_call.function.name, "calphamesh_submit_point_task");
+        assert_eq!(
+            tool_call.function.arguments,
+            json!({"components": ["Ti", "Al", "N"], "temperature": 1000})
+        );
+    }
+
+    // 测试流式完成：事件被任意切分为多个数据块时，文本增量与最终响应仍然完整
+    #[tokio::test]
+    async fn test_stream() {
+        let (head, tail) = STREAM_TEXT.split_at(STREAM_TEXT.len() / 2);
+        let http_client = MockHttpClient::new().with_event_chunks(&[head, tail]);
+        let model = mock_model(http_client.clone());
+
+        let request = model
+            .completion_request("Which structure does TiAlN keep?")
+            .build();
+        let (text, tool_calls, errors, response) = collect_stream(&model, request).await;
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert!(tool_calls.is_empty());
+        assert_eq!(text, "TiAlN keeps the cubic B1 structure.");
+        let response = response.expect("final response should be yielded");
+        assert_eq!(
+            response.request_id.as_deref(),
+            Some("3e2d1c0b-a9f8-9e7d-c6b5-a4f3e2d1c0b9")
+        );
+        assert_eq!(response.usage.total_tokens, 48);
+
+        let requests = http_client.requests();
+        assert_eq!(requests[0].headers["X-DashScope-SSE"], "enable");
+        assert_eq!(
+            requests[0].json()["parameters"]["incremental_output"],
+            json!(true)
+        );
+    }
+
+    // 测试流式工具调用：分多个事件到达的参数被合并并解析为 JSON
+    #[tokio::test]
+    async fn test_stream_tool_call() {
+        let model = mock_model(MockHttpClient::new().with_events(STREAM_TOOL_CALL));
+
+        let request = model.completion_request("Compute TiAlN at 1000 K").build();
+        let (_, tool_calls, errors, response) = collect_stream(&model, request).await;
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id, "call_8e1f4b2d6a9c4e7b8d3f");
+        assert_eq!(tool_calls[0].function.name, "calphamesh_submit_point_task");
+        assert_eq!(
+            tool_calls[0].function.arguments,
+            json!({"components": ["Ti", "Al", "N"], "temperature": 1000})
+        );
+        assert_eq!(response.unwrap().usage.total_tokens, 212);
+    }
+
+    // 测试接口省略工具调用 ID：流式与非流式生成相同的 ID，工具结果可按该 ID 关联到调用
+    #[tokio::test]
+    async fn test_tool_call_without_id() {
+        let mut completion: serde_json::Value = serde_json::from_str(COMPLETION_TOOL_CALL).unwrap();
+        completion["output"]["choices"][0]["message"]["tool_calls"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("id");
+        let http_client = MockHttpClient::new()
+            .with_events(STREAM_TOOL_CALL_WITHOUT_ID)
+            .with_body(completion.to_string())
+            .with_body(COMPLETION_TEXT);
+        let model = mock_model(http_client.clone());
+
+        let request = model.completion_request("Compute TiAlN at 1000 K").build();
+        let (_, tool_calls, errors, _) = collect_stream(&model, request).await;
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id, "call_0");
+
+        let request = model.completion_request("Compute TiAlN at 1000 K").build();
+        let response = model.completion(request).await.unwrap();
+        let tool_call = response
+            .choice
+            .iter()
+            .find_map(|content| match content {
+                completion::AssistantContent::ToolCall(tool_call) => Some(tool_call.clone()),
+                _ => None,
+            })
+            .expect("the response should contain a tool call");
+        assert_eq!(tool_call.id, tool_calls[0].id);
+
+        // 按生成的 ID 回传工具结果，结果消息能找到对应的工具名称
+        let history = vec![
+            message::Message::user("Compute TiAlN at 1000 K"),
+            message::Message::Assistant {
+                id: None,
+                content: crate::OneOrMany::one(completion::AssistantContent::ToolCall(
+                    tool_calls[0].clone(),
+                )),
+            },
+            message::Message::User {
+                content: crate::OneOrMany::one(message::UserContent::tool_result(
+                    tool_calls[0].id.clone(),
+                    crate::OneOrMany::one(message::ToolResultContent::text("task 42 submitted")),
+                )),
+            },
+        ];
+        let request = model
+            .completion_request("Summarize the result")
+            .messages(history)
+            .build();
+        model.completion(request).await.unwrap();
+
+        let body = http_client.requests()[2].json();
+        let messages = &body["input"]["messages"];
+        assert_eq!(messages[1]["tool_calls"][0]["id"], "call_0");
+        assert_eq!(
+            messages[2],
+            json!({
+                "role": "tool",
+                "tool_call_id": "call_0",
+                "name": "calphamesh_submit_point_task",
+                "content": "task 42 submitted"
+            })
+        );
+    }
+
+    // 测试提取器：强制调用 `submit` 工具，并将其参数解析为结构体
+    #[tokio::test]
+    async fn test_extractor() {
+        #[derive(Debug, PartialEq, Deserialize, Serialize, schemars::JsonSchema)]
+        struct QualityScore {
+            score: f32,
+            feedback: String,
+        }
+
+        let http_client = MockHttpClient::new().with_body(
+            json!({
+                "output": {"choices": [{
+                    "finish_reason": "tool_calls",
+                    "message": {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "index": 0,
+                            "id": "call_2b7e4d1a9c3f",
+                            "type": "function",
+                            "function": {
+                                "name": "submit",
+                                "arguments": "{\"score\": 0.85, \"feedback\": \"Clear and well structured.\"}"
+                            }
+                        }]
+                    }
+                }]},
+                "usage": {"total_tokens": 160, "output_tokens": 24, "input_tokens": 136},
+                "request_id": "1d2c3b4a-5e6f-7a8b-9c0d-e1f2a3b4c5d6"
+            })
+            .to_string(),
+        );
+        let client = Client::<reqwest::Client>::builder("test-api-key")
+            .with_client(http_client.clone())
+            .build()
+            .unwrap();
+        let extractor = client.extractor::<QualityScore>(QWEN_PLUS).build();
+
+        let score = extractor.extract("Rust guarantees memory safety.").await.unwrap();
+        assert_eq!(
+            score,
+            QualityScore { score: 0.85, feedback: "Clear and well structured.".to_string() }
+        );
+
+        let parameters = &http_client.requests()[0].json()["parameters"];
+        assert_eq!(
+            parameters["tool_choice"],
+            json!({"type": "function", "function": {"name": "submit"}})
+        );
+        assert_eq!(parameters["tools"][0]["function"]["name"], "submit");
+        assert!(parameters["tools"][0]["function"]["parameters"]["properties"]["score"].is_object());
+    }
+
+    // 测试工具选择的转换：DashScope 只能强制调用单个函数
+    #[test]
+    fn test_convert_tool_choice() {
+        let tool = |name: &str| crate::completion::ToolDefinition {
+            name: name.to_string(),
+            description: String::new(),
+            parameters: json!({"type": "object"}),
+        };
+        let forced = json!({"type": "function", "function": {"name": "submit"}});
+
+        assert_eq!(convert_tool_choice(&message::ToolChoice::Auto, &[]), json!("auto"));
+        assert_eq!(convert_tool_choice(&message::ToolChoice::None, &[]), json!("none"));
+        assert_eq!(convert_tool_choice(&message::ToolChoice::Required, &[tool("submit")]), forced);
+        assert_eq!(
+            convert_tool_choice(&message::ToolChoice::Required, &[tool("submit"), tool("think")]),
+            json!("auto")
+        );
+        assert_eq!(
+            convert_tool_choice(
+                &message::ToolChoice::Specific { function_names: vec!["submit".to_string()] },
+                &[tool("submit"), tool("think")]
+            ),
+            forced
+        );
+    }
+
+    // 测试错误映射：DashScope 错误响应、传输层错误在非流式与流式请求中的表现一致
+    #[tokio::test]
+    async fn test_error_mapping() {
+        let model = mock_model(
+            MockHttpClient::new()
+                .with_status(http::StatusCode::TOO_MANY_REQUESTS, ERROR_THROTTLING)
+                .with_status(http::StatusCode::TOO_MANY_REQUESTS, ERROR_THROTTLING)
+                .with_connection_error(),
+        );
+
+        let request = model.completion_request("Hello").build();
+        let err = model.completion(request).await.unwrap_err();
+        assert_eq!(dashscope_error_code(&err), Some("Throttling.RateQuota"));
+        assert_eq!(
+            QwenError::from_completion_error(&err).unwrap().request_id.as_deref(),
+            Some("5a4b3c2d-1e0f-9a8b-c7d6-e5f4a3b2c1d0")
+        );
+        assert!(err.is_retryable());
+
+        let request = model.completion_request("Hello").build();
+        let (_, _, errors, _) = collect_stream(&model, request).await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(dashscope_error_code(&errors[0]), Some("Throttling.RateQuota"));
+
+        let request = model.completion_request("Hello").build();
+        let err = model.completion(request).await.unwrap_err();
+        assert!(matches!(err, CompletionError::HttpError(_)), "{err:?}");
+        assert!(err.is_retryable());
+    }
+
+    // 测试多轮历史（工具调用及其结果）转换为 DashScope 消息
+    #[tokio::test]
+    async fn test_history_conversion() {
+        let http_client = MockHttpClient::new().with_body(COMPLETION_TEXT);
+        let model = mock_model(http_client.clone());
+
+        let history = vec![
+            message::Message::user("Compute TiAlN at 1000 K"),
+            message::Message::Assistant {
+                id: None,
+                content: crate::OneOrMany::one(completion::AssistantContent::tool_call(
+                    "call_1",
+                    "calphamesh_submit_point_task",
+                    json!({"temperature": 1000}),
+                )),
+            },
+            message::Message::User {
+                content: crate::OneOrMany::one(message::UserContent::tool_result(
+                    "call_1",
+                    crate::OneOrMany::one(message::ToolResultContent::text("task 42 submitted")),
+                )),
+            },
+        ];
+        let request = model
+            .completion_request("Summarize the result")
+            .messages(history)
+            .build();
+        model.completion(request).await.unwrap();
+
+        let body = http_client.requests()[0].json();
+        assert_eq!(
+            body["input"]["messages"],
+            json!([
+                {"role": "user", "content": "Compute TiAlN at 1000 K"},
+                {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "index": 0,
+                        "type": "function",
+                        "function": {
+                            "name": "calphamesh_submit_point_task",
+                            "arguments": "{\"temperature\":1000}"
+                        }
+                    }]
+                },
+                {
+                    "role": "tool",
+                    "tool_call_id": "call_1",
+                    "name": "calphamesh_submit_point_task",
+                    "content": "task 42 submitted"
+                },
+                {"role": "user", "content": "Summarize the result"}
+            ])
+        );
+    }
+
+    // 统计被克隆次数的 HTTP 客户端，其余行为委托给 MockHttpClient
+    #[derive(Debug, Default)]
+    struct CountingHttpClient {
+        inner: MockHttpClient,
+        clones: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Clone for CountingHttpClient {
+        fn clone(&self) -> Self {
+            self.clones
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Self {
+                inner: self.inner.clone(),
+                clones: self.clones.clone(),
+            }
+        }
+    }
+
+    impl HttpClientExt for CountingHttpClient {
+        fn send<T, U>(
+            &self,
+            req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            T: Into<bytes::Bytes>,
+            T: crate::wasm_compat::WasmCompatSend,
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            self.inner.send(req)
+        }
+
+        fn send_multipart<U>(
+            &self,
+            req: http::Request<reqwest::multipart::Form>,
+        ) -> impl Future<Output = http_client::Result<http::Response<http_client::LazyBody<U>>>>
+        + crate::wasm_compat::WasmCompatSend
+        + 'static
+        where
+            U: From<bytes::Bytes>,
+            U: crate::wasm_compat::WasmCompatSend + 'static,
+        {
+            self.inner.send_multipart(req)
+        }
+
+        fn send_streaming<T>(
+            &self,
+            req: http::Request<T>,
+        ) -> impl Future<Output = http_client::Result<http_client::StreamingResponse>>
+        + crate::wasm_compat::WasmCompatSend
+        where
+            T: Into<bytes::Bytes>,
+        {
+            self.inner.send_streaming(req)
+        }
+    }
+
+    // 测试从同一客户端创建大量模型时不会深拷贝 HTTP 客户端
+    #[tokio::test]
+    async fn test_models_share_http_client() {
+        let http_client = CountingHttpClient {
+            inner: MockHttpClient::new()
+                .with_body(COMPLETION_TEXT)
+                .with_events(STREAM_TEXT),
+            ..Default::default()
+        };
+        let clones = http_client.clones.clone();
+        let client = Client::<reqwest::Client>::builder("test-api-key")
+            .with_client(http_client)
+            .build()
+            .unwrap();
+
+        let models: Vec<_> = (0..100)
+            .map(|_| client.completion_model(QWEN_PLUS))
+            .collect();
+        let embedding_model = client.embedding_model(TEXT_EMBEDDING_V3);
+
+        for model in &models {
+            assert!(Arc::ptr_eq(&model.client.http_client, &client.http_client));
+        }
+        assert!(Arc::ptr_eq(
+            &embedding_model.client.http_client,
+            &client.http_client
+        ));
+
+        // 发送请求（包括流式请求）同样不克隆底层客户端
+        let request = models[0].completion_request("Hello").build();
+        models[0].completion(request.clone()).await.unwrap();
+        let mut stream = models[1].stream(request).await.unwrap();
+        while stream.next().await.is_some() {}
+
+        assert_eq!(clones.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert_eq!(client.http_client.inner.requests().len(), 2);
+    }
+
+    // 测试读取结束原因（"length" 表示输出被截断）
+    #[test]
+    fn test_finish_reason() {
+        let truncated: CompletionResponse = serde_json::from_str(COMPLETION_TRUNCATED).unwrap();
+        let completed: CompletionResponse = serde_json::from_str(COMPLETION_TEXT).unwrap();
+
+        assert_eq!(
+            CompletionModel::<MockHttpClient>::finish_reason(&truncated),
+            Some(completion::FinishReason::Length)
+        );
+        assert_eq!(
+            CompletionModel::<MockHttpClient>::finish_reason(&completed),
+            Some(completion::FinishReason::Stop)
+        );
+    }
+
+    // 测试输出被截断时自动发起续写轮次，并拼接各轮输出
+    #[tokio::test]
+    async fn test_auto_continue_truncated_answer() {
+        let http_client = MockHttpClient::new()
+            .with_body(COMPLETION_TRUNCATED)
+            .with_body(COMPLETION_CONTINUED);
+        let agent = crate::agent::AgentBuilder::new(mock_model(http_client.clone())).build();
+
+        let response = agent
+            .prompt("How does Cr affect TiAlN?")
+            .multi_turn(2)
+            .auto_continue(true)
+            .await
+            .unwrap();
+
+        assert_eq!(response, CONTINUED_ANSWER);
+        let body = http_client.requests()[1].json();
+        let messages = body["input"]["messages"].as_array().unwrap();
+        assert_eq!(
+            messages[messages.len() - 2]["content"],
+            "Adding Cr to TiAlN raises the onset of spinodal decomposition and"
+        );
+        assert_eq!(
+            messages.last().unwrap()["content"],
+            crate::agent::prompt_request::continue_prompt()
+                .rag_text()
+                .unwrap()
+        );
+    }
+
+    // 测试未启用续写或轮次用尽时返回截断的回答
+    #[tokio::test]
+    async fn test_truncated_answer_without_auto_continue() {
+        let http_client = MockHttpClient::new().with_body(COMPLETION_TRUNCATED);
+        let agent = crate::agent::AgentBuilder::new(mock_model(http_client.clone())).build();
+        let response = agent.prompt("How does Cr affect TiAlN?").await.unwrap();
+        assert!(response.ends_with("decomposition and"));
+
+        // 默认不允许额外的轮次
+        let http_client = MockHttpClient::new().with_body(COMPLETION_TRUNCATED);
+        let agent = crate::agent::AgentBuilder::new(mock_model(http_client.clone())).build();
+        let response = agent
+            .prompt("How does Cr affect TiAlN?")
+            .auto_continue(true)
+            .await
+            .unwrap();
+        assert!(response.ends_with("decomposition and"));
+        assert_eq!(http_client.requests().len(), 1);
+    }
+
+    // 测试流式输出被截断时自动续写，最终回答为拼接后的完整文本
+    #[tokio::test]
+    async fn test_stream_auto_continue_truncated_answer() {
+        let http_client = MockHttpClient::new()
+            .with_events(STREAM_TRUNCATED)
+            .with_events(STREAM_CONTINUED);
+        let agent = crate::agent::AgentBuilder::new(mock_model(http_client.clone())).build();
+
+        let (messages, final_response) = crate::agent::stream_collect(
+            agent
+                .stream_prompt("How does Cr affect TiAlN?")
+                .multi_turn(2)
+                .auto_continue(true),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(final_response.response(), CONTINUED_ANSWER);
+        assert_eq!(
+            messages.last(),
+            Some(&message::Message::assistant(CONTINUED_ANSWER))
+        );
+        assert_eq!(http_client.requests().len(), 2);
+    }
+
+    // 测试 Agent 的备用模型可以与主模型类型不同：主模型失败后由 Qwen 模型回答，
+    // 备用模型的用量计入响应
+    #[tokio::test]
+    async fn test_agent_falls_back_to_qwen_model() {
+        let primary = crate::test_utils::MockCompletionModel::new()
+            .with_error("503 Service Unavailable")
+            .with_stream_error("503 Service Unavailable");
+        let http_client = MockHttpClient::new()
+            .with_body(COMPLETION_TEXT)
+            .with_events(STREAM_TEXT);
+        let agent = crate::agent::AgentBuilder::new(primary.clone())
+            .fallback_model(mock_model(http_client.clone()))
+            .build();
+
+        let response = agent
+            .prompt("Which structure does TiAlN keep at 1000 K?")
+            .extended_details()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.output,
+            "TiAlN keeps the cubic B1 structure up to about 65 at.% AlN at 1000 K."
+        );
+        assert_eq!(response.total_usage.output_tokens, 22);
+        assert_eq!(response.usage_breakdown.fallbacks.get(&1), Some(&1));
+
+        let (_, final_response) = crate::agent::stream_collect(
+            agent.stream_prompt("Which structure does TiAlN keep?"),
+            |_| {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(final_response.response(), "TiAlN keeps the cubic B1 structure.");
+        assert_eq!(final_response.usage().total_tokens, 48);
+        assert_eq!(final_response.usage_breakdown().fallbacks.get(&1), Some(&1));
+
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(http_client.requests().len(), 2);
+    }
+
+    // 测试通过 Agent 调用时，采样参数被记录到 Agent 的 chat / chat_streaming span 中
+    #[tokio::test]
+    async fn test_agent_spans_record_sampling_params() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let http_client = MockHttpClient::new()
+            .with_body(COMPLETION_TEXT)
+            .with_events(STREAM_TEXT);
+        let agent = crate::agent::AgentBuilder::new(mock_model(http_client))
+            .temperature(0.7)
+            .max_tokens(512)
+            .additional_params(json!({"top_p": 0.8}))
+            .build();
+
+        for streaming in [false, true] {
+            let capture = super::tests::SpanFieldCapture::default();
+            let _guard = tracing::subscriber::set_default(
+                tracing_subscriber::registry().with(capture.clone()),
+            );
+
+            if streaming {
+                crate::agent::stream_collect(agent.stream_prompt("Which structure?"), |_| {})
+                    .await
+                    .unwrap();
+            } else {
+                agent.prompt("Which structure?").await.unwrap();
+            }
+
+            let fields = capture.0.lock().unwrap();
+            assert_eq!(fields["gen_ai.request.temperature"], "0.7");
+            assert_eq!(fields["gen_ai.request.max_tokens"], "512");
+            assert_eq!(fields["gen_ai.request.top_p"], "0.8");
+        }
+    }
+
+    // 构造 DashScope 嵌入响应，按顺序返回给定向量
+    fn embedding_response(vectors: impl IntoIterator<Item = Vec<f64>>) -> String {
+        let embeddings: Vec<_> = vectors
+            .into_iter()
+            .enumerate()
+            .map(|(i, vector)| json!({"text_index": i, "embedding": vector}))
+            .collect();
+        json!({
+            "request_id": "req-embedding",
+            "output": {"embeddings": embeddings},
+            "usage": {"total_tokens": embeddings.len() * 8}
+        })
+        .to_string()
+    }
+
+    // 第 i 维为 1 的单位向量，使每条文档只与自身的查询向量相似
+    fn one_hot(i: usize) -> Vec<f64> {
+        (0..20).map(|j| if i == j { 1.0 } else { 0.0 }).collect()
+    }
+
+    #[derive(Deserialize)]
+    struct SubmitArgs {}
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Submit error")]
+    struct SubmitError;
+
+    // 返回固定任务ID的点计算提交工具
+    struct StubSubmitPointTask;
+
+    impl crate::tool::Tool for StubSubmitPointTask {
+        const NAME: &'static str = "calphamesh_submit_point_task";
+        type Error = SubmitError;
+        type Args = SubmitArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> completion::ToolDefinition {
+            completion::ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Submit a point task".to_string(),
+                parameters: json!({"type": "object", "properties": {}}),
+            }
+        }
+
+        async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok("task 42 submitted".to_string())
+        }
+    }
+
+    // 测试 dynamic_context 使用 Qwen 嵌入模型检索历史涂层数据，
+    // 检索结果作为文档进入 Qwen 请求，且多轮运行的每一轮都会重新检索
+    #[tokio::test]
+    async fn test_dynamic_context_with_qwen_embeddings() {
+        use crate::embeddings::EmbeddingModel as _;
+        use crate::vector_store::in_memory_store::InMemoryVectorStore;
+
+        let coatings: Vec<String> = (0..20)
+            .map(|i| format!("Coating {i}: TiAlN with {i} at.% Cr"))
+            .collect();
+        let http_client = MockHttpClient::new()
+            // 20 条文档按每批 10 条嵌入
+            .with_body(embedding_response((0..10).map(one_hot)))
+            .with_body(embedding_response((10..20).map(one_hot)))
+            // 每一轮先嵌入查询，再请求完成
+            .with_body(embedding_response([one_hot(7)]))
+            .with_body(COMPLETION_TOOL_CALL)
+            .with_body(embedding_response([one_hot(7)]))
+            .with_body(COMPLETION_TEXT);
+        let client = Client::<reqwest::Client>::builder("test-api-key")
+            .with_client(http_client.clone())
+            .build()
+            .unwrap();
+
+        let embedding_model = client.embedding_model(TEXT_EMBEDDING_V4);
+        let embeddings = embedding_model.embed_texts(coatings.clone()).await.unwrap();
+        let documents: Vec<_> = coatings
+            .into_iter()
+            .zip(embeddings)
+            .enumerate()
+            .map(|(i, (coating, embedding))| {
+                let id = format!("coating-{i}");
+                (id, coating, crate::OneOrMany::one(embedding))
+            })
+            .collect();
+        let index = InMemoryVectorStore::from_documents_with_ids(documents).index(embedding_model);
+
+        let agent = client
+            .agent(QWEN_PLUS)
+            .dynamic_context(1, index)
+            .tool(StubSubmitPointTask)
+            .build();
+        agent
+            .prompt("Which coating keeps the B1 structure at 1000 K?")
+            .multi_turn(2)
+            .await
+            .unwrap();
+
+        let requests = http_client.requests();
+        assert_eq!(requests.len(), 6);
+        let completions: Vec<_> = requests
+            .iter()
+            .filter(|request| request.uri.ends_with(QWEN_COMPLETION_PATH))
+            .map(|request| request.json())
+            .collect();
+        assert_eq!(completions.len(), 2);
+
+        for body in completions {
+            let messages = body["input"]["messages"].as_array().unwrap();
+            let context = messages[0]["content"].as_str().unwrap();
+            assert_eq!(messages[0]["role"], "user");
+            assert!(context.contains("<file id: coating-7>"));
+            assert!(context.contains("Coating 7: TiAlN with 7 at.% Cr"));
+            assert!(!context.contains("Coating 8"));
+        }
     }
 }
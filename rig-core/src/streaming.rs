@@ -506,6 +506,10 @@ mod tests {
                     println!("\nTool Call delta: {delta:?}");
                     chunk_count += 1;
                 }
+                Ok(StreamedAssistantContent::ToolResult { name, result, .. }) => {
+                    println!("\nTool Result ({name}): {result}");
+                    chunk_count += 1;
+                }
                 Ok(StreamedAssistantContent::Final(res)) => {
                     println!("\nFinal response: {res:?}");
                 }
@@ -555,8 +559,18 @@ mod tests {
 pub enum StreamedAssistantContent<R> {
     Text(Text),
     ToolCall(ToolCall),
-    ToolCallDelta { id: String, delta: String },
-    ToolResult { id: String, result: String },
+    ToolCallDelta {
+        id: String,
+        delta: String,
+    },
+    ToolResult {
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        call_id: Option<String>,
+        #[serde(default)]
+        name: String,
+        result: String,
+    },
     Reasoning(Reasoning),
     Final(R),
 }
@@ -612,11 +626,42 @@ where
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(untagged)]
 pub enum StreamedUserContent {
-    ToolResult(ToolResult),
+    /// The result of a tool call. `tool_result` carries the `id` and `call_id` of the call it
+    /// answers, so it can be added to the chat history as is.
+    ToolResult {
+        #[serde(flatten)]
+        tool_result: ToolResult,
+        /// The name of the tool that produced the result
+        tool_name: String,
+    },
 }
 
 impl StreamedUserContent {
-    pub fn tool_result(tool_result: ToolResult) -> Self {
-        Self::ToolResult(tool_result)
+    pub fn tool_result(tool_result: ToolResult, tool_name: impl Into<String>) -> Self {
+        Self::ToolResult {
+            tool_result,
+            tool_name: tool_name.into(),
+        }
+    }
+
+    /// The id of the tool call this content answers.
+    pub fn id(&self) -> &str {
+        match self {
+            Self::ToolResult { tool_result, .. } => &tool_result.id,
+        }
+    }
+
+    /// The provider call id of the tool call this content answers, if the model supplied one.
+    pub fn call_id(&self) -> Option<&str> {
+        match self {
+            Self::ToolResult { tool_result, .. } => tool_result.call_id.as_deref(),
+        }
+    }
+
+    /// The name of the tool that produced this content.
+    pub fn tool_name(&self) -> &str {
+        match self {
+            Self::ToolResult { tool_name, .. } => tool_name,
+        }
     }
 }
@@ -0,0 +1,395 @@
+//! Helpers shared by unit tests across the crate.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_stream::stream;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    OneOrMany,
+    completion::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, GetTokenUsage,
+        Usage,
+        message::{AssistantContent, ToolCall, ToolFunction},
+    },
+    http_client::{self, HttpClientExt, LazyBody, sse::BoxedStream},
+    streaming::{RawStreamingChoice, StreamingCompletionResponse, StreamingResult},
+    wasm_compat::WasmCompatSend,
+};
+
+/// A scripted turn returned by [`MockCompletionModel`].
+enum MockTurn {
+    Content(Vec<AssistantContent>),
+    /// The request fails with a provider error.
+    Error(String),
+    /// Streaming requests fail with a provider error before the first token. Other requests
+    /// fail as with [MockTurn::Error].
+    StreamError(String),
+}
+
+/// Final streaming response yielded by [`MockCompletionModel`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct MockStreamingResponse {
+    usage: Option<Usage>,
+}
+
+impl GetTokenUsage for MockStreamingResponse {
+    fn token_usage(&self) -> Option<Usage> {
+        self.usage
+    }
+}
+
+/// A completion model that replays scripted turns and records every request it receives.
+///
+/// Once the script is exhausted, every further request is answered with the text
+/// `"mock response"`.
+#[derive(Clone, Default)]
+pub(crate) struct MockCompletionModel {
+    turns: Arc<Mutex<VecDeque<MockTurn>>>,
+    requests: Arc<Mutex<Vec<CompletionRequest>>>,
+    usage: Option<Usage>,
+    latency: Option<Duration>,
+}
+
+impl MockCompletionModel {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a turn consisting of a single text response.
+    pub(crate) fn with_text(self, text: &str) -> Self {
+        self.with_turn(vec![AssistantContent::text(text)])
+    }
+
+    /// Queue a turn consisting of a single tool call.
+    pub(crate) fn with_tool_call(self, id: &str, name: &str, arguments: serde_json::Value) -> Self {
+        self.with_turn(vec![AssistantContent::ToolCall(ToolCall {
+            id: id.to_string(),
+            call_id: None,
+            function: ToolFunction {
+                name: name.to_string(),
+                arguments,
+            },
+        })])
+    }
+
+    /// Queue an arbitrary turn.
+    pub(crate) fn with_turn(self, content: Vec<AssistantContent>) -> Self {
+        self.turns
+            .lock()
+            .unwrap()
+            .push_back(MockTurn::Content(content));
+        self
+    }
+
+    /// Queue a turn failing with a provider error with the given message.
+    pub(crate) fn with_error(self, message: &str) -> Self {
+        self.turns
+            .lock()
+            .unwrap()
+            .push_back(MockTurn::Error(message.to_string()));
+        self
+    }
+
+    /// Queue a turn whose stream fails with a provider error before the first token.
+    pub(crate) fn with_stream_error(self, message: &str) -> Self {
+        self.turns
+            .lock()
+            .unwrap()
+            .push_back(MockTurn::StreamError(message.to_string()));
+        self
+    }
+
+    /// Report `usage` for every response.
+    pub(crate) fn with_usage(mut self, usage: Usage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Wait for `latency` before answering every request, eg. to simulate a slow model.
+    pub(crate) fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// All requests received so far, in order.
+    pub(crate) fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    async fn next_turn(&self, request: CompletionRequest) -> MockTurn {
+        self.requests.lock().unwrap().push(request);
+        if let Some(latency) = self.latency {
+            tokio::time::sleep(latency).await;
+        }
+        self.turns
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| MockTurn::Content(vec![AssistantContent::text("mock response")]))
+    }
+}
+
+impl CompletionModel for MockCompletionModel {
+    type Response = ();
+    type StreamingResponse = MockStreamingResponse;
+    type Client = ();
+
+    fn make(_client: &Self::Client, _model: impl Into<String>) -> Self {
+        Self::default()
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        let content = match self.next_turn(request).await {
+            MockTurn::Content(content) => content,
+            MockTurn::Error(message) | MockTurn::StreamError(message) => {
+                return Err(CompletionError::ProviderError(message));
+            }
+        };
+        let choice = OneOrMany::many(content)
+            .map_err(|_| CompletionError::ResponseError("empty mock turn".to_string()))?;
+
+        Ok(CompletionResponse {
+            choice,
+            usage: self.usage.unwrap_or_default(),
+            raw_response: (),
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<MockStreamingResponse>, CompletionError> {
+        let (content, stream_error) = match self.next_turn(request).await {
+            MockTurn::Content(content) => (content, None),
+            MockTurn::Error(message) => return Err(CompletionError::ProviderError(message)),
+            MockTurn::StreamError(message) => (vec![], Some(message)),
+        };
+        let usage = self.usage;
+
+        let stream: StreamingResult<MockStreamingResponse> = Box::pin(stream! {
+            if let Some(message) = stream_error {
+                yield Err(CompletionError::ProviderError(message));
+                return;
+            }
+            for item in content {
+                match item {
+                    AssistantContent::Text(text) => {
+                        yield Ok(RawStreamingChoice::Message(text.text));
+                    }
+                    AssistantContent::ToolCall(tool_call) => {
+                        yield Ok(RawStreamingChoice::ToolCall {
+                            id: tool_call.id,
+                            call_id: tool_call.call_id,
+                            name: tool_call.function.name,
+                            arguments: tool_call.function.arguments,
+                        });
+                    }
+                    AssistantContent::Reasoning(reasoning) => {
+                        yield Ok(RawStreamingChoice::Reasoning {
+                            id: reasoning.id,
+                            reasoning: reasoning.reasoning.join(""),
+                            signature: reasoning.signature,
+                        });
+                    }
+                }
+            }
+            yield Ok(RawStreamingChoice::FinalResponse(MockStreamingResponse { usage }));
+        });
+
+        Ok(StreamingCompletionResponse::stream(stream))
+    }
+}
+
+/// A scripted response returned by [`MockHttpClient`].
+#[derive(Clone, Debug)]
+pub(crate) enum MockHttpResponse {
+    /// A successful response with the given body.
+    Body(String),
+    /// A successful server-sent events response, delivered in the given chunks.
+    Events(Vec<String>),
+    /// A response with the given failure status and body.
+    Status(http::StatusCode, String),
+    /// A transport failure, eg. a connection reset.
+    ConnectionError,
+}
+
+impl MockHttpResponse {
+    /// The error the real HTTP client returns for a failed response.
+    fn into_error(self) -> http_client::Error {
+        match self {
+            MockHttpResponse::Status(status, body) => {
+                http_client::Error::InvalidStatusCodeWithMessage(status, body)
+            }
+            MockHttpResponse::ConnectionError => {
+                http_client::Error::Instance(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "connection reset by peer",
+                )))
+            }
+            MockHttpResponse::Body(_) | MockHttpResponse::Events(_) => {
+                unreachable!("successful responses are not errors")
+            }
+        }
+    }
+}
+
+/// A request received by [`MockHttpClient`].
+#[derive(Clone, Debug)]
+pub(crate) struct MockHttpRequest {
+    pub(crate) uri: String,
+    pub(crate) headers: http::HeaderMap,
+    pub(crate) body: Bytes,
+}
+
+impl MockHttpRequest {
+    /// The body of the request, parsed as JSON.
+    pub(crate) fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("the request body should be JSON")
+    }
+}
+
+/// An HTTP client that replays scripted responses, in order, and records every request it
+/// receives. Provider clients built on it can be tested end-to-end without network access.
+///
+/// Like the real client, failure statuses are returned as
+/// [`InvalidStatusCodeWithMessage`](http_client::Error::InvalidStatusCodeWithMessage) errors.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MockHttpClient {
+    responses: Arc<Mutex<VecDeque<MockHttpResponse>>>,
+    requests: Arc<Mutex<Vec<MockHttpRequest>>>,
+}
+
+impl MockHttpClient {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a successful response with the given body.
+    pub(crate) fn with_body(self, body: impl Into<String>) -> Self {
+        self.with_response(MockHttpResponse::Body(body.into()))
+    }
+
+    /// Queue a successful server-sent events response, delivered in one chunk.
+    pub(crate) fn with_events(self, events: impl Into<String>) -> Self {
+        self.with_response(MockHttpResponse::Events(vec![events.into()]))
+    }
+
+    /// Queue a successful server-sent events response, delivered in the given chunks. Chunk
+    /// boundaries may fall anywhere, eg. in the middle of an event.
+    pub(crate) fn with_event_chunks(self, chunks: &[&str]) -> Self {
+        let chunks = chunks.iter().map(|chunk| chunk.to_string()).collect();
+        self.with_response(MockHttpResponse::Events(chunks))
+    }
+
+    /// Queue a response with the given failure status and body.
+    pub(crate) fn with_status(self, status: http::StatusCode, body: impl Into<String>) -> Self {
+        self.with_response(MockHttpResponse::Status(status, body.into()))
+    }
+
+    /// Queue a transport failure.
+    pub(crate) fn with_connection_error(self) -> Self {
+        self.with_response(MockHttpResponse::ConnectionError)
+    }
+
+    /// Queue an arbitrary response.
+    pub(crate) fn with_response(self, response: MockHttpResponse) -> Self {
+        self.responses.lock().unwrap().push_back(response);
+        self
+    }
+
+    /// All requests received so far, in order.
+    pub(crate) fn requests(&self) -> Vec<MockHttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn respond(&self, parts: http::request::Parts, body: Bytes) -> MockHttpResponse {
+        self.requests.lock().unwrap().push(MockHttpRequest {
+            uri: parts.uri.to_string(),
+            headers: parts.headers,
+            body,
+        });
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("MockHttpClient received more requests than it has scripted responses")
+    }
+}
+
+impl HttpClientExt for MockHttpClient {
+    fn send<T, U>(
+        &self,
+        req: http::Request<T>,
+    ) -> impl Future<Output = http_client::Result<http::Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        T: Into<Bytes>,
+        T: WasmCompatSend,
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        let (parts, body) = req.into_parts();
+        let response = self.respond(parts, body.into());
+
+        async move {
+            let body = match response {
+                MockHttpResponse::Body(body) => body,
+                MockHttpResponse::Events(chunks) => chunks.concat(),
+                failure => return Err(failure.into_error()),
+            };
+            let body: LazyBody<U> = Box::pin(async move { Ok(U::from(Bytes::from(body))) });
+
+            http::Response::builder()
+                .status(http::StatusCode::OK)
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .map_err(http_client::Error::Protocol)
+        }
+    }
+
+    fn send_multipart<U>(
+        &self,
+        req: http::Request<reqwest::multipart::Form>,
+    ) -> impl Future<Output = http_client::Result<http::Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        let (parts, _form) = req.into_parts();
+        self.send(http::Request::from_parts(parts, Bytes::new()))
+    }
+
+    fn send_streaming<T>(
+        &self,
+        req: http::Request<T>,
+    ) -> impl Future<Output = http_client::Result<http_client::StreamingResponse>> + WasmCompatSend
+    where
+        T: Into<Bytes>,
+    {
+        let (parts, body) = req.into_parts();
+        let response = self.respond(parts, body.into());
+
+        async move {
+            let chunks = match response {
+                MockHttpResponse::Events(chunks) => chunks,
+                MockHttpResponse::Body(body) => vec![body],
+                failure => return Err(failure.into_error()),
+            };
+            let stream: BoxedStream = Box::pin(futures::stream::iter(
+                chunks.into_iter().map(|chunk| Ok(Bytes::from(chunk))),
+            ));
+
+            http::Response::builder()
+                .status(http::StatusCode::OK)
+                .header(http::header::CONTENT_TYPE, "text/event-stream")
+                .body(stream)
+                .map_err(http_client::Error::Protocol)
+        }
+    }
+}
@@ -10,7 +10,7 @@
 //! and optionally RAGged.
 
 pub mod server;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use futures::Future;
 use serde::{Deserialize, Serialize};
@@ -96,6 +96,11 @@ pub trait Tool: Sized + WasmCompatSend + WasmCompatSync {
     /// The name of the tool. This name should be unique.
     const NAME: &'static str;
 
+    /// Whether calling the tool can have side effects, eg. creating or modifying resources.
+    /// Read-only tools set this to `false`, so callers can treat them as safe to run without
+    /// approval.
+    const MUTATES: bool = true;
+
     /// The error type of the tool.
     type Error: std::error::Error + WasmCompatSend + WasmCompatSync + 'static;
     /// The arguments type of the tool.
@@ -155,6 +160,11 @@ pub trait ToolEmbedding: Tool {
 pub trait ToolDyn: WasmCompatSend + WasmCompatSync {
     fn name(&self) -> String;
 
+    /// Whether calling the tool can have side effects. See [Tool::MUTATES].
+    fn mutates(&self) -> bool {
+        true
+    }
+
     fn definition<'a>(&'a self, prompt: String) -> WasmBoxedFuture<'a, ToolDefinition>;
 
     fn call<'a>(&'a self, args: String) -> WasmBoxedFuture<'a, Result<String, ToolError>>;
@@ -165,6 +175,10 @@ impl<T: Tool> ToolDyn for T {
         self.name()
     }
 
+    fn mutates(&self) -> bool {
+        T::MUTATES
+    }
+
     fn definition<'a>(&'a self, prompt: String) -> WasmBoxedFuture<'a, ToolDefinition> {
         Box::pin(<Self as Tool>::definition(self, prompt))
     }
@@ -243,6 +257,15 @@ pub mod rmcp {
             self.definition.name.to_string()
         }
 
+        fn mutates(&self) -> bool {
+            !self
+                .definition
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.read_only_hint)
+                .unwrap_or(false)
+        }
+
         fn definition(&self, _prompt: String) -> WasmBoxedFuture<'_, ToolDefinition> {
             Box::pin(async move {
                 ToolDefinition {
@@ -353,9 +376,10 @@ where
     }
 }
 
+#[derive(Clone)]
 pub(crate) enum ToolType {
-    Simple(Box<dyn ToolDyn>),
-    Embedding(Box<dyn ToolEmbeddingDyn>),
+    Simple(Arc<dyn ToolDyn>),
+    Embedding(Arc<dyn ToolEmbeddingDyn>),
 }
 
 impl ToolType {
@@ -406,6 +430,22 @@ pub struct ToolSet {
     pub(crate) tools: HashMap<String, ToolType>,
 }
 
+impl Extend<Box<dyn ToolDyn>> for ToolSet {
+    fn extend<I: IntoIterator<Item = Box<dyn ToolDyn>>>(&mut self, tools: I) {
+        for tool in tools {
+            self.add_tool_boxed(tool);
+        }
+    }
+}
+
+impl FromIterator<Box<dyn ToolDyn>> for ToolSet {
+    fn from_iter<I: IntoIterator<Item = Box<dyn ToolDyn>>>(tools: I) -> Self {
+        let mut toolset = Self::default();
+        toolset.extend(tools);
+        toolset
+    }
+}
+
 impl ToolSet {
     /// Create a new ToolSet from a list of tools
     pub fn from_tools(tools: Vec<impl ToolDyn + 'static>) -> Self {
@@ -429,12 +469,13 @@ impl ToolSet {
     /// Add a tool to the toolset
     pub fn add_tool(&mut self, tool: impl ToolDyn + 'static) {
         self.tools
-            .insert(tool.name(), ToolType::Simple(Box::new(tool)));
+            .insert(tool.name(), ToolType::Simple(Arc::new(tool)));
     }
 
     /// Adds a boxed tool to the toolset. Useful for situations when dynamic dispatch is required.
     pub fn add_tool_boxed(&mut self, tool: Box<dyn ToolDyn>) {
-        self.tools.insert(tool.name(), ToolType::Simple(tool));
+        self.tools
+            .insert(tool.name(), ToolType::Simple(tool.into()));
     }
 
     pub fn delete_tool(&mut self, tool_name: &str) {
@@ -536,12 +577,12 @@ pub struct ToolSetBuilder {
 
 impl ToolSetBuilder {
     pub fn static_tool(mut self, tool: impl ToolDyn + 'static) -> Self {
-        self.tools.push(ToolType::Simple(Box::new(tool)));
+        self.tools.push(ToolType::Simple(Arc::new(tool)));
         self
     }
 
     pub fn dynamic_tool(mut self, tool: impl ToolEmbeddingDyn + 'static) -> Self {
-        self.tools.push(ToolType::Embedding(Box::new(tool)));
+        self.tools.push(ToolType::Embedding(Arc::new(tool)));
         self
     }
 
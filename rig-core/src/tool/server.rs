@@ -1,7 +1,13 @@
-use futures::{StreamExt, TryStreamExt, channel::oneshot::Canceled, stream};
+use futures::{
+    StreamExt, TryStreamExt,
+    channel::oneshot::Canceled,
+    future::{Either, select},
+    stream,
+};
 use tokio::sync::mpsc::{Sender, error::SendError};
 
 use crate::{
+    agent::UsageAccumulator,
     completion::{CompletionError, ToolDefinition},
     tool::{Tool, ToolDyn, ToolError, ToolSet, ToolSetError},
     vector_store::{VectorSearchRequest, VectorStoreError, VectorStoreIndexDyn, request::Filter},
@@ -132,17 +138,49 @@ impl ToolServer {
                     .send(ToolServerResponse::ToolDeleted)
                     .unwrap();
             }
-            ToolServerRequestMessageKind::CallTool { name, args } => {
-                match self.toolset.call(&name, args.clone()).await {
-                    Ok(result) => {
-                        let _ = callback_channel.send(ToolServerResponse::ToolExecuted { result });
-                    }
-                    Err(err) => {
-                        let _ = callback_channel.send(ToolServerResponse::ToolError {
-                            error: err.to_string(),
-                        });
-                    }
-                }
+            ToolServerRequestMessageKind::CallTool { name, args, usage } => {
+                let Some(tool) = self.toolset.get(&name).cloned() else {
+                    let _ = callback_channel.send(ToolServerResponse::ToolError {
+                        error: ToolSetError::ToolNotFoundError(name).to_string(),
+                    });
+                    return;
+                };
+
+                // Run the call in its own task, so that the server keeps handling messages (and
+                // other tool calls) while the tool is running. The tool is dropped, ie. cancelled,
+                // if the caller stops waiting for its result (eg. after a tool timeout). The usage
+                // accumulator of the caller stays current, so that sub-agents can record their usage.
+                let mut callback_channel = callback_channel;
+                let call = async move {
+                    tracing::debug!(target: "rig", "Calling tool {name} with args:\n{args}");
+                    let tool_call = async {
+                        match &usage {
+                            Some(usage) => usage.scope(tool.call(args)).await,
+                            None => tool.call(args).await,
+                        }
+                    };
+                    let result =
+                        match select(Box::pin(tool_call), callback_channel.cancellation()).await {
+                            Either::Left((result, _)) => result,
+                            Either::Right(_) => {
+                                tracing::debug!(target: "rig", "Call to tool {name} was cancelled");
+                                return;
+                            }
+                        };
+                    let response = match result {
+                        Ok(result) => ToolServerResponse::ToolExecuted { result },
+                        Err(err) => ToolServerResponse::ToolError {
+                            error: ToolSetError::from(err).to_string(),
+                        },
+                    };
+                    let _ = callback_channel.send(response);
+                };
+
+                #[cfg(not(target_family = "wasm"))]
+                tokio::spawn(call);
+
+                #[cfg(all(feature = "worker", target_family = "wasm"))]
+                wasm_bindgen_futures::spawn_local(call);
             }
             ToolServerRequestMessageKind::GetToolDefs { prompt } => {
                 let res = self.get_tool_definitions(prompt).await.unwrap();
@@ -209,8 +247,10 @@ pub struct ToolServerHandle(Sender<ToolServerRequest>);
 
 impl ToolServerHandle {
     pub async fn add_tool(&self, tool: impl ToolDyn + 'static) -> Result<(), ToolServerError> {
-        let tool = Box::new(tool);
+        self.add_boxed_tool(Box::new(tool)).await
+    }
 
+    pub async fn add_boxed_tool(&self, tool: Box<dyn ToolDyn>) -> Result<(), ToolServerError> {
         let (tx, rx) = futures::channel::oneshot::channel();
 
         self.0
@@ -278,6 +318,7 @@ impl ToolServerHandle {
                 data: ToolServerRequestMessageKind::CallTool {
                     name: tool_name.to_string(),
                     args: args.to_string(),
+                    usage: UsageAccumulator::current(),
                 },
             })
             .await?;
@@ -324,9 +365,17 @@ pub struct ToolServerRequest {
 pub enum ToolServerRequestMessageKind {
     AddTool(Box<dyn ToolDyn>),
     AppendToolset(ToolSet),
-    RemoveTool { tool_name: String },
-    CallTool { name: String, args: String },
-    GetToolDefs { prompt: Option<String> },
+    RemoveTool {
+        tool_name: String,
+    },
+    CallTool {
+        name: String,
+        args: String,
+        usage: Option<UsageAccumulator>,
+    },
+    GetToolDefs {
+        prompt: Option<String>,
+    },
 }
 
 #[derive(PartialEq, Debug)]
@@ -4,21 +4,40 @@
 
 use std::collections::HashMap;
 use std::future::Future;
-use std::pin::Pin;
+use std::time::Duration;
 
+use futures::{Stream, StreamExt, stream};
+use schemars::JsonSchema;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use thiserror::Error;
 
 use crate::{
     completion::ToolDefinition,
-    tool::{Tool, ToolError},
-    wasm_compat::WasmBoxedFuture,
+    tool::Tool,
+    tools::{
+        composition::{composition_schema, deserialize_composition},
+        parameters_schema,
+    },
 };
 
 // API 基础 URL
 const API_BASE_URL: &str = "https://api.topmaterial-tech.com";
 
+// 提交任务的最大尝试次数
+const SUBMIT_ATTEMPTS: usize = 3;
+// 重试前按标题查找已创建任务时检查的最近任务数
+const RECENT_TASKS_WINDOW: i32 = 20;
+// 跟踪任务日志时的轮询间隔
+const LOG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// 等待任务完成时默认的初始轮询间隔、退避倍数、间隔上限和抖动比例
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+pub const DEFAULT_POLL_BACKOFF: f64 = 1.5;
+pub const DEFAULT_MAX_POLL_INTERVAL: Duration = Duration::from_secs(60);
+pub const DEFAULT_POLL_JITTER: f64 = 0.1;
+
 // 工具错误类型
 #[derive(Debug, Error)]
 pub enum CalphaMeshError {
@@ -32,6 +51,21 @@ pub enum CalphaMeshError {
     InvalidTaskId(i32),
     #[error("Missing required parameter: {0}")]
     MissingParameter(String),
+    #[error("Invalid task result: {0}")]
+    InvalidResult(String),
+    #[error("Authentication failed (status {status}): {message}")]
+    Unauthorized { status: u16, message: String },
+}
+
+impl CalphaMeshError {
+    // 网络错误、限流和服务端错误可以重试
+    pub fn is_transient(&self) -> bool {
+        match self {
+            CalphaMeshError::HttpError(_) => true,
+            CalphaMeshError::ApiError { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
 }
 
 // 任务相关结构体
@@ -41,6 +75,9 @@ pub struct CreateTaskApiKeyRequest {
     pub title: String,
     pub description: String,
     pub task_type: String,
+    // 客户端生成的幂等键（UUID），同一次提交的所有重试共用，服务端据此去重
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -61,10 +98,82 @@ pub struct UpdateTaskStatusApiKeyRequest {
     pub result: String,
 }
 
+/// 任务状态
+///
+/// 序列化为接口使用的小写字符串；接口返回的未知状态保存在 `Other` 中，不会导致反序列化失败。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TaskStatus {
+    Pending,
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Other(String),
+}
+
+impl TaskStatus {
+    /// 任务是否已结束（完成或失败）
+    pub fn is_finished(&self) -> bool {
+        matches!(self, TaskStatus::Completed | TaskStatus::Failed)
+    }
+
+    // 展示任务状态时使用的图标
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            TaskStatus::Pending => "⏳",
+            TaskStatus::Queued => "📋",
+            TaskStatus::Running => "⚙️",
+            TaskStatus::Completed => "✅",
+            TaskStatus::Failed => "❌",
+            TaskStatus::Other(_) => "❓",
+        }
+    }
+}
+
+impl From<String> for TaskStatus {
+    fn from(status: String) -> Self {
+        match status.as_str() {
+            "pending" => TaskStatus::Pending,
+            "queued" => TaskStatus::Queued,
+            "running" => TaskStatus::Running,
+            "completed" => TaskStatus::Completed,
+            "failed" => TaskStatus::Failed,
+            _ => TaskStatus::Other(status),
+        }
+    }
+}
+
+impl From<&str> for TaskStatus {
+    fn from(status: &str) -> Self {
+        status.to_string().into()
+    }
+}
+
+impl From<TaskStatus> for String {
+    fn from(status: TaskStatus) -> Self {
+        status.to_string()
+    }
+}
+
+impl std::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            TaskStatus::Pending => "pending",
+            TaskStatus::Queued => "queued",
+            TaskStatus::Running => "running",
+            TaskStatus::Completed => "completed",
+            TaskStatus::Failed => "failed",
+            TaskStatus::Other(status) => status,
+        };
+        f.write_str(status)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TaskResponse {
     pub id: i32,
-    pub status: String,
+    pub status: TaskStatus,
     pub task_type: String,
 }
 
@@ -73,13 +182,55 @@ pub struct TaskStatusResponse {
     pub id: i32,
     pub title: String,
     pub description: String,
-    pub status: String,
+    pub status: TaskStatus,
     pub task_type: String,
     pub result: Option<String>,
     pub logs: Option<String>,
     pub user_id: i32,
-    pub created_at: String,
-    pub updated_at: String,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl TaskStatusResponse {
+    /// 已结束任务的耗时（从创建到最后更新）
+    ///
+    /// 任务未结束或时间戳缺失时返回 `None`。
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        if !self.status.is_finished() {
+            return None;
+        }
+        Some(self.updated_at? - self.created_at?)
+    }
+}
+
+// 接口返回的时间戳不带时区（如 "2025-01-01T00:00:00"），按 UTC 处理
+const TIMESTAMP_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+    TIMESTAMP_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|timestamp| timestamp.and_utc())
+}
+
+// 缺失或无法识别的时间戳解析为 None，而不是让整个响应反序列化失败
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.as_ref().and_then(|value| value.as_str()).and_then(parse_timestamp))
+}
+
+fn format_timestamp(timestamp: Option<DateTime<Utc>>) -> String {
+    timestamp
+        .map(|timestamp| timestamp.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "未知".to_string())
 }
 
 #[derive(Debug, Deserialize)]
@@ -91,65 +242,218 @@ pub struct TaskListResponse {
 }
 
 // Point 计算参数
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct PointTaskParams {
+    /// 组分列表
     #[serde(default = "default_components")]
     pub components: Vec<String>,
-    #[serde(default = "default_composition")]
+    /// 成分组成 (元素:原子分数)，原子分数之和必须为1；也可传入 "AL 50%, MG 30%, SI 20%" 形式的文本
+    #[serde(default = "default_composition", deserialize_with = "deserialize_composition")]
+    #[schemars(schema_with = "composition_schema")]
     pub composition: HashMap<String, f64>,
+    /// 计算温度(K)
     #[serde(default = "default_temperature")]
     pub temperature: f64,
+    /// 计算压力(atm)
     #[serde(default = "default_pressure")]
     pub pressure: f64,
+    /// 数据库名称
     #[serde(default = "default_database")]
     pub database: String,
+    /// 仅预览：返回将要提交的任务请求，不实际提交
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 // Line 计算参数
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct LineTaskParams {
+    /// 组分列表
     #[serde(default = "default_components")]
     pub components: Vec<String>,
-    #[serde(default = "default_composition")]
+    /// 起始成分组成 (元素:原子分数)，原子分数之和必须为1
+    #[serde(default = "default_composition", deserialize_with = "deserialize_composition")]
+    #[schemars(schema_with = "composition_schema")]
     pub start_composition: HashMap<String, f64>,
+    /// 起始温度(K)
     #[serde(default = "default_temperature")]
     pub start_temperature: f64,
-    #[serde(default = "default_composition")]
+    /// 结束成分组成 (元素:原子分数)，原子分数之和必须为1
+    #[serde(default = "default_composition", deserialize_with = "deserialize_composition")]
+    #[schemars(schema_with = "composition_schema")]
     pub end_composition: HashMap<String, f64>,
+    /// 结束温度(K)
     #[serde(default = "default_end_temperature")]
     pub end_temperature: f64,
+    /// 计算压力(atm)
     #[serde(default = "default_pressure")]
     pub pressure: f64,
+    /// 计算步数
     #[serde(default = "default_steps")]
     pub steps: i64,
+    /// 数据库名称
     #[serde(default = "default_database")]
     pub database: String,
+    /// 仅预览：返回将要提交的任务请求，不实际提交
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 // Scheil 计算参数
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ScheilTaskParams {
+    /// 组分列表
     #[serde(default = "default_components")]
     pub components: Vec<String>,
-    #[serde(default = "default_composition")]
+    /// 成分组成 (元素:原子分数)，原子分数之和必须为1；也可传入 "AL 50%, MG 30%, SI 20%" 形式的文本
+    #[serde(default = "default_composition", deserialize_with = "deserialize_composition")]
+    #[schemars(schema_with = "composition_schema")]
     pub composition: HashMap<String, f64>,
+    /// 起始温度(K)
     #[serde(default = "default_scheil_temperature")]
     pub temperature: f64,
+    /// 计算压力(atm)
     #[serde(default = "default_scheil_pressure")]
     pub pressure: f64,
+    /// 数据库名称
     #[serde(default = "default_database")]
     pub database: String,
+    /// 仅预览：返回将要提交的任务请求，不实际提交
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl PointTaskParams {
+    /// 构建提交 Point 任务的请求
+    pub fn task_request(&self) -> CreateTaskApiKeyRequest {
+        let task_description = json!({
+            "task_type": "point",
+            "components": self.components,
+            "config": {
+                "conditions": {
+                    "equilibrium_type": {"@type": "global"},
+                    "driving_force": {"@value": true}
+                },
+                "suspended_phases": ["*"],
+                "entered_phases": ["Liquid", "Fcc"],
+                "targets": ["T", "G(@*)", "phase_name", "mu(*@*)"],
+                "n_unit": "x"
+            },
+            "ctp": {
+                "composition": self.composition,
+                "temperature": self.temperature,
+                "pressure": self.pressure
+            },
+            "database": self.database
+        });
+
+        CreateTaskApiKeyRequest {
+            db_key: self.database.clone(),
+            title: format!("Task-Point-{}", chrono::Utc::now().timestamp()),
+            description: task_description.to_string(),
+            task_type: "point".to_string(),
+            idempotency_key: None,
+        }
+    }
+}
+
+impl LineTaskParams {
+    /// 构建提交 Line 任务的请求
+    pub fn task_request(&self) -> CreateTaskApiKeyRequest {
+        let title = format!("Task-Line-{}", chrono::Utc::now().timestamp());
+        let task_description = json!({
+            "task_type": "line",
+            "components": self.components,
+            "ctp": {
+                "composition": self.start_composition,
+                "temperature": self.start_temperature,
+                "pressure": self.pressure
+            },
+            "ctp_1": {
+                "composition": self.end_composition,
+                "temperature": self.end_temperature,
+                "pressure": self.pressure
+            },
+            "ctp_steps": self.steps,
+            "config": {
+                "conditions": {
+                    "equilibrium_type": {"@type": "global"},
+                    "driving_force": {"@value": true}
+                },
+                "suspended_phases": ["*"],
+                "entered_phases": ["Liquid", "Fcc"],
+                "targets": ["T", "G(@*)", "phase_name", "mu(*@*)"],
+                "n_unit": "x"
+            },
+            "database": self.database,
+            "type": "line",
+            "name": title
+        });
+
+        CreateTaskApiKeyRequest {
+            db_key: self.database.clone(),
+            title,
+            description: task_description.to_string(),
+            task_type: "line".to_string(),
+            idempotency_key: None,
+        }
+    }
+}
+
+impl ScheilTaskParams {
+    /// 构建提交 Scheil 任务的请求
+    pub fn task_request(&self) -> CreateTaskApiKeyRequest {
+        let title = format!("Task-Scheil-{}", chrono::Utc::now().timestamp());
+        let task_description = json!({
+            "task_type": "scheil",
+            "components": self.components,
+            "ctp": {
+                "composition": self.composition,
+                "temperature": self.temperature,
+                "pressure": self.pressure
+            },
+            "config": {
+                "targets": ["fl", "fs", "phase_name", "Label", "f_tot(@*)", "f(@*)", "T//fs", "Q"],
+                "entered_phases": ["*"],
+                "suspended_phases": ["*"],
+                "n_unit": "x",
+                "conditions": {
+                    "step_T_max": {"@value": "1"},
+                    "model": {"@type": "Scheil"},
+                    "start_from_liquidus_surface": {"@value": "yes"},
+                    "end_when_no_more_liquid": {"@value": "yes"},
+                    "T_end": {"@value": "300"},
+                    "step_T_min": {"@value": "0.01"},
+                    "liquid_amount_min": {"@value": "0.001"},
+                    "x_min": {"@value": "1e-12"}
+                }
+            },
+            "database": self.database,
+            "name": title
+        });
+
+        CreateTaskApiKeyRequest {
+            db_key: self.database.clone(),
+            title,
+            description: task_description.to_string(),
+            task_type: "scheil".to_string(),
+            idempotency_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct TaskIdParams {
+    /// 任务ID
     pub task_id: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ListTasksParams {
+    /// 页码
     #[serde(default = "default_page")]
     pub page: i32,
+    /// 每页项目数
     #[serde(default = "default_items_per_page")]
     pub items_per_page: i32,
 }
@@ -177,26 +481,98 @@ fn default_database() -> String { "default".to_string() }
 fn default_page() -> i32 { 1 }
 fn default_items_per_page() -> i32 { 50 }
 
+// Calpha Mesh API 客户端构建器
+pub struct CalphaMeshClientBuilder {
+    // API 密钥
+    api_key: String,
+    // 基础 URL
+    base_url: String,
+    // 单个请求的超时时间
+    timeout: Option<Duration>,
+    // HTTP 客户端
+    client: reqwest::Client,
+}
+
+impl CalphaMeshClientBuilder {
+    // 创建新的客户端构建器
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            base_url: API_BASE_URL.to_string(),
+            timeout: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    // 设置 API 密钥
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = api_key.into();
+        self
+    }
+
+    // 设置基础 URL（例如测试环境或私有部署）
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// 设置单个请求的超时时间，超时按 `HttpError` 处理（可重试）
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // 设置自定义 HTTP 客户端（例如配置了代理或连接池）
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    // 构建客户端
+    pub fn build(self) -> CalphaMeshClient {
+        CalphaMeshClient {
+            api_key: self.api_key,
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            timeout: self.timeout,
+            client: self.client,
+        }
+    }
+}
+
 // Calpha Mesh API 客户端
 #[derive(Clone)]
 pub struct CalphaMeshClient {
     api_key: String,
+    base_url: String,
+    timeout: Option<Duration>,
     client: reqwest::Client,
 }
 
 impl CalphaMeshClient {
     pub fn new(api_key: String) -> Self {
-        Self {
-            api_key,
-            client: reqwest::Client::new(),
-        }
+        Self::builder(api_key).build()
+    }
+
+    // 创建客户端构建器
+    pub fn builder(api_key: impl Into<String>) -> CalphaMeshClientBuilder {
+        CalphaMeshClientBuilder::new(api_key)
     }
 
-    async fn make_request(&self, url: &str, body: String) -> Result<String, CalphaMeshError> {
-        let response = self.client
-            .post(url)
+    // 构造发往 `path` 的 POST 请求，带上认证头和超时设置
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client
+            .post(format!("{}{}", self.base_url, path))
             .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
+            .header("Content-Type", "application/json");
+
+        match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+
+    async fn make_request(&self, path: &str, body: String) -> Result<String, CalphaMeshError> {
+        let response = self.post(path)
             .body(body)
             .send()
             .await
@@ -217,127 +593,51 @@ impl CalphaMeshClient {
     }
 
     pub async fn submit_point_task(&self, params: PointTaskParams) -> Result<TaskResponse, CalphaMeshError> {
-        let task_description = json!({
-            "task_type": "point",
-            "components": params.components,
-            "config": {
-                "conditions": {
-                    "equilibrium_type": {"@type": "global"},
-                    "driving_force": {"@value": true}
-                },
-                "suspended_phases": ["*"],
-                "entered_phases": ["Liquid", "Fcc"],
-                "targets": ["T", "G(@*)", "phase_name", "mu(*@*)"],
-                "n_unit": "x"
-            },
-            "ctp": {
-                "composition": params.composition,
-                "temperature": params.temperature,
-                "pressure": params.pressure
-            },
-            "database": params.database
-        });
-
-        let title = format!("Task-Point-{}", chrono::Utc::now().timestamp());
-        let create_body = CreateTaskApiKeyRequest {
-            db_key: params.database.clone(),
-            title: title.clone(),
-            description: task_description.to_string(),
-            task_type: "point".to_string(),
-        };
-
-        let url = format!("{}/api/v1/create_task", API_BASE_URL);
-        let response_text = self.make_request(&url, serde_json::to_string(&create_body)?).await?;
-        let task_response: TaskResponse = serde_json::from_str(&response_text)?;
-
-        Ok(task_response)
+        self.create_task(params.task_request()).await
     }
 
     pub async fn submit_line_task(&self, params: LineTaskParams) -> Result<TaskResponse, CalphaMeshError> {
-        let task_description = json!({
-            "task_type": "line",
-            "components": params.components,
-            "ctp": {
-                "composition": params.start_composition,
-                "temperature": params.start_temperature,
-                "pressure": params.pressure
-            },
-            "ctp_1": {
-                "composition": params.end_composition,
-                "temperature": params.end_temperature,
-                "pressure": params.pressure
-            },
-            "ctp_steps": params.steps,
-            "config": {
-                "conditions": {
-                    "equilibrium_type": {"@type": "global"},
-                    "driving_force": {"@value": true}
-                },
-                "suspended_phases": ["*"],
-                "entered_phases": ["Liquid", "Fcc"],
-                "targets": ["T", "G(@*)", "phase_name", "mu(*@*)"],
-                "n_unit": "x"
-            },
-            "database": params.database,
-            "type": "line",
-            "name": format!("Task-Line-{}", chrono::Utc::now().timestamp())
-        });
-
-        let create_body = CreateTaskApiKeyRequest {
-            db_key: params.database.clone(),
-            title: format!("Task-Line-{}", chrono::Utc::now().timestamp()),
-            description: task_description.to_string(),
-            task_type: "line".to_string(),
-        };
+        self.create_task(params.task_request()).await
+    }
 
-        let url = format!("{}/api/v1/create_task", API_BASE_URL);
-        let response_text = self.make_request(&url, serde_json::to_string(&create_body)?).await?;
-        let task_response: TaskResponse = serde_json::from_str(&response_text)?;
+    pub async fn submit_scheil_task(&self, params: ScheilTaskParams) -> Result<TaskResponse, CalphaMeshError> {
+        self.create_task(params.task_request()).await
+    }
 
-        Ok(task_response)
+    /// 创建任务，网络错误等暂时性失败时自动重试
+    ///
+    /// 未指定幂等键时生成一个 UUID，所有重试都使用同一个键，便于服务端去重。
+    /// 对不支持幂等键的后端，重试前会先在最近的任务中按标题查找，
+    /// 若上一次尝试其实已经创建了任务（例如响应超时），直接返回该任务而不重复提交。
+    pub async fn create_task(&self, mut body: CreateTaskApiKeyRequest) -> Result<TaskResponse, CalphaMeshError> {
+        body.idempotency_key.get_or_insert_with(|| uuid::Uuid::new_v4().to_string());
+        create_with_retry(
+            &body,
+            SUBMIT_ATTEMPTS,
+            |payload| self.make_request("/api/v1/create_task", payload),
+            |title| async move { self.find_recent_task(&title).await },
+        )
+        .await
     }
 
-    pub async fn submit_scheil_task(&self, params: ScheilTaskParams) -> Result<TaskResponse, CalphaMeshError> {
-        let task_description = json!({
-            "task_type": "scheil",
-            "components": params.components,
-            "ctp": {
-                "composition": params.composition,
-                "temperature": params.temperature,
-                "pressure": params.pressure
-            },
-            "config": {
-                "targets": ["fl", "fs", "phase_name", "Label", "f_tot(@*)", "f(@*)", "T//fs", "Q"],
-                "entered_phases": ["*"],
-                "suspended_phases": ["*"],
-                "n_unit": "x",
-                "conditions": {
-                    "step_T_max": {"@value": "1"},
-                    "model": {"@type": "Scheil"},
-                    "start_from_liquidus_surface": {"@value": "yes"},
-                    "end_when_no_more_liquid": {"@value": "yes"},
-                    "T_end": {"@value": "300"},
-                    "step_T_min": {"@value": "0.01"},
-                    "liquid_amount_min": {"@value": "0.001"},
-                    "x_min": {"@value": "1e-12"}
-                }
-            },
-            "database": params.database,
-            "name": format!("Task-Scheil-{}", chrono::Utc::now().timestamp())
-        });
+    // 在最近的任务中按标题查找
+    async fn find_recent_task(&self, title: &str) -> Result<Option<TaskResponse>, CalphaMeshError> {
+        let list = self.list_tasks(1, RECENT_TASKS_WINDOW).await?;
 
-        let create_body = CreateTaskApiKeyRequest {
-            db_key: params.database.clone(),
-            title: format!("Task-Scheil-{}", chrono::Utc::now().timestamp()),
-            description: task_description.to_string(),
-            task_type: "scheil".to_string(),
-        };
+        Ok(list.data.into_iter().find(|task| task.title == title).map(|task| TaskResponse {
+            id: task.id,
+            status: task.status,
+            task_type: task.task_type,
+        }))
+    }
 
-        let url = format!("{}/api/v1/create_task", API_BASE_URL);
-        let response_text = self.make_request(&url, serde_json::to_string(&create_body)?).await?;
-        let task_response: TaskResponse = serde_json::from_str(&response_text)?;
+    /// 检查 API 是否可达、API Key 是否有效，适合在批量提交任务前调用
+    ///
+    /// 以每页 1 条的方式请求任务列表；401/403 返回 `Unauthorized`，其他失败按原样返回。
+    pub async fn verify(&self) -> Result<(), CalphaMeshError> {
+        let get_tasks_body = GetTasksApiKeyRequest { page: 1, items_per_page: 1 };
 
-        Ok(task_response)
+        verify_response(self.make_request("/api/v1/get_tasks", serde_json::to_string(&get_tasks_body)?).await)
     }
 
     pub async fn get_task_status(&self, task_id: i32) -> Result<TaskStatusResponse, CalphaMeshError> {
@@ -346,8 +646,7 @@ impl CalphaMeshClient {
         }
 
         let get_task_body = GetTaskApiKeyRequest { id: task_id };
-        let url = format!("{}/api/v1/get_task", API_BASE_URL);
-        let response_text = self.make_request(&url, serde_json::to_string(&get_task_body)?).await?;
+        let response_text = self.make_request("/api/v1/get_task", serde_json::to_string(&get_task_body)?).await?;
         let task: TaskStatusResponse = serde_json::from_str(&response_text)?;
 
         Ok(task)
@@ -355,12 +654,274 @@ impl CalphaMeshClient {
 
     pub async fn list_tasks(&self, page: i32, items_per_page: i32) -> Result<TaskListResponse, CalphaMeshError> {
         let get_tasks_body = GetTasksApiKeyRequest { page, items_per_page };
-        let url = format!("{}/api/v1/get_tasks", API_BASE_URL);
-        let response_text = self.make_request(&url, serde_json::to_string(&get_tasks_body)?).await?;
+        let response_text = self.make_request("/api/v1/get_tasks", serde_json::to_string(&get_tasks_body)?).await?;
         let list: TaskListResponse = serde_json::from_str(&response_text)?;
 
         Ok(list)
     }
+
+    /// 以有限并发查询多个任务的状态，返回 任务ID → 状态 的映射
+    ///
+    /// 同时进行中的请求不超过 `concurrency` 个（至少为 1），避免批量轮询时触发接口限流。
+    /// 重复的任务ID只查询一次；任一查询失败时返回该错误。
+    pub async fn poll_many(&self, task_ids: impl IntoIterator<Item = i32>, concurrency: usize) -> Result<HashMap<i32, TaskStatusResponse>, CalphaMeshError> {
+        poll_bounded(task_ids, concurrency, |task_id| self.get_task_status(task_id)).await
+    }
+
+    /// 实时跟踪任务日志，逐行返回新追加的日志，直到任务完成或失败
+    ///
+    /// 每次轮询只返回之前未见过的行；尚未以换行结束的最后一行会等到完整后再返回。
+    /// 查询失败时返回该错误并结束。
+    pub fn stream_logs(&self, task_id: i32) -> impl Stream<Item = Result<String, CalphaMeshError>> + '_ {
+        tail_logs(task_id, LOG_POLL_INTERVAL, move |task_id| self.get_task_status(task_id))
+    }
+
+    /// 轮询任务状态直到任务完成或失败，返回最后一次查询到的任务
+    ///
+    /// 轮询间隔按 `policy` 逐渐增大，耗时较长的 Scheil 等任务后期会更少地请求接口。
+    /// 查询失败时返回该错误。
+    pub async fn wait_for_completion(&self, task_id: i32, policy: PollPolicy) -> Result<TaskStatusResponse, CalphaMeshError> {
+        poll_until_finished(task_id, &policy, |task_id| self.get_task_status(task_id), futures_timer::Delay::new).await
+    }
+}
+
+/// 等待任务完成时的轮询策略
+///
+/// 第 n 次轮询后等待 `interval * backoff^(n-1)`，最长不超过 `max_interval`；
+/// 实际等待时间再在 ±`jitter` 比例内随机浮动，避免同时等待的多个任务一起请求接口。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PollPolicy {
+    interval: Duration,
+    backoff: f64,
+    max_interval: Duration,
+    jitter: f64,
+}
+
+impl Default for PollPolicy {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_POLL_INTERVAL,
+            backoff: DEFAULT_POLL_BACKOFF,
+            max_interval: DEFAULT_MAX_POLL_INTERVAL,
+            jitter: DEFAULT_POLL_JITTER,
+        }
+    }
+}
+
+impl PollPolicy {
+    /// 以固定间隔轮询，不退避也不抖动
+    pub fn fixed(interval: Duration) -> Self {
+        Self { interval, backoff: 1.0, max_interval: interval, jitter: 0.0 }
+    }
+
+    /// 每次轮询后间隔乘以 `factor`（至少为 1），最长不超过 `max_interval`
+    pub fn backoff(mut self, factor: f64, max_interval: Duration) -> Self {
+        self.backoff = factor.max(1.0);
+        self.max_interval = max_interval.max(self.interval);
+        self
+    }
+
+    /// 间隔随机浮动的比例，取值 0 到 1
+    pub fn jitter(mut self, ratio: f64) -> Self {
+        self.jitter = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// 第 `poll` 次轮询（从 1 开始）之后的等待时间，不含抖动
+    pub fn delay(&self, poll: u32) -> Duration {
+        let exponent = poll.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let secs = self.interval.as_secs_f64() * self.backoff.powi(exponent);
+        if secs < self.max_interval.as_secs_f64() {
+            Duration::from_secs_f64(secs)
+        } else {
+            self.max_interval
+        }
+    }
+
+    // 在 delay 上叠加抖动；random 取值 0 到 1
+    fn jittered(&self, delay: Duration, random: f64) -> Duration {
+        delay.mul_f64(1.0 + self.jitter * (2.0 * random - 1.0))
+    }
+}
+
+// 0 到 1 之间的随机数，仅用于轮询抖动
+fn random_unit() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    // 每个 RandomState 使用不同的随机种子
+    let hash = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// 按 policy 的间隔调用 fetch 查询任务直到任务结束，用 sleep 等待每次轮询之间的间隔
+async fn poll_until_finished<F, Fut, S, SFut>(task_id: i32, policy: &PollPolicy, fetch: F, sleep: S) -> Result<TaskStatusResponse, CalphaMeshError>
+where
+    F: Fn(i32) -> Fut,
+    Fut: Future<Output = Result<TaskStatusResponse, CalphaMeshError>>,
+    S: Fn(Duration) -> SFut,
+    SFut: Future<Output = ()>,
+{
+    let mut poll = 1;
+    loop {
+        let task = fetch(task_id).await?;
+        if task.status.is_finished() {
+            return Ok(task);
+        }
+
+        sleep(policy.jittered(policy.delay(poll), random_unit())).await;
+        poll += 1;
+    }
+}
+
+// 将认证失败的响应映射为 Unauthorized
+fn verify_response(response: Result<String, CalphaMeshError>) -> Result<(), CalphaMeshError> {
+    match response {
+        Ok(_) => Ok(()),
+        Err(CalphaMeshError::ApiError { status: status @ (401 | 403), message }) => {
+            Err(CalphaMeshError::Unauthorized { status, message })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// 以最多 concurrency 个并发请求调用 fetch 查询每个任务
+async fn poll_bounded<F, Fut>(task_ids: impl IntoIterator<Item = i32>, concurrency: usize, fetch: F) -> Result<HashMap<i32, TaskStatusResponse>, CalphaMeshError>
+where
+    F: Fn(i32) -> Fut,
+    Fut: Future<Output = Result<TaskStatusResponse, CalphaMeshError>>,
+{
+    let mut task_ids: Vec<i32> = task_ids.into_iter().collect();
+    task_ids.sort_unstable();
+    task_ids.dedup();
+
+    let mut statuses = stream::iter(task_ids)
+        .map(|task_id| {
+            let status = fetch(task_id);
+            async move { (task_id, status.await) }
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut results = HashMap::new();
+    while let Some((task_id, status)) = statuses.next().await {
+        results.insert(task_id, status?);
+    }
+
+    Ok(results)
+}
+
+// 每隔 interval 调用 fetch 查询任务，返回日志中新增的行
+fn tail_logs<F, Fut>(task_id: i32, interval: Duration, fetch: F) -> impl Stream<Item = Result<String, CalphaMeshError>>
+where
+    F: Fn(i32) -> Fut,
+    Fut: Future<Output = Result<TaskStatusResponse, CalphaMeshError>>,
+{
+    async_stream::stream! {
+        let mut seen: Vec<String> = Vec::new();
+        loop {
+            let task = match fetch(task_id).await {
+                Ok(task) => task,
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            };
+            let finished = task.status.is_finished();
+
+            let lines = complete_log_lines(task.logs.as_deref().unwrap_or_default(), finished);
+            for line in &lines[first_unseen_line(&seen, &lines)..] {
+                yield Ok(line.clone());
+            }
+            seen = lines;
+
+            if finished {
+                break;
+            }
+            futures_timer::Delay::new(interval).await;
+        }
+    }
+}
+
+// 日志中已完整的行；任务未结束时，最后一行若没有换行则可能还在写入
+fn complete_log_lines(logs: &str, finished: bool) -> Vec<String> {
+    let mut lines: Vec<String> = logs.lines().map(str::to_string).collect();
+    if !finished && !logs.ends_with('\n') {
+        lines.pop();
+    }
+    lines
+}
+
+// current 中第一个未见过的行：日志通常只在末尾追加，
+// 但接口可能只返回最近的一段日志，此时跳过与上次结尾重叠的部分
+fn first_unseen_line(seen: &[String], current: &[String]) -> usize {
+    if current.starts_with(seen) {
+        return seen.len();
+    }
+    (1..=seen.len().min(current.len()))
+        .rev()
+        .find(|&overlap| seen[seen.len() - overlap..] == current[..overlap])
+        .unwrap_or(0)
+}
+
+// 发送创建任务请求，暂时性失败时最多尝试 attempts 次；每次重试前先用 find_existing 按标题查找已创建的任务
+async fn create_with_retry<S, SFut, F, FFut>(body: &CreateTaskApiKeyRequest, attempts: usize, send: S, find_existing: F) -> Result<TaskResponse, CalphaMeshError>
+where
+    S: Fn(String) -> SFut,
+    SFut: Future<Output = Result<String, CalphaMeshError>>,
+    F: Fn(String) -> FFut,
+    FFut: Future<Output = Result<Option<TaskResponse>, CalphaMeshError>>,
+{
+    let payload = serde_json::to_string(body)?;
+
+    let mut attempt = 1;
+    loop {
+        let error = match send(payload.clone()).await {
+            Ok(response_text) => return Ok(serde_json::from_str(&response_text)?),
+            Err(e) if e.is_transient() && attempt < attempts => e,
+            Err(e) => return Err(e),
+        };
+        tracing::warn!("Creating task {} failed (attempt {attempt}/{attempts}): {error}", body.title);
+
+        // 上一次尝试可能已经创建了任务
+        match find_existing(body.title.clone()).await {
+            Ok(Some(task)) => return Ok(task),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Could not look up task {}: {e}", body.title),
+        }
+        attempt += 1;
+    }
+}
+
+// 设置该环境变量（非空且不为 "0" 或 "false"）时，工具输出使用不含 emoji 的纯文本风格
+pub const PLAIN_OUTPUT_ENV: &str = "CALPHAMESH_PLAIN_OUTPUT";
+
+/// 工具输出风格
+///
+/// 默认带 emoji，便于在聊天界面中阅读；纯文本风格不含 emoji，适合不支持 emoji 的终端与下游解析。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputStyle {
+    #[default]
+    Emoji,
+    Plain,
+}
+
+impl OutputStyle {
+    /// 根据 `CALPHAMESH_PLAIN_OUTPUT` 环境变量确定输出风格
+    pub fn from_env() -> Self {
+        match std::env::var(PLAIN_OUTPUT_ENV) {
+            Ok(value) if !matches!(value.trim().to_lowercase().as_str(), "" | "0" | "false") => {
+                OutputStyle::Plain
+            }
+            _ => OutputStyle::Emoji,
+        }
+    }
+
+    // 行首图标（带分隔空格）；纯文本风格下为空
+    fn icon(self, icon: &str) -> String {
+        match self {
+            OutputStyle::Emoji => format!("{icon} "),
+            OutputStyle::Plain => String::new(),
+        }
+    }
 }
 
 // 工具实现
@@ -380,45 +941,14 @@ impl Tool for SubmitPointTask {
         ToolDefinition {
             name: "calphamesh_submit_point_task".to_string(),
             description: "提交 Point 平衡计算任务到 Calpha Mesh 服务器".to_string(),
-            parameters: json!({
-                "type": "object",
-                "properties": {
-                    "components": {
-                        "type": "array",
-                        "items": {"type": "string"},
-                        "description": "组分列表"
-                    },
-                    "composition": {
-                        "type": "object",
-                        "additionalProperties": {"type": "number"},
-                        "description": "成分组成 (元素:原子分数)，原子分数之和必须为1"
-                    },
-                    "temperature": {
-                        "type": "number",
-                        "description": "计算温度(K)"
-                    },
-                    "pressure": {
-                        "type": "number",
-                        "description": "计算压力(atm)"
-                    },
-                    "database": {
-                        "type": "string",
-                        "description": "数据库名称，默认为 default"
-                    }
-                },
-                "required": []
-            }),
+            parameters: parameters_schema::<PointTaskParams>(),
         }
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         let client = CalphaMeshClient::new("tk_zaEVQtzrfFIXKh7EnBoja8KnGIfjV0T8".to_string());
-        let task_response = client.submit_point_task(args).await?;
 
-        Ok(format!(
-            "✅ Point 计算任务提交成功！\n📋 任务ID: {}\n📊 状态: {}\n🔬 类型: point",
-            task_response.id, task_response.status
-        ))
+        submit_task(&client, "Point", args.task_request(), args.dry_run, OutputStyle::from_env()).await
     }
 }
 
@@ -437,58 +967,14 @@ impl Tool for SubmitLineTask {
         ToolDefinition {
             name: "calphamesh_submit_line_task".to_string(),
             description: "提交 Line 线性计算任务到 Calpha Mesh 服务器".to_string(),
-            parameters: json!({
-                "type": "object",
-                "properties": {
-                    "components": {
-                        "type": "array",
-                        "items": {"type": "string"},
-                        "description": "组分列表"
-                    },
-                    "start_composition": {
-                        "type": "object",
-                        "additionalProperties": {"type": "number"},
-                        "description": "起始成分组成 (元素:原子分数)，原子分数之和必须为1"
-                    },
-                    "start_temperature": {
-                        "type": "number",
-                        "description": "起始温度(K)"
-                    },
-                    "end_composition": {
-                        "type": "object",
-                        "additionalProperties": {"type": "number"},
-                        "description": "结束成分组成 (元素:原子分数)，原子分数之和必须为1"
-                    },
-                    "end_temperature": {
-                        "type": "number",
-                        "description": "结束温度(K)"
-                    },
-                    "steps": {
-                        "type": "integer",
-                        "description": "计算步数"
-                    },
-                    "pressure": {
-                        "type": "number",
-                        "description": "计算压力(atm)"
-                    },
-                    "database": {
-                        "type": "string",
-                        "description": "数据库名称，默认为 default"
-                    }
-                },
-                "required": []
-            }),
+            parameters: parameters_schema::<LineTaskParams>(),
         }
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         let client = CalphaMeshClient::new("tk_zaEVQtzrfFIXKh7EnBoja8KnGIfjV0T8".to_string());
-        let task_response = client.submit_line_task(args).await?;
 
-        Ok(format!(
-            "✅ Line 计算任务提交成功！\n📋 任务ID: {}\n📊 状态: {}\n🔬 类型: line",
-            task_response.id, task_response.status
-        ))
+        submit_task(&client, "Line", args.task_request(), args.dry_run, OutputStyle::from_env()).await
     }
 }
 
@@ -507,45 +993,14 @@ impl Tool for SubmitScheilTask {
         ToolDefinition {
             name: "calphamesh_submit_scheil_task".to_string(),
             description: "提交 Scheil 凝固计算任务到 Calpha Mesh 服务器".to_string(),
-            parameters: json!({
-                "type": "object",
-                "properties": {
-                    "components": {
-                        "type": "array",
-                        "items": {"type": "string"},
-                        "description": "组分列表"
-                    },
-                    "composition": {
-                        "type": "object",
-                        "additionalProperties": {"type": "number"},
-                        "description": "成分组成 (元素:原子分数)，原子分数之和必须为1"
-                    },
-                    "temperature": {
-                        "type": "number",
-                        "description": "起始温度(K)"
-                    },
-                    "pressure": {
-                        "type": "number",
-                        "description": "计算压力(atm)"
-                    },
-                    "database": {
-                        "type": "string",
-                        "description": "数据库名称，默认为 default"
-                    }
-                },
-                "required": []
-            }),
+            parameters: parameters_schema::<ScheilTaskParams>(),
         }
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         let client = CalphaMeshClient::new("tk_zaEVQtzrfFIXKh7EnBoja8KnGIfjV0T8".to_string());
-        let task_response = client.submit_scheil_task(args).await?;
 
-        Ok(format!(
-            "✅ Scheil 计算任务提交成功！\n📋 任务ID: {}\n📊 状态: {}\n🔬 类型: scheil",
-            task_response.id, task_response.status
-        ))
+        submit_task(&client, "Scheil", args.task_request(), args.dry_run, OutputStyle::from_env()).await
     }
 }
 
@@ -555,6 +1010,7 @@ pub struct GetTaskStatus;
 
 impl Tool for GetTaskStatus {
     const NAME: &'static str = "calphamesh_get_task_status";
+    const MUTATES: bool = false;
 
     type Error = CalphaMeshError;
     type Args = TaskIdParams;
@@ -564,16 +1020,7 @@ impl Tool for GetTaskStatus {
         ToolDefinition {
             name: "calphamesh_get_task_status".to_string(),
             description: "根据任务ID查询 Calpha Mesh 任务状态和结果".to_string(),
-            parameters: json!({
-                "type": "object",
-                "properties": {
-                    "task_id": {
-                        "type": "integer",
-                        "description": "任务ID"
-                    }
-                },
-                "required": ["task_id"]
-            }),
+            parameters: parameters_schema::<TaskIdParams>(),
         }
     }
 
@@ -581,30 +1028,7 @@ impl Tool for GetTaskStatus {
         let client = CalphaMeshClient::new("tk_zaEVQtzrfFIXKh7EnBoja8KnGIfjV0T8".to_string());
         let task = client.get_task_status(args.task_id).await?;
 
-        let status_emoji = match task.status.as_str() {
-            "pending" => "⏳",
-            "queued" => "📋",
-            "running" => "⚙️",
-            "completed" => "✅",
-            "failed" => "❌",
-            _ => "❓"
-        };
-
-        let mut result = format!(
-            "{} 任务状态查询结果\n\n📋 任务ID: {}\n📝 标题: {}\n🔬 类型: {}\n📊 状态: {} {}\n👤 用户ID: {}\n🕐 创建时间: {}\n🕒 更新时间: {}",
-            status_emoji, task.id, task.title, task.task_type, status_emoji, task.status, task.user_id, task.created_at, task.updated_at
-        );
-
-        if let Some(result_data) = &task.result {
-            result.push_str("\n\n🎯 计算结果:\n");
-            result.push_str(result_data);
-        }
-
-        if let Some(logs) = &task.logs {
-            result.push_str(&format!("\n\n📄 日志:\n{}", logs));
-        }
-
-        Ok(result)
+        Ok(render_task_status(&task, OutputStyle::from_env()))
     }
 }
 
@@ -614,6 +1038,7 @@ pub struct ListTasks;
 
 impl Tool for ListTasks {
     const NAME: &'static str = "calphamesh_list_tasks";
+    const MUTATES: bool = false;
 
     type Error = CalphaMeshError;
     type Args = ListTasksParams;
@@ -623,20 +1048,7 @@ impl Tool for ListTasks {
         ToolDefinition {
             name: "calphamesh_list_tasks".to_string(),
             description: "列出当前用户的 Calpha Mesh 任务列表".to_string(),
-            parameters: json!({
-                "type": "object",
-                "properties": {
-                    "page": {
-                        "type": "integer",
-                        "description": "页码 (默认: 1)"
-                    },
-                    "items_per_page": {
-                        "type": "integer",
-                        "description": "每页项目数 (默认: 50)"
-                    }
-                },
-                "required": []
-            }),
+            parameters: parameters_schema::<ListTasksParams>(),
         }
     }
 
@@ -644,27 +1056,637 @@ impl Tool for ListTasks {
         let client = CalphaMeshClient::new("tk_zaEVQtzrfFIXKh7EnBoja8KnGIfjV0T8".to_string());
         let list = client.list_tasks(args.page, args.items_per_page).await?;
 
-        let mut result = format!("📋 我的任务列表 (第 {} 页，共 {} 页)\n\n", list.page, list.total_pages);
+        Ok(render_task_list(&list, OutputStyle::from_env()))
+    }
+}
 
-        if list.data.is_empty() {
-            result.push_str("🤷‍♂️ 暂无任务");
-        } else {
-            for (idx, task) in list.data.iter().enumerate() {
-                let status_emoji = match task.status.as_str() {
-                    "pending" => "⏳",
-                    "queued" => "📋",
-                    "running" => "⚙️",
-                    "completed" => "✅",
-                    "failed" => "❌",
-                    _ => "❓"
+// 提交任务；dry_run 时只返回将要提交的请求，不发送任何 HTTP 请求
+async fn submit_task(
+    client: &CalphaMeshClient,
+    kind: &str,
+    request: CreateTaskApiKeyRequest,
+    dry_run: bool,
+    style: OutputStyle,
+) -> Result<String, CalphaMeshError> {
+    if dry_run {
+        return render_dry_run(kind, &request, style);
+    }
+
+    let task_response = client.create_task(request).await?;
+    Ok(render_submitted(kind, &task_response, style))
+}
+
+// 工具输出渲染
+
+// 提交任务成功的结果
+fn render_submitted(kind: &str, task: &TaskResponse, style: OutputStyle) -> String {
+    format!(
+        "{}{} 计算任务提交成功！\n{}任务ID: {}\n{}状态: {}\n{}类型: {}",
+        style.icon("✅"), kind, style.icon("📋"), task.id, style.icon("📊"), task.status, style.icon("🔬"), kind.to_lowercase()
+    )
+}
+
+// 预览模式下将要提交的任务
+fn render_dry_run(kind: &str, request: &CreateTaskApiKeyRequest, style: OutputStyle) -> Result<String, CalphaMeshError> {
+    let description: serde_json::Value = serde_json::from_str(&request.description)?;
+
+    Ok(format!(
+        "{}{} 计算任务预览（未提交）\n{}标题: {}\n{}数据库: {}\n{}任务描述:\n{}",
+        style.icon("👀"), kind, style.icon("📝"), request.title, style.icon("🗄️"), request.db_key, style.icon("🔬"),
+        serde_json::to_string_pretty(&description)?
+    ))
+}
+
+// 任务状态查询结果
+fn render_task_status(task: &TaskStatusResponse, style: OutputStyle) -> String {
+    let status_icon = style.icon(task.status.emoji());
+
+    let mut result = format!(
+        "{}任务状态查询结果\n\n{}任务ID: {}\n{}标题: {}\n{}类型: {}\n{}状态: {}{}\n{}用户ID: {}\n{}创建时间: {}\n{}更新时间: {}",
+        status_icon,
+        style.icon("📋"), task.id,
+        style.icon("📝"), task.title,
+        style.icon("🔬"), task.task_type,
+        style.icon("📊"), status_icon, task.status,
+        style.icon("👤"), task.user_id,
+        style.icon("🕐"), format_timestamp(task.created_at),
+        style.icon("🕒"), format_timestamp(task.updated_at)
+    );
+
+    if let Some(duration) = task.duration() {
+        result.push_str(&format!("\n{}耗时: {} 秒", style.icon("⏱️"), duration.num_seconds()));
+    }
+
+    if let Some(result_data) = &task.result {
+        result.push_str(&format!("\n\n{}计算结果:\n", style.icon("🎯")));
+        result.push_str(result_data);
+    }
+
+    if let Some(logs) = &task.logs {
+        result.push_str(&format!("\n\n{}日志:\n{}", style.icon("📄"), logs));
+    }
+
+    result
+}
+
+// 任务列表
+fn render_task_list(list: &TaskListResponse, style: OutputStyle) -> String {
+    let mut result = format!("{}我的任务列表 (第 {} 页，共 {} 页)\n\n", style.icon("📋"), list.page, list.total_pages);
+
+    if list.data.is_empty() {
+        result.push_str(&format!("{}暂无任务", style.icon("🤷‍♂️")));
+    } else {
+        for (idx, task) in list.data.iter().enumerate() {
+            result.push_str(&format!(
+                "{}. {}ID:{} | {} | {} | {}\n",
+                idx + 1, style.icon(task.status.emoji()), task.id, task.task_type, task.status, task.title
+            ));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use chrono::TimeZone;
+
+    fn task_status(id: i32) -> TaskStatusResponse {
+        serde_json::from_value(json!({
+            "id": id,
+            "title": format!("Task-Point-{id}"),
+            "description": "",
+            "status": "completed",
+            "task_type": "point",
+            "result": null,
+            "logs": null,
+            "user_id": 1,
+            "created_at": "2025-01-01T00:00:00",
+            "updated_at": "2025-01-01T00:00:00"
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_poll_bounded_limits_in_flight_requests() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let statuses = poll_bounded(1..=12, 3, |task_id| {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            let requests = requests.clone();
+            async move {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+                requests.fetch_add(1, Ordering::SeqCst);
+
+                tokio::time::sleep(Duration::from_millis(10)).await;
+
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(task_status(task_id))
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 12);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+        assert_eq!(statuses.len(), 12);
+        for task_id in 1..=12 {
+            assert_eq!(statuses[&task_id].id, task_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_poll_bounded_deduplicates_and_propagates_errors() {
+        let requests = AtomicUsize::new(0);
+
+        let statuses = poll_bounded([5, 5, 7], 0, |task_id| {
+            requests.fetch_add(1, Ordering::SeqCst);
+            async move { Ok(task_status(task_id)) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(statuses.len(), 2);
+
+        let err = poll_bounded([1, 2], 2, |task_id| async move {
+            if task_id == 2 {
+                Err(CalphaMeshError::InvalidTaskId(task_id))
+            } else {
+                Ok(task_status(task_id))
+            }
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(err, CalphaMeshError::InvalidTaskId(2)));
+    }
+
+    fn create_body() -> CreateTaskApiKeyRequest {
+        CreateTaskApiKeyRequest {
+            db_key: "default".to_string(),
+            title: "Task-Point-1700000000".to_string(),
+            description: "{}".to_string(),
+            task_type: "point".to_string(),
+            idempotency_key: Some("3f1c9a52-8d4e-4b7a-9c2e-5e6f7a8b9c0d".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_retry_reuses_idempotency_key() {
+        let sent = std::sync::Mutex::new(Vec::new());
+
+        let task = create_with_retry(
+            &create_body(),
+            3,
+            |payload| {
+                let mut sent = sent.lock().unwrap();
+                sent.push(serde_json::from_str::<serde_json::Value>(&payload).unwrap());
+                let response = if sent.len() == 1 {
+                    Err(CalphaMeshError::HttpError("operation timed out".to_string()))
+                } else {
+                    Ok(json!({"id": 7, "status": "pending", "task_type": "point"}).to_string())
                 };
-                result.push_str(&format!(
-                    "{}. {} ID:{} | {} | {} | {}\n",
-                    idx + 1, status_emoji, task.id, task.task_type, task.status, task.title
-                ));
+                async move { response }
+            },
+            |_title| async { Ok(None) },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(task.id, 7);
+        let sent = sent.into_inner().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0]["idempotency_key"], "3f1c9a52-8d4e-4b7a-9c2e-5e6f7a8b9c0d");
+        assert_eq!(sent[0]["idempotency_key"], sent[1]["idempotency_key"]);
+    }
+
+    #[tokio::test]
+    async fn test_create_retry_returns_task_created_by_failed_attempt() {
+        let sends = AtomicUsize::new(0);
+
+        let task = create_with_retry(
+            &create_body(),
+            3,
+            |_payload| {
+                sends.fetch_add(1, Ordering::SeqCst);
+                async { Err(CalphaMeshError::ApiError { status: 504, message: "Gateway Timeout".to_string() }) }
+            },
+            |title| async move {
+                assert_eq!(title, "Task-Point-1700000000");
+                Ok(Some(TaskResponse { id: 9, status: TaskStatus::Queued, task_type: "point".to_string() }))
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(task.id, 9);
+        assert_eq!(sends.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_does_not_retry_client_errors() {
+        let sends = AtomicUsize::new(0);
+
+        let err = create_with_retry(
+            &create_body(),
+            3,
+            |_payload| {
+                sends.fetch_add(1, Ordering::SeqCst);
+                async { Err(CalphaMeshError::ApiError { status: 400, message: "Bad Request".to_string() }) }
+            },
+            |_title| async { Ok(None) },
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(err, CalphaMeshError::ApiError { status: 400, .. }));
+        assert_eq!(sends.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_builder_applies_configuration_to_requests() {
+        let client = CalphaMeshClient::builder("tk_old")
+            .api_key("tk_test")
+            .base_url("http://localhost:8080/")
+            .timeout(Duration::from_secs(5))
+            .with_client(reqwest::Client::new())
+            .build();
+
+        let request = client.post("/api/v1/get_task").build().unwrap();
+
+        assert_eq!(request.url().as_str(), "http://localhost:8080/api/v1/get_task");
+        assert_eq!(request.headers()["Authorization"], "Bearer tk_test");
+        assert_eq!(request.timeout(), Some(&Duration::from_secs(5)));
+
+        let request = CalphaMeshClient::new("tk_test".to_string()).post("/api/v1/get_task").build().unwrap();
+        assert_eq!(request.url().as_str(), "https://api.topmaterial-tech.com/api/v1/get_task");
+        assert_eq!(request.timeout(), None);
+    }
+
+    #[test]
+    fn test_task_status_deserializes_known_and_unknown_statuses() {
+        for (raw, status) in [
+            ("pending", TaskStatus::Pending),
+            ("queued", TaskStatus::Queued),
+            ("running", TaskStatus::Running),
+            ("completed", TaskStatus::Completed),
+            ("failed", TaskStatus::Failed),
+            ("cancelled", TaskStatus::Other("cancelled".to_string())),
+        ] {
+            assert_eq!(serde_json::from_value::<TaskStatus>(json!(raw)).unwrap(), status);
+            assert_eq!(serde_json::to_value(&status).unwrap(), json!(raw));
+            assert_eq!(status.to_string(), raw);
+        }
+
+        let task: TaskStatusResponse = serde_json::from_value(json!({
+            "id": 3,
+            "title": "Task-Point-3",
+            "description": "",
+            "status": "running",
+            "task_type": "point",
+            "result": null,
+            "logs": null,
+            "user_id": 1,
+            "created_at": "2025-01-01T00:00:00",
+            "updated_at": "2025-01-01T00:00:00"
+        }))
+        .unwrap();
+        assert_eq!(task.status, TaskStatus::Running);
+        assert!(!task.status.is_finished());
+        assert!(TaskStatus::Failed.is_finished());
+    }
+
+    #[test]
+    fn test_task_timestamps_parse_and_give_duration() {
+        let task: TaskStatusResponse = serde_json::from_value(json!({
+            "id": 5,
+            "title": "Task-Point-5",
+            "description": "",
+            "status": "completed",
+            "task_type": "point",
+            "result": null,
+            "logs": null,
+            "user_id": 1,
+            "created_at": "2025-03-04T10:15:30.250",
+            "updated_at": "2025-03-04T10:17:00.250"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            task.created_at,
+            Some(Utc.with_ymd_and_hms(2025, 3, 4, 10, 15, 30).unwrap() + chrono::Duration::milliseconds(250))
+        );
+        assert_eq!(task.duration(), Some(chrono::Duration::seconds(90)));
+        assert_eq!(
+            parse_timestamp("2025-03-04T10:15:30+08:00"),
+            Some(Utc.with_ymd_and_hms(2025, 3, 4, 2, 15, 30).unwrap())
+        );
+
+        let running = TaskStatusResponse { status: TaskStatus::Running, ..task_status(5) };
+        assert_eq!(running.duration(), None);
+
+        // 缺失或格式异常的时间戳不影响其余字段的解析
+        let odd: TaskStatusResponse = serde_json::from_value(json!({
+            "id": 6,
+            "title": "Task-Point-6",
+            "description": "",
+            "status": "completed",
+            "task_type": "point",
+            "result": null,
+            "logs": null,
+            "user_id": 1,
+            "created_at": "yesterday"
+        }))
+        .unwrap();
+        assert_eq!(odd.created_at, None);
+        assert_eq!(odd.updated_at, None);
+        assert_eq!(odd.duration(), None);
+    }
+
+    #[test]
+    fn test_plain_output_has_no_emoji() {
+        // 纯文本风格只允许 ASCII、汉字以及中文标点
+        let is_plain = |output: &str| {
+            output.chars().all(|c| c.is_ascii() || c.is_alphabetic() || "，！（）：".contains(c))
+        };
+
+        let submitted = TaskResponse { id: 7, status: TaskStatus::Pending, task_type: "point".to_string() };
+        let task = TaskStatusResponse {
+            result: Some("{\"phases\": [\"FCC_A1\"]}".to_string()),
+            logs: Some("done".to_string()),
+            ..task_status(7)
+        };
+        let list = TaskListResponse { data: vec![task_status(7), task_status(8)], total_pages: 1, page: 1, items_per_page: 10 };
+        let empty = TaskListResponse { data: vec![], total_pages: 0, page: 1, items_per_page: 10 };
+
+        for (emoji, plain) in [
+            (render_submitted("Point", &submitted, OutputStyle::Emoji), render_submitted("Point", &submitted, OutputStyle::Plain)),
+            (render_task_status(&task, OutputStyle::Emoji), render_task_status(&task, OutputStyle::Plain)),
+            (render_task_list(&list, OutputStyle::Emoji), render_task_list(&list, OutputStyle::Plain)),
+            (render_task_list(&empty, OutputStyle::Emoji), render_task_list(&empty, OutputStyle::Plain)),
+        ] {
+            assert!(!is_plain(&emoji), "{emoji}");
+            assert!(is_plain(&plain), "{plain}");
+        }
+
+        assert_eq!(
+            render_submitted("Point", &submitted, OutputStyle::Plain),
+            "Point 计算任务提交成功！\n任务ID: 7\n状态: pending\n类型: point"
+        );
+        assert!(render_task_status(&task, OutputStyle::Plain).starts_with("任务状态查询结果\n\n任务ID: 7\n"));
+        assert_eq!(OutputStyle::default(), OutputStyle::Emoji);
+    }
+
+    #[test]
+    fn test_verify_accepts_successful_response() {
+        let response = Ok(json!({"data": [], "total": 0}).to_string());
+
+        assert!(verify_response(response).is_ok());
+    }
+
+    #[test]
+    fn test_verify_maps_unauthorized_status_to_auth_error() {
+        for status in [401, 403] {
+            let response = Err(CalphaMeshError::ApiError { status, message: "invalid api key".to_string() });
+
+            match verify_response(response) {
+                Err(CalphaMeshError::Unauthorized { status: actual, message }) => {
+                    assert_eq!(actual, status);
+                    assert_eq!(message, "invalid api key");
+                }
+                other => panic!("expected an auth error, got {other:?}"),
             }
         }
 
-        Ok(result)
+        let response = Err(CalphaMeshError::ApiError { status: 500, message: "boom".to_string() });
+        assert!(matches!(verify_response(response), Err(CalphaMeshError::ApiError { status: 500, .. })));
+    }
+
+    fn task_with_logs(status: &str, logs: &str) -> TaskStatusResponse {
+        TaskStatusResponse { status: status.into(), logs: Some(logs.to_string()), ..task_status(1) }
+    }
+
+    // 依次返回给定的任务状态，并记录查询次数
+    async fn tail(responses: Vec<Result<TaskStatusResponse, CalphaMeshError>>) -> (Vec<Result<String, CalphaMeshError>>, usize) {
+        let responses = std::sync::Mutex::new(responses.into_iter());
+        let requests = AtomicUsize::new(0);
+
+        let lines = tail_logs(1, Duration::ZERO, |_| {
+            requests.fetch_add(1, Ordering::SeqCst);
+            let response = responses.lock().unwrap().next().expect("polled after the task finished");
+            async move { response }
+        })
+        .collect()
+        .await;
+
+        (lines, requests.load(Ordering::SeqCst))
     }
-}
\ No newline at end of file
+
+    // 依次返回给定状态的任务，记录每次轮询之间的等待时间
+    async fn wait(policy: PollPolicy, statuses: &[&str]) -> (TaskStatusResponse, Vec<Duration>) {
+        let statuses = std::sync::Mutex::new(statuses.iter());
+        let delays = std::sync::Mutex::new(Vec::new());
+
+        let task = poll_until_finished(
+            1,
+            &policy,
+            |_| {
+                let status = statuses.lock().unwrap().next().expect("polled after the task finished");
+                async move { Ok(task_with_logs(status, "")) }
+            },
+            |delay| {
+                delays.lock().unwrap().push(delay);
+                async {}
+            },
+        )
+        .await
+        .unwrap();
+
+        (task, delays.into_inner().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_completion_backs_off_up_to_cap() {
+        let policy = PollPolicy::fixed(Duration::from_secs(1)).backoff(2.0, Duration::from_secs(10));
+        let (task, delays) = wait(policy, &["pending", "queued", "running", "running", "running", "running", "running", "completed"]).await;
+
+        assert_eq!(task.status, TaskStatus::Completed);
+        let secs: Vec<u64> = delays.iter().map(Duration::as_secs).collect();
+        assert_eq!(secs, vec![1, 2, 4, 8, 10, 10, 10]);
+
+        // 固定间隔
+        let (task, delays) = wait(PollPolicy::fixed(Duration::from_millis(500)), &["running", "running", "failed"]).await;
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(delays, vec![Duration::from_millis(500); 2]);
+
+        // 已结束的任务不等待
+        let (_, delays) = wait(PollPolicy::default(), &["completed"]).await;
+        assert!(delays.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_completion_jitter_stays_in_range() {
+        let policy = PollPolicy::default();
+        assert_eq!(policy.delay(1), DEFAULT_POLL_INTERVAL);
+        assert_eq!(policy.delay(100), DEFAULT_MAX_POLL_INTERVAL);
+        assert_eq!(policy.jittered(Duration::from_secs(10), 0.0), Duration::from_secs(9));
+        assert_eq!(policy.jittered(Duration::from_secs(10), 1.0), Duration::from_secs(11));
+
+        let policy = policy.jitter(0.5);
+        let (_, delays) = wait(policy, &["running"; 20].into_iter().chain(["completed"]).collect::<Vec<_>>()).await;
+        for (poll, delay) in (1..).zip(&delays) {
+            let base = policy.delay(poll);
+            assert!(*delay >= base.mul_f64(0.5) && *delay <= base.mul_f64(1.5), "poll {poll}: {delay:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_logs_yields_only_new_lines() {
+        let (lines, requests) = tail(vec![
+            Ok(task_with_logs("pending", "")),
+            Ok(task_with_logs("running", "start\nstep 1\n")),
+            Ok(task_with_logs("running", "start\nstep 1\n")),
+            Ok(task_with_logs("running", "start\nstep 1\nstep 2\nstep 3 (partial")),
+            Ok(task_with_logs("completed", "start\nstep 1\nstep 2\nstep 3 (partial)\ndone")),
+        ])
+        .await;
+
+        let lines: Vec<String> = lines.into_iter().map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["start", "step 1", "step 2", "step 3 (partial)", "done"]);
+        assert_eq!(requests, 5);
+    }
+
+    #[tokio::test]
+    async fn test_stream_logs_skips_overlapping_log_windows() {
+        let (lines, _) = tail(vec![
+            Ok(task_with_logs("running", "a\nb\nc\n")),
+            // 接口只返回最近三行
+            Ok(task_with_logs("running", "b\nc\nd\n")),
+            Ok(task_with_logs("failed", "d\ne\nerror\n")),
+        ])
+        .await;
+
+        let lines: Vec<String> = lines.into_iter().map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["a", "b", "c", "d", "e", "error"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_logs_ends_on_error() {
+        let (lines, requests) = tail(vec![
+            Ok(task_with_logs("running", "start\n")),
+            Err(CalphaMeshError::HttpError("connection reset".to_string())),
+        ])
+        .await;
+
+        assert_eq!(requests, 2);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].as_ref().unwrap(), "start");
+        assert!(matches!(lines[1], Err(CalphaMeshError::HttpError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tool_schemas_match_args() {
+        use crate::tools::assert_schema_matches_args;
+
+        let composition = json!({"AL": 0.9, "MG": 0.05, "SI": 0.05});
+        assert_schema_matches_args::<PointTaskParams>(
+            &SubmitPointTask.definition(String::new()).await,
+            json!({"components": ["AL", "MG", "SI"], "composition": composition, "temperature": 800.0, "pressure": 1.0, "database": "default", "dry_run": true}),
+        );
+        assert_schema_matches_args::<LineTaskParams>(
+            &SubmitLineTask.definition(String::new()).await,
+            json!({
+                "components": ["AL", "MG", "SI"],
+                "start_composition": composition,
+                "start_temperature": 500.0,
+                "end_composition": "AL 80%, MG 10%, SI 10%",
+                "end_temperature": 900.0,
+                "pressure": 1.0,
+                "steps": 20,
+                "database": "default",
+                "dry_run": true
+            }),
+        );
+        assert_schema_matches_args::<ScheilTaskParams>(
+            &SubmitScheilTask.definition(String::new()).await,
+            json!({"components": ["AL", "MG", "SI"], "composition": composition, "temperature": 1073.15, "pressure": 1.01325, "database": "default", "dry_run": true}),
+        );
+        assert_schema_matches_args::<TaskIdParams>(&GetTaskStatus.definition(String::new()).await, json!({"task_id": 42}));
+        assert_schema_matches_args::<ListTasksParams>(&ListTasks.definition(String::new()).await, json!({"page": 2, "items_per_page": 10}));
+    }
+
+    #[tokio::test]
+    async fn test_tool_schemas_expose_defaults() {
+        // 默认值来自参数类型上的 serde 默认值函数
+        let point = SubmitPointTask.definition(String::new()).await.parameters;
+        assert_eq!(point["properties"]["temperature"]["default"], json!(298.15));
+        assert_eq!(point["properties"]["pressure"]["default"], json!(1.0));
+        assert_eq!(point["properties"]["database"]["default"], json!("default"));
+        assert_eq!(point["properties"]["components"]["default"], json!(default_components()));
+        assert!(!point["required"].as_array().is_some_and(|required| required.contains(&json!("temperature"))));
+
+        let line = SubmitLineTask.definition(String::new()).await.parameters;
+        assert_eq!(line["properties"]["end_temperature"]["default"], json!(1000.0));
+        assert_eq!(line["properties"]["steps"]["default"], json!(50));
+
+        let scheil = SubmitScheilTask.definition(String::new()).await.parameters;
+        assert_eq!(scheil["properties"]["temperature"]["default"], json!(1073.15));
+        assert_eq!(scheil["properties"]["pressure"]["default"], json!(1.01325));
+
+        let list = ListTasks.definition(String::new()).await.parameters;
+        assert_eq!(list["properties"]["items_per_page"]["default"], json!(50));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_returns_request_without_http_call() {
+        // 任何发到该地址的请求都会在监听端排队，可据此确认没有发出请求
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let client = CalphaMeshClient::builder("tk_test")
+            .base_url(format!("http://{}", listener.local_addr().unwrap()))
+            .build();
+
+        let params: PointTaskParams =
+            serde_json::from_value(json!({"composition": "AL 90%, MG 5%, SI 5%", "temperature": 800.0, "dry_run": true})).unwrap();
+        assert!(params.dry_run);
+        let request = params.task_request();
+        let title = request.title.clone();
+
+        let output = submit_task(&client, "Point", request, params.dry_run, OutputStyle::Plain).await.unwrap();
+
+        assert!(output.starts_with("Point 计算任务预览（未提交）\n"));
+        assert!(output.contains(&format!("标题: {title}")));
+        let description: serde_json::Value = serde_json::from_str(output.split_once("任务描述:\n").unwrap().1).unwrap();
+        assert_eq!(description["task_type"], "point");
+        assert_eq!(description["ctp"]["temperature"], json!(800.0));
+        assert_eq!(description["ctp"]["composition"]["AL"], json!(0.9));
+        assert_eq!(listener.accept().unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+
+        // 默认不是预览模式
+        let params: PointTaskParams = serde_json::from_value(json!({})).unwrap();
+        assert!(!params.dry_run);
+    }
+
+    #[test]
+    fn test_only_query_tools_are_read_only() {
+        use crate::tool::ToolDyn;
+
+        let tools: Vec<(Box<dyn ToolDyn>, bool)> = vec![
+            (Box::new(SubmitPointTask), true),
+            (Box::new(SubmitLineTask), true),
+            (Box::new(SubmitScheilTask), true),
+            (Box::new(GetTaskStatus), false),
+            (Box::new(ListTasks), false),
+        ];
+        for (tool, mutates) in tools {
+            assert_eq!(tool.mutates(), mutates, "{}", tool.name());
+        }
+    }
+}
@@ -0,0 +1,751 @@
+//! Calpha Mesh 计算结果解析
+//!
+//! 已完成任务的 `result` 字段是一段按列存储的 JSON：每个计算目标（`targets`）对应一列，
+//! 列中每个元素对应一个计算步。本模块将其解析为带类型的结构体，便于绘图和后续分析。
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::calphaMesh::{CalphaMeshError, TaskStatus, TaskStatusResponse};
+
+/// Scheil 凝固计算结果
+///
+/// 期望的 `result` 格式（温度单位 K）：
+/// ```json
+/// {
+///     "T": [933.0, 900.0, 850.0],
+///     "fl": [1.0, 0.4, 0.0005],
+///     "fs": [0.0, 0.6, 0.9995],
+///     "phase_name": ["LIQUID", "LIQUID+FCC_A1", "LIQUID+FCC_A1+MG2SI"],
+///     "f_tot(@FCC_A1)": [0.0, 0.6, 0.95],
+///     "f_tot(@MG2SI)": [0.0, 0.0, 0.0495],
+///     "conditions": {"T_end": {"@value": "300"}, "liquid_amount_min": {"@value": "0.001"}}
+/// }
+/// ```
+/// 数值既可以是数字也可以是数字字符串；`conditions` 为提交任务时的终止条件，可省略。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheilResult {
+    /// 每一步的温度 (K)，随凝固进行递减
+    pub temperatures: Vec<f64>,
+    /// 每一步的固相分数
+    pub solid_fractions: Vec<f64>,
+    /// 每一步的液相分数
+    pub liquid_fractions: Vec<f64>,
+    /// 每一步存在的相，例如 `LIQUID+FCC_A1`
+    pub phases: Vec<String>,
+    /// 各相的累计分数（来自 `f_tot(@相名)` 列），键为相名
+    pub phase_fractions: HashMap<String, Vec<f64>>,
+    /// 终止条件
+    pub termination: ScheilTermination,
+}
+
+/// Scheil 计算的终止条件：温度降到 `T_end` 以下，或液相分数低于 `liquid_amount_min`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScheilTermination {
+    pub t_end: Option<f64>,
+    pub liquid_amount_min: Option<f64>,
+}
+
+/// Scheil 计算停止的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheilStopReason {
+    /// 剩余液相低于 `liquid_amount_min`，凝固完成
+    NoMoreLiquid,
+    /// 温度达到 `T_end` 时仍有液相残留
+    ReachedTEnd,
+    /// 结果未满足任何终止条件（计算可能被中断）
+    Incomplete,
+}
+
+impl ScheilResult {
+    /// 从已完成任务的 `result` 字符串解析
+    pub fn from_result(result: &str) -> Result<Self, CalphaMeshError> {
+        let value: Value = serde_json::from_str(result)?;
+        let columns = value
+            .as_object()
+            .ok_or_else(|| invalid("Scheil result is not a JSON object"))?;
+
+        let temperatures = numeric_column(columns, "T")?;
+        let steps = temperatures.len();
+        let solid_fractions = numeric_column(columns, "fs")?;
+        let liquid_fractions = match columns.get("fl") {
+            Some(_) => numeric_column(columns, "fl")?,
+            None => solid_fractions.iter().map(|fs| 1.0 - fs).collect(),
+        };
+        let phases = string_column(columns, "phase_name")?;
+
+        let mut phase_fractions = HashMap::new();
+        for key in columns.keys() {
+            if let Some(phase) = phase_argument(key, "f_tot") {
+                phase_fractions.insert(phase.to_string(), numeric_column(columns, key)?);
+            }
+        }
+
+        let lengths = [solid_fractions.len(), liquid_fractions.len(), phases.len()];
+        let phase_lengths = phase_fractions.values().map(Vec::len);
+        if let Some(len) = lengths
+            .into_iter()
+            .chain(phase_lengths)
+            .find(|len| *len != steps)
+        {
+            return Err(invalid(format!(
+                "Scheil result columns have different lengths ({steps} temperatures, {len} values)"
+            )));
+        }
+
+        let conditions = columns.get("conditions");
+        let termination = ScheilTermination {
+            t_end: conditions.and_then(|c| condition_value(c, "T_end")),
+            liquid_amount_min: conditions.and_then(|c| condition_value(c, "liquid_amount_min")),
+        };
+
+        Ok(Self {
+            temperatures,
+            solid_fractions,
+            liquid_fractions,
+            phases,
+            phase_fractions,
+            termination,
+        })
+    }
+
+    /// 从任务状态解析，任务未完成或没有结果时返回错误
+    pub fn from_task(task: &TaskStatusResponse) -> Result<Self, CalphaMeshError> {
+        Self::from_result(completed_result(task, "scheil")?)
+    }
+
+    /// 凝固曲线：(温度, 固相分数) 点列
+    pub fn solidification_curve(&self) -> Vec<(f64, f64)> {
+        self.temperatures
+            .iter()
+            .copied()
+            .zip(self.solid_fractions.iter().copied())
+            .collect()
+    }
+
+    /// 液相线温度（第一步的温度）
+    pub fn liquidus(&self) -> Option<f64> {
+        self.temperatures.first().copied()
+    }
+
+    /// 最后一步的温度，即凝固终了温度
+    pub fn final_temperature(&self) -> Option<f64> {
+        self.temperatures.last().copied()
+    }
+
+    /// 根据终止条件判断计算停止的原因
+    pub fn stop_reason(&self) -> ScheilStopReason {
+        let (Some(&temperature), Some(&liquid)) =
+            (self.temperatures.last(), self.liquid_fractions.last())
+        else {
+            return ScheilStopReason::Incomplete;
+        };
+
+        let ScheilTermination {
+            t_end,
+            liquid_amount_min,
+        } = self.termination;
+        if liquid_amount_min.is_some_and(|min| liquid <= min) {
+            ScheilStopReason::NoMoreLiquid
+        } else if t_end.is_some_and(|t_end| temperature <= t_end) {
+            ScheilStopReason::ReachedTEnd
+        } else {
+            ScheilStopReason::Incomplete
+        }
+    }
+}
+
+/// Line 计算结果：沿成分/温度路径逐步计算的平衡结果
+///
+/// 期望的 `result` 格式与提交任务时的 `targets` 对应，每列一个元素对应一步：
+/// ```json
+/// {
+///     "T": [1000.0, 900.0],
+///     "phase_name": ["LIQUID", "LIQUID+FCC_A1"],
+///     "f(@LIQUID)": [1.0, 0.62],
+///     "f(@FCC_A1)": [null, 0.38],
+///     "G(@LIQUID)": [-52000.1, -45012.7],
+///     "mu(AL)": [-48000.5, -41000.2],
+///     "mu(MG)": [-60100.0, -55400.9]
+/// }
+/// ```
+/// `phase_name` 的每个元素可以是以 `+` 连接的相名，也可以是相名数组；`null` 表示该步没有对应的值。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineResult {
+    pub steps: Vec<LineStep>,
+}
+
+/// Line 计算中的一步
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineStep {
+    /// 温度 (K)
+    pub temperature: f64,
+    /// 该步存在的相
+    pub phases: Vec<String>,
+    /// 各相的分数（来自 `f(@相名)` 列），键为相名
+    pub phase_fractions: HashMap<String, f64>,
+    /// 各相的吉布斯自由能（来自 `G(@相名)` 列），键为相名
+    pub gibbs_energies: HashMap<String, f64>,
+    /// 化学势，键为目标列名，例如 `mu(AL)` 或 `mu(AL@FCC_A1)`
+    pub chemical_potentials: HashMap<String, f64>,
+}
+
+impl LineResult {
+    /// 从已完成任务的 `result` 字符串解析
+    pub fn from_result(result: &str) -> Result<Self, CalphaMeshError> {
+        let value: Value = serde_json::from_str(result)?;
+        let columns = value
+            .as_object()
+            .ok_or_else(|| invalid("line result is not a JSON object"))?;
+
+        let temperatures = numeric_column(columns, "T")?;
+        let phases = column(columns, "phase_name")?
+            .iter()
+            .map(step_phases)
+            .collect::<Result<Vec<_>, _>>()?;
+        if phases.len() != temperatures.len() {
+            return Err(invalid(format!(
+                "line result has {} temperatures but {} phase names",
+                temperatures.len(),
+                phases.len()
+            )));
+        }
+
+        let mut steps: Vec<LineStep> = temperatures
+            .into_iter()
+            .zip(phases)
+            .map(|(temperature, phases)| LineStep {
+                temperature,
+                phases,
+                phase_fractions: HashMap::new(),
+                gibbs_energies: HashMap::new(),
+                chemical_potentials: HashMap::new(),
+            })
+            .collect();
+
+        for key in columns.keys() {
+            let (name, select): (&str, StepValues) = if let Some(phase) = phase_argument(key, "f") {
+                (phase, |step| &mut step.phase_fractions)
+            } else if let Some(phase) = phase_argument(key, "G") {
+                (phase, |step| &mut step.gibbs_energies)
+            } else if key.starts_with("mu(") && key.ends_with(')') {
+                (key, |step| &mut step.chemical_potentials)
+            } else {
+                continue;
+            };
+
+            let values = optional_numeric_column(columns, key)?;
+            if values.len() != steps.len() {
+                return Err(invalid(format!(
+                    "`{key}` has {} values for {} steps",
+                    values.len(),
+                    steps.len()
+                )));
+            }
+            for (step, value) in steps.iter_mut().zip(values) {
+                if let Some(value) = value {
+                    select(step).insert(name.to_string(), value);
+                }
+            }
+        }
+
+        Ok(Self { steps })
+    }
+
+    /// 从任务状态解析，任务未完成或没有结果时返回错误
+    pub fn from_task(task: &TaskStatusResponse) -> Result<Self, CalphaMeshError> {
+        Self::from_result(completed_result(task, "line")?)
+    }
+
+    /// 所有步中出现过的相，按首次出现的顺序排列
+    pub fn phase_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for phase in self.steps.iter().flat_map(|step| &step.phases) {
+            if !names.contains(&phase.as_str()) {
+                names.push(phase);
+            }
+        }
+        names
+    }
+
+    /// 某一相在每一步的分数，该相不存在的步记为 0，用于绘制相分数-步数曲线
+    pub fn phase_fraction_series(&self, phase: &str) -> Vec<f64> {
+        self.steps
+            .iter()
+            .map(|step| step.phase_fractions.get(phase).copied().unwrap_or(0.0))
+            .collect()
+    }
+}
+
+/// Point 计算结果：单个温度、成分点的平衡状态
+///
+/// 期望的 `result` 格式与提交任务时的 `targets`（`T`、`G(@*)`、`phase_name`、`mu(*@*)`）对应，
+/// 与 Line 结果相同但每列只有一个元素，也可以直接是标量：
+/// ```json
+/// {
+///     "T": [773.15],
+///     "phase_name": ["FCC_A1+MG2SI"],
+///     "G(@FCC_A1)": [-35210.4],
+///     "G(@MG2SI)": [-41877.2],
+///     "mu(AL@FCC_A1)": [-30112.8],
+///     "mu(SI@MG2SI)": [-38215.6]
+/// }
+/// ```
+/// `mu(*@*)` 展开为 `mu(元素)` 或 `mu(元素@相名)` 列。平衡时同一元素在各相中的化学势相等，
+/// 因此按元素归并，优先使用不带相名的列；`null` 表示没有对应的值。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PointResult {
+    /// 温度 (K)
+    pub temperature: f64,
+    /// 平衡时的稳定相
+    pub stable_phases: Vec<String>,
+    /// 各相的吉布斯自由能（来自 `G(@相名)` 列），键为相名
+    pub gibbs_energies: HashMap<String, f64>,
+    /// 各元素的化学势（来自 `mu(元素)` 或 `mu(元素@相名)` 列），键为元素
+    pub chemical_potentials: HashMap<String, f64>,
+}
+
+impl PointResult {
+    /// 从已完成任务的 `result` 字符串解析
+    pub fn from_result(result: &str) -> Result<Self, CalphaMeshError> {
+        let value: Value = serde_json::from_str(result)?;
+        let columns = value
+            .as_object()
+            .ok_or_else(|| invalid("point result is not a JSON object"))?;
+
+        let temperature = point_number(columns, "T")?
+            .ok_or_else(|| invalid("point result has no temperature"))?;
+        let stable_phases = step_phases(point_value(columns, "phase_name")?)?;
+
+        let mut gibbs_energies = HashMap::new();
+        let mut chemical_potentials = HashMap::new();
+        for key in columns.keys() {
+            if let Some(phase) = phase_argument(key, "G") {
+                if let Some(energy) = point_number(columns, key)? {
+                    gibbs_energies.insert(phase.to_string(), energy);
+                }
+            } else if let Some((element, phase)) = chemical_potential_argument(key) {
+                let Some(potential) = point_number(columns, key)? else {
+                    continue;
+                };
+                // 不带相名的列优先，其余列只补充缺失的元素
+                if phase.is_none() {
+                    chemical_potentials.insert(element.to_string(), potential);
+                } else {
+                    chemical_potentials
+                        .entry(element.to_string())
+                        .or_insert(potential);
+                }
+            }
+        }
+
+        Ok(Self {
+            temperature,
+            stable_phases,
+            gibbs_energies,
+            chemical_potentials,
+        })
+    }
+
+    /// 从任务状态解析，任务未完成或没有结果时返回错误
+    pub fn from_task(task: &TaskStatusResponse) -> Result<Self, CalphaMeshError> {
+        Self::from_result(completed_result(task, "point")?)
+    }
+
+    /// 某一元素的化学势，元素名不区分大小写
+    pub fn chemical_potential(&self, element: &str) -> Option<f64> {
+        self.chemical_potentials
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(element))
+            .map(|(_, potential)| *potential)
+    }
+
+    /// 某一相是否稳定，相名不区分大小写
+    pub fn is_stable(&self, phase: &str) -> bool {
+        self.stable_phases
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(phase))
+    }
+}
+
+// Point 结果中的单个值：单元素数组或标量
+fn point_value<'a>(
+    columns: &'a Map<String, Value>,
+    key: &str,
+) -> Result<&'a Value, CalphaMeshError> {
+    match columns.get(key) {
+        Some(Value::Array(values)) => match values.as_slice() {
+            [value] => Ok(value),
+            values => Err(invalid(format!(
+                "`{key}` has {} values, a point result has one",
+                values.len()
+            ))),
+        },
+        Some(value) => Ok(value),
+        None => Err(invalid(format!("missing `{key}` column"))),
+    }
+}
+
+// 允许 `null` 的 Point 数值
+fn point_number(columns: &Map<String, Value>, key: &str) -> Result<Option<f64>, CalphaMeshError> {
+    match point_value(columns, key)? {
+        Value::Null => Ok(None),
+        value => as_number(value)
+            .map(Some)
+            .ok_or_else(|| invalid(format!("non-numeric value {value} in `{key}`"))),
+    }
+}
+
+// 解析 `mu(AL)` 或 `mu(AL@FCC_A1)` 形式的列名，返回元素与可选的相名
+fn chemical_potential_argument(key: &str) -> Option<(&str, Option<&str>)> {
+    let argument = key.strip_prefix("mu(")?.strip_suffix(')')?;
+    let (element, phase) = match argument.split_once('@') {
+        Some((element, phase)) => (element, Some(phase)),
+        None => (argument, None),
+    };
+    (!element.is_empty() && element != "*").then_some((element, phase))
+}
+
+// 一步中的相：`"LIQUID+FCC_A1"` 或 `["LIQUID", "FCC_A1"]`
+fn step_phases(value: &Value) -> Result<Vec<String>, CalphaMeshError> {
+    match value {
+        Value::String(names) => Ok(names
+            .split('+')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect()),
+        Value::Array(names) => names
+            .iter()
+            .map(|name| match name {
+                Value::String(name) => Ok(name.clone()),
+                other => Err(invalid(format!("invalid phase name {other}"))),
+            })
+            .collect(),
+        Value::Null => Ok(Vec::new()),
+        other => Err(invalid(format!("invalid phase names {other}"))),
+    }
+}
+
+// 返回已完成任务的 result，并检查任务类型
+fn completed_result<'a>(
+    task: &'a TaskStatusResponse,
+    task_type: &str,
+) -> Result<&'a str, CalphaMeshError> {
+    if task.task_type != task_type {
+        return Err(invalid(format!(
+            "task {} is a {} task, not a {task_type} task",
+            task.id, task.task_type
+        )));
+    }
+    if task.status != TaskStatus::Completed {
+        return Err(invalid(format!(
+            "task {} is not completed (status: {})",
+            task.id, task.status
+        )));
+    }
+    task.result
+        .as_deref()
+        .ok_or_else(|| invalid(format!("task {} has no result", task.id)))
+}
+
+fn invalid(reason: impl Into<String>) -> CalphaMeshError {
+    CalphaMeshError::InvalidResult(reason.into())
+}
+
+// 数字或数字字符串
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn numeric_column(columns: &Map<String, Value>, key: &str) -> Result<Vec<f64>, CalphaMeshError> {
+    column(columns, key)?
+        .iter()
+        .map(|value| {
+            as_number(value).ok_or_else(|| invalid(format!("non-numeric value {value} in `{key}`")))
+        })
+        .collect()
+}
+
+// 允许 `null` 的数值列
+fn optional_numeric_column(
+    columns: &Map<String, Value>,
+    key: &str,
+) -> Result<Vec<Option<f64>>, CalphaMeshError> {
+    column(columns, key)?
+        .iter()
+        .map(|value| match value {
+            Value::Null => Ok(None),
+            value => as_number(value)
+                .map(Some)
+                .ok_or_else(|| invalid(format!("non-numeric value {value} in `{key}`"))),
+        })
+        .collect()
+}
+
+fn string_column(columns: &Map<String, Value>, key: &str) -> Result<Vec<String>, CalphaMeshError> {
+    column(columns, key)?
+        .iter()
+        .map(|value| match value {
+            Value::String(text) => Ok(text.clone()),
+            other => Err(invalid(format!("non-string value {other} in `{key}`"))),
+        })
+        .collect()
+}
+
+fn column<'a>(columns: &'a Map<String, Value>, key: &str) -> Result<&'a [Value], CalphaMeshError> {
+    match columns.get(key) {
+        Some(Value::Array(values)) => Ok(values),
+        Some(_) => Err(invalid(format!("`{key}` is not an array"))),
+        None => Err(invalid(format!("missing `{key}` column"))),
+    }
+}
+
+// 取出一步中存放某类列值（相分数、吉布斯能、化学势）的映射
+type StepValues = fn(&mut LineStep) -> &mut HashMap<String, f64>;
+
+// 解析 `f_tot(@FCC_A1)` 形式的列名，返回括号中 `@` 之后的部分
+fn phase_argument<'a>(key: &'a str, function: &str) -> Option<&'a str> {
+    key.strip_prefix(function)?
+        .strip_prefix("(@")?
+        .strip_suffix(')')
+        .filter(|phase| !phase.is_empty() && *phase != "*")
+}
+
+// 条件既可以是 `{"@value": "300"}` 也可以直接是数值
+fn condition_value(conditions: &Value, name: &str) -> Option<f64> {
+    let condition = conditions.get(name)?;
+    condition
+        .get("@value")
+        .map_or_else(|| as_number(condition), as_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn scheil_payload() -> Value {
+        json!({
+            "T": [933.47, 905.2, 870.0, 851.3],
+            "fl": [1.0, 0.52, 0.08, 0.0008],
+            "fs": [0.0, 0.48, 0.92, 0.9992],
+            "phase_name": ["LIQUID", "LIQUID+FCC_A1", "LIQUID+FCC_A1", "LIQUID+FCC_A1+MG2SI"],
+            "f_tot(@FCC_A1)": ["0", "0.48", "0.92", "0.9701"],
+            "f_tot(@MG2SI)": [0.0, 0.0, 0.0, 0.0291],
+            "f(@*)": [[1.0], [0.52, 0.48], [0.08, 0.92], [0.0008, 0.9701, 0.0291]],
+            "Q": [0.0, 120.5, 230.1, 260.7],
+            "conditions": {
+                "T_end": {"@value": "300"},
+                "liquid_amount_min": {"@value": "0.001"}
+            }
+        })
+    }
+
+    #[test]
+    fn test_decode_scheil_result() {
+        let result = ScheilResult::from_result(&scheil_payload().to_string()).unwrap();
+
+        assert_eq!(result.temperatures, vec![933.47, 905.2, 870.0, 851.3]);
+        assert_eq!(result.solid_fractions, vec![0.0, 0.48, 0.92, 0.9992]);
+        assert_eq!(result.phases[3], "LIQUID+FCC_A1+MG2SI");
+        assert_eq!(result.phase_fractions.len(), 2);
+        assert_eq!(
+            result.phase_fractions["FCC_A1"],
+            vec![0.0, 0.48, 0.92, 0.9701]
+        );
+        assert_eq!(result.liquidus(), Some(933.47));
+        assert_eq!(result.solidification_curve()[1], (905.2, 0.48));
+        assert_eq!(
+            result.termination,
+            ScheilTermination {
+                t_end: Some(300.0),
+                liquid_amount_min: Some(0.001),
+            }
+        );
+        assert_eq!(result.stop_reason(), ScheilStopReason::NoMoreLiquid);
+    }
+
+    #[test]
+    fn test_scheil_stop_reason_uses_termination_metadata() {
+        let mut payload = scheil_payload();
+        payload["T"] = json!([933.47, 700.0, 500.0, 300.0]);
+        payload["fl"] = json!([1.0, 0.3, 0.1, 0.05]);
+        let result = ScheilResult::from_result(&payload.to_string()).unwrap();
+        assert_eq!(result.stop_reason(), ScheilStopReason::ReachedTEnd);
+
+        payload.as_object_mut().unwrap().remove("conditions");
+        let result = ScheilResult::from_result(&payload.to_string()).unwrap();
+        assert_eq!(result.termination.t_end, None);
+        assert_eq!(result.stop_reason(), ScheilStopReason::Incomplete);
+    }
+
+    #[test]
+    fn test_invalid_scheil_result() {
+        let mut payload = scheil_payload();
+        payload["fs"] = json!([0.0, 0.48]);
+        let err = ScheilResult::from_result(&payload.to_string()).unwrap_err();
+        assert!(matches!(err, CalphaMeshError::InvalidResult(_)), "{err}");
+
+        payload.as_object_mut().unwrap().remove("T");
+        let err = ScheilResult::from_result(&payload.to_string()).unwrap_err();
+        assert!(err.to_string().contains("missing `T` column"), "{err}");
+    }
+
+    fn line_payload() -> Value {
+        json!({
+            "T": [1000.0, "900.5"],
+            "phase_name": ["LIQUID", "LIQUID+FCC_A1"],
+            "f(@LIQUID)": [1.0, 0.62],
+            "f(@FCC_A1)": [null, 0.38],
+            "G(@LIQUID)": [-52000.1, -45012.7],
+            "G(@FCC_A1)": [null, -45100.3],
+            "mu(AL)": [-48000.5, -41000.2],
+            "mu(MG)": [-60100.0, -55400.9],
+            "Q": [1.0, 2.0]
+        })
+    }
+
+    #[test]
+    fn test_decode_line_result() {
+        let result = LineResult::from_result(&line_payload().to_string()).unwrap();
+
+        assert_eq!(result.steps.len(), 2);
+        let first = &result.steps[0];
+        assert_eq!(first.temperature, 1000.0);
+        assert_eq!(first.phases, vec!["LIQUID"]);
+        assert_eq!(
+            first.phase_fractions,
+            HashMap::from([("LIQUID".to_string(), 1.0)])
+        );
+        assert!(!first.gibbs_energies.contains_key("FCC_A1"));
+
+        let second = &result.steps[1];
+        assert_eq!(second.temperature, 900.5);
+        assert_eq!(second.phases, vec!["LIQUID", "FCC_A1"]);
+        assert_eq!(second.phase_fractions["FCC_A1"], 0.38);
+        assert_eq!(second.gibbs_energies["FCC_A1"], -45100.3);
+        assert_eq!(
+            second.chemical_potentials,
+            HashMap::from([
+                ("mu(AL)".to_string(), -41000.2),
+                ("mu(MG)".to_string(), -55400.9)
+            ])
+        );
+
+        assert_eq!(result.phase_names(), vec!["LIQUID", "FCC_A1"]);
+        assert_eq!(result.phase_fraction_series("FCC_A1"), vec![0.0, 0.38]);
+    }
+
+    #[test]
+    fn test_invalid_line_result() {
+        let mut payload = line_payload();
+        payload["mu(AL)"] = json!([-48000.5]);
+        let err = LineResult::from_result(&payload.to_string()).unwrap_err();
+        assert!(err.to_string().contains("`mu(AL)` has 1 values"), "{err}");
+
+        let task: TaskStatusResponse = serde_json::from_value(json!({
+            "id": 3,
+            "title": "Task-Line-3",
+            "description": "",
+            "status": "running",
+            "task_type": "line",
+            "result": null,
+            "logs": null,
+            "user_id": 1,
+            "created_at": "2025-01-01T00:00:00",
+            "updated_at": "2025-01-01T00:00:00"
+        }))
+        .unwrap();
+        let err = LineResult::from_task(&task).unwrap_err();
+        assert!(err.to_string().contains("not completed"), "{err}");
+    }
+
+    fn point_payload() -> Value {
+        json!({
+            "T": [773.15],
+            "phase_name": ["FCC_A1+MG2SI"],
+            "G(@FCC_A1)": [-35210.4],
+            "G(@MG2SI)": ["-41877.2"],
+            "G(@LIQUID)": [null],
+            "mu(AL@FCC_A1)": [-30112.8],
+            "mu(MG@FCC_A1)": [-45870.3],
+            "mu(MG@MG2SI)": [-45870.3],
+            "mu(SI@MG2SI)": [-38215.6],
+            "mu(SI)": [-38215.5]
+        })
+    }
+
+    #[test]
+    fn test_decode_point_result() {
+        let result = PointResult::from_result(&point_payload().to_string()).unwrap();
+
+        assert_eq!(result.temperature, 773.15);
+        assert_eq!(result.stable_phases, vec!["FCC_A1", "MG2SI"]);
+        assert!(result.is_stable("mg2si"));
+        assert!(!result.is_stable("LIQUID"));
+        assert_eq!(
+            result.gibbs_energies,
+            HashMap::from([
+                ("FCC_A1".to_string(), -35210.4),
+                ("MG2SI".to_string(), -41877.2)
+            ])
+        );
+        assert_eq!(
+            result.chemical_potentials,
+            HashMap::from([
+                ("AL".to_string(), -30112.8),
+                ("MG".to_string(), -45870.3),
+                ("SI".to_string(), -38215.5)
+            ])
+        );
+        assert_eq!(result.chemical_potential("Al"), Some(-30112.8));
+        assert_eq!(result.chemical_potential("CU"), None);
+    }
+
+    #[test]
+    fn test_decode_point_result_from_task() {
+        let scalars = json!({
+            "T": 1273.15,
+            "phase_name": "LIQUID",
+            "G(@LIQUID)": -98000.2,
+            "mu(AL)": -87000.1
+        });
+        let mut task: TaskStatusResponse = serde_json::from_value(json!({
+            "id": 5,
+            "title": "Task-Point-5",
+            "description": "",
+            "status": "completed",
+            "task_type": "point",
+            "result": scalars.to_string(),
+            "logs": null,
+            "user_id": 1,
+            "created_at": "2025-01-01T00:00:00",
+            "updated_at": "2025-01-01T00:00:00"
+        }))
+        .unwrap();
+
+        let result = PointResult::from_task(&task).unwrap();
+        assert_eq!(result.temperature, 1273.15);
+        assert_eq!(result.stable_phases, vec!["LIQUID"]);
+        assert_eq!(result.chemical_potential("AL"), Some(-87000.1));
+
+        let mut payload = point_payload();
+        payload["T"] = json!([773.15, 800.0]);
+        task.result = Some(payload.to_string());
+        let err = PointResult::from_task(&task).unwrap_err();
+        assert!(err.to_string().contains("`T` has 2 values"), "{err}");
+
+        task.task_type = "line".to_string();
+        let err = PointResult::from_task(&task).unwrap_err();
+        assert!(err.to_string().contains("not a point task"), "{err}");
+    }
+}
@@ -0,0 +1,481 @@
+//! 成分字符串解析工具
+//!
+//! 模型传入的成分信息格式不一，例如自由文本（"Al 50%, Ti 40%, N 10%"）、
+//! JSON 对象（`{"Al": 0.5, "Ti": 0.4}`）、JSON 数组或 "AL:0.5 TI:0.4" 形式。
+//! [parse_composition] 将这些格式统一解析为 `元素符号(大写) -> 原子分数` 的映射，
+//! 并将总和归一化为 1.0。
+
+use std::collections::HashMap;
+
+use schemars::{Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use thiserror::Error;
+
+// 元素周期表中的元素符号
+const ELEMENT_SYMBOLS: [&str; 118] = [
+    "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S", "Cl",
+    "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge", "As",
+    "Se", "Br", "Kr", "Rb", "Sr", "Y", "Zr", "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd", "In",
+    "Sn", "Sb", "Te", "I", "Xe", "Cs", "Ba", "La", "Ce", "Pr", "Nd", "Pm", "Sm", "Eu", "Gd", "Tb",
+    "Dy", "Ho", "Er", "Tm", "Yb", "Lu", "Hf", "Ta", "W", "Re", "Os", "Ir", "Pt", "Au", "Hg", "Tl",
+    "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th", "Pa", "U", "Np", "Pu", "Am", "Cm", "Bk",
+    "Cf", "Es", "Fm", "Md", "No", "Lr", "Rf", "Db", "Sg", "Bh", "Hs", "Mt", "Ds", "Rg", "Cn", "Nh",
+    "Fl", "Mc", "Lv", "Ts", "Og",
+];
+
+/// 成分解析错误
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseError {
+    #[error("composition is empty")]
+    Empty,
+    #[error("invalid composition JSON: {0}")]
+    InvalidJson(String),
+    #[error("unknown element symbol: {0}")]
+    UnknownElement(String),
+    #[error("invalid amount for element {element}: {value}")]
+    InvalidAmount { element: String, value: String },
+    #[error("negative amount for element {element}: {value}")]
+    NegativeAmount { element: String, value: f64 },
+    #[error("duplicate element: {0}")]
+    DuplicateElement(String),
+    #[error("composition amounts sum to zero")]
+    ZeroTotal,
+    #[error("unexpected input at position {position}: {found:?}")]
+    Unexpected { position: usize, found: String },
+}
+
+/// 解析成分字符串，返回大写元素符号到原子分数的映射（总和归一化为 1.0）
+///
+/// 支持的格式：
+/// - 百分比文本：`"Al 50%, Ti 40%, N 10%"`
+/// - 键值文本：`"AL:0.5 TI:0.4 N=0.1"`
+/// - JSON 对象：`{"Al": 0.5, "Ti": 0.4, "N": "10%"}`
+/// - JSON 数组：`[["Al", 0.5], ["Ti", 0.4]]`、`[{"element": "Al", "fraction": 0.5}]` 或 `["Al 50%", "Ti 50%"]`
+pub fn parse_composition(input: &str) -> Result<HashMap<String, f64>, ParseError> {
+    let trimmed = input.trim();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        let value: Value =
+            serde_json::from_str(trimmed).map_err(|e| ParseError::InvalidJson(e.to_string()))?;
+        parse_composition_value(&value)
+    } else {
+        normalize(parse_text(trimmed)?)
+    }
+}
+
+/// 解析 JSON 形式的成分信息（对象、数组或字符串）
+pub fn parse_composition_value(value: &Value) -> Result<HashMap<String, f64>, ParseError> {
+    match value {
+        Value::String(text) => parse_composition(text),
+        Value::Object(map) => {
+            let mut entries = Vec::with_capacity(map.len());
+            for (element, amount) in map {
+                entries.push((element.clone(), amount_from_value(element, amount)?));
+            }
+            normalize(entries)
+        }
+        Value::Array(items) => {
+            let mut entries = Vec::with_capacity(items.len());
+            for item in items {
+                entries.extend(entries_from_array_item(item)?);
+            }
+            normalize(entries)
+        }
+        other => Err(ParseError::InvalidJson(format!(
+            "expected an object, array or string, found {other}"
+        ))),
+    }
+}
+
+/// 用于 `#[serde(deserialize_with = "...")]` 的成分反序列化函数
+pub fn deserialize_composition<'de, D>(deserializer: D) -> Result<HashMap<String, f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Value::deserialize(deserializer)?;
+    parse_composition_value(&value).map_err(serde::de::Error::custom)
+}
+
+/// 用于 `#[schemars(schema_with = "...")]` 的成分 JSON Schema，与 [deserialize_composition] 接受的格式一致：
+/// `元素 -> 原子分数` 对象，或 `"Al 50%, Ti 40%, N 10%"` 形式的文本
+pub fn composition_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "anyOf": [
+            {"type": "object", "additionalProperties": {"type": "number", "minimum": 0}},
+            {"type": "string"}
+        ]
+    })
+}
+
+/// 将成分映射格式化为按元素排序的百分比文本，如 `"AL 50.0%, TI 50.0%"`
+pub fn format_composition(composition: &HashMap<String, f64>) -> String {
+    let mut elements = composition.iter().collect::<Vec<_>>();
+    elements.sort_by(|a, b| a.0.cmp(b.0));
+    elements
+        .iter()
+        .map(|(element, fraction)| format!("{element} {:.1}%", *fraction * 100.0))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// 解析数组中的单个元素
+fn entries_from_array_item(item: &Value) -> Result<Vec<(String, f64)>, ParseError> {
+    match item {
+        Value::String(text) => parse_text(text),
+        Value::Array(pair) => match pair.as_slice() {
+            [Value::String(element), amount] => {
+                Ok(vec![(element.clone(), amount_from_value(element, amount)?)])
+            }
+            _ => Err(ParseError::InvalidJson(format!(
+                "expected an [element, amount] pair, found {item}"
+            ))),
+        },
+        Value::Object(map) => {
+            let element = map
+                .get("element")
+                .or_else(|| map.get("symbol"))
+                .and_then(Value::as_str);
+            let amount = ["fraction", "amount", "value", "percent"]
+                .iter()
+                .find_map(|key| map.get(*key));
+
+            match (element, amount) {
+                (Some(element), Some(amount)) => Ok(vec![(
+                    element.to_string(),
+                    amount_from_value(element, amount)?,
+                )]),
+                // 也接受单键对象，如 {"Al": 0.5}
+                _ if map.len() == 1 => {
+                    let (element, amount) = map.iter().next().expect("map has one entry");
+                    Ok(vec![(element.clone(), amount_from_value(element, amount)?)])
+                }
+                _ => Err(ParseError::InvalidJson(format!(
+                    "expected an object with `element` and `fraction` keys, found {item}"
+                ))),
+            }
+        }
+        other => Err(ParseError::InvalidJson(format!(
+            "unexpected composition entry: {other}"
+        ))),
+    }
+}
+
+// 解析 JSON 中的数量（数字或 "50%" 形式的字符串）
+fn amount_from_value(element: &str, amount: &Value) -> Result<f64, ParseError> {
+    let invalid = || ParseError::InvalidAmount {
+        element: element.to_string(),
+        value: amount.to_string(),
+    };
+
+    match amount {
+        Value::Number(number) => number.as_f64().ok_or_else(invalid),
+        Value::String(text) => text
+            .trim()
+            .trim_end_matches('%')
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| invalid()),
+        _ => Err(invalid()),
+    }
+}
+
+// 解析自由文本格式，如 "Al 50%, Ti 40%" 或 "AL:0.5 TI:0.4"
+fn parse_text(input: &str) -> Result<Vec<(String, f64)>, ParseError> {
+    let chars = input.char_indices().collect::<Vec<_>>();
+    let mut entries = Vec::new();
+    let mut i = 0;
+
+    let is_separator = |c: char| c.is_whitespace() || matches!(c, ',' | ';' | '，' | '；' | '、');
+
+    while i < chars.len() {
+        // 跳过条目之间的分隔符
+        if is_separator(chars[i].1) {
+            i += 1;
+            continue;
+        }
+
+        // 元素符号
+        let start = i;
+        while i < chars.len() && chars[i].1.is_ascii_alphabetic() {
+            i += 1;
+        }
+        if start == i {
+            return Err(ParseError::Unexpected {
+                position: chars[start].0,
+                found: chars[start].1.to_string(),
+            });
+        }
+        let element = chars[start..i].iter().map(|(_, c)| c).collect::<String>();
+
+        // 元素与数量之间的分隔符
+        while i < chars.len() && (chars[i].1 == ' ' || matches!(chars[i].1, ':' | '=' | '：')) {
+            i += 1;
+        }
+
+        // 数量
+        let number_start = i;
+        if i < chars.len() && matches!(chars[i].1, '-' | '+') {
+            i += 1;
+        }
+        while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+            i += 1;
+        }
+        // 科学计数法的指数部分（避免把 "Er" 等元素符号的首字母当作指数）
+        if i < chars.len() && matches!(chars[i].1, 'e' | 'E') {
+            let mut j = i + 1;
+            if j < chars.len() && matches!(chars[j].1, '-' | '+') {
+                j += 1;
+            }
+            if j < chars.len() && chars[j].1.is_ascii_digit() {
+                i = j;
+                while i < chars.len() && chars[i].1.is_ascii_digit() {
+                    i += 1;
+                }
+            }
+        }
+        let number = chars[number_start..i]
+            .iter()
+            .map(|(_, c)| c)
+            .collect::<String>();
+        let amount = number
+            .parse::<f64>()
+            .map_err(|_| ParseError::InvalidAmount {
+                element: element.clone(),
+                value: number.clone(),
+            })?;
+
+        // 可选的百分号
+        while i < chars.len() && chars[i].1 == ' ' {
+            i += 1;
+        }
+        if i < chars.len() && matches!(chars[i].1, '%' | '％') {
+            i += 1;
+        }
+
+        entries.push((element, amount));
+    }
+
+    Ok(entries)
+}
+
+// 校验元素符号、检查重复和负值，并将总和归一化为 1.0
+fn normalize(entries: Vec<(String, f64)>) -> Result<HashMap<String, f64>, ParseError> {
+    if entries.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    let mut composition = HashMap::with_capacity(entries.len());
+    for (element, amount) in entries {
+        let element = element.trim();
+        if !ELEMENT_SYMBOLS
+            .iter()
+            .any(|symbol| symbol.eq_ignore_ascii_case(element))
+        {
+            return Err(ParseError::UnknownElement(element.to_string()));
+        }
+        if !amount.is_finite() {
+            return Err(ParseError::InvalidAmount {
+                element: element.to_string(),
+                value: amount.to_string(),
+            });
+        }
+        if amount < 0.0 {
+            return Err(ParseError::NegativeAmount {
+                element: element.to_string(),
+                value: amount,
+            });
+        }
+
+        let symbol = element.to_ascii_uppercase();
+        if composition.insert(symbol.clone(), amount).is_some() {
+            return Err(ParseError::DuplicateElement(symbol));
+        }
+    }
+
+    let total: f64 = composition.values().sum();
+    if total <= 0.0 {
+        return Err(ParseError::ZeroTotal);
+    }
+    composition.values_mut().for_each(|amount| *amount /= total);
+
+    Ok(composition)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn assert_composition(actual: HashMap<String, f64>, expected: &[(&str, f64)]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?}");
+        for (element, fraction) in expected {
+            let value = actual
+                .get(*element)
+                .unwrap_or_else(|| panic!("missing {element} in {actual:?}"));
+            assert!(
+                (value - fraction).abs() < 1e-9,
+                "{element}: expected {fraction}, got {value}"
+            );
+        }
+    }
+
+    const AL_TI_N: [(&str, f64); 3] = [("AL", 0.5), ("TI", 0.4), ("N", 0.1)];
+
+    #[test]
+    fn test_percentage_text() {
+        assert_composition(parse_composition("Al 50%, Ti 40%, N 10%").unwrap(), &AL_TI_N);
+        assert_composition(parse_composition("Al50% Ti40% N10%").unwrap(), &AL_TI_N);
+        assert_composition(parse_composition("Al 50 %; Ti 40 %; N 10 %").unwrap(), &AL_TI_N);
+        assert_composition(parse_composition("Al 50％，Ti 40％，N 10％").unwrap(), &AL_TI_N);
+    }
+
+    #[test]
+    fn test_key_value_text() {
+        assert_composition(parse_composition("AL:0.5 TI:0.4 N:0.1").unwrap(), &AL_TI_N);
+        assert_composition(parse_composition("al=0.5, ti=0.4, n=0.1").unwrap(), &AL_TI_N);
+        assert_composition(parse_composition("AL: 0.5 TI: 0.4 N: 0.1").unwrap(), &AL_TI_N);
+        assert_composition(parse_composition("Al：0.5、Ti：0.4、N：0.1").unwrap(), &AL_TI_N);
+    }
+
+    #[test]
+    fn test_json_object() {
+        assert_composition(
+            parse_composition(r#"{"Al": 0.5, "Ti": 0.4, "N": 0.1}"#).unwrap(),
+            &AL_TI_N,
+        );
+        assert_composition(
+            parse_composition(r#"{"AL": 50, "TI": 40, "N": 10}"#).unwrap(),
+            &AL_TI_N,
+        );
+        assert_composition(
+            parse_composition(r#"{"al": "50%", "ti": "40%", "n": "10%"}"#).unwrap(),
+            &AL_TI_N,
+        );
+    }
+
+    #[test]
+    fn test_json_arrays() {
+        assert_composition(
+            parse_composition(r#"[["Al", 0.5], ["Ti", 0.4], ["N", 0.1]]"#).unwrap(),
+            &AL_TI_N,
+        );
+        assert_composition(
+            parse_composition(
+                r#"[{"element": "Al", "fraction": 0.5}, {"element": "Ti", "fraction": 0.4}, {"symbol": "N", "fraction": "0.1"}]"#,
+            )
+            .unwrap(),
+            &AL_TI_N,
+        );
+        assert_composition(
+            parse_composition(r#"["Al 50%", "Ti 40%", "N 10%"]"#).unwrap(),
+            &AL_TI_N,
+        );
+        assert_composition(
+            parse_composition(r#"[{"Al": 0.5}, {"Ti": 0.4}, {"N": 0.1}]"#).unwrap(),
+            &AL_TI_N,
+        );
+    }
+
+    #[test]
+    fn test_normalizes_totals() {
+        assert_composition(
+            parse_composition("Al 2, Ti 1, N 1").unwrap(),
+            &[("AL", 0.5), ("TI", 0.25), ("N", 0.25)],
+        );
+        assert_composition(
+            parse_composition("Al50Er25Fe2.5e1").unwrap(),
+            &[("AL", 0.5), ("ER", 0.25), ("FE", 0.25)],
+        );
+        assert_composition(
+            parse_composition("AL:1 MG:0 SI:0").unwrap(),
+            &[("AL", 1.0), ("MG", 0.0), ("SI", 0.0)],
+        );
+    }
+
+    #[test]
+    fn test_malformed_inputs() {
+        assert_eq!(parse_composition(""), Err(ParseError::Empty));
+        assert_eq!(parse_composition("  , ; "), Err(ParseError::Empty));
+        assert_eq!(parse_composition("{}"), Err(ParseError::Empty));
+        assert!(matches!(
+            parse_composition(r#"{"Al": 0.5"#),
+            Err(ParseError::InvalidJson(_))
+        ));
+        assert_eq!(
+            parse_composition("Xx 50%, Ti 50%"),
+            Err(ParseError::UnknownElement("Xx".to_string()))
+        );
+        assert_eq!(
+            parse_composition("TiAlN"),
+            Err(ParseError::InvalidAmount {
+                element: "TiAlN".to_string(),
+                value: String::new(),
+            })
+        );
+        assert!(matches!(
+            parse_composition("Al abc"),
+            Err(ParseError::InvalidAmount { .. })
+        ));
+        assert!(matches!(
+            parse_composition("Al 1.2.3"),
+            Err(ParseError::InvalidAmount { .. })
+        ));
+        assert_eq!(
+            parse_composition("Al -0.5, Ti 1.5"),
+            Err(ParseError::NegativeAmount {
+                element: "Al".to_string(),
+                value: -0.5,
+            })
+        );
+        assert_eq!(
+            parse_composition("Al 0.5, AL 0.5"),
+            Err(ParseError::DuplicateElement("AL".to_string()))
+        );
+        assert_eq!(parse_composition("Al 0, Ti 0"), Err(ParseError::ZeroTotal));
+        assert!(matches!(
+            parse_composition("50% Al"),
+            Err(ParseError::Unexpected { position: 0, .. })
+        ));
+        assert!(matches!(
+            parse_composition(r#"{"Al": true}"#),
+            Err(ParseError::InvalidAmount { .. })
+        ));
+        assert!(matches!(
+            parse_composition(r#"[["Al"]]"#),
+            Err(ParseError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            parse_composition_value(&json!(42)),
+            Err(ParseError::InvalidJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_composition() {
+        #[derive(Deserialize)]
+        struct Args {
+            #[serde(deserialize_with = "deserialize_composition")]
+            composition: HashMap<String, f64>,
+        }
+
+        let args: Args = serde_json::from_value(json!({"composition": "Al 50%, Ti 40%, N 10%"}))
+            .unwrap();
+        assert_composition(args.composition, &AL_TI_N);
+
+        let args: Args =
+            serde_json::from_value(json!({"composition": {"Al": 5, "Ti": 4, "N": 1}})).unwrap();
+        assert_composition(args.composition, &AL_TI_N);
+
+        let err = serde_json::from_value::<Args>(json!({"composition": "Qq 1"}))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("unknown element symbol"));
+    }
+
+    #[test]
+    fn test_format_composition() {
+        let composition = parse_composition("Ti 40%, Al 50%, N 10%").unwrap();
+        assert_eq!(format_composition(&composition), "AL 50.0%, N 10.0%, TI 40.0%");
+    }
+}
@@ -1,12 +1,79 @@
 pub mod think;
 pub use think::ThinkTool;
+pub mod composition;
+pub use composition::{parse_composition, ParseError};
+#[allow(non_snake_case)]
 pub mod calphaMesh;
 pub use calphaMesh::{
     SubmitPointTask, SubmitLineTask, SubmitScheilTask,
-    GetTaskStatus, ListTasks, CalphaMeshClient, CalphaMeshError
+    GetTaskStatus, ListTasks, CalphaMeshClient, CalphaMeshClientBuilder, CalphaMeshError, TaskStatus,
+    OutputStyle, PLAIN_OUTPUT_ENV, PollPolicy
+};
+pub mod calphamesh_result;
+pub use calphamesh_result::{
+    LineResult, LineStep, PointResult, ScheilResult, ScheilStopReason, ScheilTermination
 };
 pub mod simulation;
 pub use simulation::{
     TopPhiSimulator, TopPhiArgs, MLPerformancePredictor, MLPredictorArgs,
-    HistoricalDataQuery, HistoricalQueryArgs, ExperimentalDataReader, ExperimentalReaderArgs
-};
\ No newline at end of file
+    HistoricalDataQuery, HistoricalQueryArgs, ExperimentalDataReader, ExperimentalReaderArgs,
+    SimulationToolError, FaultInjector, PerformancePrediction, PerformanceProperty, PropertyPrediction,
+    GenerateWorkOrder, WorkOrder, WorkOrderArgs, PerformanceGate, PerformanceGateArgs,
+    PerformanceTargets, GateReport, MetricCheck, SimulationBackend, SIMULATION_MOCK_ENV,
+    SIMULATION_API_URL_ENV, DEFAULT_SIMULATION_API_URL
+};
+
+/// 由工具参数类型生成 `parameters` JSON Schema，嵌套类型直接内联（部分模型不支持 `$ref`）。
+/// 带 `#[serde(default = "...")]` 的字段会在 Schema 中给出 `default`，提示模型可以省略
+pub(crate) fn parameters_schema<T: schemars::JsonSchema>() -> serde_json::Value {
+    let generator = schemars::generate::SchemaSettings::draft2020_12()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator();
+    serde_json::to_value(generator.into_root_schema_for::<T>())
+        .expect("converting JSON schema to JSON value should never fail")
+}
+
+/// 检查工具的 `parameters` Schema 与参数类型一致：示例参数覆盖全部字段，
+/// 逐一删除字段时，只有 Schema 中的必填字段缺失会导致反序列化失败，
+/// Schema 给出的 `default` 值本身也能被反序列化
+#[cfg(test)]
+pub(crate) fn assert_schema_matches_args<T: serde::de::DeserializeOwned>(
+    definition: &crate::completion::ToolDefinition,
+    example: serde_json::Value,
+) {
+    let schema = &definition.parameters;
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|fields| fields.iter().filter_map(|field| field.as_str()).collect())
+        .unwrap_or_default();
+    let properties = schema["properties"].as_object().expect("schema properties");
+
+    let mut expected: Vec<&String> = properties.keys().collect();
+    let mut actual: Vec<&String> = example.as_object().unwrap().keys().collect();
+    expected.sort();
+    actual.sort();
+    assert_eq!(actual, expected, "{}: example fields", definition.name);
+    serde_json::from_value::<T>(example.clone()).unwrap();
+
+    for field in properties.keys() {
+        let mut args = example.clone();
+        args.as_object_mut().unwrap().remove(field);
+        let missing_is_error = serde_json::from_value::<T>(args).is_err();
+        assert_eq!(
+            missing_is_error,
+            required.contains(&field.as_str()),
+            "{}: field `{field}`",
+            definition.name
+        );
+
+        if let Some(default) = properties[field].get("default") {
+            let mut args = example.clone();
+            args[field] = default.clone();
+            assert!(
+                serde_json::from_value::<T>(args).is_ok(),
+                "{}: default of `{field}`",
+                definition.name
+            );
+        }
+    }
+}
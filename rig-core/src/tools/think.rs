@@ -30,6 +30,7 @@ pub struct ThinkTool;
 
 impl Tool for ThinkTool {
     const NAME: &'static str = "think";
+    const MUTATES: bool = false;
 
     type Error = ThinkError;
     type Args = ThinkArgs;